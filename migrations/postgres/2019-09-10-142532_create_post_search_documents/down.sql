@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE post_search_words;
+DROP TABLE post_search_documents;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE post_search_documents (
+    post_id INTEGER PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+    creation_date INTEGER NOT NULL,
+    instance TEXT NOT NULL DEFAULT '',
+    author TEXT NOT NULL DEFAULT '',
+    tag TEXT NOT NULL DEFAULT '',
+    blog TEXT NOT NULL DEFAULT '',
+    title TEXT NOT NULL DEFAULT '',
+    subtitle TEXT NOT NULL DEFAULT '',
+    content TEXT NOT NULL DEFAULT '',
+    lang TEXT NOT NULL DEFAULT '',
+    license TEXT NOT NULL DEFAULT '',
+    document TSVECTOR NOT NULL
+);
+CREATE INDEX post_search_documents_document ON post_search_documents USING GIN (document);
+CREATE TABLE post_search_words (
+    word TEXT PRIMARY KEY,
+    ndoc INTEGER NOT NULL
+);
+CREATE INDEX post_search_words_prefix ON post_search_words (word text_pattern_ops);
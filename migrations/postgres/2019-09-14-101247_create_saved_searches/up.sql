@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE saved_searches (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    query TEXT NOT NULL,
+    creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX saved_searches_user_id ON saved_searches (user_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE post_search_documents DROP COLUMN visible;
+DELETE FROM post_search_words;
+INSERT INTO post_search_words (word, ndoc)
+    SELECT word, ndoc FROM ts_stat('SELECT to_tsvector(''simple'', title) FROM post_search_documents');
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+ALTER TABLE post_search_documents ADD COLUMN visible BOOLEAN NOT NULL DEFAULT 't';
+UPDATE post_search_documents SET visible = 'f' WHERE post_id IN (
+    SELECT posts.id FROM posts
+        INNER JOIN blogs ON blogs.id = posts.blog_id
+        INNER JOIN instances ON instances.id = blogs.instance_id
+        WHERE instances.blocked
+    UNION SELECT post_authors.post_id FROM post_authors
+        INNER JOIN users ON users.id = post_authors.author_id
+        INNER JOIN instances ON instances.id = users.instance_id
+        WHERE instances.blocked
+);
+-- suggestions only use the words of visible titles
+DELETE FROM post_search_words;
+INSERT INTO post_search_words (word, ndoc)
+    SELECT word, ndoc FROM ts_stat('SELECT to_tsvector(''simple'', title) FROM post_search_documents WHERE visible');
+-- rebuild the tantivy index, with its visible field and fast creation dates
+--#!|conn: &Connection, path: &Path| {
+--#!    let mut pb = path.to_path_buf();
+--#!    pb.push("search_index");
+--#!    if pb.exists() {
+--#!        std::fs::remove_dir_all(&pb)?;
+--#!    }
+--#!    let searcher = super::search::Searcher::create(&pb)?;
+--#!    searcher.fill(conn)?;
+--#!    searcher.commit();
+--#!    Ok(())
+--#!}
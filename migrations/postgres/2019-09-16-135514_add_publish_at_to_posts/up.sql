@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE posts ADD COLUMN publish_at TIMESTAMP;
+CREATE INDEX posts_publish_at ON posts (publish_at);
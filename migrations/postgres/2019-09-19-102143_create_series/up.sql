@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE series (
+    id SERIAL PRIMARY KEY,
+    blog_id INTEGER NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+    slug VARCHAR NOT NULL,
+    title VARCHAR NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    ap_url VARCHAR NOT NULL DEFAULT '' UNIQUE,
+    creation_date TIMESTAMP NOT NULL DEFAULT now(),
+    CONSTRAINT series_unique UNIQUE (blog_id, slug)
+);
+
+CREATE TABLE series_posts (
+    id SERIAL PRIMARY KEY,
+    series_id INTEGER NOT NULL REFERENCES series(id) ON DELETE CASCADE,
+    post_id INTEGER NOT NULL UNIQUE REFERENCES posts(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL
+);
+
+CREATE INDEX series_posts_series_id ON series_posts (series_id);
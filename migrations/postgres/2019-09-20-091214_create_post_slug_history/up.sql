@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE post_slug_history (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    blog_id INTEGER NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+    slug VARCHAR NOT NULL,
+    creation_date TIMESTAMP NOT NULL DEFAULT now(),
+    CONSTRAINT post_slug_history_unique UNIQUE (blog_id, slug)
+);
+
+CREATE INDEX post_slug_history_post_id ON post_slug_history (post_id);
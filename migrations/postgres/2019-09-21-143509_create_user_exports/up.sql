@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE user_exports (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    file_path VARCHAR NOT NULL,
+    ready BOOLEAN NOT NULL DEFAULT 'f',
+    creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE blog_invitations;
+
+ALTER TABLE blog_authors ADD COLUMN is_owner BOOLEAN NOT NULL DEFAULT 'f';
+UPDATE blog_authors SET is_owner = 't' WHERE role = 'owner';
+ALTER TABLE blog_authors DROP COLUMN role;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE blog_authors ADD COLUMN role VARCHAR NOT NULL DEFAULT 'writer';
+UPDATE blog_authors SET role = CASE WHEN is_owner THEN 'owner' ELSE 'editor' END;
+ALTER TABLE blog_authors DROP COLUMN is_owner;
+
+CREATE TABLE blog_invitations (
+    id SERIAL PRIMARY KEY,
+    blog_id INTEGER NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invited_by_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR NOT NULL DEFAULT 'writer',
+    ap_url VARCHAR NOT NULL DEFAULT '',
+    creation_date TIMESTAMP NOT NULL DEFAULT now(),
+    CONSTRAINT blog_invitations_unique UNIQUE (blog_id, user_id)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE review_comments;
+DROP TABLE post_reviews;
+ALTER TABLE blogs DROP COLUMN require_review;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE medias DROP COLUMN width;
+ALTER TABLE medias DROP COLUMN height;
+ALTER TABLE medias DROP COLUMN variants;
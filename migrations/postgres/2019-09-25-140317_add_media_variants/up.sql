@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE medias ADD COLUMN width INTEGER;
+ALTER TABLE medias ADD COLUMN height INTEGER;
+ALTER TABLE medias ADD COLUMN variants TEXT NOT NULL DEFAULT '';
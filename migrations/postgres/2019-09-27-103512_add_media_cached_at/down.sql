@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE medias DROP COLUMN cached_at;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE medias ADD COLUMN cached_at TIMESTAMP;
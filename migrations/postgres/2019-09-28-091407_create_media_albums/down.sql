@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE medias DROP COLUMN original_name;
+ALTER TABLE medias DROP COLUMN album_id;
+DROP TABLE media_albums;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE media_albums (
+    id SERIAL PRIMARY KEY,
+    owner_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
+
+ALTER TABLE medias ADD COLUMN album_id INTEGER REFERENCES media_albums(id) ON DELETE SET NULL;
+ALTER TABLE medias ADD COLUMN original_name TEXT NOT NULL DEFAULT '';
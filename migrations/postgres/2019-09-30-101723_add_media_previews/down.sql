@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE medias DROP COLUMN focal_y;
+ALTER TABLE medias DROP COLUMN focal_x;
+ALTER TABLE medias DROP COLUMN blurhash;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE medias ADD COLUMN blurhash TEXT;
+ALTER TABLE medias ADD COLUMN focal_x REAL;
+ALTER TABLE medias ADD COLUMN focal_y REAL;
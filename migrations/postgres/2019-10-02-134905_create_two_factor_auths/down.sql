@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE instances DROP COLUMN require_admin_2fa;
+DROP TABLE recovery_codes;
+DROP TABLE two_factor_auths;
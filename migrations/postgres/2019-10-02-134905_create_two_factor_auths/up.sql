@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE two_factor_auths (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR NOT NULL,
+    last_step INTEGER NOT NULL DEFAULT 0,
+    creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
+CREATE TABLE recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR NOT NULL
+);
+ALTER TABLE instances ADD COLUMN require_admin_2fa BOOLEAN NOT NULL DEFAULT 'f';
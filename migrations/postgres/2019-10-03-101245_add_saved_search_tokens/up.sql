@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE saved_searches ADD COLUMN token VARCHAR NOT NULL DEFAULT '';
+UPDATE saved_searches SET token = md5(random()::text || id::text);
+ALTER TABLE saved_searches ALTER COLUMN token DROP DEFAULT;
+CREATE UNIQUE INDEX saved_searches_token ON saved_searches (token);
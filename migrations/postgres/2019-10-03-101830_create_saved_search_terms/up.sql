@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE saved_search_terms (
+    id SERIAL PRIMARY KEY,
+    saved_search_id INTEGER NOT NULL REFERENCES saved_searches(id) ON DELETE CASCADE,
+    term TEXT NOT NULL
+);
+CREATE INDEX saved_search_terms_saved_search_id ON saved_search_terms (saved_search_id);
+CREATE INDEX saved_search_terms_term ON saved_search_terms (term);
+--#!|conn: &Connection, _path: &Path| {
+--#!    super::saved_searches::SavedSearch::index_all(conn)
+--#!}
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE medias ADD COLUMN variants_size INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE two_factor_auths DROP COLUMN failed_attempts;
+ALTER TABLE two_factor_auths DROP COLUMN locked_until;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE saved_searches (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    query TEXT NOT NULL,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX saved_searches_user_id ON saved_searches (user_id);
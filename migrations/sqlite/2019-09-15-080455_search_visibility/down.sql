@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+-- The tables of the search index are created by the SQLite backend, with their visible column: there is nothing to undo
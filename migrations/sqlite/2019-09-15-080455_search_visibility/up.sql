@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- rebuild the tantivy index, with its visible field and fast creation dates
+--#!|conn: &Connection, path: &Path| {
+--#!    let mut pb = path.to_path_buf();
+--#!    pb.push("search_index");
+--#!    if pb.exists() {
+--#!        std::fs::remove_dir_all(&pb)?;
+--#!    }
+--#!    let searcher = super::search::Searcher::create(&pb)?;
+--#!    searcher.fill(conn)?;
+--#!    searcher.commit();
+--#!    Ok(())
+--#!}
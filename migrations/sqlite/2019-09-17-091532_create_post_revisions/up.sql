@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE post_revisions (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    author_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    title TEXT NOT NULL,
+    subtitle TEXT NOT NULL,
+    source TEXT NOT NULL,
+    license TEXT NOT NULL,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX post_revisions_post_id ON post_revisions (post_id);
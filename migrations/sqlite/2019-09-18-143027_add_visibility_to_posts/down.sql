@@ -0,0 +1,26 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX posts_publish_at;
+
+CREATE TABLE posts2 (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    blog_id INTEGER REFERENCES blogs(id) ON DELETE CASCADE NOT NULL,
+    slug VARCHAR NOT NULL,
+    title VARCHAR NOT NULL,
+    content TEXT NOT NULL DEFAULT '',
+    published BOOLEAN NOT NULL DEFAULT 'f',
+    license VARCHAR NOT NULL DEFAULT 'CC-BY-SA',
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ap_url VARCHAR NOT NULL DEFAULT '' UNIQUE,
+    subtitle TEXT NOT NULL DEFAULT '',
+    source TEXT NOT NULL DEFAULT '',
+    cover_id INTEGER REFERENCES medias(id) ON DELETE SET NULL DEFAULT NULL,
+    publish_at DATETIME,
+    CONSTRAINT blog_authors_unique UNIQUE (blog_id, slug)
+);
+
+INSERT INTO posts2 SELECT id, blog_id, slug, title, content, published, license, creation_date,
+    ap_url, subtitle, source, cover_id, publish_at FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts2 RENAME TO posts;
+
+CREATE INDEX posts_publish_at ON posts (publish_at);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN also_known_as TEXT NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN moved_to TEXT;
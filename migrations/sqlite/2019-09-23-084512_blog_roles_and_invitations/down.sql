@@ -0,0 +1,19 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE blog_invitations;
+
+CREATE TABLE blog_authors2 (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    blog_id INTEGER REFERENCES blogs(id) ON DELETE CASCADE NOT NULL,
+    author_id INTEGER REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+    is_owner BOOLEAN NOT NULL DEFAULT 'f',
+    CONSTRAINT blog_authors_unique UNIQUE (blog_id, author_id)
+);
+
+INSERT INTO blog_authors2 SELECT
+    id,
+    blog_id,
+    author_id,
+    role = 'owner'
+FROM blog_authors;
+DROP TABLE blog_authors;
+ALTER TABLE blog_authors2 RENAME TO blog_authors;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE blog_authors2 (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    blog_id INTEGER REFERENCES blogs(id) ON DELETE CASCADE NOT NULL,
+    author_id INTEGER REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+    role VARCHAR NOT NULL DEFAULT 'writer',
+    CONSTRAINT blog_authors_unique UNIQUE (blog_id, author_id)
+);
+
+INSERT INTO blog_authors2 SELECT
+    id,
+    blog_id,
+    author_id,
+    CASE WHEN is_owner THEN 'owner' ELSE 'editor' END
+FROM blog_authors;
+DROP TABLE blog_authors;
+ALTER TABLE blog_authors2 RENAME TO blog_authors;
+
+CREATE TABLE blog_invitations (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    blog_id INTEGER NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invited_by_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR NOT NULL DEFAULT 'writer',
+    ap_url VARCHAR NOT NULL DEFAULT '',
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT blog_invitations_unique UNIQUE (blog_id, user_id)
+);
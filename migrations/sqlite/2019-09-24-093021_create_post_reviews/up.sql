@@ -0,0 +1,22 @@
+-- Your SQL goes here
+ALTER TABLE blogs ADD COLUMN require_review BOOLEAN NOT NULL DEFAULT 'f';
+
+CREATE TABLE post_reviews (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE UNIQUE,
+    requested_by_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reviewer_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE review_comments (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    review_id INTEGER NOT NULL REFERENCES post_reviews(id) ON DELETE CASCADE,
+    author_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    quote TEXT NOT NULL DEFAULT '',
+    content TEXT NOT NULL,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX review_comments_review_id ON review_comments (review_id);
@@ -0,0 +1,39 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE medias_before_previews (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL DEFAULT '',
+    alt_text TEXT NOT NULL DEFAULT '',
+    is_remote BOOLEAN NOT NULL DEFAULT 'f',
+    remote_url TEXT,
+    sensitive BOOLEAN NOT NULL DEFAULT 'f',
+    content_warning TEXT,
+    owner_id INTEGER REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    variants TEXT NOT NULL DEFAULT '',
+    size INTEGER NOT NULL DEFAULT 0,
+    cached_at DATETIME,
+    album_id INTEGER REFERENCES media_albums(id) ON DELETE SET NULL,
+    original_name TEXT NOT NULL DEFAULT '',
+    duration INTEGER
+);
+INSERT INTO medias_before_previews SELECT
+    id,
+    file_path,
+    alt_text,
+    is_remote,
+    remote_url,
+    sensitive,
+    content_warning,
+    owner_id,
+    width,
+    height,
+    variants,
+    size,
+    cached_at,
+    album_id,
+    original_name,
+    duration
+FROM medias;
+DROP TABLE medias;
+ALTER TABLE medias_before_previews RENAME TO medias;
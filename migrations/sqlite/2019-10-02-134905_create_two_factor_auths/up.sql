@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE two_factor_auths (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR NOT NULL,
+    last_step INTEGER NOT NULL DEFAULT 0,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE recovery_codes (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR NOT NULL
+);
+ALTER TABLE instances ADD COLUMN require_admin_2fa BOOLEAN NOT NULL DEFAULT 'f';
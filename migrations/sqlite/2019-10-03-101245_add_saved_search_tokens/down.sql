@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE saved_searches2 (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    query TEXT NOT NULL,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO saved_searches2 SELECT id, user_id, query, creation_date FROM saved_searches;
+DROP TABLE saved_searches;
+ALTER TABLE saved_searches2 RENAME TO saved_searches;
+CREATE INDEX saved_searches_user_id ON saved_searches (user_id);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE saved_searches ADD COLUMN token VARCHAR NOT NULL DEFAULT '';
+UPDATE saved_searches SET token = lower(hex(randomblob(16)));
+CREATE UNIQUE INDEX saved_searches_token ON saved_searches (token);
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE two_factor_auths_before_lockout (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR NOT NULL,
+    last_step INTEGER NOT NULL DEFAULT 0,
+    creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO two_factor_auths_before_lockout SELECT
+    id,
+    user_id,
+    secret,
+    last_step,
+    creation_date
+FROM two_factor_auths;
+DROP TABLE two_factor_auths;
+ALTER TABLE two_factor_auths_before_lockout RENAME TO two_factor_auths;
@@ -0,0 +1,106 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use plume_models::{
+    blogs::Blog,
+    import::{Export, Federation, ImportFormat, Importer},
+    instance::Instance,
+    search::Searcher,
+    users::User,
+    Connection,
+};
+use std::path::Path;
+
+pub fn command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("import")
+        .about("Import posts from another blogging platform")
+        .arg(
+            Arg::with_name("file")
+                .required(true)
+                .takes_value(true)
+                .help("A WordPress (WXR) or Ghost (JSON) export, or a directory or zip archive of Markdown files"),
+        )
+        .arg(
+            Arg::with_name("blog")
+                .short("b")
+                .long("blog")
+                .takes_value(true)
+                .help("The name of the local blog to import the posts in"),
+        )
+        .arg(
+            Arg::with_name("user")
+                .short("u")
+                .long("user")
+                .alias("username")
+                .takes_value(true)
+                .help("The username of the author of the imported posts"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["wordpress", "ghost", "markdown"])
+                .help("The format of the export, guessed from its extension by default"),
+        )
+        .arg(
+            Arg::with_name("federation")
+                .long("federation")
+                .takes_value(true)
+                .possible_values(&["none", "backdated"])
+                .default_value("none")
+                .help("Whether to send the imported posts to the followers of the blog, with their original dates"),
+        )
+}
+
+pub fn run<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    let path = Path::new(args.value_of("file").expect("No file to import"));
+    let format = match args.value_of("format") {
+        Some("wordpress") => ImportFormat::WordPress,
+        Some("ghost") => ImportFormat::Ghost,
+        Some("markdown") => ImportFormat::Markdown,
+        _ => ImportFormat::guess(path)
+            .expect("Couldn't guess the format of the export, please use --format"),
+    };
+    let federation = match args.value_of("federation") {
+        Some("backdated") => Federation::Backdated,
+        _ => Federation::None,
+    };
+
+    let instance = Instance::get_local().expect("Couldn't get the local instance");
+    let blog_name = args
+        .value_of("blog")
+        .map(String::from)
+        .unwrap_or_else(|| super::ask_for("Blog name"));
+    let blog = Blog::find_by_name(conn, &blog_name, instance.id).expect("Blog not found");
+    let username = args
+        .value_of("user")
+        .map(String::from)
+        .unwrap_or_else(|| super::ask_for("Username"));
+    let author = User::find_by_name(conn, &username, instance.id).expect("User not found");
+    if !author
+        .is_author_in(conn, &blog)
+        .expect("Couldn't list the authors of the blog")
+    {
+        eprintln!("{} is not an author of {}", username, blog_name);
+        return;
+    }
+
+    let export = Export::read(path, format).expect("Couldn't read the export");
+    println!("Importing {} posts", export.posts.len());
+    let searcher = Searcher::open_configured().expect("Couldn't open the search index");
+    let posts = Importer {
+        conn,
+        searcher: &searcher,
+        blog: &blog,
+        author: &author,
+        federation,
+    }
+    .import(&export)
+    .expect("Couldn't import the posts");
+    searcher.commit();
+    println!(
+        "{} posts imported, {} already existed",
+        posts.len(),
+        export.posts.len() - posts.len()
+    );
+}
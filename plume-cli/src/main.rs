@@ -9,7 +9,9 @@ use diesel::Connection;
 use plume_models::{instance::Instance, Connection as Conn, CONFIG};
 use std::io::{self, prelude::*};
 
+mod import;
 mod instance;
+mod medias;
 mod migration;
 mod search;
 mod users;
@@ -19,7 +21,9 @@ fn main() {
         .bin_name("plm")
         .version(env!("CARGO_PKG_VERSION"))
         .about("Collection of tools to manage your Plume instance.")
+        .subcommand(import::command())
         .subcommand(instance::command())
+        .subcommand(medias::command())
         .subcommand(migration::command())
         .subcommand(search::command())
         .subcommand(users::command());
@@ -34,9 +38,15 @@ fn main() {
     let _ = conn.as_ref().map(|conn| Instance::cache_local(conn));
 
     match matches.subcommand() {
+        ("import", Some(args)) => {
+            import::run(args, &conn.expect("Couldn't connect to the database."))
+        }
         ("instance", Some(args)) => {
             instance::run(args, &conn.expect("Couldn't connect to the database."))
         }
+        ("medias", Some(args)) => {
+            medias::run(args, &conn.expect("Couldn't connect to the database."))
+        }
         ("migration", Some(args)) => {
             migration::run(args, &conn.expect("Couldn't connect to the database."))
         }
@@ -0,0 +1,92 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use diesel::{self, ExpressionMethods, RunQueryDsl};
+
+use plume_models::{
+    media_store::{LocalStore, MediaStore, MEDIA_STORE},
+    medias::Media,
+    schema::medias,
+    Connection, MediaStorage, CONFIG,
+};
+
+pub fn command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("medias")
+        .about("Manage media files")
+        .subcommand(
+            SubCommand::with_name("migrate-storage")
+                .arg(
+                    Arg::with_name("from")
+                        .short("f")
+                        .long("from")
+                        .takes_value(true)
+                        .help("The directory the files are currently in, static/media by default"),
+                )
+                .arg(
+                    Arg::with_name("delete")
+                        .short("d")
+                        .long("delete")
+                        .help("Delete the local files once they have been copied"),
+                )
+                .about("Copy the local media files to the configured media storage"),
+        )
+        .subcommand(
+            SubCommand::with_name("prune-remote")
+                .about("Delete the expired copies of remote media, and the oldest ones if they use too much space"),
+        )
+}
+
+pub fn run<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    let conn = conn;
+    match args.subcommand() {
+        ("migrate-storage", Some(x)) => migrate_storage(x, conn),
+        ("prune-remote", Some(_)) => prune_remote(conn),
+        ("", None) => command().print_help().unwrap(),
+        _ => println!("Unknown subcommand"),
+    }
+}
+
+fn migrate_storage<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    let dir = args.value_of("from").unwrap_or("static/media");
+    let from = LocalStore::new(dir);
+    let delete = args.is_present("delete");
+    if let MediaStorage::Local(ref to) = CONFIG.media.storage {
+        if delete && to == dir {
+            eprintln!("The files are already in the configured storage, they won't be deleted.");
+            return;
+        }
+    }
+
+    let medias = Media::list_all_medias(conn)
+        .expect("Couldn't list media")
+        .into_iter()
+        .filter(|m| !m.is_remote)
+        .collect::<Vec<_>>();
+    let (mut moved, mut missing) = (0, 0);
+    for media in medias {
+        let key = media.key().to_owned();
+        match from.read(&key) {
+            Ok(content) => {
+                MEDIA_STORE
+                    .save(&key, &content)
+                    .expect("Couldn't save a file in the media storage");
+                diesel::update(&media)
+                    .set(medias::file_path.eq(&key))
+                    .execute(conn)
+                    .expect("Couldn't update media");
+                if delete {
+                    from.delete(&key).expect("Couldn't delete a local file");
+                }
+                moved += 1;
+            }
+            Err(_) => {
+                eprintln!("File of media {} not found: {}", media.id, key);
+                missing += 1;
+            }
+        }
+    }
+    println!("{} files copied, {} missing.", moved, missing);
+}
+
+fn prune_remote(conn: &Connection) {
+    let evicted = Media::prune_remote(conn).expect("Couldn't prune remote media");
+    println!("{} copies of remote media deleted.", evicted);
+}
@@ -1,6 +1,6 @@
 use clap::{App, Arg, ArgMatches, SubCommand};
 
-use plume_models::{search::Searcher, Connection, CONFIG};
+use plume_models::{search::Searcher, Connection, SearchEngine, CONFIG};
 use std::fs::{read_dir, remove_file};
 use std::io::ErrorKind;
 use std::path::Path;
@@ -64,6 +64,12 @@ pub fn run<'a>(args: &ArgMatches<'a>, conn: &Connection) {
 }
 
 fn init<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    if CONFIG.search_engine == SearchEngine::Database {
+        // the index is a table, created with the migrations
+        refill(args, conn, None);
+        return;
+    }
+
     let path = args
         .value_of("path")
         .map(|p| Path::new(p).join("search_index"))
@@ -93,9 +99,14 @@ fn init<'a>(args: &ArgMatches<'a>, conn: &Connection) {
 }
 
 fn refill<'a>(args: &ArgMatches<'a>, conn: &Connection, searcher: Option<Searcher>) {
-    let path = args.value_of("path").unwrap_or(".");
-    let path = Path::new(path).join("search_index");
-    let searcher = searcher.unwrap_or_else(|| Searcher::open(&path).unwrap());
+    let searcher = searcher.unwrap_or_else(|| match CONFIG.search_engine {
+        SearchEngine::Tantivy => {
+            let path = args.value_of("path").unwrap_or(".");
+            let path = Path::new(path).join("search_index");
+            Searcher::open(&path).unwrap()
+        }
+        SearchEngine::Database => Searcher::open_configured().unwrap(),
+    });
 
     searcher.fill(conn).expect("Couldn't import post");
     println!("Commiting result");
@@ -103,6 +114,11 @@ fn refill<'a>(args: &ArgMatches<'a>, conn: &Connection, searcher: Option<Searche
 }
 
 fn unlock<'a>(args: &ArgMatches<'a>) {
+    if CONFIG.search_engine == SearchEngine::Database {
+        println!("The database search engine doesn't use any lock");
+        return;
+    }
+
     let path = args.value_of("path").unwrap_or(".");
     let meta = Path::new(path).join("search_index/.tantivy-meta.lock");
     remove_file(meta).unwrap();
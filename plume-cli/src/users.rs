@@ -1,7 +1,11 @@
 use clap::{App, Arg, ArgMatches, SubCommand};
 
-use plume_models::{instance::Instance, users::*, Connection};
+use plume_models::{
+    instance::Instance, two_factor::TwoFactorAuth, user_exports::write_archive, users::*,
+    Connection,
+};
 use rpassword;
+use std::fs::File;
 use std::io::{self, Write};
 
 pub fn command<'a, 'b>() -> App<'a, 'b> {
@@ -73,6 +77,39 @@ pub fn command<'a, 'b>() -> App<'a, 'b> {
                 )
                 .about("Reset user password"),
         )
+        .subcommand(
+            SubCommand::with_name("reset-2fa")
+                .arg(
+                    Arg::with_name("name")
+                        .short("u")
+                        .long("user")
+                        .alias("username")
+                        .takes_value(true)
+                        .help("The username of the user who lost access to their codes"),
+                )
+                .about("Disable two-factor authentication for a user"),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .arg(
+                    Arg::with_name("name")
+                        .short("u")
+                        .long("user")
+                        .alias("username")
+                        .takes_value(true)
+                        .help("The username of the user whose data should be exported"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("Where to save the archive, <username>.zip by default"),
+                )
+                .about(
+                    "Export the articles, media, comments and follows of a user in a zip archive",
+                ),
+        )
 }
 
 pub fn run<'a>(args: &ArgMatches<'a>, conn: &Connection) {
@@ -80,6 +117,8 @@ pub fn run<'a>(args: &ArgMatches<'a>, conn: &Connection) {
     match args.subcommand() {
         ("new", Some(x)) => new(x, conn),
         ("reset-password", Some(x)) => reset_password(x, conn),
+        ("reset-2fa", Some(x)) => reset_2fa(x, conn),
+        ("export", Some(x)) => export(x, conn),
         ("", None) => command().print_help().unwrap(),
         _ => println!("Unknown subcommand"),
     }
@@ -145,3 +184,49 @@ fn reset_password<'a>(args: &ArgMatches<'a>, conn: &Connection) {
     user.reset_password(conn, &password)
         .expect("Failed to reset password");
 }
+
+fn reset_2fa<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    let username = args
+        .value_of("name")
+        .map(String::from)
+        .unwrap_or_else(|| super::ask_for("Username"));
+    let user = User::find_by_name(
+        conn,
+        &username,
+        Instance::get_local()
+            .expect("Failed to get local instance")
+            .id,
+    )
+    .expect("Failed to get user");
+    TwoFactorAuth::disable(conn, user.id).expect("Failed to disable two-factor authentication");
+    println!(
+        "Two-factor authentication has been disabled for {}, they can now log in with their password only",
+        username
+    );
+}
+
+fn export<'a>(args: &ArgMatches<'a>, conn: &Connection) {
+    let username = args
+        .value_of("name")
+        .map(String::from)
+        .unwrap_or_else(|| super::ask_for("Username"));
+    let user = User::find_by_name(
+        conn,
+        &username,
+        Instance::get_local()
+            .expect("Failed to get local instance")
+            .id,
+    )
+    .expect("Failed to get user");
+    let output = args
+        .value_of("output")
+        .map(String::from)
+        .unwrap_or_else(|| format!("{}.zip", username));
+    write_archive(
+        conn,
+        &user,
+        File::create(&output).expect("Couldn't create the archive"),
+    )
+    .expect("Failed to export user data");
+    println!("Archive saved to {}", output);
+}
@@ -50,7 +50,8 @@ pub fn context() -> serde_json::Value {
                 "@container":"@list",
                 "@id":"toot:focalPoint"
             },
-            "featured":"toot:featured"
+            "featured":"toot:featured",
+            "blurhash":"toot:blurhash"
         }
     ])
 }
@@ -188,6 +189,16 @@ impl Link for Id {}
 pub struct ApSignature {
     #[activitystreams(concrete(PublicKey), functional)]
     pub public_key: Option<serde_json::Value>,
+
+    /// The other accounts of this actor, that it can be moved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[activitystreams(concrete(String))]
+    pub also_known_as: Option<serde_json::Value>,
+
+    /// The account this actor moved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[activitystreams(concrete(String), functional)]
+    pub moved_to: Option<serde_json::Value>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Properties)]
@@ -238,3 +249,19 @@ pub struct Licensed {
 }
 
 impl Object for Licensed {}
+
+/// The properties Mastodon uses to display a picture while it loads, and to crop it
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Properties)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePreview {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[activitystreams(concrete(String), functional)]
+    pub blurhash: Option<serde_json::Value>,
+
+    /// The point to keep visible when cropping the picture, with coordinates between -1 and 1,
+    /// from the left and from the bottom
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focal_point: Option<Vec<f64>>,
+}
+
+impl Object for ImagePreview {}
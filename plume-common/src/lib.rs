@@ -7,6 +7,7 @@ extern crate activitystreams_traits;
 extern crate array_tool;
 extern crate base64;
 extern crate chrono;
+extern crate deunicode;
 extern crate heck;
 extern crate hex;
 extern crate openssl;
@@ -1,4 +1,5 @@
-use heck::CamelCase;
+use deunicode::deunicode;
+use heck::{CamelCase, KebabCase};
 use openssl::rand::rand_bytes;
 use pulldown_cmark::{html, Event, Options, Parser, Tag};
 use rocket::{
@@ -25,6 +26,11 @@ pub fn make_actor_id(name: &str) -> String {
         .collect()
 }
 
+/// Transliterate a title to ASCII and kebab-case it, to use it in URLs
+pub fn make_slug(title: &str) -> String {
+    deunicode(title).to_kebab_case()
+}
+
 /**
 * Redirects to the login page with a given message.
 *
@@ -97,7 +103,51 @@ fn inline_tags<'a>(
     }
 }
 
-pub type MediaProcessor<'a> = Box<'a + Fn(i32) -> Option<(String, Option<String>)>>;
+/// What `md_to_html` needs to know to display a media
+pub struct MediaInfo {
+    pub url: String,
+    pub content_warning: Option<String>,
+    /// Extra attributes of its tag, like `srcset`
+    pub attrs: Option<String>,
+    /// The element to display it with: `img`, `audio` or `video`
+    pub tag: &'static str,
+}
+
+impl MediaInfo {
+    /// Whether it can be rendered as a simple Markdown image
+    fn is_plain_image(&self) -> bool {
+        self.tag == "img" && self.content_warning.is_none() && self.attrs.is_none()
+    }
+
+    /// The start of its tag, to be followed by its description
+    fn open_tag(&self) -> String {
+        if self.tag == "img" {
+            format!(
+                r#"<img src="{}" {} alt=""#,
+                self.url,
+                self.attrs.clone().unwrap_or_default()
+            )
+        } else {
+            format!(
+                r#"<{} src="{}" {} controls title=""#,
+                self.tag,
+                self.url,
+                self.attrs.clone().unwrap_or_default()
+            )
+        }
+    }
+
+    fn close_tag(&self) -> String {
+        if self.tag == "img" {
+            r#""/>"#.to_owned()
+        } else {
+            format!(r#""></{}>"#, self.tag)
+        }
+    }
+}
+
+/// Returns the informations needed to display the media with a given ID, if it can be used
+pub type MediaProcessor<'a> = Box<'a + Fn(i32) -> Option<MediaInfo>>;
 
 fn process_image<'a, 'b>(
     evt: Event<'a>,
@@ -107,10 +157,10 @@ fn process_image<'a, 'b>(
     if let Some(ref processor) = *processor {
         match evt {
             Event::Start(Tag::Image(id, title)) => {
-                if let Some((url, cw)) = id.parse::<i32>().ok().and_then(processor.as_ref()) {
-                    if inline || cw.is_none() {
-                        Event::Start(Tag::Image(Cow::Owned(url), title))
-                    } else {
+                if let Some(media) = id.parse::<i32>().ok().and_then(processor.as_ref()) {
+                    if inline || media.is_plain_image() {
+                        Event::Start(Tag::Image(Cow::Owned(media.url), title))
+                    } else if let Some(ref cw) = media.content_warning {
                         // there is a cw, and where are not inline
                         Event::Html(Cow::Owned(format!(
                             r#"<label for="postcontent-cw-{id}">
@@ -119,26 +169,31 @@ fn process_image<'a, 'b>(
     <span class="cw-text">
         {cw}
     </span>
-  <img src="{url}" alt=""#,
+  {tag}"#,
                             id = random_hex(),
-                            cw = cw.unwrap(),
-                            url = url
+                            cw = cw,
+                            tag = media.open_tag(),
                         )))
+                    } else {
+                        Event::Html(Cow::Owned(media.open_tag()))
                     }
                 } else {
                     Event::Start(Tag::Image(id, title))
                 }
             }
             Event::End(Tag::Image(id, title)) => {
-                if let Some((url, cw)) = id.parse::<i32>().ok().and_then(processor.as_ref()) {
-                    if inline || cw.is_none() {
-                        Event::End(Tag::Image(Cow::Owned(url), title))
-                    } else {
-                        Event::Html(Cow::Borrowed(
-                            r#""/>
+                if let Some(media) = id.parse::<i32>().ok().and_then(processor.as_ref()) {
+                    if inline || media.is_plain_image() {
+                        Event::End(Tag::Image(Cow::Owned(media.url), title))
+                    } else if media.content_warning.is_some() {
+                        Event::Html(Cow::Owned(format!(
+                            r#"{}
   </span>
 </label>"#,
-                        ))
+                            media.close_tag()
+                        )))
+                    } else {
+                        Event::Html(Cow::Owned(media.close_tag()))
                     }
                 } else {
                     Event::End(Tag::Image(id, title))
@@ -151,6 +206,20 @@ fn process_image<'a, 'b>(
     }
 }
 
+/// The IDs of the media inserted in a Markdown text, in the order they appear
+pub fn media_ids(md: &str) -> Vec<i32> {
+    let mut ids = Parser::new_ext(md, Options::all())
+        .filter_map(|evt| match evt {
+            Event::Start(Tag::Image(id, _)) => id.parse::<i32>().ok(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // keep the first occurrence of each media only
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+    ids
+}
+
 /// Returns (HTML, mentions, hashtags)
 pub fn md_to_html<'a>(
     md: &str,
@@ -376,6 +445,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_slugs() {
+        assert_eq!(make_slug("Hello, World!"), "hello-world");
+        assert_eq!(make_slug("Crème brûlée"), "creme-brulee");
+        assert_eq!(make_slug("Привет мир"), "privet-mir");
+        assert_eq!(make_slug("!?"), "");
+    }
+
     #[test]
     fn test_inline() {
         assert_eq!(
@@ -387,4 +464,27 @@ mod tests {
             String::from("<p>Hello</p>\n")
         );
     }
+
+    #[test]
+    fn test_media() {
+        assert_eq!(
+            media_ids("![one](1) text ![two](2 \"title\") ![again](1) ![url](http://a/b.png)"),
+            vec![1, 2]
+        );
+
+        let processor: MediaProcessor = Box::new(|id| {
+            Some(MediaInfo {
+                url: format!("/static/media/{}.ogg", id),
+                content_warning: None,
+                attrs: None,
+                tag: "audio",
+            })
+        });
+        assert_eq!(
+            md_to_html("![A song](4)", None, false, Some(processor)).0,
+            String::from(
+                "<p><audio src=\"/static/media/4.ogg\"  controls title=\"A song\"></audio></p>\n"
+            )
+        );
+    }
 }
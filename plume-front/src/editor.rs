@@ -90,9 +90,12 @@ fn init_widget(
 }
 
 fn filter_paste(elt: &HtmlElement) {
-    // Only insert text when pasting something
+    // Only insert text when pasting something, except for pictures that are uploaded
     js! {
         @{&elt}.addEventListener("paste", function (evt) {
+            if (@{&elt}.dataset.uploads === "true" && evt.clipboardData.files.length) {
+                return;
+            }
             evt.preventDefault();
             document.execCommand("insertText", false, evt.clipboardData.getData("text"));
         });
@@ -158,6 +161,7 @@ fn init_editor() -> Result<(), EditorError> {
             false,
         )?;
         js! { @{&content}.innerHTML = @{content_val}; };
+        init_uploads(&content);
 
         // character counter
         content.add_event_listener(mv!(content => move |_: KeyDownEvent| {
@@ -192,6 +196,110 @@ fn init_editor() -> Result<(), EditorError> {
     Ok(())
 }
 
+/// Upload the pictures that are dropped or pasted in the article, and insert their
+/// Markdown code where they were dropped, once they have a description
+fn init_uploads(content: &HtmlElement) {
+    js! {
+        var editor = @{content};
+        var describe = @{i18n!(CATALOG, "Describe this picture for people who can't see it")};
+        var uploading = @{i18n!(CATALOG, "Uploading…")};
+        var failed = @{i18n!(CATALOG, "The upload failed.")};
+        editor.dataset.uploads = "true";
+
+        function upload(file, range) {
+            var dot = file.name.lastIndexOf(".");
+            var alt = window.prompt(describe, dot > 0 ? file.name.substring(0, dot) : file.name);
+            if (alt === null) {
+                return;
+            }
+            if (editor.dataset.edited !== "true") {
+                // remove the placeholder of the editor
+                editor.innerHTML = "";
+                editor.dataset.edited = "true";
+                range.selectNodeContents(editor);
+            }
+            var placeholder = document.createTextNode(uploading);
+            range.deleteContents();
+            range.insertNode(placeholder);
+            range.setStartAfter(placeholder);
+            range.collapse(true);
+
+            var form = new FormData();
+            var token = document.querySelector("input[name=csrf-token]");
+            if (token) {
+                form.append("csrf-token", token.value);
+            }
+            form.append("alt", alt);
+            form.append("file", file);
+
+            var req = new XMLHttpRequest();
+            req.open("POST", "/medias/new.json");
+            req.responseType = "json";
+            req.upload.onprogress = function (evt) {
+                if (evt.lengthComputable) {
+                    placeholder.textContent = uploading + " " + Math.round(evt.loaded * 100 / evt.total) + "%";
+                }
+            };
+            req.onload = function () {
+                if (req.status === 200 && req.response) {
+                    placeholder.textContent = req.response.markdown;
+                } else {
+                    placeholder.parentNode.removeChild(placeholder);
+                    window.alert(req.response && req.response.error ? req.response.error : failed);
+                }
+            };
+            req.onerror = function () {
+                placeholder.parentNode.removeChild(placeholder);
+                window.alert(failed);
+            };
+            req.send(form);
+        }
+
+        function pictures(files) {
+            return Array.prototype.filter.call(files, function (file) {
+                return file.type.indexOf("image/") === 0;
+            });
+        }
+
+        editor.addEventListener("paste", function (evt) {
+            var files = pictures(evt.clipboardData.files);
+            var selection = window.getSelection();
+            if (!files.length || !selection.rangeCount) {
+                return;
+            }
+            evt.preventDefault();
+            var range = selection.getRangeAt(0);
+            files.forEach(function (file) { upload(file, range); });
+        });
+        editor.addEventListener("dragover", function (evt) {
+            if (Array.prototype.indexOf.call(evt.dataTransfer.types, "Files") !== -1) {
+                evt.preventDefault();
+            }
+        });
+        editor.addEventListener("drop", function (evt) {
+            var files = pictures(evt.dataTransfer.files);
+            if (!files.length) {
+                return;
+            }
+            evt.preventDefault();
+            var range = null;
+            if (document.caretRangeFromPoint) {
+                range = document.caretRangeFromPoint(evt.clientX, evt.clientY);
+            } else if (document.caretPositionFromPoint) {
+                var position = document.caretPositionFromPoint(evt.clientX, evt.clientY);
+                range = document.createRange();
+                range.setStart(position.offsetNode, position.offset);
+            }
+            if (!range || !editor.contains(range.startContainer)) {
+                range = document.createRange();
+                range.selectNodeContents(editor);
+                range.collapse(false);
+            }
+            files.forEach(function (file) { upload(file, range); });
+        });
+    };
+}
+
 fn setup_close_button() {
     if let Some(button) = document().get_element_by_id("close-editor") {
         button.add_event_listener(|_: ClickEvent| {
@@ -242,6 +350,10 @@ fn init_popup(
     let license = get_elt_value("license");
     make_input(&i18n!(CATALOG, "Tags"), "popup-tags", &popup).set_raw_value(&tags.join(", "));
     make_input(&i18n!(CATALOG, "License"), "popup-license", &popup).set_raw_value(&license);
+    if document().get_element_by_id("authors").is_some() {
+        make_input(&i18n!(CATALOG, "Co-authors"), "popup-authors", &popup)
+            .set_raw_value(&get_elt_value("authors"));
+    }
 
     let cover_label = document().create_element("label")?;
     cover_label.append_child(&document().create_text_node(&i18n!(CATALOG, "Cover")));
@@ -251,6 +363,33 @@ fn init_popup(
     popup.append_child(&cover_label);
     popup.append_child(&cover);
 
+    if let Some(series) = document().get_element_by_id("series") {
+        let series_label = document().create_element("label")?;
+        series_label.append_child(&document().create_text_node(&i18n!(CATALOG, "Series")));
+        series_label.set_attribute("for", "series")?;
+        series.parent_element()?.remove_child(&series).ok();
+        popup.append_child(&series_label);
+        popup.append_child(&series);
+    }
+
+    let visibility_label = document().create_element("label")?;
+    visibility_label.append_child(&document().create_text_node(&i18n!(CATALOG, "Visibility")));
+    visibility_label.set_attribute("for", "visibility")?;
+    let visibility = document().get_element_by_id("visibility")?;
+    visibility.parent_element()?.remove_child(&visibility).ok();
+    popup.append_child(&visibility_label);
+    popup.append_child(&visibility);
+
+    if document().get_element_by_id("publish_at").is_some() {
+        let publish_at = make_input(
+            &i18n!(CATALOG, "Publication date"),
+            "popup-publish-at",
+            &popup,
+        );
+        publish_at.set_attribute("type", "datetime-local")?;
+        publish_at.set_raw_value(&get_elt_value("publish_at"));
+    }
+
     if let Some(draft_checkbox) = document().get_element_by_id("draft") {
         let draft_label = document().create_element("label")?;
         draft_label.set_attribute("for", "popup-draft")?;
@@ -296,6 +435,12 @@ fn init_popup(
                 format!("{}\n\n{}", md, to_append)
             }));
             set_value("tags", get_elt_value("popup-tags"));
+            if document().get_element_by_id("popup-authors").is_some() {
+                set_value("authors", get_elt_value("popup-authors"));
+            }
+            if document().get_element_by_id("popup-publish-at").is_some() {
+                set_value("publish_at", get_elt_value("popup-publish-at"));
+            }
             if let Some(draft) = document().get_element_by_id("popup-draft") {
                 js!{
                     document.getElementById("draft").checked = @{draft}.checked;
@@ -304,6 +449,13 @@ fn init_popup(
             let cover = document().get_element_by_id("cover").unwrap();
             cover.parent_element().unwrap().remove_child(&cover).ok();
             old_ed.append_child(&cover);
+            if let Some(series) = document().get_element_by_id("series") {
+                series.parent_element().unwrap().remove_child(&series).ok();
+                old_ed.append_child(&series);
+            }
+            let visibility = document().get_element_by_id("visibility").unwrap();
+            visibility.parent_element().unwrap().remove_child(&visibility).ok();
+            old_ed.append_child(&visibility);
             set_value("license", get_elt_value("popup-license"));
             js! {
                 @{&old_ed}.submit();
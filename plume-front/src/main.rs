@@ -38,6 +38,7 @@ init_i18n!(
 );
 
 mod editor;
+mod media;
 
 compile_i18n!();
 
@@ -63,6 +64,8 @@ lazy_static! {
 fn main() {
     menu();
     search();
+    search_suggestions();
+    media::init();
     editor::init()
         .map_err(|e| console!(error, format!("Editor error: {:?}", e)))
         .ok();
@@ -111,3 +114,49 @@ fn search() {
         });
     }
 }
+
+/// Show completions while typing a search query
+///
+/// In the main search box, `tag:`, `author:` and `blog:` can be used to only
+/// complete one kind of value, like in the query syntax.
+fn search_suggestions() {
+    for field in &["q", "tag", "author", "blog"] {
+        if let Some(input) = document().get_element_by_id(field) {
+            let field = *field;
+            let target = input.clone();
+            input.add_event_listener(move |_: InputEvent| {
+                js! {
+                    var field = @{field};
+                    var input = @{&target};
+                    var words = input.value.split(" ");
+                    var last = words.pop();
+                    var head = words.length ? words.join(" ") + " " : "";
+                    var query = field === "q" ? last : field + ":" + last;
+                    // only ask for suggestions once the user stopped typing for a moment
+                    clearTimeout(input.suggestionTimeout);
+                    if (!last) {
+                        return;
+                    }
+
+                    input.suggestionTimeout = setTimeout(function () {
+                        fetch("/search/suggest?q=" + encodeURIComponent(query))
+                            .then(function (res) { return res.json(); })
+                            .then(function (suggestions) {
+                                var list = document.getElementById("search-suggestions");
+                                list.innerHTML = "";
+                                suggestions.forEach(function (suggestion) {
+                                    var option = document.createElement("option");
+                                    option.value = head + (field === "q"
+                                        ? suggestion.value
+                                        : suggestion.value.replace(field + ":", ""));
+                                    option.label = suggestion.label;
+                                    list.appendChild(option);
+                                });
+                            })
+                            .catch(function () {});
+                    }, 250);
+                };
+            });
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use stdweb::web::{TypedArray, *};
+
+/// Size of the pictures drawn from blurhashes, they are stretched to fill their element
+const PLACEHOLDER_SIZE: usize = 32;
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+pub fn init() {
+    placeholders();
+    focal_point_picker();
+}
+
+/// Display the blurhash of covers and banners behind them, until they are loaded
+fn placeholders() {
+    document()
+        .query_selector_all("[data-blurhash]")
+        .map(|elements| {
+            for elt in elements {
+                let hash = js! { return @{&elt}.dataset.blurhash; }
+                    .into_string()
+                    .unwrap_or_default();
+                let pixels = match decode(&hash, PLACEHOLDER_SIZE, PLACEHOLDER_SIZE) {
+                    Some(pixels) => TypedArray::<u8>::from(&pixels[..]),
+                    None => continue,
+                };
+                js! {
+                    var elt = @{&elt};
+                    var canvas = document.createElement("canvas");
+                    canvas.width = @{PLACEHOLDER_SIZE as u32};
+                    canvas.height = @{PLACEHOLDER_SIZE as u32};
+                    var context = canvas.getContext("2d");
+                    var data = context.createImageData(canvas.width, canvas.height);
+                    data.data.set(@{pixels});
+                    context.putImageData(data, 0, 0);
+
+                    var placeholder = "url(" + canvas.toDataURL() + ")";
+                    var image = window.getComputedStyle(elt).backgroundImage;
+                    elt.style.backgroundImage = image && image !== "none"
+                        ? image + ", " + placeholder
+                        : placeholder;
+                    elt.style.backgroundSize = "cover";
+                };
+            }
+        })
+        .ok();
+}
+
+/// Choose the focal point of a picture by clicking on it
+fn focal_point_picker() {
+    if let Ok(Some(picker)) = document().query_selector(".focal-point-picker") {
+        js! {
+            var picker = @{picker};
+            var marker = picker.querySelector(".focal-point-marker");
+            var inputX = document.getElementById("focal-x");
+            var inputY = document.getElementById("focal-y");
+            function moveMarker(x, y) {
+                marker.style.left = x + "%";
+                marker.style.top = y + "%";
+                marker.classList.remove("hidden");
+            }
+
+            picker.addEventListener("click", function (e) {
+                var rect = picker.querySelector("img").getBoundingClientRect();
+                var percents = function (v) {
+                    return Math.min(100, Math.max(0, Math.round(v * 1000) / 10));
+                };
+                var x = percents((e.clientX - rect.left) / rect.width);
+                var y = percents((e.clientY - rect.top) / rect.height);
+                inputX.value = x;
+                inputY.value = y;
+                moveMarker(x, y);
+            });
+            var onInput = function () {
+                moveMarker(inputX.value, inputY.value);
+            };
+            inputX.addEventListener("input", onInput);
+            inputY.addEventListener("input", onInput);
+        };
+    }
+}
+
+fn base83(chars: &[u8]) -> Option<u32> {
+    chars.iter().try_fold(0u32, |value, c| {
+        BASE83
+            .iter()
+            .position(|d| d == c)
+            .map(|digit| value * 83 + digit as u32)
+    })
+}
+
+fn to_linear(value: u32) -> f64 {
+    let v = f64::from(value) / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(value: f64) -> u8 {
+    let v = value.max(0.0).min(1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u8
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u8
+    }
+}
+
+/// Draw a blurhash (see <https://blurha.sh>) as RGBA pixels
+fn decode(hash: &str, width: usize, height: usize) -> Option<Vec<u8>> {
+    let hash = hash.as_bytes();
+    let size = base83(hash.get(0..1)?)?;
+    let (components_x, components_y) = (size as usize % 9 + 1, size as usize / 9 + 1);
+    if hash.len() != 4 + 2 * components_x * components_y {
+        return None;
+    }
+    let max_value = f64::from(base83(&hash[1..2])? + 1) / 166.0;
+
+    let dc = base83(&hash[2..6])?;
+    let mut colors = vec![[
+        to_linear(dc >> 16),
+        to_linear((dc >> 8) & 255),
+        to_linear(dc & 255),
+    ]];
+    for chunk in hash[6..].chunks(2) {
+        let ac = base83(chunk)?;
+        let component = |quantised: u32| {
+            let v = (f64::from(quantised) - 9.0) / 9.0;
+            v.signum() * v * v * max_value
+        };
+        colors.push([
+            component(ac / (19 * 19)),
+            component((ac / 19) % 19),
+            component(ac % 19),
+        ]);
+    }
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0.0; 3];
+            for j in 0..components_y {
+                for i in 0..components_x {
+                    let basis = (std::f64::consts::PI * (x * i) as f64 / width as f64).cos()
+                        * (std::f64::consts::PI * (y * j) as f64 / height as f64).cos();
+                    let color = colors[i + j * components_x];
+                    for (p, c) in pixel.iter_mut().zip(color.iter()) {
+                        *p += c * basis;
+                    }
+                }
+            }
+            pixels.extend(pixel.iter().map(|p| to_srgb(*p)));
+            pixels.push(255);
+        }
+    }
+    Some(pixels)
+}
@@ -4,9 +4,15 @@ use rocket::{
     Outcome,
 };
 
+use db_conn::DbConn;
+use instance::Instance;
+use two_factor::TwoFactorAuth;
 use users::User;
 
 /// Wrapper around User to use as a request guard on pages reserved to admins.
+///
+/// If the instance requires it, admins who didn't enable two-factor authentication are
+/// refused too.
 pub struct Admin(pub User);
 
 impl<'a, 'r> FromRequest<'a, 'r> for Admin {
@@ -14,10 +20,14 @@ impl<'a, 'r> FromRequest<'a, 'r> for Admin {
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<Admin, ()> {
         let user = request.guard::<User>()?;
-        if user.is_admin {
-            Outcome::Success(Admin(user))
-        } else {
+        if !user.is_admin {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        }
+        let conn = request.guard::<DbConn>()?;
+        if user.must_enable_2fa(&*conn) {
             Outcome::Failure((Status::Unauthorized, ()))
+        } else {
+            Outcome::Success(Admin(user))
         }
     }
 }
@@ -1,14 +1,29 @@
 use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
 
 use schema::blog_authors;
-use {Error, Result};
+use {Connection, Error, Result};
 
-#[derive(Clone, Queryable, Identifiable)]
+/// What an author can do in a blog
+pub mod author_role {
+    /// Can publish, and manage the blog and its authors
+    pub const OWNER: &str = "owner";
+    /// Can publish, and edit the articles of the other authors
+    pub const EDITOR: &str = "editor";
+    /// Can only write drafts of their own articles
+    pub const WRITER: &str = "writer";
+
+    pub fn is_valid(role: &str) -> bool {
+        [OWNER, EDITOR, WRITER].contains(&role)
+    }
+}
+
+#[derive(Clone, Queryable, Identifiable, AsChangeset)]
 pub struct BlogAuthor {
     pub id: i32,
     pub blog_id: i32,
     pub author_id: i32,
-    pub is_owner: bool,
+    /// One of the `author_role` constants
+    pub role: String,
 }
 
 #[derive(Insertable)]
@@ -16,10 +31,39 @@ pub struct BlogAuthor {
 pub struct NewBlogAuthor {
     pub blog_id: i32,
     pub author_id: i32,
-    pub is_owner: bool,
+    pub role: String,
 }
 
 impl BlogAuthor {
     insert!(blog_authors, NewBlogAuthor);
     get!(blog_authors);
+    find_by!(blog_authors, find, blog_id as i32, author_id as i32);
+    list_by!(blog_authors, list_for_blog, blog_id as i32);
+    list_by!(blog_authors, list_for_author, author_id as i32);
+
+    pub fn is_owner(&self) -> bool {
+        self.role == author_role::OWNER
+    }
+
+    /// Whether this author can publish articles and edit the ones of the other
+    /// authors, or only write drafts of their own articles
+    pub fn can_publish(&self) -> bool {
+        self.role != author_role::WRITER
+    }
+
+    pub fn count_owners(conn: &Connection, blog_id: i32) -> Result<i64> {
+        blog_authors::table
+            .filter(blog_authors::blog_id.eq(blog_id))
+            .filter(blog_authors::role.eq(author_role::OWNER))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
 }
@@ -0,0 +1,233 @@
+use activitypub::activity::{Accept, Invite, Reject};
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+
+use blog_authors::{author_role, BlogAuthor, NewBlogAuthor};
+use blogs::Blog;
+use notifications::*;
+use plume_common::activity_pub::{
+    inbox::{AsActor, AsObject, FromId},
+    Id, IntoId,
+};
+use schema::blog_invitations;
+use users::User;
+use {ap_url, Connection, Error, PlumeRocket, Result, CONFIG};
+
+/// An invitation to become an author of a blog, that has not been answered yet
+#[derive(Clone, Queryable, Identifiable, AsChangeset)]
+pub struct BlogInvitation {
+    pub id: i32,
+    pub blog_id: i32,
+    /// The invited user
+    pub user_id: i32,
+    pub invited_by_id: i32,
+    /// The role the user will have in the blog, one of the `author_role` constants
+    pub role: String,
+    pub ap_url: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "blog_invitations"]
+pub struct NewBlogInvitation {
+    pub blog_id: i32,
+    pub user_id: i32,
+    pub invited_by_id: i32,
+    pub role: String,
+    pub ap_url: String,
+}
+
+impl BlogInvitation {
+    insert!(
+        blog_invitations,
+        NewBlogInvitation,
+        |inserted, conn| if inserted.ap_url.is_empty() {
+            inserted.ap_url = ap_url(&format!("{}/invitations/{}", CONFIG.base_url, inserted.id));
+            inserted.save_changes(conn).map_err(Error::from)
+        } else {
+            Ok(inserted)
+        }
+    );
+    get!(blog_invitations);
+    find_by!(blog_invitations, find_by_ap_url, ap_url as &str);
+    find_by!(blog_invitations, find, blog_id as i32, user_id as i32);
+    list_by!(blog_invitations, list_for_blog, blog_id as i32);
+
+    /// Invite `user` to join `blog` as `role`, replacing any previous invitation.
+    ///
+    /// Local users are notified, the invitation still has to be sent to remote ones.
+    pub fn invite(
+        conn: &Connection,
+        blog: &Blog,
+        invited_by: &User,
+        user: &User,
+        role: &str,
+    ) -> Result<BlogInvitation> {
+        if !author_role::is_valid(role) || user.is_author_in(conn, blog)? {
+            return Err(Error::InvalidValue);
+        }
+        if let Ok(previous) = BlogInvitation::find(conn, blog.id, user.id) {
+            previous.delete(conn)?;
+        }
+
+        let invitation = BlogInvitation::insert(
+            conn,
+            NewBlogInvitation {
+                blog_id: blog.id,
+                user_id: user.id,
+                invited_by_id: invited_by.id,
+                role: role.to_owned(),
+                ap_url: String::new(),
+            },
+        )?;
+        if user.is_local() {
+            Notification::insert(
+                conn,
+                NewNotification {
+                    kind: notification_kind::INVITATION.to_string(),
+                    object_id: invitation.id,
+                    user_id: user.id,
+                },
+            )?;
+        }
+        Ok(invitation)
+    }
+
+    pub fn get_blog(&self, conn: &Connection) -> Result<Blog> {
+        Blog::get(conn, self.blog_id)
+    }
+
+    pub fn get_user(&self, conn: &Connection) -> Result<User> {
+        User::get(conn, self.user_id)
+    }
+
+    pub fn to_activity(&self, conn: &Connection) -> Result<Invite> {
+        let user = self.get_user(conn)?;
+        let mut act = Invite::default();
+        act.object_props.set_id_string(self.ap_url.clone())?;
+        act.invite_props
+            .set_actor_link(User::get(conn, self.invited_by_id)?.into_id())?;
+        act.invite_props
+            .set_object_link(self.get_blog(conn)?.into_id())?;
+        act.invite_props.set_target_link(user.clone().into_id())?;
+        act.object_props.set_to_link_vec(vec![user.into_id()])?;
+        Ok(act)
+    }
+
+    /// Make the invited user an author of the blog
+    pub fn accept(&self, conn: &Connection) -> Result<BlogAuthor> {
+        let author = BlogAuthor::insert(
+            conn,
+            NewBlogAuthor {
+                blog_id: self.blog_id,
+                author_id: self.user_id,
+                role: self.role.clone(),
+            },
+        )?;
+        self.delete(conn)?;
+        Ok(author)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        if let Ok(notif) = Notification::find(conn, notification_kind::INVITATION, self.id) {
+            notif.delete(conn)?;
+        }
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+impl FromId<PlumeRocket> for BlogInvitation {
+    type Error = Error;
+    type Object = Invite;
+
+    fn from_db(c: &PlumeRocket, id: &str) -> Result<Self> {
+        BlogInvitation::find_by_ap_url(&c.conn, id)
+    }
+
+    fn from_activity(_: &PlumeRocket, _: Invite) -> Result<Self> {
+        // only the invitations sent from this instance can be answered
+        Err(Error::NotFound)
+    }
+}
+
+impl AsObject<User, Accept, &PlumeRocket> for BlogInvitation {
+    type Error = Error;
+    type Output = ();
+
+    fn activity(self, c: &PlumeRocket, actor: User, _id: &str) -> Result<()> {
+        if actor.id == self.user_id {
+            self.accept(&c.conn).map(|_| ())
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+impl AsObject<User, Reject, &PlumeRocket> for BlogInvitation {
+    type Error = Error;
+    type Output = ();
+
+    fn activity(self, c: &PlumeRocket, actor: User, _id: &str) -> Result<()> {
+        if actor.id == self.user_id {
+            self.delete(&c.conn)
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+impl IntoId for BlogInvitation {
+    fn into_id(self) -> Id {
+        Id::new(self.ap_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use tests::db;
+
+    #[test]
+    fn invite() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            // users[1] is already an author of blogs[0]
+            assert!(BlogInvitation::invite(
+                conn,
+                &blogs[0],
+                &users[0],
+                &users[1],
+                author_role::EDITOR
+            )
+            .is_err());
+            assert!(
+                BlogInvitation::invite(conn, &blogs[0], &users[0], &users[2], "admin").is_err()
+            );
+
+            let invitation =
+                BlogInvitation::invite(conn, &blogs[0], &users[0], &users[2], author_role::EDITOR)
+                    .unwrap();
+            let notif =
+                Notification::find(conn, notification_kind::INVITATION, invitation.id).unwrap();
+            assert_eq!(notif.user_id, users[2].id);
+            assert!(!users[2].is_author_in(conn, &blogs[0]).unwrap());
+
+            let author = invitation.accept(conn).unwrap();
+            assert_eq!(author.role, author_role::EDITOR);
+            assert!(author.can_publish());
+            assert!(users[2].is_author_in(conn, &blogs[0]).unwrap());
+            assert!(BlogInvitation::get(conn, invitation.id).is_err());
+            assert!(
+                Notification::find(conn, notification_kind::INVITATION, invitation.id).is_err()
+            );
+
+            Ok(())
+        });
+    }
+}
@@ -1,6 +1,9 @@
 use activitypub::{actor::Group, collection::OrderedCollection, object::Image, CustomObject};
 use chrono::NaiveDateTime;
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+use diesel::{
+    self, EscapeExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl,
+    TextExpressionMethods,
+};
 use openssl::{
     hash::MessageDigest,
     pkey::{PKey, Private},
@@ -13,16 +16,17 @@ use webfinger::*;
 
 use instance::*;
 use medias::Media;
+use notifications::notification_kind;
 use plume_common::activity_pub::{
     inbox::{AsActor, FromId},
     sign, ActivityStream, ApSignature, Id, IntoId, PublicKey, Source,
 };
 use posts::Post;
 use safe_string::SafeString;
-use schema::blogs;
+use schema::{blogs, notifications};
 use search::Searcher;
 use users::User;
-use {Connection, Error, PlumeRocket, Result};
+use {like_prefix, Connection, Error, PlumeRocket, Result};
 
 pub type CustomGroup = CustomObject<ApSignature, Group>;
 
@@ -45,6 +49,8 @@ pub struct Blog {
     pub icon_id: Option<i32>,
     pub banner_id: Option<i32>,
     pub theme: Option<String>,
+    /// Whether the articles of writers have to be approved by an editor before being published
+    pub require_review: bool,
 }
 
 #[derive(Default, Insertable)]
@@ -63,6 +69,7 @@ pub struct NewBlog {
     pub icon_id: Option<i32>,
     pub banner_id: Option<i32>,
     pub theme: Option<String>,
+    pub require_review: bool,
 }
 
 const BLOG_PREFIX: &str = "~";
@@ -132,6 +139,16 @@ impl Blog {
             .map_err(Error::from)
     }
 
+    /// Blogs whose fully qualified name starts with `prefix`
+    pub fn suggest(conn: &Connection, prefix: &str, limit: i64) -> Result<Vec<Blog>> {
+        blogs::table
+            .filter(blogs::fqn.like(like_prefix(prefix)).escape('\\'))
+            .order(blogs::fqn.asc())
+            .limit(limit)
+            .load::<Blog>(conn)
+            .map_err(Error::from)
+    }
+
     pub fn find_by_fqn(c: &PlumeRocket, fqn: &str) -> Result<Blog> {
         let from_db = blogs::table
             .filter(blogs::fqn.eq(fqn))
@@ -277,6 +294,10 @@ impl Blog {
             .unwrap_or_else(|| "/static/images/default-avatar.png".to_string())
     }
 
+    pub fn banner(&self, conn: &Connection) -> Option<Media> {
+        self.banner_id.and_then(|i| Media::get(conn, i).ok())
+    }
+
     pub fn banner_url(&self, conn: &Connection) -> Option<String> {
         self.banner_id
             .and_then(|i| Media::get(conn, i).ok())
@@ -287,6 +308,12 @@ impl Blog {
         for post in Post::get_for_blog(conn, &self)? {
             post.delete(conn, searcher)?;
         }
+        diesel::delete(
+            notifications::table
+                .filter(notifications::kind.eq(notification_kind::IMPORT_FAILED))
+                .filter(notifications::object_id.eq(self.id)),
+        )
+        .execute(conn)?;
         diesel::delete(self)
             .execute(conn)
             .map(|_| ())
@@ -395,6 +422,7 @@ impl FromId<PlumeRocket> for Blog {
                         .unwrap_or_default(),
                 ),
                 theme: None,
+                require_review: false,
             },
         )
     }
@@ -512,7 +540,7 @@ pub(crate) mod tests {
             NewBlogAuthor {
                 blog_id: blog1.id,
                 author_id: users[0].id,
-                is_owner: true,
+                role: author_role::OWNER.to_owned(),
             },
         )
         .unwrap();
@@ -522,7 +550,7 @@ pub(crate) mod tests {
             NewBlogAuthor {
                 blog_id: blog1.id,
                 author_id: users[1].id,
-                is_owner: false,
+                role: author_role::WRITER.to_owned(),
             },
         )
         .unwrap();
@@ -532,7 +560,7 @@ pub(crate) mod tests {
             NewBlogAuthor {
                 blog_id: blog2.id,
                 author_id: users[1].id,
-                is_owner: true,
+                role: author_role::OWNER.to_owned(),
             },
         )
         .unwrap();
@@ -542,7 +570,7 @@ pub(crate) mod tests {
             NewBlogAuthor {
                 blog_id: blog3.id,
                 author_id: users[2].id,
-                is_owner: true,
+                role: author_role::OWNER.to_owned(),
             },
         )
         .unwrap();
@@ -612,7 +640,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[0].id,
                     author_id: user[0].id,
-                    is_owner: true,
+                    role: author_role::OWNER.to_owned(),
                 },
             )
             .unwrap();
@@ -622,7 +650,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[0].id,
                     author_id: user[1].id,
-                    is_owner: false,
+                    role: author_role::WRITER.to_owned(),
                 },
             )
             .unwrap();
@@ -632,7 +660,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[1].id,
                     author_id: user[0].id,
-                    is_owner: true,
+                    role: author_role::OWNER.to_owned(),
                 },
             )
             .unwrap();
@@ -777,7 +805,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[0].id,
                     author_id: user[0].id,
-                    is_owner: true,
+                    role: author_role::OWNER.to_owned(),
                 },
             )
             .unwrap();
@@ -787,7 +815,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[0].id,
                     author_id: user[1].id,
-                    is_owner: false,
+                    role: author_role::WRITER.to_owned(),
                 },
             )
             .unwrap();
@@ -797,7 +825,7 @@ pub(crate) mod tests {
                 NewBlogAuthor {
                     blog_id: blog[1].id,
                     author_id: user[0].id,
-                    is_owner: true,
+                    role: author_role::OWNER.to_owned(),
                 },
             )
             .unwrap();
@@ -829,6 +857,9 @@ pub(crate) mod tests {
                         sensitive: false,
                         content_warning: None,
                         owner_id: users[0].id,
+                        size: 0,
+                        album_id: None,
+                        original_name: String::new(),
                     },
                 )
                 .unwrap()
@@ -845,6 +876,9 @@ pub(crate) mod tests {
                         sensitive: false,
                         content_warning: None,
                         owner_id: users[0].id,
+                        size: 0,
+                        album_id: None,
+                        original_name: String::new(),
                     },
                 )
                 .unwrap()
@@ -1,6 +1,8 @@
+use medias::MediaCategory;
 use rocket::config::Limits;
 use rocket::Config as RocketConfig;
 use std::env::{self, var};
+use std::str::FromStr;
 
 #[cfg(not(test))]
 const DB_NAME: &str = "plume";
@@ -12,9 +14,222 @@ pub struct Config {
     pub database_url: String,
     pub db_name: &'static str,
     pub search_index: String,
+    pub search_engine: SearchEngine,
+    pub search_relevance: SearchRelevanceConfig,
     pub rocket: Result<RocketConfig, RocketError>,
     pub logo: LogoConfig,
     pub default_theme: String,
+    pub media: MediaConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchEngine {
+    /// A tantivy index in `search_index`, that only one process can write to
+    Tantivy,
+    /// The full-text search of the database (tsvector on PostgreSQL, FTS5 on SQLite)
+    Database,
+}
+
+impl FromStr for SearchEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tantivy" => Ok(SearchEngine::Tantivy),
+            "database" => Ok(SearchEngine::Database),
+            other => Err(format!(
+                "unknown search engine \"{}\", it should be \"tantivy\" or \"database\"",
+                other
+            )),
+        }
+    }
+}
+
+/// How search results are ranked
+pub struct SearchRelevanceConfig {
+    pub title_boost: f32,
+    pub subtitle_boost: f32,
+    pub tag_boost: f32,
+    pub content_boost: f32,
+    /// Age, in days, at which the score of a post is halved. 0 disables recency decay.
+    pub recency_half_life: f32,
+}
+
+impl SearchRelevanceConfig {
+    /// Importance of a match in this field
+    pub fn boost(&self, field: &str) -> f32 {
+        match field {
+            "title" => self.title_boost,
+            "subtitle" => self.subtitle_boost,
+            "tag" => self.tag_boost,
+            "content" => self.content_boost,
+            _ => 1.0,
+        }
+    }
+
+    /// The boosts of the indexed fields (title, subtitle, tag and content), scaled so that
+    /// the highest one is 1
+    pub fn normalized_boosts(&self) -> [f32; 4] {
+        let boosts = [
+            self.title_boost,
+            self.subtitle_boost,
+            self.tag_boost,
+            self.content_boost,
+        ];
+        let max = boosts.iter().cloned().fold(0.0, f32::max);
+        if max > 0.0 {
+            [
+                boosts[0] / max,
+                boosts[1] / max,
+                boosts[2] / max,
+                boosts[3] / max,
+            ]
+        } else {
+            [1.0; 4]
+        }
+    }
+
+    /// Factor applied to the score of a post this many days old
+    pub fn recency(&self, age: i64) -> f32 {
+        if self.recency_half_life <= 0.0 || age <= 0 {
+            1.0
+        } else {
+            self.recency_half_life / (self.recency_half_life + age as f32)
+        }
+    }
+}
+
+impl Default for SearchRelevanceConfig {
+    fn default() -> Self {
+        let value = |name: &str, default: f32| {
+            var(name)
+                .ok()
+                .map(|v| {
+                    v.parse::<f32>()
+                        .ok()
+                        .filter(|v| *v >= 0.0)
+                        .unwrap_or_else(|| panic!("{} should be a positive number", name))
+                })
+                .unwrap_or(default)
+        };
+        SearchRelevanceConfig {
+            title_boost: value("SEARCH_BOOST_TITLE", 4.0),
+            subtitle_boost: value("SEARCH_BOOST_SUBTITLE", 2.0),
+            tag_boost: value("SEARCH_BOOST_TAG", 1.5),
+            content_boost: value("SEARCH_BOOST_CONTENT", 1.0),
+            recency_half_life: value("SEARCH_RECENCY_HALF_LIFE", 365.0),
+        }
+    }
+}
+
+/// Where the media uploaded to this instance are stored, and served from
+pub struct MediaConfig {
+    pub storage: MediaStorage,
+    /// Base URL the media are served from, if it is not the store itself (a CDN for instance)
+    pub url_prefix: Option<String>,
+    /// Maximum size of uploaded files, in bytes
+    pub max_image_size: u64,
+    pub max_audio_size: u64,
+    pub max_video_size: u64,
+    /// Maximum size of all the files of a user, in bytes
+    pub user_quota: Option<u64>,
+    /// How long copies of remote media are kept, in seconds
+    pub remote_cache_ttl: u64,
+    /// Maximum size of all the copies of remote media, in bytes
+    pub remote_cache_size: u64,
+}
+
+impl MediaConfig {
+    /// The maximum size of a file of this category, in bytes
+    pub fn max_size(&self, category: &MediaCategory) -> u64 {
+        match *category {
+            MediaCategory::Image => self.max_image_size,
+            MediaCategory::Audio => self.max_audio_size,
+            MediaCategory::Video => self.max_video_size,
+            MediaCategory::Unknown => 0,
+        }
+    }
+
+    /// The maximum size of any file
+    pub fn max_file_size(&self) -> u64 {
+        self.max_image_size
+            .max(self.max_audio_size)
+            .max(self.max_video_size)
+    }
+}
+
+/// Read a size in KiB from the environment, and convert it to bytes
+fn size_var(name: &str) -> Option<u64> {
+    var(name).ok().map(|s| {
+        s.parse::<u64>()
+            .unwrap_or_else(|_| panic!("{} should be a number of KiB", name))
+            * 1024
+    })
+}
+
+pub enum MediaStorage {
+    /// A directory, served by Plume at `/static/media`
+    Local(String),
+    /// A bucket of an S3-compatible object storage.
+    ///
+    /// Files are uploaded with the `public-read` ACL. If ACLs are disabled on the bucket,
+    /// its policy should allow anyone to read its objects instead.
+    S3(S3Config),
+}
+
+pub struct S3Config {
+    /// URL of the storage service, like `https://s3.eu-west-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        let storage = match var("MEDIA_STORAGE")
+            .unwrap_or_else(|_| "local".to_owned())
+            .as_ref()
+        {
+            "local" => MediaStorage::Local(
+                var("MEDIA_DIRECTORY").unwrap_or_else(|_| "static/media".to_owned()),
+            ),
+            "s3" => MediaStorage::S3(S3Config {
+                endpoint: var("S3_ENDPOINT")
+                    .expect("S3_ENDPOINT is required when MEDIA_STORAGE=s3")
+                    .trim_end_matches('/')
+                    .to_owned(),
+                bucket: var("S3_BUCKET").expect("S3_BUCKET is required when MEDIA_STORAGE=s3"),
+                region: var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_owned()),
+                access_key: var("S3_ACCESS_KEY")
+                    .expect("S3_ACCESS_KEY is required when MEDIA_STORAGE=s3"),
+                secret_key: var("S3_SECRET_KEY")
+                    .expect("S3_SECRET_KEY is required when MEDIA_STORAGE=s3"),
+            }),
+            other => panic!("Unknown media storage: {}", other),
+        };
+        MediaConfig {
+            storage,
+            url_prefix: var("MEDIA_URL_PREFIX")
+                .ok()
+                .map(|p| p.trim_end_matches('/').to_owned()),
+            max_image_size: size_var("MEDIA_MAX_IMAGE_SIZE").unwrap_or(10 * 1024 * 1024),
+            max_audio_size: size_var("MEDIA_MAX_AUDIO_SIZE").unwrap_or(50 * 1024 * 1024),
+            max_video_size: size_var("MEDIA_MAX_VIDEO_SIZE").unwrap_or(100 * 1024 * 1024),
+            user_quota: size_var("MEDIA_USER_QUOTA"),
+            remote_cache_ttl: var("MEDIA_REMOTE_CACHE_TTL")
+                .ok()
+                .map(|h| {
+                    h.parse::<u64>()
+                        .expect("MEDIA_REMOTE_CACHE_TTL should be a number of hours")
+                })
+                .unwrap_or(24 * 7)
+                * 60
+                * 60,
+            remote_cache_size: size_var("MEDIA_REMOTE_CACHE_SIZE").unwrap_or(1024 * 1024 * 1024),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -198,8 +413,14 @@ lazy_static! {
         #[cfg(feature = "sqlite")]
         database_url: var("DATABASE_URL").unwrap_or_else(|_| format!("{}.sqlite", DB_NAME)),
         search_index: var("SEARCH_INDEX").unwrap_or_else(|_| "search_index".to_owned()),
+        search_engine: var("SEARCH_ENGINE")
+            .unwrap_or_else(|_| "tantivy".to_owned())
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid SEARCH_ENGINE: {}", err)),
+        search_relevance: SearchRelevanceConfig::default(),
         rocket: get_rocket_config(),
         logo: LogoConfig::default(),
         default_theme: var("DEFAULT_THEME").unwrap_or_else(|_| "default-light".to_owned()),
+        media: MediaConfig::default(),
     };
 }
@@ -0,0 +1,140 @@
+//! Downloads of files at addresses chosen by users or remote instances
+//!
+//! These addresses could point to the network Plume is running in, so only public ones
+//! are fetched, even after redirections.
+
+use reqwest::{Client, RedirectPolicy, Response};
+use std::{
+    net::{IpAddr, Ipv4Addr, ToSocketAddrs},
+    time::Duration,
+};
+use url::{Host, Url};
+
+use {Error, Result};
+
+/// How long a download can take, including the redirections
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many redirections are followed
+const MAX_REDIRECTS: usize = 5;
+
+/// Start downloading `url`, if it and the addresses it redirects to are public
+pub fn get_public(url: &str) -> Result<Response> {
+    if !is_public_url(url) {
+        return Err(Error::Url);
+    }
+    let res = Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(RedirectPolicy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.too_many_redirects()
+            } else if is_public_url(attempt.url().as_str()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        }))
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?;
+    // a redirection that has not been followed
+    if res.status().is_redirection() {
+        return Err(Error::Url);
+    }
+    Ok(res)
+}
+
+/// Whether `url` is an HTTP(S) URL whose host only resolves to public addresses
+pub fn is_public_url(url: &str) -> bool {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return false;
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(Host::Domain(domain)) => match (domain, port).to_socket_addrs() {
+            Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+            Err(_) => return false,
+        },
+        None => return false,
+    };
+    !addrs.is_empty() && addrs.into_iter().all(is_public_ip)
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            // IPv4-mapped and IPv4-compatible addresses, including ::1 and ::
+            Some(ipv4) => is_public_ipv4(ipv4),
+            None => {
+                let first = ip.segments()[0];
+                !ip.is_loopback()
+                    && !ip.is_unspecified()
+                    && !ip.is_multicast()
+                    // unique local addresses, fc00::/7
+                    && first & 0xfe00 != 0xfc00
+                    // link-local addresses, fe80::/10
+                    && first & 0xffc0 != 0xfe80
+                    // site-local addresses, fec0::/10
+                    && first & 0xffc0 != 0xfec0
+                    // documentation, 2001:db8::/32
+                    && !(first == 0x2001 && ip.segments()[1] == 0x0db8)
+            }
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !ip.is_private()
+        && !ip.is_loopback()
+        && !ip.is_link_local()
+        && !ip.is_broadcast()
+        && !ip.is_documentation()
+        && !ip.is_unspecified()
+        && !ip.is_multicast()
+        // "this network", 0.0.0.0/8
+        && octets[0] != 0
+        // shared address space, 100.64.0.0/10
+        && !(octets[0] == 100 && octets[1] & 0xc0 == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        && !(octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+        // benchmarking, 198.18.0.0/15
+        && !(octets[0] == 198 && octets[1] & 0xfe == 18)
+        // reserved, 240.0.0.0/4
+        && octets[0] < 240
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_urls() {
+        assert!(is_public_url("https://93.184.216.34/image.png"));
+        assert!(is_public_url(
+            "http://[2606:2800:220:1:248:1893:25c8:1946]/"
+        ));
+
+        assert!(!is_public_url("ftp://93.184.216.34/image.png"));
+        assert!(!is_public_url("http://127.0.0.1:8000/"));
+        assert!(!is_public_url("http://localhost/"));
+        assert!(!is_public_url("http://10.1.2.3/"));
+        assert!(!is_public_url("http://172.20.0.1/"));
+        assert!(!is_public_url("http://192.168.1.1/"));
+        assert!(!is_public_url("http://169.254.169.254/latest/meta-data/"));
+        assert!(!is_public_url("http://100.100.100.200/"));
+        assert!(!is_public_url("http://0.0.0.0/"));
+        assert!(!is_public_url("http://[::1]/"));
+        assert!(!is_public_url("http://[::ffff:127.0.0.1]/"));
+        assert!(!is_public_url("http://[fd00::1]/"));
+        assert!(!is_public_url("http://[fe80::1]/"));
+    }
+}
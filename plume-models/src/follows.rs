@@ -1,6 +1,7 @@
 use activitypub::activity::{Accept, Follow as FollowAct, Undo};
 use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
 
+use instance::Instance;
 use notifications::*;
 use plume_common::activity_pub::{
     broadcast,
@@ -12,6 +13,9 @@ use schema::follows;
 use users::User;
 use {ap_url, Connection, Error, PlumeRocket, Result, CONFIG};
 
+/// The maximum size of the CSV files that can be imported, in bytes
+pub const MAX_CSV_SIZE: u64 = 1024 * 1024;
+
 #[derive(Clone, Queryable, Identifiable, Associations, AsChangeset)]
 #[belongs_to(User, foreign_key = "following_id")]
 pub struct Follow {
@@ -51,6 +55,68 @@ impl Follow {
             .map_err(Error::from)
     }
 
+    /// The accounts `user` follows, in the CSV format used by Mastodon
+    pub fn export_csv(conn: &Connection, user: &User) -> Result<String> {
+        let mut csv = String::from("Account address\n");
+        for account in user.get_followed(conn)? {
+            csv.push_str(&account.account_address()?);
+            csv.push('\n');
+        }
+        Ok(csv)
+    }
+
+    /// Follow the accounts listed in a CSV file, like the ones made by `export_csv`
+    /// or by Mastodon.
+    ///
+    /// Returns the new follows, whose activities still have to be sent, and the
+    /// addresses of the accounts that could not be found.
+    pub fn import_csv(
+        c: &PlumeRocket,
+        user: &User,
+        csv: &str,
+    ) -> Result<(Vec<Follow>, Vec<String>)> {
+        let local_domain = format!("@{}", Instance::get_local()?.public_domain);
+        let mut follows = vec![];
+        let mut not_found = vec![];
+        for line in csv.lines() {
+            // other columns (like "Show boosts") are ignored
+            let address = line
+                .split(',')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_start_matches('@');
+            // skip the header and empty lines
+            if address.is_empty() || address.contains(char::is_whitespace) {
+                continue;
+            }
+
+            let fqn = address.trim_end_matches(local_domain.as_str());
+            let target = match User::find_by_fqn(c, fqn) {
+                Ok(target) => target,
+                Err(_) => {
+                    not_found.push(address.to_owned());
+                    continue;
+                }
+            };
+            if target.id == user.id || user.is_following(&c.conn, target.id)? {
+                continue;
+            }
+
+            let follow = Follow::insert(
+                &c.conn,
+                NewFollow {
+                    follower_id: user.id,
+                    following_id: target.id,
+                    ap_url: String::new(),
+                },
+            )?;
+            follow.notify(&c.conn)?;
+            follows.push(follow);
+        }
+        Ok((follows, not_found))
+    }
+
     pub fn to_activity(&self, conn: &Connection) -> Result<FollowAct> {
         let user = User::get(conn, self.follower_id)?;
         let target = User::get(conn, self.following_id)?;
@@ -203,7 +269,7 @@ impl IntoId for Follow {
 mod tests {
     use super::*;
     use diesel::Connection;
-    use tests::db;
+    use tests::{db, rockets};
     use users::tests as user_tests;
 
     #[test]
@@ -238,4 +304,29 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn csv() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let users = user_tests::fill_database(conn);
+            let csv = format!(
+                "Account address,Show boosts\n{},true\n@{}\n\n",
+                users[1].account_address().unwrap(),
+                users[2].fqn
+            );
+            let (follows, not_found) = Follow::import_csv(&r, &users[0], &csv).unwrap();
+            assert_eq!(follows.len(), 2);
+            assert!(not_found.is_empty());
+            assert!(users[0].is_following(conn, users[1].id).unwrap());
+
+            // already followed accounts are skipped
+            let exported = Follow::export_csv(conn, &users[0]).unwrap();
+            assert_eq!(exported.lines().count(), 3);
+            let (follows, _) = Follow::import_csv(&r, &users[0], &exported).unwrap();
+            assert!(follows.is_empty());
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,764 @@
+//! Import posts exported from other blogging platforms
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use guid_create::GUID;
+use heck::CamelCase;
+use roxmltree::{Document, Node};
+use serde_json;
+use serde_yaml;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use blogs::Blog;
+use fetch::get_public;
+use instance::Instance;
+use medias::{Media, MediaCategory, NewMedia};
+use plume_common::{
+    activity_pub::broadcast,
+    utils::{make_slug, md_to_html},
+};
+use post_authors::{NewPostAuthor, PostAuthor};
+use posts::{post_visibility, NewPost, Post};
+use safe_string::SafeString;
+use search::Searcher;
+use tags::{NewTag, Tag};
+use users::User;
+use {Connection, Error, Result, CONFIG};
+
+/// The maximum size of an uploaded export, in bytes
+pub const MAX_EXPORT_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The maximum total size of the files extracted from a zip archive, in bytes
+const MAX_EXTRACTED_SIZE: u64 = 500 * 1024 * 1024;
+
+/// The maximum number of files and directories in a zip archive
+const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+/// The kinds of exports that can be imported
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    /// A WordPress eXtended RSS (WXR) file
+    WordPress,
+    /// The JSON export of a Ghost blog
+    Ghost,
+    /// Markdown files with a YAML front matter, in a directory or a zip archive
+    Markdown,
+}
+
+impl ImportFormat {
+    /// Guess the format of an export from its path
+    pub fn guess(path: &Path) -> Option<ImportFormat> {
+        if path.is_dir() {
+            return Some(ImportFormat::Markdown);
+        }
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_ref()
+            .map(String::as_str)
+        {
+            Some("xml") | Some("wxr") => Some(ImportFormat::WordPress),
+            Some("json") => Some(ImportFormat::Ghost),
+            Some("zip") | Some("md") | Some("markdown") => Some(ImportFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// How imported posts are made known to the rest of the fediverse
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Federation {
+    /// They are not sent anywhere
+    None,
+    /// A Create activity is sent for each published post, with its original date
+    Backdated,
+}
+
+/// A post read from an export, before it is saved
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportedPost {
+    pub title: String,
+    pub subtitle: String,
+    pub slug: Option<String>,
+    /// Markdown, possibly mixed with HTML
+    pub source: String,
+    pub creation_date: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+    pub license: Option<String>,
+    pub draft: bool,
+}
+
+/// The posts of an export, and where to find the files they reference
+pub struct Export {
+    pub posts: Vec<ImportedPost>,
+    base_dir: Option<PathBuf>,
+    // archives are extracted in a temporary directory, that is removed with the export
+    extracted: bool,
+}
+
+impl Export {
+    pub fn read(path: &Path, format: ImportFormat) -> Result<Export> {
+        match format {
+            ImportFormat::WordPress => Ok(Export {
+                posts: parse_wordpress(&fs::read_to_string(path)?)?,
+                base_dir: None,
+                extracted: false,
+            }),
+            ImportFormat::Ghost => Ok(Export {
+                posts: parse_ghost(&fs::read_to_string(path)?)?,
+                base_dir: None,
+                extracted: false,
+            }),
+            ImportFormat::Markdown if path.is_dir() => Ok(Export {
+                posts: read_markdown_dir(path)?,
+                base_dir: Some(path.to_path_buf()),
+                extracted: false,
+            }),
+            ImportFormat::Markdown if is_zip(path) => {
+                let dir = extract_zip(path)?;
+                Ok(Export {
+                    posts: read_markdown_dir(&dir)?,
+                    base_dir: Some(dir),
+                    extracted: true,
+                })
+            }
+            ImportFormat::Markdown => Ok(Export {
+                posts: vec![parse_markdown(
+                    &path.file_stem()?.to_string_lossy(),
+                    &fs::read_to_string(path)?,
+                )?],
+                base_dir: path.parent().map(Path::to_path_buf),
+                extracted: false,
+            }),
+        }
+    }
+
+    /// The local file a post refers to, if it is part of the export
+    fn file(&self, url: &str) -> Option<PathBuf> {
+        let relative = Path::new(url.trim_start_matches('/'));
+        if relative.components().any(|c| c == Component::ParentDir) {
+            return None;
+        }
+        let file = self.base_dir.as_ref()?.join(relative);
+        if file.is_file() {
+            Some(file)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Export {
+    fn drop(&mut self) {
+        if self.extracted {
+            if let Some(ref dir) = self.base_dir {
+                fs::remove_dir_all(dir).ok();
+            }
+        }
+    }
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase() == "zip")
+        .unwrap_or(false)
+}
+
+fn extract_zip(path: &Path) -> Result<PathBuf> {
+    let dir = env::temp_dir().join(format!("plume-import-{}", GUID::rand().to_string()));
+    let extracted = unzip(path, &dir);
+    if extracted.is_err() {
+        fs::remove_dir_all(&dir).ok();
+    }
+    extracted.map(|_| dir)
+}
+
+fn unzip(path: &Path, dir: &Path) -> Result<()> {
+    let mut archive = ZipArchive::new(fs::File::open(path)?).map_err(|_| Error::InvalidValue)?;
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(Error::InvalidValue);
+    }
+    // the sizes written in the archive can't be trusted, count what is actually extracted
+    let mut remaining = MAX_EXTRACTED_SIZE;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|_| Error::InvalidValue)?;
+        // sanitized_name drops absolute paths and parent directories
+        let dest = dir.join(file.sanitized_name());
+        if file.name().ends_with('/') {
+            fs::create_dir_all(&dest)?;
+        } else {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let written = io::copy(&mut file.take(remaining + 1), &mut fs::File::create(&dest)?)?;
+            if written > remaining {
+                return Err(Error::InvalidValue);
+            }
+            remaining -= written;
+        }
+    }
+    Ok(())
+}
+
+fn read_markdown_dir(dir: &Path) -> Result<Vec<ImportedPost>> {
+    let mut files = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .map(|ext| ext == "md" || ext == "markdown")
+                    .unwrap_or(false)
+        })
+        .map(|entry| entry.path().to_path_buf())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            parse_markdown(
+                &file.file_stem()?.to_string_lossy(),
+                &fs::read_to_string(&file)?,
+            )
+        })
+        .collect()
+}
+
+/// Parse dates in the various formats that can be found in exports
+fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    let date = date.trim();
+    DateTime::parse_from_rfc3339(date)
+        .map(|d| d.naive_utc())
+        .or_else(|_| DateTime::parse_from_rfc2822(date).map(|d| d.naive_utc()))
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+        .ok()
+}
+
+// The text of the first child of `node` with this name, in a namespace containing `ns`
+fn child_text(node: &Node, ns: &str, name: &str) -> Option<String> {
+    node.children()
+        .find(|c| {
+            c.tag_name().name() == name && c.tag_name().namespace().unwrap_or("").contains(ns)
+        })
+        .map(|c| c.text().unwrap_or("").to_owned())
+}
+
+/// Read the posts of a WordPress export. Pages, attachments and deleted posts are ignored.
+pub fn parse_wordpress(xml: &str) -> Result<Vec<ImportedPost>> {
+    let doc = Document::parse(xml).map_err(|_| Error::SerDe)?;
+    Ok(doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "item")
+        .filter(|item| {
+            child_text(item, "wordpress.org/export", "post_type")
+                .map(|t| t == "post")
+                .unwrap_or(true)
+        })
+        .filter_map(|item| {
+            let status = child_text(&item, "wordpress.org/export", "status")
+                .unwrap_or_else(|| "publish".to_owned());
+            if status == "trash" || status == "auto-draft" || status == "inherit" {
+                return None;
+            }
+            let creation_date = child_text(&item, "wordpress.org/export", "post_date_gmt")
+                .and_then(|d| parse_date(&d))
+                .or_else(|| {
+                    child_text(&item, "wordpress.org/export", "post_date")
+                        .and_then(|d| parse_date(&d))
+                })
+                .or_else(|| child_text(&item, "", "pubDate").and_then(|d| parse_date(&d)));
+
+            Some(ImportedPost {
+                title: child_text(&item, "", "title").unwrap_or_default(),
+                subtitle: child_text(&item, "excerpt", "encoded").unwrap_or_default(),
+                slug: child_text(&item, "wordpress.org/export", "post_name")
+                    .filter(|s| !s.is_empty()),
+                source: child_text(&item, "purl.org/rss/1.0/modules/content", "encoded")
+                    .unwrap_or_default(),
+                creation_date,
+                tags: item
+                    .children()
+                    .filter(|c| c.tag_name().name() == "category")
+                    .filter_map(|c| c.text().map(str::to_owned))
+                    .filter(|t| t != "Uncategorized")
+                    .collect(),
+                license: None,
+                draft: status != "publish",
+            })
+        })
+        .collect())
+}
+
+/// Read the posts of a Ghost export (either the old or the current format)
+pub fn parse_ghost(json: &str) -> Result<Vec<ImportedPost>> {
+    let export = serde_json::from_str::<serde_json::Value>(json)?;
+    let data = export
+        .get("db")
+        .and_then(|db| db.get(0))
+        .unwrap_or(&export)
+        .get("data")?;
+
+    // ids are numbers in old exports, and strings in recent ones
+    let tag_names = data["tags"]
+        .as_array()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|t| Some((t["id"].to_string(), t["name"].as_str()?.to_owned())))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+    let posts_tags = data["posts_tags"].as_array().cloned().unwrap_or_default();
+
+    Ok(data["posts"]
+        .as_array()?
+        .iter()
+        .filter(|p| p["type"].as_str() != Some("page") && p["page"].as_bool() != Some(true))
+        .map(|p| {
+            let id = p["id"].to_string();
+            let date = |field: &str| match p[field] {
+                serde_json::Value::String(ref date) => parse_date(date),
+                serde_json::Value::Number(ref ms) => ms
+                    .as_i64()
+                    .map(|ms| NaiveDateTime::from_timestamp(ms / 1000, 0)),
+                _ => None,
+            };
+            let markdown = p["markdown"].as_str().unwrap_or("");
+
+            ImportedPost {
+                title: p["title"].as_str().unwrap_or("").to_owned(),
+                subtitle: p["custom_excerpt"].as_str().unwrap_or("").to_owned(),
+                slug: p["slug"].as_str().map(str::to_owned),
+                source: if markdown.is_empty() {
+                    p["html"].as_str().unwrap_or("").to_owned()
+                } else {
+                    markdown.to_owned()
+                },
+                creation_date: date("published_at").or_else(|| date("created_at")),
+                tags: posts_tags
+                    .iter()
+                    .filter(|pt| pt["post_id"].to_string() == id)
+                    .filter_map(|pt| tag_names.get(&pt["tag_id"].to_string()).cloned())
+                    .collect(),
+                license: None,
+                draft: p["status"].as_str() != Some("published"),
+            }
+        })
+        .collect())
+}
+
+/// Read a Markdown file, with an optional YAML front matter, like the ones of
+/// Jekyll, Hugo or Zola. `name` is used as a title if none is given.
+pub fn parse_markdown(name: &str, content: &str) -> Result<ImportedPost> {
+    let content = content.trim_start_matches('\u{feff}');
+    let (front_matter, body) = if content.starts_with("---") {
+        let rest = &content[3..];
+        match rest.find("\n---") {
+            Some(end) => (
+                &rest[..end],
+                rest[end + 4..].splitn(2, '\n').nth(1).unwrap_or(""),
+            ),
+            None => ("", content),
+        }
+    } else {
+        ("", content)
+    };
+
+    let meta = if front_matter.trim().is_empty() {
+        serde_yaml::Value::Null
+    } else {
+        serde_yaml::from_str::<serde_yaml::Value>(front_matter).map_err(|_| Error::SerDe)?
+    };
+    let text = |field: &str| meta[field].as_str().map(str::to_owned);
+    let list = |field: &str| match meta[field] {
+        serde_yaml::Value::Sequence(ref items) => items
+            .iter()
+            .filter_map(|i| i.as_str().map(str::to_owned))
+            .collect(),
+        serde_yaml::Value::String(ref items) => items
+            .split(',')
+            .map(|i| i.trim().to_owned())
+            .filter(|i| !i.is_empty())
+            .collect(),
+        _ => vec![],
+    };
+
+    let mut tags: Vec<String> = list("tags");
+    tags.extend(list("categories"));
+    Ok(ImportedPost {
+        title: text("title").unwrap_or_else(|| name.to_owned()),
+        subtitle: text("subtitle")
+            .or_else(|| text("description"))
+            .or_else(|| text("summary"))
+            .unwrap_or_default(),
+        slug: text("slug"),
+        source: body.trim().to_owned(),
+        creation_date: text("date").and_then(|d| parse_date(&d)),
+        tags,
+        license: text("license"),
+        draft: meta["draft"].as_bool().unwrap_or(false)
+            || meta["published"].as_bool() == Some(false),
+    })
+}
+
+/// The addresses of the images of a post, written in Markdown or in HTML
+pub fn image_urls(source: &str) -> Vec<String> {
+    let mut urls = vec![];
+    // ![alt](url "title")
+    for part in source.split("![").skip(1) {
+        if let Some(start) = part.find("](") {
+            let rest = &part[start + 2..];
+            if let Some(url) = rest[..rest.find(')').unwrap_or(0)]
+                .split_whitespace()
+                .next()
+            {
+                urls.push(url.trim_matches(|c| c == '<' || c == '>').to_owned());
+            }
+        }
+    }
+    // <img src="url">
+    for part in source.split("<img").skip(1) {
+        let tag = &part[..part.find('>').unwrap_or_else(|| part.len())];
+        if let Some(start) = tag.find("src=") {
+            let rest = &tag[start + 4..];
+            if let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                if let Some(end) = rest[1..].find(quote) {
+                    urls.push(rest[1..=end].to_owned());
+                }
+            }
+        }
+    }
+    // numbers are the ids of medias that are already on this instance
+    urls.retain(|url| !url.is_empty() && !url.starts_with("data:") && url.parse::<i32>().is_err());
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// Saves the posts of an export in a blog
+pub struct Importer<'a> {
+    pub conn: &'a Connection,
+    pub searcher: &'a Searcher,
+    pub blog: &'a Blog,
+    /// The author of the imported posts, who will also own their images
+    pub author: &'a User,
+    /// Whether the posts can be published, or have to be imported as drafts
+    pub publish: bool,
+    pub federation: Federation,
+}
+
+impl<'a> Importer<'a> {
+    /// Save all the posts of an export, and return the ones that were created.
+    ///
+    /// Posts that seem to have already been imported (because there is one with the same
+    /// slug and title in the blog) are skipped, so that an import can be run again.
+    pub fn import(&self, export: &Export) -> Result<Vec<Post>> {
+        let mut posts = vec![];
+        for imported in &export.posts {
+            if let Some(post) = self.import_post(export, imported)? {
+                posts.push(post);
+            }
+        }
+        Ok(posts)
+    }
+
+    fn import_post(&self, export: &Export, imported: &ImportedPost) -> Result<Option<Post>> {
+        let conn = self.conn;
+        let slug = make_slug(imported.slug.as_ref().unwrap_or(&imported.title));
+        let slug = if slug.is_empty() || slug == "new" {
+            "imported".to_owned()
+        } else {
+            slug
+        };
+        if let Ok(existing) = Post::find_by_slug(conn, &slug, self.blog.id) {
+            if existing.title == imported.title {
+                return Ok(None);
+            }
+        }
+
+        let instance = Instance::get_local()?;
+        let source = self.import_medias(export, &imported.source);
+        let (content, _, hashtags) = md_to_html(
+            &source,
+            Some(&instance.public_domain),
+            false,
+            Some(Media::get_media_processor(conn, vec![self.author])),
+        );
+        let post = Post::insert(
+            conn,
+            NewPost {
+                blog_id: self.blog.id,
+                slug: Post::unique_slug(conn, self.blog.id, &slug, None)?,
+                title: imported.title.clone(),
+                content: SafeString::new(&content),
+                published: self.publish && !imported.draft,
+                license: imported.license.clone().unwrap_or(instance.default_license),
+                ap_url: String::new(),
+                creation_date: imported.creation_date,
+                subtitle: imported.subtitle.clone(),
+                source,
+                cover_id: None,
+                publish_at: None,
+                visibility: post_visibility::PUBLIC.to_owned(),
+            },
+            self.searcher,
+        )?;
+        PostAuthor::insert(
+            conn,
+            NewPostAuthor {
+                post_id: post.id,
+                author_id: self.author.id,
+            },
+        )?;
+
+        let tags = imported
+            .tags
+            .iter()
+            .map(|t| t.trim().to_camel_case())
+            .filter(|t| !t.is_empty())
+            .collect::<HashSet<_>>();
+        for tag in tags {
+            Tag::insert(
+                conn,
+                NewTag {
+                    tag,
+                    is_hashtag: false,
+                    post_id: post.id,
+                },
+            )?;
+        }
+        for hashtag in hashtags {
+            Tag::insert(
+                conn,
+                NewTag {
+                    tag: hashtag.to_camel_case(),
+                    is_hashtag: true,
+                    post_id: post.id,
+                },
+            )?;
+        }
+
+        if post.published && self.federation == Federation::Backdated {
+            let act = post.create_activity(conn)?;
+            let dest = post.get_receivers(conn)?;
+            broadcast(self.author, act, dest);
+        }
+        Ok(Some(post))
+    }
+
+    // Save the images of a post as medias of its author, and use them instead of the
+    // original ones. Images that can't be saved are left untouched.
+    fn import_medias(&self, export: &Export, source: &str) -> String {
+        let mut source = source.to_owned();
+        for url in image_urls(&source) {
+            if let Ok(media_url) = self.save_media(export, &url).and_then(|m| m.url()) {
+                source = source.replace(&url, &media_url);
+            }
+        }
+        source
+    }
+
+    fn save_media(&self, export: &Export, url: &str) -> Result<Media> {
+        let content = if url.starts_with("http://") || url.starts_with("https://") {
+            let limit = CONFIG.media.max_size(&MediaCategory::Image);
+            let mut content = vec![];
+            get_public(url)?.take(limit + 1).read_to_end(&mut content)?;
+            content
+        } else {
+            fs::read(export.file(url)?)?
+        };
+        let (content, ext) = Media::validate_upload(self.conn, self.author.id, content)
+            .map_err(|_| Error::InvalidValue)?;
+        let size = content.len() as i32;
+        let file_path = Media::save_file(&content, ext)?;
+
+        let media = Media::insert(
+            self.conn,
+            NewMedia {
+                file_path,
+                alt_text: String::new(),
+                is_remote: false,
+                remote_url: None,
+                sensitive: false,
+                content_warning: None,
+                owner_id: self.author.id,
+                size,
+                album_id: None,
+                original_name: url.rsplit('/').next().unwrap_or_default().to_owned(),
+            },
+        )?;
+        media.detect_duration(self.conn, &content)?;
+        Ok(media)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use search::tests::get_searcher;
+    use tests::db;
+
+    #[test]
+    fn wordpress() {
+        let posts = parse_wordpress(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"
+    xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/"
+    xmlns:content="http://purl.org/rss/1.0/modules/content/"
+    xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <item>
+        <title>Hello world</title>
+        <pubDate>Tue, 01 Jan 2019 10:00:00 +0000</pubDate>
+        <content:encoded><![CDATA[<p>First post, with <img src="https://example.com/cat.jpg"></p>]]></content:encoded>
+        <excerpt:encoded><![CDATA[The beginning]]></excerpt:encoded>
+        <wp:post_date_gmt>2019-01-01 10:00:00</wp:post_date_gmt>
+        <wp:post_name>hello-world</wp:post_name>
+        <wp:status>publish</wp:status>
+        <wp:post_type>post</wp:post_type>
+        <category domain="post_tag" nicename="intro"><![CDATA[Intro]]></category>
+    </item>
+    <item>
+        <title>About</title>
+        <wp:status>publish</wp:status>
+        <wp:post_type>page</wp:post_type>
+    </item>
+    <item>
+        <title>Unfinished</title>
+        <wp:post_date_gmt>0000-00-00 00:00:00</wp:post_date_gmt>
+        <wp:status>draft</wp:status>
+        <wp:post_type>post</wp:post_type>
+    </item>
+</channel>
+</rss>"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].title, "Hello world");
+        assert_eq!(posts[0].subtitle, "The beginning");
+        assert_eq!(posts[0].slug, Some("hello-world".to_owned()));
+        assert_eq!(posts[0].tags, vec!["Intro".to_owned()]);
+        assert_eq!(
+            posts[0].creation_date,
+            Some(NaiveDate::from_ymd(2019, 1, 1).and_hms(10, 0, 0))
+        );
+        assert!(!posts[0].draft);
+        assert_eq!(
+            image_urls(&posts[0].source),
+            vec!["https://example.com/cat.jpg".to_owned()]
+        );
+        assert!(posts[1].draft);
+        assert_eq!(posts[1].creation_date, None);
+    }
+
+    #[test]
+    fn ghost() {
+        let posts = parse_ghost(
+            r#"{"db": [{"data": {
+                "posts": [
+                    {"id": "1", "title": "Ghostly", "slug": "ghostly", "html": "<p>Boo</p>",
+                     "status": "published", "published_at": "2019-02-03T04:05:06.000Z",
+                     "custom_excerpt": null, "type": "post"},
+                    {"id": "2", "title": "A page", "slug": "a-page", "html": "",
+                     "status": "published", "type": "page"}
+                ],
+                "tags": [{"id": "7", "name": "Spooky"}],
+                "posts_tags": [{"post_id": "1", "tag_id": "7"}]
+            }}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Ghostly");
+        assert_eq!(posts[0].source, "<p>Boo</p>");
+        assert_eq!(posts[0].tags, vec!["Spooky".to_owned()]);
+        assert_eq!(
+            posts[0].creation_date,
+            Some(NaiveDate::from_ymd(2019, 2, 3).and_hms(4, 5, 6))
+        );
+        assert!(!posts[0].draft);
+    }
+
+    #[test]
+    fn markdown() {
+        let post = parse_markdown(
+            "my-file",
+            "---\ntitle: Front matter\ndate: 2018-05-06\ntags: [a, b]\ncategories: c\nlicense: CC-0\n---\n\n# Hello\n\n![a cat](images/cat.png \"Cat\")\n",
+        )
+        .unwrap();
+        assert_eq!(post.title, "Front matter");
+        assert_eq!(post.source, "# Hello\n\n![a cat](images/cat.png \"Cat\")");
+        assert_eq!(
+            post.creation_date,
+            Some(NaiveDate::from_ymd(2018, 5, 6).and_hms(0, 0, 0))
+        );
+        assert_eq!(post.tags, vec!["a", "b", "c"]);
+        assert_eq!(post.license, Some("CC-0".to_owned()));
+        assert_eq!(image_urls(&post.source), vec!["images/cat.png".to_owned()]);
+
+        let post = parse_markdown("no-front-matter", "Just text").unwrap();
+        assert_eq!(post.title, "no-front-matter");
+        assert_eq!(post.source, "Just text");
+    }
+
+    #[test]
+    fn import() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            let searcher = get_searcher();
+            let export = Export {
+                posts: vec![parse_markdown(
+                    "old",
+                    "---\ntitle: An old post\ndate: 2015-01-01\ntags: Archives\n---\nHello #world",
+                )
+                .unwrap()],
+                base_dir: None,
+                extracted: false,
+            };
+            let importer = Importer {
+                conn,
+                searcher: &searcher,
+                blog: &blogs[0],
+                author: &users[0],
+                publish: true,
+                federation: Federation::None,
+            };
+
+            let posts = importer.import(&export).unwrap();
+            assert_eq!(posts.len(), 1);
+            assert_eq!(posts[0].slug, "an-old-post");
+            assert_eq!(
+                posts[0].creation_date,
+                NaiveDate::from_ymd(2015, 1, 1).and_hms(0, 0, 0)
+            );
+            assert!(posts[0].is_author(conn, users[0].id).unwrap());
+            let tags = Tag::for_post(conn, posts[0].id)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.tag, t.is_hashtag))
+                .collect::<Vec<_>>();
+            assert!(tags.contains(&("Archives".to_owned(), false)));
+            assert!(tags.contains(&("World".to_owned(), true)));
+
+            // importing again doesn't duplicate posts
+            assert!(importer.import(&export).unwrap().is_empty());
+
+            Ok(())
+        });
+    }
+}
@@ -2,6 +2,7 @@ use activitypub::activity::*;
 use serde_json;
 
 use crate::{
+    blog_invitations::BlogInvitation,
     comments::Comment,
     follows, likes,
     posts::{Post, PostUpdate},
@@ -47,7 +48,13 @@ impl_into_inbox_result! {
 }
 
 pub fn inbox(ctx: &PlumeRocket, act: serde_json::Value) -> Result<InboxResult, Error> {
+    // Handlers only get the object of the activity, but a Move also needs its target
+    if let Ok(move_act) = serde_json::from_value::<Move>(act.clone()) {
+        return User::receive_move(ctx, move_act).map(InboxResult::from);
+    }
+
     Inbox::handle(ctx, act)
+        .with::<User, Accept, BlogInvitation>()
         .with::<User, Announce, Post>()
         .with::<User, Create, Comment>()
         .with::<User, Create, Post>()
@@ -56,6 +63,7 @@ pub fn inbox(ctx: &PlumeRocket, act: serde_json::Value) -> Result<InboxResult, E
         .with::<User, Delete, User>()
         .with::<User, Follow, User>()
         .with::<User, Like, Post>()
+        .with::<User, Reject, BlogInvitation>()
         .with::<User, Undo, Reshare>()
         .with::<User, Undo, follows::Follow>()
         .with::<User, Undo, likes::Like>()
@@ -97,6 +105,8 @@ pub(crate) mod tests {
                 subtitle: String::new(),
                 source: String::new(),
                 cover_id: None,
+                publish_at: None,
+                visibility: post_visibility::PUBLIC.to_owned(),
             },
             &rockets.searcher,
         )
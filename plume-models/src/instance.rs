@@ -1,13 +1,15 @@
 use chrono::NaiveDateTime;
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{self, BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
 use std::iter::Iterator;
 use std::sync::RwLock;
 
 use ap_url;
 use medias::Media;
 use plume_common::utils::md_to_html;
+use posts::Post;
 use safe_string::SafeString;
-use schema::{instances, users};
+use schema::{blogs, instances, post_authors, posts, users};
+use search::Searcher;
 use users::User;
 use {Connection, Error, Result};
 
@@ -25,6 +27,8 @@ pub struct Instance {
     pub default_license: String,
     pub long_description_html: SafeString,
     pub short_description_html: SafeString,
+    /// Whether administrators must use two-factor authentication
+    pub require_admin_2fa: bool,
 }
 
 #[derive(Clone, Insertable)]
@@ -92,12 +96,29 @@ impl Instance {
     get!(instances);
     find_by!(instances, find_by_domain, public_domain as &str);
 
-    pub fn toggle_block(&self, conn: &Connection) -> Result<()> {
+    /// Block or unblock this instance, and hide or show its posts in search results
+    pub fn toggle_block(&self, conn: &Connection, searcher: &Searcher) -> Result<()> {
         diesel::update(self)
             .set(instances::blocked.eq(!self.blocked))
-            .execute(conn)
-            .map(|_| ())
-            .map_err(Error::from)
+            .execute(conn)?;
+
+        let blogs = blogs::table
+            .filter(blogs::instance_id.eq(self.id))
+            .select(blogs::id);
+        let authors = users::table
+            .filter(users::instance_id.eq(self.id))
+            .select(users::id);
+        let authored = post_authors::table
+            .filter(post_authors::author_id.eq_any(authors))
+            .select(post_authors::post_id);
+        for post in posts::table
+            .filter(posts::published.eq(true))
+            .filter(posts::blog_id.eq_any(blogs).or(posts::id.eq_any(authored)))
+            .load::<Post>(conn)?
+        {
+            searcher.update_document(conn, &post)?;
+        }
+        Ok(())
     }
 
     /// id: AP object id
@@ -182,6 +203,16 @@ impl Instance {
         res
     }
 
+    pub fn set_require_admin_2fa(&self, conn: &Connection, required: bool) -> Result<()> {
+        diesel::update(self)
+            .set(instances::require_admin_2fa.eq(required))
+            .execute(conn)?;
+        if self.local {
+            Instance::cache_local(conn);
+        }
+        Ok(())
+    }
+
     pub fn count(conn: &Connection) -> Result<i64> {
         instances::table
             .count()
@@ -248,6 +279,7 @@ impl Instance {
 pub(crate) mod tests {
     use super::*;
     use diesel::Connection;
+    use search::tests::get_searcher;
     use tests::db;
     use Connection as Conn;
 
@@ -418,7 +450,7 @@ pub(crate) mod tests {
             let inst_list = &inst_list[1..];
 
             let blocked = inst.blocked;
-            inst.toggle_block(conn).unwrap();
+            inst.toggle_block(conn, &get_searcher()).unwrap();
             let inst = Instance::get(conn, inst.id).unwrap();
             assert_eq!(inst.blocked, !blocked);
             assert_eq!(
@@ -443,7 +475,7 @@ pub(crate) mod tests {
                     .unwrap_or(false)
             );
 
-            inst.toggle_block(conn).unwrap();
+            inst.toggle_block(conn, &get_searcher()).unwrap();
             let inst = Instance::get(conn, inst.id).unwrap();
             assert_eq!(inst.blocked, blocked);
             assert_eq!(
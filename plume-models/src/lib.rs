@@ -10,8 +10,10 @@ extern crate bcrypt;
 extern crate chrono;
 #[macro_use]
 extern crate diesel;
+extern crate exif;
 extern crate guid_create;
 extern crate heck;
+extern crate image;
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
@@ -24,18 +26,22 @@ extern crate plume_macro;
 extern crate reqwest;
 extern crate rocket;
 extern crate rocket_i18n;
+extern crate roxmltree;
 extern crate scheduled_thread_pool;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate serde_yaml;
 #[macro_use]
 extern crate tantivy;
 extern crate url;
 extern crate walkdir;
 extern crate webfinger;
+extern crate webp;
 extern crate whatlang;
+extern crate zip;
 
 use plume_common::activity_pub::inbox::InboxError;
 
@@ -144,6 +150,15 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => Error::Io(err),
+            _ => Error::InvalidValue,
+        }
+    }
+}
+
 impl From<InboxError<Error>> for Error {
     fn from(err: InboxError<Error>) -> Error {
         match err {
@@ -291,12 +306,23 @@ macro_rules! last {
 }
 
 mod config;
-pub use config::CONFIG;
+pub use config::{MediaStorage, SearchEngine, CONFIG};
 
 pub fn ap_url(url: &str) -> String {
     format!("https://{}", url)
 }
 
+/// A `LIKE` pattern matching the strings starting with `prefix`, to be used with `.escape('\\')`
+pub fn like_prefix(prefix: &str) -> String {
+    format!(
+        "{}%",
+        prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
 #[cfg(test)]
 #[macro_use]
 mod tests {
@@ -356,15 +382,20 @@ pub mod admin;
 pub mod api_tokens;
 pub mod apps;
 pub mod blog_authors;
+pub mod blog_invitations;
 pub mod blogs;
 pub mod comment_seers;
 pub mod comments;
 pub mod db_conn;
+pub mod fetch;
 pub mod follows;
 pub mod headers;
+pub mod import;
 pub mod inbox;
 pub mod instance;
 pub mod likes;
+pub mod media_albums;
+pub mod media_store;
 pub mod medias;
 pub mod mentions;
 pub mod migrations;
@@ -372,11 +403,20 @@ pub mod notifications;
 pub mod password_reset_requests;
 pub mod plume_rocket;
 pub mod post_authors;
+pub mod post_reviews;
+pub mod post_revisions;
+pub mod post_slug_history;
 pub mod posts;
 pub mod reshares;
 pub mod safe_string;
+pub mod saved_searches;
 pub mod schema;
 pub mod search;
+pub mod series;
+pub mod series_posts;
+pub mod sessions;
 pub mod tags;
+pub mod two_factor;
+pub mod user_exports;
 pub mod users;
 pub use plume_rocket::PlumeRocket;
@@ -0,0 +1,42 @@
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use schema::media_albums;
+use {Connection, Error, Result};
+
+/// A folder in which a user can organize their media
+#[derive(Clone, Queryable, Identifiable)]
+pub struct MediaAlbum {
+    pub id: i32,
+    pub owner_id: i32,
+    pub name: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "media_albums"]
+pub struct NewMediaAlbum {
+    pub owner_id: i32,
+    pub name: String,
+}
+
+impl MediaAlbum {
+    insert!(media_albums, NewMediaAlbum);
+    get!(media_albums);
+
+    pub fn list_for_user(conn: &Connection, owner: i32) -> Result<Vec<MediaAlbum>> {
+        media_albums::table
+            .filter(media_albums::owner_id.eq(owner))
+            .order(media_albums::name.asc())
+            .load::<MediaAlbum>(conn)
+            .map_err(Error::from)
+    }
+
+    /// Delete this album. The media it contains are kept.
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
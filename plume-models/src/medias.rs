@@ -1,24 +1,42 @@
-use activitypub::object::Image;
+use activitypub::{object::Image, CustomObject};
+use ammonia::Builder;
 use askama_escape::escape;
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    self, dsl::sum, BoolExpressionMethods, EscapeExpressionMethods, ExpressionMethods, QueryDsl,
+    RunQueryDsl, TextExpressionMethods,
+};
+use exif::{Reader, Tag};
 use guid_create::GUID;
-use reqwest;
-use std::{fs, path::Path};
+use image::{self, DynamicImage, FilterType, GenericImageView, ImageFormat, ImageOutputFormat};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read},
+    str,
+    sync::Mutex,
+};
+use url::Url;
+use webp::Encoder;
 
 use plume_common::{
-    activity_pub::{inbox::FromId, Id},
-    utils::MediaProcessor,
+    activity_pub::{inbox::FromId, Id, ImagePreview},
+    utils::{MediaInfo, MediaProcessor},
 };
 
+use blogs::Blog;
+use fetch::get_public;
 use instance::Instance;
+use media_store::MEDIA_STORE;
+use posts::Post;
 use safe_string::SafeString;
-use schema::medias;
+use schema::{blogs, medias, post_authors, posts, users};
 use users::User;
-use {ap_url, Connection, Error, PlumeRocket, Result};
+use {ap_url, like_prefix, Connection, Error, PlumeRocket, Result, CONFIG};
 
 #[derive(Clone, Identifiable, Queryable)]
 pub struct Media {
     pub id: i32,
+    /// The key of the file in the media store, for local media
     pub file_path: String,
     pub alt_text: String,
     pub is_remote: bool,
@@ -26,8 +44,33 @@ pub struct Media {
     pub sensitive: bool,
     pub content_warning: Option<String>,
     pub owner_id: i32,
+    /// Size of the picture, once it has been processed
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Comma-separated widths of the resized versions of the picture
+    pub variants: String,
+    /// Size of the uploaded file, or of the cached copy of a remote media, in bytes
+    pub size: i32,
+    /// When the copy of a remote media was downloaded, if there is one
+    pub cached_at: Option<NaiveDateTime>,
+    pub album_id: Option<i32>,
+    /// The name of the file when it was uploaded
+    pub original_name: String,
+    /// Length of a sound or of a video, in seconds
+    pub duration: Option<i32>,
+    /// A short description of the colors of the picture, to display while it loads
+    pub blurhash: Option<String>,
+    /// The point to keep visible when the picture is cropped, with coordinates between -1 and 1,
+    /// from the left and from the bottom
+    pub focal_x: Option<f32>,
+    pub focal_y: Option<f32>,
+    /// Size of the resized and WebP versions of the picture, in bytes
+    pub variants_size: i32,
 }
 
+/// A picture, with the properties used to preview and crop it
+pub type PreviewedImage = CustomObject<ImagePreview, Image>;
+
 #[derive(Insertable)]
 #[table_name = "medias"]
 pub struct NewMedia {
@@ -38,6 +81,9 @@ pub struct NewMedia {
     pub sensitive: bool,
     pub content_warning: Option<String>,
     pub owner_id: i32,
+    pub size: i32,
+    pub album_id: Option<i32>,
+    pub original_name: String,
 }
 
 #[derive(PartialEq)]
@@ -49,6 +95,15 @@ pub enum MediaCategory {
 }
 
 impl MediaCategory {
+    pub fn from_extension(ext: &str) -> MediaCategory {
+        match &*ext.to_lowercase() {
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" => MediaCategory::Image,
+            "mp3" | "wav" | "flac" | "ogg" | "m4a" => MediaCategory::Audio,
+            "mp4" | "avi" | "webm" | "mov" => MediaCategory::Video,
+            _ => MediaCategory::Unknown,
+        }
+    }
+
     pub fn to_string(&self) -> &str {
         match *self {
             MediaCategory::Image => "image",
@@ -59,6 +114,15 @@ impl MediaCategory {
     }
 }
 
+/// Widths of the resized versions of uploaded pictures, in pixels
+pub const IMAGE_WIDTHS: [u32; 3] = [320, 640, 1280];
+
+/// The maximum number of pixels of the pictures we decode, to resize them
+pub const MAX_IMAGE_PIXELS: u64 = 40_000_000;
+
+/// The `sizes` attribute of pictures in articles, matching the width of their content
+const IMAGE_SIZES: &str = "(max-width: 800px) 100vw, 800px";
+
 impl Media {
     insert!(medias, NewMedia);
     get!(medias);
@@ -75,13 +139,40 @@ impl Media {
         medias::table.load::<Media>(conn).map_err(Error::from)
     }
 
+    /// The media of a user, optionally only the ones of an album, or matching a search.
+    ///
+    /// The search looks for `query` in the description and the name of the files.
+    fn filtered<'a>(
+        user: &User,
+        album: Option<i32>,
+        query: Option<&str>,
+    ) -> medias::BoxedQuery<'a, <Connection as diesel::Connection>::Backend> {
+        let mut filtered = medias::table
+            .filter(medias::owner_id.eq(user.id))
+            .into_boxed();
+        if let Some(album) = album {
+            filtered = filtered.filter(medias::album_id.eq(album));
+        }
+        if let Some(query) = query.filter(|q| !q.is_empty()) {
+            let pattern = format!("%{}", like_prefix(query));
+            filtered = filtered.filter(
+                medias::alt_text
+                    .like(pattern.clone())
+                    .escape('\\')
+                    .or(medias::original_name.like(pattern).escape('\\')),
+            );
+        }
+        filtered
+    }
+
     pub fn page_for_user(
         conn: &Connection,
         user: &User,
+        album: Option<i32>,
+        query: Option<&str>,
         (min, max): (i32, i32),
     ) -> Result<Vec<Media>> {
-        medias::table
-            .filter(medias::owner_id.eq(user.id))
+        Media::filtered(user, album, query)
             .order(medias::id.desc())
             .offset(i64::from(min))
             .limit(i64::from(max - min))
@@ -89,32 +180,67 @@ impl Media {
             .map_err(Error::from)
     }
 
-    pub fn count_for_user(conn: &Connection, user: &User) -> Result<i64> {
-        medias::table
-            .filter(medias::owner_id.eq(user.id))
+    pub fn count_for_user(
+        conn: &Connection,
+        user: &User,
+        album: Option<i32>,
+        query: Option<&str>,
+    ) -> Result<i64> {
+        Media::filtered(user, album, query)
             .count()
             .get_result(conn)
             .map_err(Error::from)
     }
 
+    /// The space used by the media of a user, including the resized versions of their
+    /// pictures, in bytes
+    pub fn used_space(conn: &Connection, owner: i32) -> Result<i64> {
+        medias::table
+            .filter(medias::owner_id.eq(owner))
+            .select(sum(medias::size + medias::variants_size))
+            .first::<Option<i64>>(conn)
+            .map(Option::unwrap_or_default)
+            .map_err(Error::from)
+    }
+
     pub fn category(&self) -> MediaCategory {
-        match &*self
-            .file_path
-            .rsplitn(2, '.')
-            .next()
-            .expect("Media::category: extension error")
-            .to_lowercase()
-        {
-            "png" | "jpg" | "jpeg" | "gif" | "svg" => MediaCategory::Image,
-            "mp3" | "wav" | "flac" => MediaCategory::Audio,
-            "mp4" | "avi" | "webm" | "mov" => MediaCategory::Video,
-            _ => MediaCategory::Unknown,
-        }
+        MediaCategory::from_extension(&self.extension())
+    }
+
+    /// The extension of the file, or of the remote URL if it is not cached.
+    fn extension(&self) -> String {
+        let path = if self.file_path.is_empty() {
+            self.remote_url
+                .as_ref()
+                .and_then(|url| Url::parse(url).ok())
+                .map(|url| url.path().to_owned())
+                .unwrap_or_default()
+        } else {
+            self.file_path.clone()
+        };
+        let name = path.rsplit('/').next().unwrap_or_default();
+        name.rfind('.')
+            .map(|i| name[i + 1..].to_lowercase())
+            .unwrap_or_default()
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        mime_type(&self.extension())
     }
 
     pub fn html(&self) -> Result<SafeString> {
         let url = self.url()?;
         Ok(match self.category() {
+            MediaCategory::Image if !self.variants.is_empty() => SafeString::trusted(&format!(
+                r#"<picture><source type="image/webp" srcset="{}" sizes="{}"><img src="{}" srcset="{}" sizes="{}" alt="{}" title="{}"></picture>"#,
+                self.srcset(Some("webp"))?,
+                IMAGE_SIZES,
+                url,
+                self.srcset(None)?,
+                IMAGE_SIZES,
+                escape(&self.alt_text),
+                escape(&self.alt_text)
+            )),
             MediaCategory::Image => SafeString::trusted(&format!(
                 r#"<img src="{}" alt="{}" title="{}">"#,
                 url, escape(&self.alt_text), escape(&self.alt_text)
@@ -136,29 +262,282 @@ impl Media {
 
     pub fn markdown(&self) -> Result<SafeString> {
         Ok(match self.category() {
-            MediaCategory::Image => {
+            // sounds and videos are displayed with the right element by `get_media_processor`
+            MediaCategory::Image | MediaCategory::Audio | MediaCategory::Video => {
                 SafeString::new(&format!("![{}]({})", escape(&self.alt_text), self.id))
             }
-            MediaCategory::Audio | MediaCategory::Video => self.html()?,
             MediaCategory::Unknown => SafeString::new(""),
         })
     }
 
     pub fn url(&self) -> Result<String> {
         if self.is_remote {
-            Ok(self.remote_url.clone().unwrap_or_default())
-        } else {
+            // remote media are served through our proxy, that keeps a copy of them
             Ok(ap_url(&format!(
-                "{}/{}",
+                "{}/medias/{}/proxy",
                 Instance::get_local()?.public_domain,
-                self.file_path
+                self.id
             )))
+        } else {
+            file_url(self.key())
         }
     }
 
-    pub fn delete(&self, conn: &Connection) -> Result<()> {
+    /// The widths of the resized versions of this picture
+    pub fn widths(&self) -> Vec<u32> {
+        self.variants
+            .split(',')
+            .filter_map(|w| w.parse().ok())
+            .collect()
+    }
+
+    /// The key of a version of this picture, resized to `width` if it is given, and
+    /// re-encoded to `ext` if it is given
+    fn variant_key(&self, width: Option<u32>, ext: Option<&str>) -> String {
+        let key = self.key();
+        let (stem, original_ext) = match key.rfind('.') {
+            Some(i) => (&key[..i], &key[i + 1..]),
+            None => (key, ""),
+        };
+        let ext = ext.unwrap_or(original_ext);
+        match width {
+            Some(width) => format!("{}-{}.{}", stem, width, ext),
+            None => format!("{}.{}", stem, ext),
+        }
+    }
+
+    /// The `srcset` of this picture, with its original format or `ext`
+    pub fn srcset(&self, ext: Option<&str>) -> Result<String> {
+        let mut sources = self
+            .widths()
+            .into_iter()
+            .map(|w| {
+                Ok(format!(
+                    "{} {}w",
+                    file_url(&self.variant_key(Some(w), ext))?,
+                    w
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(width) = self.width {
+            sources.push(format!(
+                "{} {}w",
+                file_url(&self.variant_key(None, ext))?,
+                width
+            ));
+        }
+        Ok(sources.join(", "))
+    }
+
+    /// The URL of the smallest version of this media, to use as a thumbnail
+    pub fn thumbnail_url(&self) -> Result<String> {
+        match self.widths().into_iter().next() {
+            Some(width) if !self.is_remote => file_url(&self.variant_key(Some(width), None)),
+            _ => self.url(),
+        }
+    }
+
+    /// Apply the EXIF orientation of an uploaded picture, and save resized and WebP versions
+    /// of it. Its other metadata have already been removed by `validate_upload`.
+    ///
+    /// This may take a while, and should be done in the worker pool.
+    pub fn process_image(&self, conn: &Connection) -> Result<()> {
+        if self.is_remote || self.category() != MediaCategory::Image {
+            return Err(Error::InvalidValue);
+        }
+        let content = self.read_file()?;
+        let format = match image::guess_format(&content) {
+            Ok(format @ ImageFormat::JPEG) | Ok(format @ ImageFormat::PNG) => format,
+            // animated GIFs and SVGs are kept as they are
+            _ => return Ok(()),
+        };
+        match image_dimensions(&content, &self.extension()) {
+            Some((width, height)) if u64::from(width) * u64::from(height) <= MAX_IMAGE_PIXELS => {}
+            _ => return Err(Error::InvalidValue),
+        }
+        let picture = image::load_from_memory_with_format(&content, format)
+            .map_err(|_| Error::InvalidValue)?;
+        let picture = match exif_orientation(&content) {
+            2 => picture.fliph(),
+            3 => picture.rotate180(),
+            4 => picture.flipv(),
+            5 => picture.rotate90().fliph(),
+            6 => picture.rotate90(),
+            7 => picture.rotate270().fliph(),
+            8 => picture.rotate270(),
+            _ => picture,
+        };
+        let (width, height) = picture.dimensions();
+
+        // encoding the picture again drops the orientation, that has been applied
+        let original = encode(&picture, format)?;
+        MEDIA_STORE.save(self.key(), &original)?;
+        let webp = encode_webp(&picture);
+        MEDIA_STORE.save(&self.variant_key(None, Some("webp")), &webp)?;
+        let mut variants_size = webp.len();
+        let mut widths = vec![];
+        for w in IMAGE_WIDTHS.iter().cloned().filter(|w| *w < width) {
+            let resized = picture.resize(w, height, FilterType::Lanczos3);
+            let variant = encode(&resized, format)?;
+            MEDIA_STORE.save(&self.variant_key(Some(w), None), &variant)?;
+            let webp = encode_webp(&resized);
+            MEDIA_STORE.save(&self.variant_key(Some(w), Some("webp")), &webp)?;
+            variants_size += variant.len() + webp.len();
+            widths.push(w.to_string());
+        }
+
+        diesel::update(self)
+            .set((
+                medias::width.eq(width as i32),
+                medias::height.eq(height as i32),
+                medias::variants.eq(widths.join(",")),
+                medias::size.eq(original.len() as i32),
+                medias::variants_size.eq(variants_size as i32),
+                medias::blurhash.eq(blurhash(&picture)),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// The key of the file of this local media in the media store
+    pub fn key(&self) -> &str {
+        // media saved before the stores were introduced have their full path in `static/media`
+        self.file_path.trim_start_matches("static/media/")
+    }
+
+    /// Save the file of a new media in the media store, and return its key
+    pub fn save_file(content: &[u8], ext: &str) -> Result<String> {
+        let key = if ext.is_empty() {
+            GUID::rand().to_string()
+        } else {
+            format!("{}.{}", GUID::rand().to_string(), ext)
+        };
+        MEDIA_STORE.save(&key, content)?;
+        Ok(key)
+    }
+
+    /// Check that `content` can be uploaded by `owner`.
+    ///
+    /// The type of the file is detected from its content, and must be one of the formats
+    /// we can display. SVG files are sanitized, to remove scripts and external references.
+    /// Returns the content to save, and its extension.
+    pub fn validate_upload(
+        conn: &Connection,
+        owner: i32,
+        content: Vec<u8>,
+    ) -> std::result::Result<(Vec<u8>, &'static str), UploadError> {
+        let (content, ext) = check_content(content)?;
+        if let Some(quota) = CONFIG.media.user_quota {
+            let used = Media::used_space(conn, owner)? as u64;
+            if used + content.len() as u64 > quota {
+                return Err(UploadError::QuotaExceeded(quota));
+            }
+        }
+        Ok((content, ext))
+    }
+
+    pub fn read_file(&self) -> Result<Vec<u8>> {
+        MEDIA_STORE.read(self.key())
+    }
+
+    /// Whether there is a copy of this remote media that didn't expire yet
+    pub fn is_cached(&self) -> bool {
+        let ttl = Duration::seconds(CONFIG.media.remote_cache_ttl as i64);
+        self.cached_at
+            .map(|date| date > Utc::now().naive_utc() - ttl)
+            .unwrap_or(false)
+    }
+
+    /// The content of the copy of a remote media, and its key in the store, if there is
+    /// a valid one
+    pub fn cached_copy(&self) -> Option<(String, Vec<u8>)> {
+        if !self.is_remote || !self.is_cached() {
+            return None;
+        }
+        let content = MEDIA_STORE.read(self.key()).ok()?;
+        Some((self.key().to_owned(), content))
+    }
+
+    /// Download a copy of a remote media, from a public address only.
+    ///
+    /// Returns `None` if it is already being downloaded. The copy still has to be saved,
+    /// which needs a connection to the database: none is used during the download.
+    pub fn download_remote(&self) -> Result<Option<RemoteDownload>> {
         if !self.is_remote {
-            fs::remove_file(self.file_path.as_str())?;
+            return Err(Error::InvalidValue);
+        }
+        let url = self.remote_url.clone()?;
+        let mut download = match RemoteDownload::start(self.id) {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+
+        let mut content = vec![];
+        get_public(&url)?
+            .take(CONFIG.media.max_file_size() + 1)
+            .read_to_end(&mut content)?;
+        let (content, ext) = check_content(content).map_err(|_| Error::InvalidValue)?;
+        if MediaCategory::from_extension(ext) == MediaCategory::Unknown {
+            return Err(Error::InvalidValue);
+        }
+        download.content = content;
+        download.ext = ext;
+        Ok(Some(download))
+    }
+
+    /// Delete the copy of a remote media. It will be downloaded again when it is needed.
+    fn evict(&self, conn: &Connection) -> Result<()> {
+        if !self.is_remote || self.file_path.is_empty() {
+            return Ok(());
+        }
+        MEDIA_STORE.delete(self.key())?;
+        diesel::update(self)
+            .set((
+                medias::file_path.eq(""),
+                medias::size.eq(0),
+                medias::cached_at.eq(None::<NaiveDateTime>),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Delete the copies of remote media that expired, and the oldest ones if they use more
+    /// space than allowed. Returns the number of deleted copies.
+    pub fn prune_remote(conn: &Connection) -> Result<usize> {
+        let cached = medias::table
+            .filter(medias::is_remote.eq(true))
+            .filter(medias::cached_at.is_not_null())
+            .order(medias::cached_at.desc())
+            .load::<Media>(conn)?;
+        let (mut total, mut evicted) = (0, 0);
+        for media in cached {
+            let size = media.size as u64;
+            if !media.is_cached() || total + size > CONFIG.media.remote_cache_size {
+                media.evict(conn)?;
+                evicted += 1;
+            } else {
+                total += size;
+            }
+        }
+        Ok(evicted)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        if self.is_remote {
+            if !self.file_path.is_empty() {
+                MEDIA_STORE.delete(self.key())?;
+            }
+        } else {
+            MEDIA_STORE.delete(self.key())?;
+            if self.width.is_some() {
+                MEDIA_STORE.delete(&self.variant_key(None, Some("webp")))?;
+            }
+            for width in self.widths() {
+                MEDIA_STORE.delete(&self.variant_key(Some(width), None))?;
+                MEDIA_STORE.delete(&self.variant_key(Some(width), Some("webp")))?;
+            }
         }
         diesel::delete(self)
             .execute(conn)
@@ -180,11 +559,169 @@ impl Media {
                     sensitive: false,
                     content_warning: None,
                     owner_id: user.id,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
             )
         }
     }
 
+    pub fn set_album(&self, conn: &Connection, album: Option<i32>) -> Result<()> {
+        diesel::update(self)
+            .set(medias::album_id.eq(album))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Add a content warning to this media, or remove it if `cw` is `None`
+    pub fn set_content_warning(&self, conn: &Connection, cw: Option<String>) -> Result<()> {
+        diesel::update(self)
+            .set((
+                medias::sensitive.eq(cw.is_some()),
+                medias::content_warning.eq(cw),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn focal_point(&self) -> Option<(f32, f32)> {
+        match (self.focal_x, self.focal_y) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    /// The focal point in percents of the size of the picture, from the left and from the top
+    pub fn focal_percents(&self) -> Option<(f32, f32)> {
+        let percents = |v: f32| (v * 500.0).round() / 10.0;
+        self.focal_point()
+            .map(|(x, y)| (percents(x + 1.0), percents(1.0 - y)))
+    }
+
+    /// The focal point as a CSS position, for `object-position` or `background-position`
+    pub fn focal_position(&self) -> Option<String> {
+        self.focal_percents().map(|(x, y)| format!("{}% {}%", x, y))
+    }
+
+    /// The CSS style of an element displaying this picture as a cover
+    pub fn cover_style(&self) -> String {
+        match self.focal_position() {
+            Some(position) => format!(
+                "object-position: {pos}; background-position: {pos};",
+                pos = position
+            ),
+            None => String::new(),
+        }
+    }
+
+    pub fn set_focal_point(&self, conn: &Connection, point: Option<(f32, f32)>) -> Result<()> {
+        let point = point.map(|(x, y)| (x.max(-1.0).min(1.0), y.max(-1.0).min(1.0)));
+        diesel::update(self)
+            .set((
+                medias::focal_x.eq(point.map(|p| p.0)),
+                medias::focal_y.eq(point.map(|p| p.1)),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// The properties of this picture that federate its blurhash and its focal point
+    pub fn preview_props(&self) -> ImagePreview {
+        ImagePreview {
+            blurhash: self.blurhash.clone().map(serde_json::Value::String),
+            focal_point: self
+                .focal_point()
+                .map(|(x, y)| vec![f64::from(x), f64::from(y)]),
+        }
+    }
+
+    /// Read the length of a sound or of a video from its content, and save it
+    pub fn detect_duration(&self, conn: &Connection, content: &[u8]) -> Result<()> {
+        let duration = match self.category() {
+            MediaCategory::Audio | MediaCategory::Video => {
+                media_duration(content, &self.extension())
+            }
+            _ => None,
+        };
+        if duration.is_none() {
+            return Ok(());
+        }
+        diesel::update(self)
+            .set(medias::duration.eq(duration))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// This media as an attachment of a post, with the type remote software should
+    /// display it as
+    pub fn to_attachment(&self) -> Result<serde_json::Value> {
+        let kind = match self.category() {
+            MediaCategory::Audio => "Audio",
+            MediaCategory::Video => "Video",
+            _ => "Document",
+        };
+        let mut attachment = json!({
+            "type": kind,
+            "url": self.url()?,
+            "mediaType": self.mime_type(),
+            "name": self.alt_text,
+        });
+        if self.size > 0 {
+            attachment["size"] = json!(self.size);
+        }
+        if let Some(duration) = self.duration {
+            attachment["duration"] = json!(format!("PT{}S", duration));
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            attachment["width"] = json!(width);
+            attachment["height"] = json!(height);
+        }
+        if let Some(ref blurhash) = self.blurhash {
+            attachment["blurhash"] = json!(blurhash);
+        }
+        if let Some((x, y)) = self.focal_point() {
+            attachment["focalPoint"] = json!([x, y]);
+        }
+        if self.sensitive {
+            attachment["summary"] = json!(self.content_warning.clone().unwrap_or_default());
+        }
+        Ok(attachment)
+    }
+
+    /// The places where this media is used
+    pub fn usage(&self, conn: &Connection) -> Result<MediaUsage> {
+        // only the authors can insert a media in their posts, see `get_media_processor`
+        let in_content = posts::table
+            .inner_join(post_authors::table)
+            .filter(post_authors::author_id.eq(self.owner_id))
+            .filter(
+                posts::source
+                    .like(format!("%]({})%", self.id))
+                    .or(posts::source.like(format!("%]({} %", self.id))),
+            )
+            .select(posts::all_columns)
+            .order(posts::id.desc())
+            .load::<Post>(conn)?;
+        Ok(MediaUsage {
+            posts: in_content,
+            covers: posts::table
+                .filter(posts::cover_id.eq(self.id))
+                .order(posts::id.desc())
+                .load::<Post>(conn)?,
+            avatars: users::table
+                .filter(users::avatar_id.eq(self.id))
+                .load::<User>(conn)?,
+            blogs: blogs::table
+                .filter(blogs::icon_id.eq(self.id).or(blogs::banner_id.eq(self.id)))
+                .load::<Blog>(conn)?,
+        })
+    }
+
     pub fn set_owner(&self, conn: &Connection, user: &User) -> Result<()> {
         diesel::update(self)
             .set(medias::owner_id.eq(user.id))
@@ -194,49 +731,56 @@ impl Media {
     }
 
     // TODO: merge with save_remote?
-    pub fn from_activity(c: &PlumeRocket, image: &Image) -> Result<Media> {
+    pub fn from_activity(c: &PlumeRocket, image: &PreviewedImage) -> Result<Media> {
         let conn = &*c.conn;
+        let preview = &image.custom_props;
+        let image = &image.object;
         let remote_url = image.object_props.url_string().ok()?;
-        let ext = remote_url
-            .rsplit('.')
-            .next()
-            .map(ToOwned::to_owned)
-            .unwrap_or_else(|| String::from("png"));
-        let path =
-            Path::new("static")
-                .join("media")
-                .join(format!("{}.{}", GUID::rand().to_string(), ext));
-
-        let mut dest = fs::File::create(path.clone()).ok()?;
-        reqwest::get(remote_url.as_str())
-            .ok()?
-            .copy_to(&mut dest)
-            .ok()?;
-
-        Media::insert(
+        let owner_id = User::from_id(
+            c,
+            image
+                .object_props
+                .attributed_to_link_vec::<Id>()
+                .ok()?
+                .into_iter()
+                .next()?
+                .as_ref(),
+            None,
+        )
+        .map_err(|(_, e)| e)?
+        .id;
+
+        let media = Media::insert(
             conn,
             NewMedia {
-                file_path: path.to_str()?.to_string(),
+                file_path: String::new(),
                 alt_text: image.object_props.content_string().ok()?,
-                is_remote: false,
-                remote_url: None,
+                is_remote: true,
+                remote_url: Some(remote_url),
                 sensitive: image.object_props.summary_string().is_ok(),
                 content_warning: image.object_props.summary_string().ok(),
-                owner_id: User::from_id(
-                    c,
-                    image
-                        .object_props
-                        .attributed_to_link_vec::<Id>()
-                        .ok()?
-                        .into_iter()
-                        .next()?
-                        .as_ref(),
-                    None,
-                )
-                .map_err(|(_, e)| e)?
-                .id,
+                owner_id,
+                size: 0,
+                album_id: None,
+                original_name: String::new(),
             },
-        )
+        )?;
+
+        let blurhash = preview
+            .blurhash_string()
+            .ok()
+            .filter(|hash| is_blurhash(hash));
+        if blurhash.is_some() {
+            diesel::update(&media)
+                .set(medias::blurhash.eq(blurhash))
+                .execute(conn)?;
+        }
+        if let Some(ref point) = preview.focal_point {
+            if let [x, y] = point.as_slice() {
+                media.set_focal_point(conn, Some((*x as f32, *y as f32)))?;
+            }
+        }
+        Media::get(conn, media.id)
     }
 
     pub fn get_media_processor<'a>(conn: &'a Connection, user: Vec<&User>) -> MediaProcessor<'a> {
@@ -245,7 +789,26 @@ impl Media {
             let media = Media::get(conn, id).ok()?;
             // if owner is user or check is disabled
             if uid.contains(&media.owner_id) || uid.is_empty() {
-                Some((media.url().ok()?, media.content_warning))
+                let attrs = if media.variants.is_empty() {
+                    None
+                } else {
+                    Some(format!(
+                        r#"srcset="{}" sizes="{}""#,
+                        media.srcset(None).ok()?,
+                        IMAGE_SIZES
+                    ))
+                };
+                let tag = match media.category() {
+                    MediaCategory::Audio => "audio",
+                    MediaCategory::Video => "video",
+                    _ => "img",
+                };
+                Some(MediaInfo {
+                    url: media.url().ok()?,
+                    content_warning: media.content_warning,
+                    attrs,
+                    tag,
+                })
             } else {
                 None
             }
@@ -253,10 +816,687 @@ impl Media {
     }
 }
 
+lazy_static! {
+    /// The ids of the remote media that are being downloaded
+    static ref DOWNLOADS: Mutex<HashSet<i32>> = Mutex::new(HashSet::new());
+}
+
+/// A copy of a remote media, that has been downloaded but not saved yet.
+///
+/// While it exists, the same media can't be downloaded again.
+pub struct RemoteDownload {
+    media_id: i32,
+    content: Vec<u8>,
+    ext: &'static str,
+}
+
+impl RemoteDownload {
+    fn start(media_id: i32) -> Option<RemoteDownload> {
+        if DOWNLOADS.lock().ok()?.insert(media_id) {
+            Some(RemoteDownload {
+                media_id,
+                content: vec![],
+                ext: "",
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Save this copy in the media store, replacing the previous one
+    pub fn save(self, conn: &Connection) -> Result<()> {
+        let media = Media::get(conn, self.media_id)?;
+        // another copy may have been saved since this download started
+        if media.is_cached() {
+            return Ok(());
+        }
+        media.evict(conn)?;
+        let key = Media::save_file(&self.content, self.ext)?;
+        diesel::update(&media)
+            .set((
+                medias::file_path.eq(&key),
+                medias::size.eq(self.content.len() as i32),
+                medias::cached_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+impl Drop for RemoteDownload {
+    fn drop(&mut self) {
+        if let Ok(mut downloads) = DOWNLOADS.lock() {
+            downloads.remove(&self.media_id);
+        }
+    }
+}
+
+/// The places where a media is used
+pub struct MediaUsage {
+    /// Posts including it in their content
+    pub posts: Vec<Post>,
+    /// Posts using it as their cover
+    pub covers: Vec<Post>,
+    /// Users using it as their avatar
+    pub avatars: Vec<User>,
+    /// Blogs using it as their icon or banner
+    pub blogs: Vec<Blog>,
+}
+
+impl MediaUsage {
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty()
+            && self.covers.is_empty()
+            && self.avatars.is_empty()
+            && self.blogs.is_empty()
+    }
+}
+
+/// Why an uploaded file was refused
+#[derive(Debug, PartialEq)]
+pub enum UploadError {
+    /// The file is not in one of the formats we accept
+    UnsupportedType,
+    /// The file is bigger than the limit for its category, in bytes
+    TooLarge(u64),
+    /// The file would make its owner use more than their quota, in bytes
+    QuotaExceeded(u64),
+    /// The picture has more pixels than we can process
+    TooManyPixels(u64),
+    Internal,
+}
+
+impl From<Error> for UploadError {
+    fn from(_: Error) -> Self {
+        UploadError::Internal
+    }
+}
+
+/// Check that the type of a file is one we accept, and that it is not too big.
+/// Returns the content to save, with SVGs sanitized and the metadata of JPEG, PNG and WebP
+/// pictures removed, and its extension.
+fn check_content(content: Vec<u8>) -> std::result::Result<(Vec<u8>, &'static str), UploadError> {
+    let ext = sniff(&content).ok_or(UploadError::UnsupportedType)?;
+    let content = match ext {
+        "svg" => sanitize_svg(&content).ok_or(UploadError::UnsupportedType)?,
+        "jpg" | "png" => {
+            let (width, height) =
+                image_dimensions(&content, ext).ok_or(UploadError::UnsupportedType)?;
+            if u64::from(width) * u64::from(height) > MAX_IMAGE_PIXELS {
+                return Err(UploadError::TooManyPixels(MAX_IMAGE_PIXELS));
+            }
+            strip_metadata(&content, ext).ok_or(UploadError::UnsupportedType)?
+        }
+        "webp" => strip_metadata(&content, ext).ok_or(UploadError::UnsupportedType)?,
+        _ => content,
+    };
+
+    let limit = CONFIG.media.max_size(&MediaCategory::from_extension(ext));
+    if content.len() as u64 > limit {
+        return Err(UploadError::TooLarge(limit));
+    }
+    Ok((content, ext))
+}
+
+/// Detect the type of a file from its first bytes, and return the extension to save it with,
+/// if it is one of the formats we accept
+fn sniff(content: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| content.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| content.get(offset..offset + magic.len()) == Some(magic);
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if starts(b"\xff\xd8\xff") {
+        Some("jpg")
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Some("gif")
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        Some("webp")
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        Some("wav")
+    } else if starts(b"ID3")
+        || (content.len() > 1 && content[0] == 0xff && content[1] & 0xe0 == 0xe0)
+    {
+        Some("mp3")
+    } else if starts(b"fLaC") {
+        Some("flac")
+    } else if starts(b"OggS") {
+        Some("ogg")
+    } else if at(4, b"ftyp") {
+        // HEIF and AVIF pictures use the same container as videos, their major brand tells
+        // them apart
+        match content.get(8..12)? {
+            b"M4A " => Some("m4a"),
+            b"qt  " => Some("mov"),
+            b"isom" | b"iso2" | b"avc1" | b"M4V " | b"dash" => Some("mp4"),
+            brand if brand.starts_with(b"mp4") => Some("mp4"),
+            _ => None,
+        }
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        Some("webm")
+    } else if is_svg(content) {
+        Some("svg")
+    } else {
+        None
+    }
+}
+
+/// Read the length in seconds of a sound or of a video from the headers of its format
+fn media_duration(content: &[u8], ext: &str) -> Option<i32> {
+    let bytes = |offset: usize, len: usize| content.get(offset..offset + len);
+    let u32_le = |offset: usize| {
+        bytes(offset, 4).map(|b| {
+            u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24
+        })
+    };
+    let u32_be = |offset: usize| {
+        bytes(offset, 4).map(|b| {
+            u32::from(b[3]) | u32::from(b[2]) << 8 | u32::from(b[1]) << 16 | u32::from(b[0]) << 24
+        })
+    };
+    let find = |magic: &[u8], from: usize| {
+        content
+            .get(from..)?
+            .windows(magic.len())
+            .position(|w| w == magic)
+            .map(|i| i + from)
+    };
+
+    let seconds = match ext {
+        "wav" => {
+            // a list of chunks follows the RIFF header: the format one gives the number of
+            // bytes per second, and the data one the number of bytes
+            let mut offset = 12;
+            let mut byte_rate = None;
+            let mut data_size = None;
+            while let (Some(id), Some(size)) = (bytes(offset, 4), u32_le(offset + 4)) {
+                match id {
+                    b"fmt " => byte_rate = u32_le(offset + 16),
+                    b"data" => data_size = Some(size),
+                    _ => {}
+                }
+                offset += 8 + size as usize + size as usize % 2;
+            }
+            f64::from(data_size?) / f64::from(byte_rate.filter(|r| *r > 0)?)
+        }
+        "flac" => {
+            // the STREAMINFO block always comes first
+            let info = bytes(8, 18)?;
+            let rate =
+                u32::from(info[10]) << 12 | u32::from(info[11]) << 4 | u32::from(info[12]) >> 4;
+            let samples = u64::from(info[13] & 0x0f) << 32 | u64::from(u32_be(8 + 14)?);
+            if rate == 0 {
+                return None;
+            }
+            samples as f64 / f64::from(rate)
+        }
+        "ogg" => {
+            let rate = if let Some(i) = find(b"\x01vorbis", 0) {
+                u32_le(i + 12)?
+            } else if find(b"OpusHead", 0).is_some() {
+                // Opus timestamps always use this rate
+                48_000
+            } else {
+                return None;
+            };
+            // the position of the last page is the number of samples
+            let last = content.windows(4).rposition(|w| w == b"OggS")?;
+            let granule = u64::from(u32_le(last + 6)?) | u64::from(u32_le(last + 10)?) << 32;
+            if rate == 0 {
+                return None;
+            }
+            granule as f64 / f64::from(rate)
+        }
+        "mp4" | "m4a" | "mov" => {
+            // the movie header box gives the duration in its own time scale
+            let header = find(b"mvhd", 0)? + 4;
+            let (scale, duration) = if *content.get(header)? == 1 {
+                let high = u64::from(u32_be(header + 24)?);
+                (
+                    u32_be(header + 20)?,
+                    high << 32 | u64::from(u32_be(header + 28)?),
+                )
+            } else {
+                (u32_be(header + 12)?, u64::from(u32_be(header + 16)?))
+            };
+            if scale == 0 {
+                return None;
+            }
+            duration as f64 / f64::from(scale)
+        }
+        "webm" => {
+            // Matroska stores it as a float, counted in units of the timecode scale
+            let scale = find(b"\x2a\xd7\xb1", 0)
+                .and_then(|i| {
+                    let len = (*content.get(i + 3)? & 0x7f) as usize;
+                    Some(
+                        bytes(i + 4, len)?
+                            .iter()
+                            .fold(0u64, |n, b| n << 8 | u64::from(*b)),
+                    )
+                })
+                .unwrap_or(1_000_000);
+            let i = find(b"\x44\x89", 0)?;
+            let duration = match *content.get(i + 2)? {
+                0x84 => f64::from(f32::from_bits(u32_be(i + 3)?)),
+                0x88 => f64::from_bits(u64::from(u32_be(i + 3)?) << 32 | u64::from(u32_be(i + 7)?)),
+                _ => return None,
+            };
+            duration * scale as f64 / 1_000_000_000.0
+        }
+        "mp3" => {
+            // skip the ID3 tag, its size is stored on 7 bits per byte
+            let start = if content.starts_with(b"ID3") {
+                10 + bytes(6, 4)?
+                    .iter()
+                    .fold(0usize, |n, b| n << 7 | (b & 0x7f) as usize)
+            } else {
+                0
+            };
+            let header = bytes(start, 4)?;
+            if header[0] != 0xff || header[1] & 0xe0 != 0xe0 || (header[1] >> 1) & 3 != 1 {
+                // only MPEG layer III is supported
+                return None;
+            }
+            let mpeg1 = (header[1] >> 3) & 3 == 3;
+            let bitrates: [u32; 15] = if mpeg1 {
+                [
+                    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+                ]
+            } else {
+                [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]
+            };
+            let rates: [u32; 3] = match (header[1] >> 3) & 3 {
+                3 => [44_100, 48_000, 32_000],
+                2 => [22_050, 24_000, 16_000],
+                _ => [11_025, 12_000, 8_000],
+            };
+            let bitrate = *bitrates.get((header[2] >> 4) as usize)? * 1000;
+            let rate = *rates.get(((header[2] >> 2) & 3) as usize)?;
+            let samples_per_frame = if mpeg1 { 1152 } else { 576 };
+
+            // variable bitrate files count their frames in a Xing header
+            let frames = find(b"Xing", start)
+                .or_else(|| find(b"Info", start))
+                .filter(|i| *i < start + 64)
+                .and_then(|i| {
+                    if u32_be(i + 4)? & 1 == 1 {
+                        u32_be(i + 8)
+                    } else {
+                        None
+                    }
+                });
+            match frames {
+                Some(frames) => f64::from(frames) * f64::from(samples_per_frame) / f64::from(rate),
+                None if bitrate > 0 => (content.len() - start) as f64 * 8.0 / f64::from(bitrate),
+                None => return None,
+            }
+        }
+        _ => return None,
+    };
+    if seconds.is_finite() && seconds >= 0.0 && seconds < f64::from(i32::max_value()) {
+        Some(seconds.round() as i32)
+    } else {
+        None
+    }
+}
+
+fn is_svg(content: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&content[..content.len().min(1024)]).to_lowercase();
+    head.trim_start().starts_with('<') && head.contains("<svg")
+}
+
+lazy_static! {
+    /// Only keeps the elements and attributes needed to draw a picture: no scripts, styles,
+    /// event handlers or links to other documents.
+    static ref SVG_CLEANER: Builder<'static> = {
+        let mut b = Builder::new();
+        b.tags(
+            [
+                "svg", "g", "defs", "title", "desc", "path", "rect", "circle", "ellipse",
+                "line", "polyline", "polygon", "text", "tspan", "linearGradient",
+                "radialGradient", "stop", "clipPath", "mask",
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .generic_attributes(
+            [
+                "xmlns", "version", "id", "width", "height", "viewBox", "preserveAspectRatio",
+                "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry", "d", "points",
+                "transform", "opacity", "fill", "fill-opacity", "fill-rule", "stroke",
+                "stroke-width", "stroke-opacity", "stroke-linecap", "stroke-linejoin",
+                "stroke-dasharray", "stroke-miterlimit", "offset", "stop-color", "stop-opacity",
+                "gradientUnits", "gradientTransform", "clip-path", "clip-rule", "mask",
+                "font-family", "font-size", "font-weight", "text-anchor",
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .tag_attributes(HashMap::new())
+        .link_rel(None);
+        b
+    };
+}
+
+fn sanitize_svg(content: &[u8]) -> Option<Vec<u8>> {
+    let svg = SVG_CLEANER.clean(str::from_utf8(content).ok()?).to_string();
+    if svg.trim_start().starts_with("<svg") {
+        Some(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", svg).into_bytes())
+    } else {
+        None
+    }
+}
+
+/// The public URL of a file of the media store
+fn file_url(key: &str) -> Result<String> {
+    if let Some(ref prefix) = CONFIG.media.url_prefix {
+        Ok(format!("{}/{}", prefix, key))
+    } else if let Some(url) = MEDIA_STORE.url(key) {
+        Ok(url)
+    } else {
+        Ok(ap_url(&format!(
+            "{}/static/media/{}",
+            Instance::get_local()?.public_domain,
+            key
+        )))
+    }
+}
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+fn is_blurhash(hash: &str) -> bool {
+    hash.len() >= 6 && hash.len() <= 100 && hash.bytes().all(|c| BASE83.contains(&c))
+}
+
+/// Compute the blurhash of a picture (see <https://blurha.sh>), with 4×3 components
+fn blurhash(picture: &DynamicImage) -> String {
+    const COMPONENTS_X: usize = 4;
+    const COMPONENTS_Y: usize = 3;
+
+    fn to_linear(value: u8) -> f32 {
+        let v = f32::from(value) / 255.0;
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn to_srgb(value: f32) -> u32 {
+        let v = value.max(0.0).min(1.0);
+        if v <= 0.003_130_8 {
+            (v * 12.92 * 255.0 + 0.5) as u32
+        } else {
+            ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+        }
+    }
+
+    fn base83(value: u32, length: u32, hash: &mut String) {
+        for i in 1..=length {
+            let digit = (value / 83u32.pow(length - i)) % 83;
+            hash.push(BASE83[digit as usize] as char);
+        }
+    }
+
+    // the colors don't need more details than that
+    let small = picture.thumbnail(32, 32).to_rgb();
+    let (width, height) = small.dimensions();
+    let mut factors = Vec::with_capacity(COMPONENTS_X * COMPONENTS_Y);
+    for j in 0..COMPONENTS_Y {
+        for i in 0..COMPONENTS_X {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f32; 3];
+            for (x, y, pixel) in small.enumerate_pixels() {
+                let basis = normalisation
+                    * (std::f32::consts::PI * i as f32 * x as f32 / width as f32).cos()
+                    * (std::f32::consts::PI * j as f32 * y as f32 / height as f32).cos();
+                for (f, c) in factor.iter_mut().zip(pixel.data.iter()) {
+                    *f += basis * to_linear(*c);
+                }
+            }
+            let scale = 1.0 / (width * height) as f32;
+            factors.push([factor[0] * scale, factor[1] * scale, factor[2] * scale]);
+        }
+    }
+
+    let mut hash = String::new();
+    base83(
+        ((COMPONENTS_X - 1) + (COMPONENTS_Y - 1) * 9) as u32,
+        1,
+        &mut hash,
+    );
+    let dc = factors[0];
+    let ac = &factors[1..];
+    let actual_max = ac
+        .iter()
+        .flat_map(|f| f.iter())
+        .fold(0.0f32, |max, v| max.max(v.abs()));
+    let quantised_max = (actual_max * 166.0 - 0.5).floor().max(0.0).min(82.0) as u32;
+    let max_value = (quantised_max + 1) as f32 / 166.0;
+    base83(quantised_max, 1, &mut hash);
+    base83(
+        (to_srgb(dc[0]) << 16) + (to_srgb(dc[1]) << 8) + to_srgb(dc[2]),
+        4,
+        &mut hash,
+    );
+    for f in ac {
+        let quantise = |v: f32| {
+            let v = v / max_value;
+            (v.signum() * v.abs().sqrt() * 9.0 + 9.5)
+                .floor()
+                .max(0.0)
+                .min(18.0) as u32
+        };
+        base83(
+            quantise(f[0]) * 19 * 19 + quantise(f[1]) * 19 + quantise(f[2]),
+            2,
+            &mut hash,
+        );
+    }
+    hash
+}
+
+/// The EXIF orientation of a picture, 1 (upright) if it can't be read
+fn exif_orientation(content: &[u8]) -> u32 {
+    Reader::new(&mut Cursor::new(content))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(Tag::Orientation, false)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// The MIME type of files with a given extension
+pub fn mime_type(ext: &str) -> &'static str {
+    match ext.to_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "ogg" => "audio/ogg",
+        "m4a" => "audio/mp4",
+        "mp4" => "video/mp4",
+        "avi" => "video/x-msvideo",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The width and height of a JPEG or PNG picture, read from its headers
+fn image_dimensions(content: &[u8], ext: &str) -> Option<(u32, u32)> {
+    let number = |pos: usize, len: usize| {
+        content
+            .get(pos..pos + len)
+            .map(|bytes| bytes.iter().fold(0, |n, b| n << 8 | u32::from(*b)))
+    };
+    match ext {
+        "png" if content.get(12..16) == Some(&b"IHDR"[..]) => {
+            Some((number(16, 4)?, number(20, 4)?))
+        }
+        "jpg" => {
+            let mut pos = 2;
+            loop {
+                if *content.get(pos)? != 0xff {
+                    return None;
+                }
+                match *content.get(pos + 1)? {
+                    // padding
+                    0xff => pos += 1,
+                    // start of frame (0xc4, 0xc8 and 0xcc are other segments)
+                    marker @ 0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                        return Some((number(pos + 7, 2)?, number(pos + 5, 2)?));
+                    }
+                    // start of scan, the frame should have been found before it
+                    0xda => return None,
+                    _ => pos += 2 + number(pos + 2, 2)? as usize,
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Remove the metadata of a JPEG, PNG or WebP picture, like the location where a photo was taken.
+///
+/// The EXIF orientation of JPEG pictures is kept, to rotate them when they are processed.
+/// Returns `None` if the picture is malformed.
+fn strip_metadata(content: &[u8], ext: &str) -> Option<Vec<u8>> {
+    match ext {
+        "jpg" => {
+            let orientation = exif_orientation(content);
+            let mut stripped = content.get(..2)?.to_vec();
+            let mut orientation_written = orientation < 2 || orientation > 8;
+            let mut pos = 2;
+            loop {
+                if *content.get(pos)? != 0xff {
+                    return None;
+                }
+                let marker = *content.get(pos + 1)?;
+                if marker == 0xff {
+                    pos += 1;
+                    continue;
+                }
+                // the EXIF segment should come just after the JFIF one, if there is one
+                if marker != 0xe0 && !orientation_written {
+                    stripped.extend_from_slice(&orientation_exif(orientation as u8));
+                    orientation_written = true;
+                }
+                // the compressed data starts with the first scan, and has no metadata
+                if marker == 0xda {
+                    stripped.extend_from_slice(&content[pos..]);
+                    return Some(stripped);
+                }
+                let len = content
+                    .get(pos + 2..pos + 4)
+                    .map(|len| usize::from(len[0]) << 8 | usize::from(len[1]))?;
+                if len < 2 {
+                    return None;
+                }
+                let segment = content.get(pos..pos + 2 + len)?;
+                // EXIF and XMP, IPTC, and comments
+                if marker != 0xe1 && marker != 0xed && marker != 0xfe {
+                    stripped.extend_from_slice(segment);
+                }
+                pos += 2 + len;
+            }
+        }
+        "png" => {
+            const METADATA: [&[u8]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+            let mut stripped = content.get(..8)?.to_vec();
+            let mut pos = 8;
+            while pos < content.len() {
+                let len = content
+                    .get(pos..pos + 4)?
+                    .iter()
+                    .fold(0, |n, b| n << 8 | usize::from(*b));
+                // length, type, data and CRC
+                let chunk = content.get(pos..pos.checked_add(12 + len)?)?;
+                if !METADATA.contains(&&chunk[4..8]) {
+                    stripped.extend_from_slice(chunk);
+                }
+                pos += chunk.len();
+            }
+            Some(stripped)
+        }
+        "webp" => {
+            let mut stripped = content.get(..12)?.to_vec();
+            let mut pos = 12;
+            while pos < content.len() {
+                let len = content
+                    .get(pos + 4..pos + 8)?
+                    .iter()
+                    .rev()
+                    .fold(0, |n, b| n << 8 | usize::from(*b));
+                // type, little-endian length, and data padded to an even length
+                let chunk = content.get(pos..pos.checked_add(8 + len + len % 2)?)?;
+                match &chunk[..4] {
+                    b"EXIF" | b"XMP " => {}
+                    b"VP8X" => {
+                        // the extended header says if there are EXIF and XMP chunks
+                        let mut header = chunk.to_vec();
+                        *header.get_mut(8)? &= !0x0c;
+                        stripped.extend_from_slice(&header);
+                    }
+                    _ => stripped.extend_from_slice(chunk),
+                }
+                pos += chunk.len();
+            }
+            let size = stripped.len() as u32 - 8;
+            stripped[4..8].copy_from_slice(&size.to_le_bytes());
+            Some(stripped)
+        }
+        _ => Some(content.to_vec()),
+    }
+}
+
+/// A JPEG segment with EXIF data only containing an orientation
+fn orientation_exif(orientation: u8) -> Vec<u8> {
+    let mut segment = vec![0xff, 0xe1, 0, 34];
+    segment.extend_from_slice(b"Exif\0\0");
+    // big-endian TIFF header, with the first IFD just after it
+    segment.extend_from_slice(b"MM\0\x2a\0\0\0\x08");
+    // one entry: the orientation, a SHORT
+    segment.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+    // no other IFD
+    segment.extend_from_slice(&[0, 0, 0, 0]);
+    segment
+}
+
+fn encode(picture: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let output = match format {
+        ImageFormat::JPEG => ImageOutputFormat::JPEG(85),
+        _ => ImageOutputFormat::PNG,
+    };
+    let mut content = vec![];
+    picture
+        .write_to(&mut content, output)
+        .map_err(|_| Error::InvalidValue)?;
+    Ok(content)
+}
+
+fn encode_webp(picture: &DynamicImage) -> Vec<u8> {
+    let rgba = picture.to_rgba();
+    let (width, height) = rgba.dimensions();
+    Encoder::from_rgba(&rgba.into_raw(), width, height)
+        .encode(80.0)
+        .to_vec()
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
     use diesel::Connection;
+    use media_albums::{MediaAlbum, NewMediaAlbum};
     use std::env::{current_dir, set_current_dir};
     use std::fs;
     use std::path::Path;
@@ -291,6 +1531,9 @@ pub(crate) mod tests {
                     sensitive: false,
                     content_warning: None,
                     owner_id: user_one,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
                 NewMedia {
                     file_path: f2,
@@ -300,6 +1543,9 @@ pub(crate) mod tests {
                     sensitive: true,
                     content_warning: Some("Content warning".to_owned()),
                     owner_id: user_one,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
                 NewMedia {
                     file_path: "".to_owned(),
@@ -309,6 +1555,9 @@ pub(crate) mod tests {
                     sensitive: false,
                     content_warning: None,
                     owner_id: user_two,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
             ]
             .into_iter()
@@ -345,6 +1594,9 @@ pub(crate) mod tests {
                     sensitive: false,
                     content_warning: None,
                     owner_id: user,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
             )
             .unwrap();
@@ -360,7 +1612,273 @@ pub(crate) mod tests {
     }
 
     #[test]
+    fn search_and_usage() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, medias) = fill_database(conn);
+            let user = &users[0];
+            assert_eq!(Media::count_for_user(conn, user, None, None).unwrap(), 2);
+            assert_eq!(
+                Media::count_for_user(conn, user, None, Some("some")).unwrap(),
+                1
+            );
+            // wildcards are searched for like any other character
+            assert_eq!(
+                Media::count_for_user(conn, user, None, Some("%")).unwrap(),
+                0
+            );
+            assert_eq!(
+                Media::count_for_user(conn, user, None, Some("_")).unwrap(),
+                0
+            );
+
+            let album = MediaAlbum::insert(
+                conn,
+                NewMediaAlbum {
+                    owner_id: user.id,
+                    name: "Holidays".to_owned(),
+                },
+            )
+            .unwrap();
+            medias[1].set_album(conn, Some(album.id)).unwrap();
+            let in_album = Media::page_for_user(conn, user, Some(album.id), None, (0, 10)).unwrap();
+            assert_eq!(in_album.len(), 1);
+            assert_eq!(in_album[0].id, medias[1].id);
+
+            assert!(medias[0].usage(conn).unwrap().is_empty());
+            user.set_avatar(conn, medias[0].id).unwrap();
+            let usage = medias[0].usage(conn).unwrap();
+            assert!(!usage.is_empty());
+            assert_eq!(usage.avatars[0].id, user.id);
+
+            clean(conn);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn prune_remote() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (_, medias) = fill_database(conn);
+            let remote = medias.into_iter().find(|m| m.is_remote).unwrap();
+            assert!(!remote.is_cached());
+
+            let key = Media::save_file(b"cached", "png").unwrap();
+            diesel::update(&remote)
+                .set((
+                    medias::file_path.eq(&key),
+                    medias::size.eq(6),
+                    medias::cached_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)
+                .unwrap();
+            let remote = Media::get(conn, remote.id).unwrap();
+            assert!(remote.is_cached());
+            assert_eq!(Media::prune_remote(conn).unwrap(), 0);
+            assert_eq!(remote.cached_copy().unwrap().1, b"cached".to_vec());
+
+            let expired = Utc::now().naive_utc()
+                - Duration::seconds(CONFIG.media.remote_cache_ttl as i64 + 60);
+            diesel::update(&remote)
+                .set(medias::cached_at.eq(expired))
+                .execute(conn)
+                .unwrap();
+            assert_eq!(Media::prune_remote(conn).unwrap(), 1);
+            let remote = Media::get(conn, remote.id).unwrap();
+            assert!(remote.cached_at.is_none());
+            assert!(remote.file_path.is_empty());
+            assert!(MEDIA_STORE.read(&key).is_err());
+
+            // the type of media that are not cached comes from their URL
+            let remote = Media {
+                remote_url: Some("https://example.com/media/Picture.JPG?size=large".to_owned()),
+                ..remote
+            };
+            assert!(remote.category() == MediaCategory::Image);
+            assert_eq!(remote.mime_type(), "image/jpeg");
+
+            clean(conn);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn one_download_at_once() {
+        let first = RemoteDownload::start(-1).unwrap();
+        assert!(RemoteDownload::start(-1).is_none());
+        assert!(RemoteDownload::start(-2).is_some());
+        drop(first);
+        assert!(RemoteDownload::start(-1).is_some());
+    }
+
+    #[test]
+    fn validate_upload() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let user = fill_database(conn).0[0].id;
+
+            let mut png = vec![];
+            DynamicImage::new_rgb8(10, 10)
+                .write_to(&mut png, ImageOutputFormat::PNG)
+                .unwrap();
+            assert_eq!(
+                Media::validate_upload(conn, user, png.clone()),
+                Ok((png, "png"))
+            );
+            assert_eq!(
+                Media::validate_upload(conn, user, b"ID3\x03\x00".to_vec()).map(|r| r.1),
+                Ok("mp3")
+            );
+            assert_eq!(
+                Media::validate_upload(conn, user, b"<html><script>".to_vec()),
+                Err(UploadError::UnsupportedType)
+            );
+            assert_eq!(
+                Media::validate_upload(conn, user, vec![0; 1024]),
+                Err(UploadError::UnsupportedType)
+            );
+            // HEIF pictures are not videos
+            assert_eq!(
+                Media::validate_upload(conn, user, b"\0\0\0\x18ftypheic\0\0\0\0mif1heic".to_vec()),
+                Err(UploadError::UnsupportedType)
+            );
+            assert_eq!(sniff(b"\0\0\0\x18ftypmp42\0\0\0\0isommp42"), Some("mp4"));
+
+            let (svg, ext) = Media::validate_upload(
+                conn,
+                user,
+                br#"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10" onload="alert(1)">
+<script>alert(2)</script>
+<a href="javascript:alert(3)"><rect width="10" height="10" fill="red"/></a>
+</svg>"#
+                    .to_vec(),
+            )
+            .unwrap();
+            assert_eq!(ext, "svg");
+            let svg = String::from_utf8(svg).unwrap();
+            assert!(svg.contains("viewBox"));
+            assert!(svg.contains("<rect"));
+            assert!(!svg.contains("alert"));
+            assert!(!svg.contains("href"));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn process_image() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let user = fill_database(conn).0[0].id;
+
+            let picture = DynamicImage::new_rgb8(800, 600);
+            let mut content = vec![];
+            picture
+                .write_to(&mut content, ImageOutputFormat::PNG)
+                .unwrap();
+            let media = Media::insert(
+                conn,
+                NewMedia {
+                    file_path: Media::save_file(&content, "png").unwrap(),
+                    alt_text: "alt message".to_owned(),
+                    is_remote: false,
+                    remote_url: None,
+                    sensitive: false,
+                    content_warning: None,
+                    owner_id: user,
+                    size: content.len() as i32,
+                    album_id: None,
+                    original_name: String::new(),
+                },
+            )
+            .unwrap();
+
+            media.process_image(conn).unwrap();
+            let media = Media::get(conn, media.id).unwrap();
+            assert_eq!(media.width, Some(800));
+            assert_eq!(media.height, Some(600));
+            let blurhash = media.blurhash.clone().unwrap();
+            assert_eq!(blurhash.len(), 28);
+            assert!(is_blurhash(&blurhash));
+
+            assert_eq!(media.focal_position(), None);
+            media.set_focal_point(conn, Some((0.5, 2.0))).unwrap();
+            let media = Media::get(conn, media.id).unwrap();
+            assert_eq!(media.focal_point(), Some((0.5, 1.0)));
+            assert_eq!(media.focal_position(), Some("75% 0%".to_owned()));
+            let preview = serde_json::to_value(media.preview_props()).unwrap();
+            assert_eq!(preview["focalPoint"], json!([0.5, 1.0]));
+            assert_eq!(preview["blurhash"], json!(blurhash));
+            assert_eq!(media.widths(), vec![320, 640]);
+            let small = MEDIA_STORE
+                .read(&media.variant_key(Some(320), None))
+                .unwrap();
+            assert_eq!(
+                image::load_from_memory(&small).unwrap().dimensions(),
+                (320, 240)
+            );
+            assert!(MEDIA_STORE
+                .read(&media.variant_key(Some(640), Some("webp")))
+                .is_ok());
+            assert!(media.srcset(Some("webp")).unwrap().ends_with(".webp 800w"));
+            assert!(media.variants_size > 0);
+            assert_eq!(
+                Media::used_space(conn, user).unwrap(),
+                Media::for_user(conn, user)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| i64::from(m.size + m.variants_size))
+                    .sum::<i64>()
+            );
+
+            clean(conn);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn strip_metadata() {
+        let mut jpeg = vec![];
+        DynamicImage::new_rgb8(80, 60)
+            .write_to(&mut jpeg, ImageOutputFormat::JPEG(85))
+            .unwrap();
+        // a photo taken with a phone held upside down, somewhere
+        let mut exif = orientation_exif(3);
+        exif.extend_from_slice(&[0xff, 0xfe, 0, 16]);
+        exif.extend_from_slice(b"GPS 48.8N 2.3E");
+        let picture = [&jpeg[..2], &exif[..], &jpeg[2..]].concat();
+        assert_eq!(exif_orientation(&picture), 3);
 
+        let (content, ext) = check_content(picture).unwrap();
+        assert_eq!(ext, "jpg");
+        assert!(!content.windows(3).any(|w| w == b"GPS"));
+        assert_eq!(exif_orientation(&content), 3);
+        assert_eq!(image_dimensions(&content, ext), Some((80, 60)));
+        assert!(image::load_from_memory(&content).is_ok());
+
+        let picture = encode_webp(&DynamicImage::new_rgb8(80, 60));
+        // an extended WebP file, with EXIF data
+        let mut vp8x = b"VP8X\x0a\0\0\0\x08\0\0\0\x4f\0\0\x3b\0\0".to_vec();
+        vp8x.extend_from_slice(&picture[12..]);
+        vp8x.extend_from_slice(b"EXIF\x0e\0\0\0GPS 48.8N 2.3E");
+        let size = vp8x.len() as u32 + 4;
+        let picture = [&b"RIFF"[..], &size.to_le_bytes(), b"WEBP", &vp8x].concat();
+
+        let (content, ext) = check_content(picture.clone()).unwrap();
+        assert_eq!(ext, "webp");
+        assert!(!content.windows(3).any(|w| w == b"GPS"));
+        assert_eq!(content.len(), picture.len() - 22);
+        assert_eq!(&content[4..8], &(content.len() as u32 - 8).to_le_bytes());
+        // the EXIF flag of the extended header
+        assert_eq!(content[20] & 0x08, 0);
+    }
+
+    #[test]
     fn set_owner() {
         let conn = &db();
         conn.test_transaction::<_, (), _>(|| {
@@ -381,6 +1899,9 @@ pub(crate) mod tests {
                     sensitive: false,
                     content_warning: None,
                     owner_id: u1.id,
+                    size: 0,
+                    album_id: None,
+                    original_name: String::new(),
                 },
             )
             .unwrap();
@@ -408,4 +1929,29 @@ pub(crate) mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn duration() {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        // 8000 Hz, 16000 bytes per second
+        wav.extend_from_slice(b"\x40\x1f\0\0\x80\x3e\0\0\x02\0\x10\0");
+        wav.extend_from_slice(b"data\x80\xbb\0\0");
+        assert_eq!(media_duration(&wav, "wav"), Some(3));
+
+        let mut flac = b"fLaC\x80\0\0\x22".to_vec();
+        flac.extend_from_slice(&[0; 10]);
+        // 44100 Hz, 441000 samples
+        flac.extend_from_slice(b"\x0a\xc4\x42\xf0\x00\x06\xba\xa8");
+        flac.extend_from_slice(&[0; 16]);
+        assert_eq!(media_duration(&flac, "flac"), Some(10));
+
+        let mut mp4 = b"\0\0\0\x20ftypisom\0\0\0\0\0\0\0\x6cmoov\0\0\0\x6cmvhd".to_vec();
+        mp4.extend_from_slice(&[0; 12]);
+        // 1000 units per second, 125000 units
+        mp4.extend_from_slice(b"\0\0\x03\xe8\0\x01\xe8\x48");
+        assert_eq!(media_duration(&mp4, "mp4"), Some(125));
+
+        assert_eq!(media_duration(b"not a sound", "ogg"), None);
+        assert_eq!(media_duration(&[], "mp3"), None);
+    }
 }
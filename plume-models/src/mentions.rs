@@ -128,7 +128,7 @@ impl Mention {
             .map_err(Error::from)
     }
 
-    fn notify(&self, conn: &Connection) -> Result<()> {
+    pub fn notify(&self, conn: &Connection) -> Result<()> {
         let m = self.get_mentioned(conn)?;
         if m.is_local() {
             Notification::insert(
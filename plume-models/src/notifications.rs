@@ -1,10 +1,13 @@
 use chrono::NaiveDateTime;
 use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
 
+use blog_invitations::BlogInvitation;
+use blogs::Blog;
 use comments::Comment;
 use follows::Follow;
 use likes::Like;
 use mentions::Mention;
+use post_reviews::PostReview;
 use posts::Post;
 use reshares::Reshare;
 use schema::notifications;
@@ -14,9 +17,15 @@ use {Connection, Error, Result};
 pub mod notification_kind {
     pub const COMMENT: &str = "COMMENT";
     pub const FOLLOW: &str = "FOLLOW";
+    /// An export could not be imported in a blog
+    pub const IMPORT_FAILED: &str = "IMPORT_FAILED";
+    pub const INVITATION: &str = "INVITATION";
     pub const LIKE: &str = "LIKE";
     pub const MENTION: &str = "MENTION";
     pub const RESHARE: &str = "RESHARE";
+    pub const REVIEW: &str = "REVIEW";
+    pub const REVIEW_REQUEST: &str = "REVIEW_REQUEST";
+    pub const SAVED_SEARCH: &str = "SAVED_SEARCH";
 }
 
 #[derive(Clone, Queryable, Identifiable)]
@@ -64,6 +73,14 @@ impl Notification {
             .map_err(Error::from)
     }
 
+    pub fn find_for_matched_post(conn: &Connection, post: &Post) -> Result<Vec<Notification>> {
+        notifications::table
+            .filter(notifications::kind.eq(notification_kind::SAVED_SEARCH))
+            .filter(notifications::object_id.eq(post.id))
+            .load::<Notification>(conn)
+            .map_err(Error::from)
+    }
+
     pub fn count_for_user(conn: &Connection, user: &User) -> Result<i64> {
         notifications::table
             .filter(notifications::user_id.eq(user.id))
@@ -100,6 +117,17 @@ impl Notification {
                 .get_post(conn)
                 .and_then(|p| Some(format!("{}#comment-{}", p.url(conn).ok()?, self.object_id))),
             notification_kind::FOLLOW => Some(format!("/@/{}/", self.get_actor(conn).ok()?.fqn)),
+            notification_kind::IMPORT_FAILED => Some(format!(
+                "/~/{}/import",
+                Blog::get(conn, self.object_id).ok()?.fqn
+            )),
+            notification_kind::INVITATION => Some(format!(
+                "/~/{}/",
+                BlogInvitation::get(conn, self.object_id)
+                    .and_then(|i| i.get_blog(conn))
+                    .ok()?
+                    .fqn
+            )),
             notification_kind::MENTION => Mention::get(conn, self.object_id)
                 .and_then(|mention| {
                     mention
@@ -115,6 +143,12 @@ impl Notification {
                         })
                 })
                 .ok(),
+            notification_kind::REVIEW | notification_kind::REVIEW_REQUEST => self
+                .get_post(conn)
+                .and_then(|p| Some(format!("{}/review", p.url(conn).ok()?))),
+            notification_kind::SAVED_SEARCH => Post::get(conn, self.object_id)
+                .and_then(|p| p.url(conn))
+                .ok(),
             _ => None,
         }
     }
@@ -130,6 +164,12 @@ impl Notification {
             notification_kind::RESHARE => Reshare::get(conn, self.object_id)
                 .and_then(|reshare| reshare.get_post(conn))
                 .ok(),
+            notification_kind::REVIEW | notification_kind::REVIEW_REQUEST => {
+                PostReview::get(conn, self.object_id)
+                    .and_then(|review| review.get_post(conn))
+                    .ok()
+            }
+            notification_kind::SAVED_SEARCH => Post::get(conn, self.object_id).ok(),
             _ => None,
         }
     }
@@ -140,9 +180,25 @@ impl Notification {
             notification_kind::FOLLOW => {
                 User::get(conn, Follow::get(conn, self.object_id)?.follower_id)?
             }
+            // nobody else is involved
+            notification_kind::IMPORT_FAILED => User::get(conn, self.user_id)?,
+            notification_kind::INVITATION => User::get(
+                conn,
+                BlogInvitation::get(conn, self.object_id)?.invited_by_id,
+            )?,
             notification_kind::LIKE => User::get(conn, Like::get(conn, self.object_id)?.user_id)?,
             notification_kind::MENTION => Mention::get(conn, self.object_id)?.get_user(conn)?,
             notification_kind::RESHARE => Reshare::get(conn, self.object_id)?.get_user(conn)?,
+            notification_kind::REVIEW => {
+                User::get(conn, PostReview::get(conn, self.object_id)?.reviewer_id?)?
+            }
+            notification_kind::REVIEW_REQUEST => {
+                User::get(conn, PostReview::get(conn, self.object_id)?.requested_by_id)?
+            }
+            notification_kind::SAVED_SEARCH => Post::get(conn, self.object_id)?
+                .get_authors(conn)?
+                .into_iter()
+                .next()?,
             _ => unreachable!("Notification::get_actor: Unknow type"),
         })
     }
@@ -151,9 +207,14 @@ impl Notification {
         match self.kind.as_ref() {
             notification_kind::COMMENT => "icon-message-circle",
             notification_kind::FOLLOW => "icon-user-plus",
+            notification_kind::IMPORT_FAILED => "icon-alert-triangle",
+            notification_kind::INVITATION => "icon-users",
             notification_kind::LIKE => "icon-heart",
             notification_kind::MENTION => "icon-at-sign",
             notification_kind::RESHARE => "icon-repeat",
+            notification_kind::REVIEW => "icon-check-square",
+            notification_kind::REVIEW_REQUEST => "icon-edit",
+            notification_kind::SAVED_SEARCH => "icon-search",
             _ => unreachable!("Notification::get_actor: Unknow type"),
         }
     }
@@ -0,0 +1,305 @@
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+
+use blog_authors::BlogAuthor;
+use notifications::*;
+use posts::Post;
+use schema::{notifications, post_reviews, posts, review_comments};
+use users::User;
+use {Connection, Error, Result};
+
+pub mod review_status {
+    /// Waiting for an editor
+    pub const PENDING: &str = "pending";
+    /// An editor asked the authors to change the post before submitting it again
+    pub const CHANGES_REQUESTED: &str = "changes_requested";
+    /// The post has been published by an editor
+    pub const APPROVED: &str = "approved";
+}
+
+/// A request to publish a draft, made by a writer of a blog that requires reviews
+#[derive(Clone, Queryable, Identifiable, AsChangeset)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct PostReview {
+    pub id: i32,
+    pub post_id: i32,
+    pub requested_by_id: i32,
+    /// The last editor who reviewed this post
+    pub reviewer_id: Option<i32>,
+    /// One of the `review_status` constants
+    pub status: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "post_reviews"]
+pub struct NewPostReview {
+    pub post_id: i32,
+    pub requested_by_id: i32,
+    pub status: String,
+}
+
+/// A comment left on a draft during its review
+#[derive(Clone, Queryable, Identifiable)]
+pub struct ReviewComment {
+    pub id: i32,
+    pub review_id: i32,
+    pub author_id: i32,
+    /// The passage of the draft this comment is about, if any
+    pub quote: String,
+    pub content: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "review_comments"]
+pub struct NewReviewComment {
+    pub review_id: i32,
+    pub author_id: i32,
+    pub quote: String,
+    pub content: String,
+}
+
+impl PostReview {
+    insert!(post_reviews, NewPostReview);
+    get!(post_reviews);
+    find_by!(post_reviews, find_for_post, post_id as i32);
+
+    /// Submit `post` for review, or submit it again after changes were requested.
+    ///
+    /// The owners of the blog are notified.
+    pub fn submit(conn: &Connection, post: &Post, user: &User) -> Result<PostReview> {
+        if post.published || !post.is_author(conn, user.id)? {
+            return Err(Error::InvalidValue);
+        }
+
+        let review = match PostReview::find_for_post(conn, post.id) {
+            Ok(mut review) => {
+                review.delete_notifications(conn)?;
+                review.requested_by_id = user.id;
+                review.reviewer_id = None;
+                review.status = review_status::PENDING.to_owned();
+                review.save_changes(conn)?
+            }
+            Err(Error::NotFound) => PostReview::insert(
+                conn,
+                NewPostReview {
+                    post_id: post.id,
+                    requested_by_id: user.id,
+                    status: review_status::PENDING.to_owned(),
+                },
+            )?,
+            Err(e) => return Err(e),
+        };
+
+        for owner in BlogAuthor::list_for_blog(conn, post.blog_id)?
+            .into_iter()
+            .filter(|a| a.is_owner() && a.author_id != user.id)
+        {
+            Notification::insert(
+                conn,
+                NewNotification {
+                    kind: notification_kind::REVIEW_REQUEST.to_string(),
+                    object_id: review.id,
+                    user_id: owner.author_id,
+                },
+            )?;
+        }
+        Ok(review)
+    }
+
+    /// The reviews waiting for an editor in the blogs where `user` can publish
+    pub fn pending_for_reviewer(conn: &Connection, user: &User) -> Result<Vec<(Post, PostReview)>> {
+        let blogs = BlogAuthor::list_for_author(conn, user.id)?
+            .into_iter()
+            .filter(BlogAuthor::can_publish)
+            .map(|a| a.blog_id)
+            .collect::<Vec<_>>();
+        posts::table
+            .inner_join(post_reviews::table)
+            .filter(posts::blog_id.eq_any(blogs))
+            .filter(post_reviews::status.eq(review_status::PENDING))
+            .order(post_reviews::creation_date.asc())
+            .load::<(Post, PostReview)>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn get_post(&self, conn: &Connection) -> Result<Post> {
+        Post::get(conn, self.post_id)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == review_status::PENDING
+    }
+
+    pub fn list_comments(&self, conn: &Connection) -> Result<Vec<ReviewComment>> {
+        review_comments::table
+            .filter(review_comments::review_id.eq(self.id))
+            .order(review_comments::creation_date.asc())
+            .load::<ReviewComment>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn comment(
+        &self,
+        conn: &Connection,
+        author: &User,
+        quote: String,
+        content: String,
+    ) -> Result<ReviewComment> {
+        ReviewComment::insert(
+            conn,
+            NewReviewComment {
+                review_id: self.id,
+                author_id: author.id,
+                quote,
+                content,
+            },
+        )
+    }
+
+    /// Ask the authors to change their post. They are notified, and can submit it again.
+    pub fn request_changes(&mut self, conn: &Connection, reviewer: &User) -> Result<()> {
+        self.close(conn, reviewer, review_status::CHANGES_REQUESTED)
+    }
+
+    /// Mark this review as approved. The post still has to be published.
+    pub fn approve(&mut self, conn: &Connection, reviewer: &User) -> Result<()> {
+        self.close(conn, reviewer, review_status::APPROVED)
+    }
+
+    fn close(&mut self, conn: &Connection, reviewer: &User, status: &str) -> Result<()> {
+        if !self.is_pending() {
+            return Err(Error::InvalidValue);
+        }
+
+        self.delete_notifications(conn)?;
+        self.reviewer_id = Some(reviewer.id);
+        self.status = status.to_owned();
+        *self = self.save_changes(conn)?;
+        for author in self.get_post(conn)?.get_authors(conn)? {
+            if author.id != reviewer.id {
+                Notification::insert(
+                    conn,
+                    NewNotification {
+                        kind: notification_kind::REVIEW.to_string(),
+                        object_id: self.id,
+                        user_id: author.id,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_notifications(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(
+            notifications::table
+                .filter(notifications::object_id.eq(self.id))
+                .filter(notifications::kind.eq_any(vec![
+                    notification_kind::REVIEW_REQUEST,
+                    notification_kind::REVIEW,
+                ])),
+        )
+        .execute(conn)
+        .map(|_| ())
+        .map_err(Error::from)
+    }
+}
+
+impl ReviewComment {
+    insert!(review_comments, NewReviewComment);
+
+    pub fn get_author(&self, conn: &Connection) -> Result<User> {
+        User::get(conn, self.author_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use post_authors::{NewPostAuthor, PostAuthor};
+    use posts::{post_visibility, NewPost};
+    use safe_string::SafeString;
+    use tests::rockets;
+
+    #[test]
+    fn review() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "draft".to_string(),
+                    title: "Draft".to_string(),
+                    content: SafeString::new("Hello"),
+                    published: false,
+                    license: "WTFPL".to_string(),
+                    creation_date: None,
+                    ap_url: String::new(),
+                    subtitle: String::new(),
+                    source: "Hello".into(),
+                    cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
+                },
+                &r.searcher,
+            )
+            .unwrap();
+            PostAuthor::insert(
+                conn,
+                NewPostAuthor {
+                    post_id: post.id,
+                    author_id: users[1].id,
+                },
+            )
+            .unwrap();
+
+            // only the authors can submit their post
+            assert!(PostReview::submit(conn, &post, &users[2]).is_err());
+            let mut review = PostReview::submit(conn, &post, &users[1]).unwrap();
+            assert!(review.is_pending());
+            assert_eq!(
+                Notification::find(conn, notification_kind::REVIEW_REQUEST, review.id)
+                    .unwrap()
+                    .user_id,
+                users[0].id
+            );
+            let pending = PostReview::pending_for_reviewer(conn, &users[0]).unwrap();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].0.id, post.id);
+            assert!(PostReview::pending_for_reviewer(conn, &users[1])
+                .unwrap()
+                .is_empty());
+
+            review
+                .comment(conn, &users[0], "Hello".into(), "Say goodbye".into())
+                .unwrap();
+            review.request_changes(conn, &users[0]).unwrap();
+            assert_eq!(review.status, review_status::CHANGES_REQUESTED);
+            assert!(
+                Notification::find(conn, notification_kind::REVIEW_REQUEST, review.id).is_err()
+            );
+            assert_eq!(
+                Notification::find(conn, notification_kind::REVIEW, review.id)
+                    .unwrap()
+                    .user_id,
+                users[1].id
+            );
+            assert!(review.approve(conn, &users[0]).is_err());
+
+            let mut review = PostReview::submit(conn, &post, &users[1]).unwrap();
+            assert_eq!(review.list_comments(conn).unwrap().len(), 1);
+            review.approve(conn, &users[0]).unwrap();
+            assert_eq!(review.status, review_status::APPROVED);
+            assert_eq!(review.reviewer_id, Some(users[0].id));
+
+            Ok(())
+        });
+    }
+}
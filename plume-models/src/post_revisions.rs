@@ -0,0 +1,379 @@
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::ops::{Index, IndexMut};
+
+use posts::Post;
+use schema::post_revisions;
+use users::User;
+use {Connection, Error, Result};
+
+/// A version of a post, saved every time it is edited
+#[derive(Clone, Queryable, Identifiable)]
+pub struct PostRevision {
+    pub id: i32,
+    pub post_id: i32,
+    /// The user who made this edit, if they still exist
+    pub author_id: Option<i32>,
+    pub title: String,
+    pub subtitle: String,
+    /// The Markdown source of the post
+    pub source: String,
+    pub license: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "post_revisions"]
+pub struct NewPostRevision {
+    pub post_id: i32,
+    pub author_id: Option<i32>,
+    pub title: String,
+    pub subtitle: String,
+    pub source: String,
+    pub license: String,
+    pub creation_date: Option<NaiveDateTime>,
+}
+
+impl NewPostRevision {
+    pub fn from_post(post: &Post, author_id: Option<i32>) -> Self {
+        NewPostRevision {
+            post_id: post.id,
+            author_id,
+            title: post.title.clone(),
+            subtitle: post.subtitle.clone(),
+            source: post.source.clone(),
+            license: post.license.clone(),
+            creation_date: None,
+        }
+    }
+}
+
+/// A line of the difference between two revisions
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineDiff {
+    Unchanged(String),
+    Added(String),
+    Removed(String),
+}
+
+impl PostRevision {
+    insert!(post_revisions, NewPostRevision);
+    get!(post_revisions);
+
+    /// All the revisions of a post, the most recent first
+    pub fn list_for_post(conn: &Connection, post_id: i32) -> Result<Vec<Self>> {
+        post_revisions::table
+            .filter(post_revisions::post_id.eq(post_id))
+            .order(post_revisions::id.desc())
+            .load::<Self>(conn)
+            .map_err(Error::from)
+    }
+
+    /// Save a revision of `post`, that was just edited by `author`.
+    ///
+    /// `previous` is the post before this edit. If the post has no history yet, it is saved
+    /// first, so that the original version can always be restored. Nothing is saved if the
+    /// edit didn't change anything that is versioned.
+    pub fn record(
+        conn: &Connection,
+        previous: &Post,
+        post: &Post,
+        author: Option<&User>,
+    ) -> Result<Option<Self>> {
+        if previous.title == post.title
+            && previous.subtitle == post.subtitle
+            && previous.source == post.source
+            && previous.license == post.license
+        {
+            return Ok(None);
+        }
+
+        let has_history = post_revisions::table
+            .filter(post_revisions::post_id.eq(post.id))
+            .count()
+            .get_result::<i64>(conn)?
+            > 0;
+        if !has_history {
+            let original_author = previous.get_authors(conn)?.into_iter().next();
+            Self::insert(
+                conn,
+                NewPostRevision {
+                    creation_date: Some(previous.creation_date),
+                    ..NewPostRevision::from_post(previous, original_author.map(|a| a.id))
+                },
+            )?;
+        }
+
+        Self::insert(conn, NewPostRevision::from_post(post, author.map(|a| a.id))).map(Some)
+    }
+
+    pub fn get_post(&self, conn: &Connection) -> Result<Post> {
+        Post::get(conn, self.post_id)
+    }
+
+    pub fn get_author(&self, conn: &Connection) -> Result<Option<User>> {
+        match self.author_id {
+            Some(id) => User::get(conn, id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The changes made to the source in this revision, compared to `previous`
+    pub fn diff(&self, previous: Option<&PostRevision>) -> Vec<LineDiff> {
+        line_diff(
+            previous.map(|p| p.source.as_ref()).unwrap_or(""),
+            &self.source,
+        )
+    }
+}
+
+/// Compare two texts line by line, with the linear space variant of Myers' algorithm
+pub fn line_diff(old: &str, new: &str) -> Vec<LineDiff> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    let max_d = (old.len() + new.len() + 1) / 2 + 1;
+    let mut forward = Frontier::new(max_d);
+    let mut backward = Frontier::new(max_d);
+    diff_lines(&old, &new, &mut forward, &mut backward, &mut diff);
+    diff
+}
+
+/// Past this number of edits between two parts of the texts, they are considered entirely
+/// different, to keep the time needed to compare very different texts reasonable
+const MAX_EDITS: usize = 1000;
+
+// the furthest x reached on each diagonal k = x - y
+struct Frontier {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Frontier {
+    fn new(max_d: usize) -> Self {
+        Frontier {
+            offset: max_d as isize,
+            x: vec![0; 2 * max_d + 1],
+        }
+    }
+}
+
+impl Index<isize> for Frontier {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.x[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for Frontier {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.x[(k + self.offset) as usize]
+    }
+}
+
+fn diff_lines(
+    old: &[&str],
+    new: &[&str],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+    diff: &mut Vec<LineDiff>,
+) {
+    let prefix = common_prefix(old, new);
+    diff.extend(
+        old[..prefix]
+            .iter()
+            .map(|l| LineDiff::Unchanged((*l).to_owned())),
+    );
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = common_suffix(old, new);
+    let (old, new, suffix) = (
+        &old[..old.len() - suffix],
+        &new[..new.len() - suffix],
+        &old[old.len() - suffix..],
+    );
+
+    match middle_snake(old, new, forward, backward) {
+        Some((x, y)) if !old.is_empty() && !new.is_empty() => {
+            diff_lines(&old[..x], &new[..y], forward, backward, diff);
+            diff_lines(&old[x..], &new[y..], forward, backward, diff);
+        }
+        _ => {
+            diff.extend(old.iter().map(|l| LineDiff::Removed((*l).to_owned())));
+            diff.extend(new.iter().map(|l| LineDiff::Added((*l).to_owned())));
+        }
+    }
+
+    diff.extend(suffix.iter().map(|l| LineDiff::Unchanged((*l).to_owned())));
+}
+
+fn common_prefix(old: &[&str], new: &[&str]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(old: &[&str], new: &[&str]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+// Find where the shortest edit script crosses the middle of the edit graph, by searching from
+// both ends at the same time. Returns the start of the snake found there.
+fn middle_snake(
+    old: &[&str],
+    new: &[&str],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+) -> Option<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    if n == 0 || m == 0 {
+        return None;
+    }
+    let delta = n as isize - m as isize;
+    let odd = delta % 2 != 0;
+    forward[1] = 0;
+    backward[1] = 0;
+
+    let max_d = ((n + m + 1) / 2 + 1).min(MAX_EDITS) as isize;
+    for d in 0..max_d {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < n && y < m {
+                x += common_prefix(&old[x..], &new[y..]);
+            }
+            forward[k] = x;
+            if odd && (k - delta).abs() < d && forward[k] + backward[delta - k] >= n {
+                return Some(start);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let len = common_suffix(&old[..n - x], &new[..m - y]);
+                x += len;
+                y += len;
+            }
+            backward[k] = x;
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[delta - k] >= n {
+                return Some((n - x, m - y));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use post_authors::*;
+    use posts::{post_visibility, NewPost};
+    use safe_string::SafeString;
+    use search::tests::get_searcher;
+    use tests::db;
+
+    #[test]
+    fn diff() {
+        assert_eq!(
+            line_diff("a\nb\nc", "a\nc\nd"),
+            vec![
+                LineDiff::Unchanged("a".to_owned()),
+                LineDiff::Removed("b".to_owned()),
+                LineDiff::Unchanged("c".to_owned()),
+                LineDiff::Added("d".to_owned()),
+            ]
+        );
+        assert_eq!(line_diff("", "a"), vec![LineDiff::Added("a".to_owned())]);
+
+        // long texts are compared without a table of all their pairs of lines
+        let old = (0..100_000).map(|i| format!("{}\n", i)).collect::<String>();
+        let new = old.replace("\n5000\n", "\nfive thousand\n");
+        let diff = line_diff(&old, &new);
+        assert_eq!(diff.len(), 100_001);
+        assert_eq!(diff[5000], LineDiff::Removed("5000".to_owned()));
+        assert_eq!(diff[5001], LineDiff::Added("five thousand".to_owned()));
+    }
+
+    #[test]
+    fn record() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            let searcher = get_searcher();
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "history".to_owned(),
+                    title: "History".to_owned(),
+                    content: SafeString::new("<p>First</p>"),
+                    published: true,
+                    license: "CC-BY-SA".to_owned(),
+                    ap_url: "".to_owned(),
+                    creation_date: None,
+                    subtitle: "".to_owned(),
+                    source: "First".to_owned(),
+                    cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
+                },
+                &searcher,
+            )
+            .unwrap();
+            PostAuthor::insert(
+                conn,
+                NewPostAuthor {
+                    post_id: post.id,
+                    author_id: users[0].id,
+                },
+            )
+            .unwrap();
+
+            // nothing changed
+            assert!(PostRevision::record(conn, &post, &post, Some(&users[0]))
+                .unwrap()
+                .is_none());
+            assert!(PostRevision::list_for_post(conn, post.id)
+                .unwrap()
+                .is_empty());
+
+            let mut edited = post.clone();
+            edited.source = "First\nSecond".to_owned();
+            PostRevision::record(conn, &post, &edited, Some(&users[1]))
+                .unwrap()
+                .unwrap();
+
+            let revisions = PostRevision::list_for_post(conn, post.id).unwrap();
+            assert_eq!(revisions.len(), 2);
+            assert_eq!(revisions[0].source, "First\nSecond");
+            assert_eq!(revisions[0].author_id, Some(users[1].id));
+            assert_eq!(revisions[1].source, "First");
+            assert_eq!(revisions[1].author_id, Some(users[0].id));
+            assert_eq!(
+                revisions[0].diff(Some(&revisions[1])),
+                vec![
+                    LineDiff::Unchanged("First".to_owned()),
+                    LineDiff::Added("Second".to_owned()),
+                ]
+            );
+
+            Ok(())
+        });
+    }
+}
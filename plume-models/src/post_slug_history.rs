@@ -0,0 +1,58 @@
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use posts::Post;
+use schema::{post_slug_history, posts};
+use {Connection, Error, Result};
+
+/// A slug a post used to have, kept so that its old URLs still work
+#[derive(Clone, Queryable, Identifiable)]
+#[table_name = "post_slug_history"]
+pub struct PreviousSlug {
+    pub id: i32,
+    pub post_id: i32,
+    pub blog_id: i32,
+    pub slug: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "post_slug_history"]
+pub struct NewPreviousSlug {
+    pub post_id: i32,
+    pub blog_id: i32,
+    pub slug: String,
+}
+
+impl PreviousSlug {
+    insert!(post_slug_history, NewPreviousSlug);
+    get!(post_slug_history);
+    find_by!(
+        post_slug_history,
+        find_by_slug,
+        slug as &str,
+        blog_id as i32
+    );
+    list_by!(post_slug_history, list_for_post, post_id as i32);
+
+    /// The post that used to be at `/~/<blog>/<slug>`
+    pub fn find_post(conn: &Connection, slug: &str, blog_id: i32) -> Result<Post> {
+        post_slug_history::table
+            .inner_join(posts::table)
+            .filter(post_slug_history::blog_id.eq(blog_id))
+            .filter(post_slug_history::slug.eq(slug))
+            .select(posts::all_columns)
+            .limit(1)
+            .load::<Post>(conn)?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
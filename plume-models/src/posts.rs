@@ -5,32 +5,58 @@ use activitypub::{
     CustomObject,
 };
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use diesel::{self, BelongingToDsl, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
-use heck::{CamelCase, KebabCase};
+use diesel::{
+    self, dsl::sql, sql_types::Bool, BelongingToDsl, BoolExpressionMethods, ExpressionMethods,
+    JoinOnDsl, QueryDsl, RunQueryDsl, SaveChangesDsl, TextExpressionMethods,
+};
+use heck::CamelCase;
 use serde_json;
 use std::collections::HashSet;
 
 use blogs::Blog;
 use instance::Instance;
-use medias::Media;
+use medias::{Media, MediaCategory, PreviewedImage};
 use mentions::Mention;
+use notifications::Notification;
 use plume_common::{
     activity_pub::{
+        broadcast,
         inbox::{AsObject, FromId},
         Hashtag, Id, IntoId, Licensed, Source, PUBLIC_VISIBILITY,
     },
-    utils::md_to_html,
+    utils::{make_slug, md_to_html, media_ids},
 };
 use post_authors::*;
+use post_reviews::PostReview;
+use post_revisions::PostRevision;
+use post_slug_history::{NewPreviousSlug, PreviousSlug};
 use safe_string::SafeString;
+use saved_searches::SavedSearch;
 use schema::posts;
 use search::Searcher;
+use series::Series;
 use tags::*;
 use users::User;
 use {ap_url, Connection, Error, PlumeRocket, Result, CONFIG};
 
 pub type LicensedArticle = CustomObject<Licensed, Article>;
 
+/// Who can see a post
+pub mod post_visibility {
+    /// Everyone, and it is listed in timelines and search results
+    pub const PUBLIC: &str = "public";
+    /// Everyone knowing its URL, but it is only listed on its blog and on the profile of its authors
+    pub const UNLISTED: &str = "unlisted";
+    /// The followers of its authors
+    pub const FOLLOWERS: &str = "followers";
+    /// The users mentioned in it
+    pub const DIRECT: &str = "direct";
+
+    pub fn is_valid(visibility: &str) -> bool {
+        [PUBLIC, UNLISTED, FOLLOWERS, DIRECT].contains(&visibility)
+    }
+}
+
 #[derive(Queryable, Identifiable, Clone, AsChangeset)]
 #[changeset_options(treat_none_as_null = "true")]
 pub struct Post {
@@ -46,6 +72,10 @@ pub struct Post {
     pub subtitle: String,
     pub source: String,
     pub cover_id: Option<i32>,
+    /// When this post will be published, if it is scheduled
+    pub publish_at: Option<NaiveDateTime>,
+    /// Who can see this post, one of the `post_visibility` constants
+    pub visibility: String,
 }
 
 #[derive(Insertable)]
@@ -62,6 +92,10 @@ pub struct NewPost {
     pub subtitle: String,
     pub source: String,
     pub cover_id: Option<i32>,
+    /// When this post will be published, if it is scheduled
+    pub publish_at: Option<NaiveDateTime>,
+    /// Who can see this post, one of the `post_visibility` constants
+    pub visibility: String,
 }
 
 impl Post {
@@ -69,6 +103,61 @@ impl Post {
     find_by!(posts, find_by_slug, slug as &str, blog_id as i32);
     find_by!(posts, find_by_ap_url, ap_url as &str);
 
+    /// A slug based on `slug` that no other post of the blog uses, or used before being
+    /// renamed, adding a numeric suffix if needed.
+    ///
+    /// `post_id` is the post that will use this slug, if it already exists.
+    pub fn unique_slug(
+        conn: &Connection,
+        blog_id: i32,
+        slug: &str,
+        post_id: Option<i32>,
+    ) -> Result<String> {
+        let mut candidate = slug.to_owned();
+        let mut suffix = 1;
+        loop {
+            let used_by_post = match Post::find_by_slug(conn, &candidate, blog_id) {
+                Ok(post) => Some(post.id) != post_id,
+                Err(Error::NotFound) => false,
+                Err(e) => return Err(e),
+            };
+            let used_before = match PreviousSlug::find_by_slug(conn, &candidate, blog_id) {
+                Ok(previous) => Some(previous.post_id) != post_id,
+                Err(Error::NotFound) => false,
+                Err(e) => return Err(e),
+            };
+            if !used_by_post && !used_before {
+                return Ok(candidate);
+            }
+            suffix += 1;
+            candidate = format!("{}-{}", slug, suffix);
+        }
+    }
+
+    /// Give a new slug to this post, keeping the current one in its history if it was
+    /// published, so that its URL keeps working. The post still has to be saved afterwards.
+    pub fn change_slug(&mut self, conn: &Connection, slug: String) -> Result<()> {
+        if slug == self.slug {
+            return Ok(());
+        }
+
+        if let Ok(previous) = PreviousSlug::find_by_slug(conn, &slug, self.blog_id) {
+            previous.delete(conn)?;
+        }
+        if self.published && PreviousSlug::find_by_slug(conn, &self.slug, self.blog_id).is_err() {
+            PreviousSlug::insert(
+                conn,
+                NewPreviousSlug {
+                    post_id: self.id,
+                    blog_id: self.blog_id,
+                    slug: self.slug.clone(),
+                },
+            )?;
+        }
+        self.slug = slug;
+        Ok(())
+    }
+
     last!(posts);
     pub fn insert(conn: &Connection, new: NewPost, searcher: &Searcher) -> Result<Self> {
         diesel::insert_into(posts::table)
@@ -100,8 +189,11 @@ impl Post {
         for m in Mention::list_for_post(&conn, self.id)? {
             m.delete(conn)?;
         }
+        for n in Notification::find_for_matched_post(conn, self)? {
+            n.delete(conn)?;
+        }
         diesel::delete(self).execute(conn)?;
-        searcher.delete_document(self);
+        searcher.delete_document(conn, self)?;
         Ok(())
     }
 
@@ -116,6 +208,7 @@ impl Post {
         posts::table
             .filter(posts::id.eq_any(ids))
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
             .order(posts::creation_date.desc())
             .offset(min.into())
             .limit((max - min).into())
@@ -129,6 +222,7 @@ impl Post {
         posts::table
             .filter(posts::id.eq_any(ids))
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
             .count()
             .load(conn)?
             .iter()
@@ -186,6 +280,7 @@ impl Post {
         posts::table
             .order(posts::creation_date.desc())
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
             .limit(limit)
             .load::<Post>(conn)
             .map_err(Error::from)
@@ -202,6 +297,9 @@ impl Post {
         posts::table
             .filter(posts::id.eq_any(posts))
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .order(posts::creation_date.desc())
             .limit(limit)
             .load::<Post>(conn)
@@ -212,16 +310,73 @@ impl Post {
         posts::table
             .filter(posts::blog_id.eq(blog.id))
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .order(posts::creation_date.desc())
             .limit(limit)
             .load::<Post>(conn)
             .map_err(Error::from)
     }
 
+    /// The most recent posts of a blog with a sound in their content, and this sound,
+    /// to list them as the episodes of a podcast.
+    ///
+    /// Sounds whose size is not known, like remote ones that have not been downloaded yet,
+    /// are ignored: podcast apps need it.
+    pub fn podcast_episodes(
+        conn: &Connection,
+        blog: &Blog,
+        limit: usize,
+    ) -> Result<Vec<(Post, Media)>> {
+        use schema::{medias, post_authors, users};
+        let local = Instance::get_local()?;
+        let posts = posts::table
+            .inner_join(post_authors::table.on(post_authors::post_id.eq(posts::id)))
+            .inner_join(users::table.on(users::id.eq(post_authors::author_id)))
+            .inner_join(medias::table.on(medias::owner_id.eq(users::id)))
+            .filter(posts::blog_id.eq(blog.id))
+            .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
+            .filter(users::instance_id.eq(local.id))
+            .filter(
+                medias::file_path
+                    .like("%.mp3")
+                    .or(medias::file_path.like("%.wav"))
+                    .or(medias::file_path.like("%.flac"))
+                    .or(medias::file_path.like("%.ogg"))
+                    .or(medias::file_path.like("%.m4a")),
+            )
+            .filter(medias::size.gt(0))
+            // the media inserted by the editor are written `![alt](id)`
+            .filter(sql::<Bool>("posts.source LIKE '%](' || medias.id || ')%'"))
+            .select(posts::all_columns)
+            .distinct()
+            .order(posts::creation_date.desc())
+            .limit(limit as i64)
+            .load::<Post>(conn)?;
+        let mut episodes = vec![];
+        for post in posts {
+            let sound = post
+                .attachments(conn)?
+                .into_iter()
+                .find(|m| m.category() == MediaCategory::Audio && m.size > 0);
+            if let Some(sound) = sound {
+                episodes.push((post, sound));
+            }
+        }
+        Ok(episodes)
+    }
+
     pub fn get_for_blog(conn: &Connection, blog: &Blog) -> Result<Vec<Post>> {
         posts::table
             .filter(posts::blog_id.eq(blog.id))
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .load::<Post>(conn)
             .map_err(Error::from)
     }
@@ -230,6 +385,9 @@ impl Post {
         posts::table
             .filter(posts::blog_id.eq(blog.id))
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .count()
             .get_result(conn)
             .map_err(Error::from)
@@ -239,6 +397,9 @@ impl Post {
         posts::table
             .filter(posts::blog_id.eq(blog.id))
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .order(posts::creation_date.desc())
             .offset(min.into())
             .limit((max - min).into())
@@ -251,6 +412,7 @@ impl Post {
         posts::table
             .order(posts::creation_date.desc())
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
             .offset(min.into())
             .limit((max - min).into())
             .load::<Post>(conn)
@@ -272,6 +434,7 @@ impl Post {
         posts::table
             .order(posts::creation_date.desc())
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
             .filter(posts::blog_id.eq_any(blog_ids))
             .offset(min.into())
             .limit((max - min).into())
@@ -293,6 +456,7 @@ impl Post {
         posts::table
             .order(posts::creation_date.desc())
             .filter(posts::published.eq(true))
+            .filter(posts::visibility.ne(post_visibility::DIRECT))
             .filter(posts::id.eq_any(post_ids))
             .offset(min.into())
             .limit((max - min).into())
@@ -300,18 +464,109 @@ impl Post {
             .map_err(Error::from)
     }
 
-    pub fn drafts_by_author(conn: &Connection, author: &User) -> Result<Vec<Post>> {
+    /// The drafts of `author`, with their review if they were submitted for one
+    pub fn drafts_by_author(
+        conn: &Connection,
+        author: &User,
+    ) -> Result<Vec<(Post, Option<PostReview>)>> {
         use schema::post_authors;
+        use schema::post_reviews;
 
         let posts = PostAuthor::belonging_to(author).select(post_authors::post_id);
         posts::table
+            .left_join(post_reviews::table)
             .order(posts::creation_date.desc())
             .filter(posts::published.eq(false))
+            .filter(posts::publish_at.is_null())
+            .filter(posts::id.eq_any(posts))
+            .load::<(Post, Option<PostReview>)>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn scheduled_by_author(conn: &Connection, author: &User) -> Result<Vec<Post>> {
+        use schema::post_authors;
+
+        let posts = PostAuthor::belonging_to(author).select(post_authors::post_id);
+        posts::table
+            .order(posts::publish_at.asc())
+            .filter(posts::published.eq(false))
+            .filter(posts::publish_at.is_not_null())
             .filter(posts::id.eq_any(posts))
             .load::<Post>(conn)
             .map_err(Error::from)
     }
 
+    /// Publish the scheduled posts that are due, and send them to the followers of their authors
+    pub fn publish_scheduled(conn: &Connection, searcher: &Searcher) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        for post in posts::table
+            .filter(posts::published.eq(false))
+            .filter(posts::publish_at.le(now))
+            .load::<Post>(conn)?
+        {
+            // one failing post shouldn't prevent the others from being published
+            let id = post.id;
+            if let Err(e) = post.publish_scheduled_now(conn, searcher) {
+                eprintln!("Error while publishing scheduled post {}: {:?}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn publish_scheduled_now(mut self, conn: &Connection, searcher: &Searcher) -> Result<()> {
+        // another process may have published it since it was loaded
+        if !self.claim(conn)? {
+            return Ok(());
+        }
+        self.creation_date = self
+            .publish_at
+            .take()
+            .unwrap_or_else(|| Utc::now().naive_utc());
+        let post = self.publish_claimed(conn, searcher)?;
+
+        let author = post.get_authors(conn)?.into_iter().next()?;
+        let act = post.create_activity(conn)?;
+        let dest = post.get_receivers(conn)?;
+        broadcast(&author, act, dest);
+        Ok(())
+    }
+
+    /// Mark this post as published, and notify the users it mentions or matches the saved
+    /// searches of. The `Create` activity still has to be sent.
+    ///
+    /// Fails if it has already been published, by another request or process.
+    pub fn publish(self, conn: &Connection, searcher: &Searcher) -> Result<Post> {
+        if !self.claim(conn)? {
+            return Err(Error::InvalidValue);
+        }
+        self.publish_claimed(conn, searcher)
+    }
+
+    /// Mark this post as published in the database, if it isn't yet. Returns `false` if
+    /// it already was: then it must not be published again.
+    fn claim(&self, conn: &Connection) -> Result<bool> {
+        let updated = diesel::update(
+            posts::table
+                .filter(posts::id.eq(self.id))
+                .filter(posts::published.eq(false)),
+        )
+        .set(posts::published.eq(true))
+        .execute(conn)?;
+        Ok(updated == 1)
+    }
+
+    fn publish_claimed(mut self, conn: &Connection, searcher: &Searcher) -> Result<Post> {
+        self.published = true;
+        let post = self.update(conn, searcher)?;
+
+        // mentions were saved when the post was written, but not notified
+        for mention in Mention::list_for_post(conn, post.id)? {
+            mention.notify(conn)?;
+        }
+        SavedSearch::notify_matches(conn, &post)?;
+        Ok(post)
+    }
+
     pub fn get_authors(&self, conn: &Connection) -> Result<Vec<User>> {
         use schema::post_authors;
         use schema::users;
@@ -322,6 +577,60 @@ impl Post {
             .map_err(Error::from)
     }
 
+    /// The media inserted in the content of this post, in the order they appear.
+    ///
+    /// Like when rendering it, only the media of its authors are used.
+    pub fn attachments(&self, conn: &Connection) -> Result<Vec<Media>> {
+        let local = Instance::get_local()?;
+        let authors = self
+            .get_authors(conn)?
+            .into_iter()
+            .filter(|a| a.instance_id == local.id)
+            .map(|a| a.id)
+            .collect::<Vec<_>>();
+        Ok(media_ids(&self.source)
+            .into_iter()
+            .filter_map(|id| Media::get(conn, id).ok())
+            .filter(|m| authors.contains(&m.owner_id))
+            .collect())
+    }
+
+    /// Credit `authors` for this post, and only them
+    pub fn set_authors(&self, conn: &Connection, authors: &[User]) -> Result<()> {
+        use schema::post_authors;
+        if authors.is_empty() {
+            return Err(Error::InvalidValue);
+        }
+
+        let ids = authors.iter().map(|a| a.id).collect::<Vec<_>>();
+        diesel::delete(
+            post_authors::table
+                .filter(post_authors::post_id.eq(self.id))
+                .filter(post_authors::author_id.ne_all(ids)),
+        )
+        .execute(conn)?;
+        for author in authors {
+            if !self.is_author(conn, author.id)? {
+                PostAuthor::insert(
+                    conn,
+                    NewPostAuthor {
+                        post_id: self.id,
+                        author_id: author.id,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `user` can edit this post: its authors can, and so can the editors
+    /// and the owners of its blog
+    pub fn can_edit(&self, conn: &Connection, user: &User) -> Result<bool> {
+        let blog = self.get_blog(conn)?;
+        Ok(user.is_author_in(conn, &blog)?
+            && (self.is_author(conn, user.id)? || user.can_publish_in(conn, &blog)?))
+    }
+
     pub fn is_author(&self, conn: &Connection, author_id: i32) -> Result<bool> {
         use schema::post_authors;
         Ok(PostAuthor::belonging_to(self)
@@ -374,9 +683,74 @@ impl Post {
         }))
     }
 
+    fn get_mentioned(&self, conn: &Connection) -> Result<Vec<User>> {
+        Ok(Mention::list_for_post(conn, self.id)?
+            .into_iter()
+            .filter_map(|m| m.get_mentioned(conn).ok())
+            .collect())
+    }
+
+    /// The users that should receive the activities about this post, according to its visibility
+    pub fn get_receivers(&self, conn: &Connection) -> Result<Vec<User>> {
+        match self.visibility.as_str() {
+            post_visibility::FOLLOWERS => {
+                let mut receivers = self.get_mentioned(conn)?;
+                for author in self.get_authors(conn)? {
+                    receivers.append(&mut author.get_followers(conn)?);
+                }
+                Ok(receivers)
+            }
+            post_visibility::DIRECT => self.get_mentioned(conn),
+            _ => User::one_by_instance(conn),
+        }
+    }
+
+    /// Check if `user` is allowed to see this post, according to its visibility
+    pub fn can_see(&self, conn: &Connection, user: Option<&User>) -> bool {
+        match self.visibility.as_str() {
+            post_visibility::FOLLOWERS | post_visibility::DIRECT => user
+                .map(|user| {
+                    let authors = self.get_authors(conn).unwrap_or_default();
+                    authors.iter().any(|a| a.id == user.id)
+                        || (self.visibility == post_visibility::FOLLOWERS
+                            && authors
+                                .iter()
+                                .any(|a| user.is_following(conn, a.id).unwrap_or(false)))
+                        || Mention::list_for_post(conn, self.id)
+                            .map(|m| m.iter().any(|m| m.mentioned_id == user.id))
+                            .unwrap_or(false)
+                })
+                .unwrap_or(false),
+            _ => true,
+        }
+    }
+
+    // the to and cc fields of the activities about this post
+    fn audience(&self, conn: &Connection) -> Result<(Vec<String>, Vec<String>)> {
+        let followers = self.get_receivers_urls(conn)?;
+        let mentioned = self
+            .get_mentioned(conn)?
+            .into_iter()
+            .map(|u| u.ap_url)
+            .collect::<Vec<_>>();
+        Ok(match self.visibility.as_str() {
+            post_visibility::UNLISTED => (followers, vec![PUBLIC_VISIBILITY.to_string()]),
+            post_visibility::FOLLOWERS => {
+                let mut to = self
+                    .get_authors(conn)?
+                    .into_iter()
+                    .map(|a| a.followers_endpoint)
+                    .collect::<Vec<_>>();
+                to.extend(followers);
+                (to, mentioned)
+            }
+            post_visibility::DIRECT => (mentioned, vec![]),
+            _ => (vec![PUBLIC_VISIBILITY.to_string()], followers),
+        })
+    }
+
     pub fn to_activity(&self, conn: &Connection) -> Result<LicensedArticle> {
-        let cc = self.get_receivers_urls(conn)?;
-        let to = vec![PUBLIC_VISIBILITY.to_string()];
+        let (to, cc) = self.audience(conn)?;
 
         let mut mentions_json = Mention::list_for_post(conn, self.id)?
             .into_iter()
@@ -416,8 +790,18 @@ impl Post {
             .set_summary_string(self.subtitle.clone())?;
         article.object_props.tag = Some(json!(mentions_json));
 
+        let attachments = self
+            .attachments(conn)?
+            .into_iter()
+            .map(|m| m.to_attachment())
+            .collect::<Result<Vec<_>>>()?;
+        if !attachments.is_empty() {
+            article.object_props.attachment = Some(json!(attachments));
+        }
+
         if let Some(media_id) = self.cover_id {
             let media = Media::get(conn, media_id)?;
+            let preview = media.preview_props();
             let mut cover = Image::default();
             cover.object_props.set_url_string(media.url()?)?;
             if media.sensitive {
@@ -429,10 +813,17 @@ impl Post {
             cover
                 .object_props
                 .set_attributed_to_link_vec(vec![User::get(conn, media.owner_id)?.into_id()])?;
-            article.object_props.set_icon_object(cover)?;
+            article
+                .object_props
+                .set_icon_object(PreviewedImage::new(cover, preview))?;
         }
 
         article.object_props.set_url_string(self.ap_url.clone())?;
+        if let Ok(series) = Series::find_for_post(conn, self) {
+            article
+                .object_props
+                .set_context_link(Id::new(series.ap_url))?;
+        }
         article
             .object_props
             .set_to_link_vec::<Id>(to.into_iter().map(Id::new).collect())?;
@@ -506,7 +897,8 @@ impl Post {
             .collect::<HashSet<_>>();
         for (m, id) in &mentions {
             if !old_user_mentioned.contains(&id) {
-                Mention::from_activity(&*conn, &m, self.id, true, true)?;
+                // mentions in scheduled posts will be notified on publication
+                Mention::from_activity(&*conn, &m, self.id, true, self.published)?;
             }
         }
 
@@ -600,12 +992,24 @@ impl Post {
         Ok(format!("/~/{}/{}", blog.fqn, self.slug))
     }
 
+    pub fn cover(&self, conn: &Connection) -> Option<Media> {
+        self.cover_id.and_then(|i| Media::get(conn, i).ok())
+    }
+
     pub fn cover_url(&self, conn: &Connection) -> Option<String> {
         self.cover_id
             .and_then(|i| Media::get(conn, i).ok())
             .and_then(|c| c.url().ok())
     }
 
+    /// The `srcset` of the cover, if it has resized versions
+    pub fn cover_srcset(&self, conn: &Connection) -> Option<String> {
+        self.cover_id
+            .and_then(|i| Media::get(conn, i).ok())
+            .filter(|c| !c.variants.is_empty())
+            .and_then(|c| c.srcset(None).ok())
+    }
+
     pub fn build_delete(&self, conn: &Connection) -> Result<Delete> {
         let mut act = Delete::default();
         act.delete_props
@@ -654,16 +1058,18 @@ impl FromId<PlumeRocket> for Post {
 
         let cover = article
             .object_props
-            .icon_object::<Image>()
+            .icon_object::<PreviewedImage>()
             .ok()
             .and_then(|img| Media::from_activity(&c, &img).ok().map(|m| m.id));
 
         let title = article.object_props.name_string()?;
+        let visibility = visibility_from_audience(&article, &authors);
+        let blog = blog?;
         let post = Post::insert(
             conn,
             NewPost {
-                blog_id: blog?.id,
-                slug: title.to_kebab_case(),
+                blog_id: blog.id,
+                slug: Post::unique_slug(conn, blog.id, &make_slug(&title), None)?,
                 title,
                 content: SafeString::new(&article.object_props.content_string()?),
                 published: true,
@@ -677,6 +1083,8 @@ impl FromId<PlumeRocket> for Post {
                 subtitle: article.object_props.summary_string()?,
                 source: article.ap_object_props.source_object::<Source>()?.content,
                 cover_id: cover,
+                publish_at: None,
+                visibility: visibility.to_owned(),
             },
             searcher,
         )?;
@@ -717,10 +1125,55 @@ impl FromId<PlumeRocket> for Post {
                     .ok();
             }
         }
+
+        // the series this article is part of
+        if let Ok(context) = article.object_props.context_link::<Id>() {
+            if let Ok(series) = Series::from_id(c, &context, None) {
+                if series.blog_id == post.blog_id {
+                    series.add_post(conn, &post)?;
+                }
+            }
+        }
+
+        SavedSearch::notify_matches(conn, &post)?;
         Ok(post)
     }
 }
 
+// guess the visibility of a remote article from the people it is addressed to
+fn visibility_from_audience(article: &Article, authors: &[User]) -> &'static str {
+    let addresses =
+        |v: &Option<serde_json::Value>| match v.as_ref().unwrap_or(&serde_json::Value::Null) {
+            serde_json::Value::Array(v) => v
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .map(str::to_owned)
+                .collect(),
+            serde_json::Value::String(s) => vec![s.clone()],
+            _ => vec![],
+        };
+    let to = addresses(&article.object_props.to);
+    let others = addresses(&article.object_props.cc)
+        .into_iter()
+        .chain(addresses(&article.object_props.bto))
+        .chain(addresses(&article.object_props.bcc))
+        .collect::<Vec<_>>();
+
+    if to.iter().any(|a| a == PUBLIC_VISIBILITY) {
+        post_visibility::PUBLIC
+    } else if others.iter().any(|a| a == PUBLIC_VISIBILITY) {
+        post_visibility::UNLISTED
+    } else if to
+        .iter()
+        .chain(others.iter())
+        .any(|a| authors.iter().any(|u| &u.followers_endpoint == a))
+    {
+        post_visibility::FOLLOWERS
+    } else {
+        post_visibility::DIRECT
+    }
+}
+
 impl AsObject<User, Create, &PlumeRocket> for Post {
     type Error = Error;
     type Output = Post;
@@ -777,7 +1230,7 @@ impl FromId<PlumeRocket> for PostUpdate {
             cover: updated
                 .object
                 .object_props
-                .icon_object::<Image>()
+                .icon_object::<PreviewedImage>()
                 .ok()
                 .and_then(|img| Media::from_activity(&c, &img).ok().map(|m| m.id)),
             source: updated
@@ -805,9 +1258,11 @@ impl AsObject<User, Update, &PlumeRocket> for PostUpdate {
             // TODO: maybe the author was added in the meantime
             return Err(Error::Unauthorized);
         }
+        let previous = post.clone();
 
         if let Some(title) = self.title {
-            post.slug = title.to_kebab_case();
+            let slug = Post::unique_slug(conn, post.blog_id, &make_slug(&title), Some(post.id))?;
+            post.change_slug(conn, slug)?;
             post.title = title;
         }
 
@@ -861,7 +1316,8 @@ impl AsObject<User, Update, &PlumeRocket> for PostUpdate {
             post.update_hashtags(conn, hashtags)?;
         }
 
-        post.update(conn, searcher)?;
+        let post = post.update(conn, searcher)?;
+        PostRevision::record(conn, &previous, &post, Some(&actor))?;
         Ok(())
     }
 }
@@ -875,9 +1331,12 @@ impl IntoId for Post {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::follows::{Follow, NewFollow};
     use crate::inbox::{inbox, tests::fill_database, InboxResult};
+    use crate::medias::NewMedia;
     use crate::safe_string::SafeString;
     use crate::tests::rockets;
+    use chrono::Duration;
     use diesel::Connection;
 
     // creates a post, get it's Create activity, delete the post,
@@ -902,6 +1361,8 @@ mod tests {
                     subtitle: "Testing".into(),
                     source: "Hello".into(),
                     cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
                 },
                 &r.searcher,
             )
@@ -933,6 +1394,261 @@ mod tests {
         });
     }
 
+    #[test]
+    fn publish_scheduled() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (_, users, blogs) = fill_database(&r);
+            let mut posts = vec![];
+            let today = Utc::now().naive_utc().date().and_hms(0, 0, 0);
+            for (slug, publish_at) in &[
+                ("past", today - Duration::days(1)),
+                ("future", today + Duration::days(2)),
+            ] {
+                let post = Post::insert(
+                    conn,
+                    NewPost {
+                        blog_id: blogs[0].id,
+                        slug: slug.to_string(),
+                        title: slug.to_string(),
+                        content: SafeString::new("Hello"),
+                        published: false,
+                        license: "WTFPL".to_string(),
+                        creation_date: None,
+                        ap_url: String::new(),
+                        subtitle: String::new(),
+                        source: "Hello".into(),
+                        cover_id: None,
+                        publish_at: Some(*publish_at),
+                        visibility: post_visibility::PUBLIC.to_owned(),
+                    },
+                    &r.searcher,
+                )
+                .unwrap();
+                PostAuthor::insert(
+                    conn,
+                    NewPostAuthor {
+                        post_id: post.id,
+                        author_id: users[0].id,
+                    },
+                )
+                .unwrap();
+                posts.push(post);
+            }
+            assert_eq!(Post::scheduled_by_author(conn, &users[0]).unwrap().len(), 2);
+            assert!(Post::drafts_by_author(conn, &users[0])
+                .unwrap()
+                .iter()
+                .all(|(p, _)| p.publish_at.is_none()));
+
+            Post::publish_scheduled(conn, &r.searcher).unwrap();
+
+            let past = Post::get(conn, posts[0].id).unwrap();
+            assert!(past.published);
+            assert!(past.publish_at.is_none());
+            assert_eq!(past.creation_date, posts[0].publish_at.unwrap());
+            let future = Post::get(conn, posts[1].id).unwrap();
+            assert!(!future.published);
+            assert_eq!(future.publish_at, posts[1].publish_at);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn visibility() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (posts, users, blogs) = fill_database(&r);
+            Follow::insert(
+                conn,
+                NewFollow {
+                    follower_id: users[1].id,
+                    following_id: users[0].id,
+                    ap_url: String::new(),
+                },
+            )
+            .unwrap();
+            let is_listed = |post: &Post| {
+                Post::get_recents_page(conn, (0, 10))
+                    .unwrap()
+                    .iter()
+                    .any(|p| p.id == post.id)
+            };
+
+            let mut post = posts[0].clone();
+            post.visibility = post_visibility::FOLLOWERS.to_owned();
+            let post = post.update(conn, &r.searcher).unwrap();
+            assert!(post.can_see(conn, Some(&users[0])));
+            assert!(post.can_see(conn, Some(&users[1])));
+            assert!(!post.can_see(conn, Some(&users[2])));
+            assert!(!post.can_see(conn, None));
+            assert!(!is_listed(&post));
+            let to = post
+                .to_activity(conn)
+                .unwrap()
+                .object
+                .object_props
+                .to_link_vec::<Id>()
+                .unwrap();
+            assert!(to
+                .iter()
+                .any(|id| id.as_ref() == users[0].followers_endpoint));
+            assert!(to.iter().all(|id| id.as_ref() != PUBLIC_VISIBILITY));
+
+            let mut post = post;
+            post.visibility = post_visibility::UNLISTED.to_owned();
+            let post = post.update(conn, &r.searcher).unwrap();
+            assert!(post.can_see(conn, None));
+            assert!(!is_listed(&post));
+            assert!(Post::blog_page(conn, &blogs[0], (0, 10))
+                .unwrap()
+                .iter()
+                .any(|p| p.id == post.id));
+            let cc = post
+                .to_activity(conn)
+                .unwrap()
+                .object
+                .object_props
+                .cc_link_vec::<Id>()
+                .unwrap();
+            assert!(cc.iter().any(|id| id.as_ref() == PUBLIC_VISIBILITY));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn slug_history() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (posts, _, _) = fill_database(&r);
+            let mut post = posts[0].clone();
+            let first_slug = post.slug.clone();
+            let ap_url = post.ap_url.clone();
+            assert_eq!(
+                Post::unique_slug(conn, post.blog_id, &first_slug, None).unwrap(),
+                format!("{}-2", first_slug)
+            );
+            assert_eq!(
+                Post::unique_slug(conn, post.blog_id, &first_slug, Some(post.id)).unwrap(),
+                first_slug
+            );
+
+            post.change_slug(conn, "renamed".to_owned()).unwrap();
+            let mut post = post.update(conn, &r.searcher).unwrap();
+            assert_eq!(post.slug, "renamed");
+            assert_eq!(post.ap_url, ap_url);
+            assert_eq!(
+                PreviousSlug::find_post(conn, &first_slug, post.blog_id)
+                    .unwrap()
+                    .id,
+                post.id
+            );
+            // other posts can't take the old URL
+            assert_eq!(
+                Post::unique_slug(conn, post.blog_id, &first_slug, None).unwrap(),
+                format!("{}-2", first_slug)
+            );
+
+            post.change_slug(conn, first_slug.clone()).unwrap();
+            let post = post.update(conn, &r.searcher).unwrap();
+            assert!(PreviousSlug::find_post(conn, &first_slug, post.blog_id).is_err());
+            assert_eq!(
+                PreviousSlug::find_post(conn, "renamed", post.blog_id)
+                    .unwrap()
+                    .id,
+                post.id
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn attachments() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (_, users, blogs) = fill_database(&r);
+            let media = Media::insert(
+                conn,
+                NewMedia {
+                    file_path: "episode.ogg".to_owned(),
+                    alt_text: "First episode".to_owned(),
+                    is_remote: false,
+                    remote_url: None,
+                    sensitive: false,
+                    content_warning: None,
+                    owner_id: users[0].id,
+                    size: 1234,
+                    album_id: None,
+                    original_name: String::new(),
+                },
+            )
+            .unwrap();
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "episode".into(),
+                    title: "Episode".into(),
+                    content: SafeString::new(""),
+                    published: true,
+                    license: "WTFPL".to_string(),
+                    creation_date: None,
+                    ap_url: String::new(),
+                    subtitle: String::new(),
+                    source: format!("Listen: ![First episode]({})", media.id),
+                    cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
+                },
+                &r.searcher,
+            )
+            .unwrap();
+            PostAuthor::insert(
+                conn,
+                NewPostAuthor {
+                    post_id: post.id,
+                    author_id: users[1].id,
+                },
+            )
+            .unwrap();
+            // the media doesn't belong to an author
+            assert!(post.attachments(conn).unwrap().is_empty());
+
+            PostAuthor::insert(
+                conn,
+                NewPostAuthor {
+                    post_id: post.id,
+                    author_id: users[0].id,
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                post.attachments(conn)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>(),
+                vec![media.id]
+            );
+
+            let article = serde_json::to_value(post.to_activity(conn).unwrap()).unwrap();
+            let attachment = &article["attachment"][0];
+            assert_eq!(attachment["type"], "Audio");
+            assert_eq!(attachment["mediaType"], "audio/ogg");
+            assert_eq!(attachment["name"], "First episode");
+            assert_eq!(attachment["size"], 1234);
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn licensed_article_serde() {
         let mut article = Article::default();
@@ -28,6 +28,7 @@ lazy_static! {
             )
             .add_tag_attributes("video", ["src", "title", "controls"].iter())
             .add_tag_attributes("audio", ["src", "title", "controls"].iter())
+            .add_tag_attributes("img", ["srcset", "sizes"].iter())
             .add_tag_attributes("label", ["for"].iter())
             .add_tag_attributes("input", ["type", "checked"].iter())
             .add_allowed_classes("input", ["cw-checkbox"].iter())
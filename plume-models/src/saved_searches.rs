@@ -0,0 +1,259 @@
+use chrono::NaiveDateTime;
+use diesel::{self, dsl::not, ExpressionMethods, QueryDsl, RunQueryDsl};
+use plume_common::utils::random_hex;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use notifications::*;
+use posts::Post;
+use schema::{saved_search_terms, saved_searches};
+use search::{Query, SearchDocument, Searcher};
+use {Connection, Error, Result};
+
+/// A search query a user wants to follow
+#[derive(Clone, Queryable, Identifiable)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub user_id: i32,
+    pub query: String,
+    pub creation_date: NaiveDateTime,
+    /// Used in the URL of the Atom feed of this search, instead of its id
+    pub token: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "saved_searches"]
+pub struct NewSavedSearch {
+    pub user_id: i32,
+    pub query: String,
+    pub token: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "saved_search_terms"]
+struct NewSavedSearchTerm {
+    saved_search_id: i32,
+    term: String,
+}
+
+impl SavedSearch {
+    insert!(saved_searches, NewSavedSearch, |inserted, conn| {
+        Self::index(conn, inserted.id, &inserted.query)?;
+        Ok(inserted)
+    });
+    get!(saved_searches);
+    find_by!(saved_searches, find_by_token, token as &str);
+    list_by!(saved_searches, list_for_user, user_id as i32);
+
+    /// Save a search for `user_id`, with a new secret feed token
+    pub fn create(conn: &Connection, user_id: i32, query: String) -> Result<SavedSearch> {
+        SavedSearch::insert(
+            conn,
+            NewSavedSearch {
+                user_id,
+                query,
+                token: random_hex(),
+            },
+        )
+    }
+
+    /// Save the words posts have to contain to match a search, to quickly find the searches
+    /// matching a new post. Searches that can't be indexed are checked every time.
+    fn index(conn: &Connection, id: i32, query: &str) -> Result<()> {
+        let query = Query::from_str(query).unwrap_or_default();
+        if let Some(words) = SearchDocument::required_words(query) {
+            let terms = words
+                .into_iter()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .map(|term| NewSavedSearchTerm {
+                    saved_search_id: id,
+                    term,
+                })
+                .collect::<Vec<_>>();
+            for term in terms {
+                diesel::insert_into(saved_search_terms::table)
+                    .values(term)
+                    .execute(conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Index the searches saved before their terms were
+    pub fn index_all(conn: &Connection) -> Result<()> {
+        for (id, query) in saved_searches::table
+            .select((saved_searches::id, saved_searches::query))
+            .load::<(i32, String)>(conn)?
+        {
+            Self::index(conn, id, &query)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_query(&self) -> Query {
+        Query::from_str(&self.query).unwrap_or_default()
+    }
+
+    /// The most relevant posts matching this search, newest first
+    pub fn results(&self, conn: &Connection, searcher: &Searcher, limit: i32) -> Result<Vec<Post>> {
+        let mut posts = searcher.search_document(conn, self.to_query(), (0, limit))?;
+        posts.sort_by(|a, b| b.creation_date.cmp(&a.creation_date));
+        Ok(posts)
+    }
+
+    /// Notify the users who saved a search matching this newly published post
+    pub fn notify_matches(conn: &Connection, post: &Post) -> Result<()> {
+        if !post.published {
+            return Ok(());
+        }
+
+        let document = SearchDocument::from_post(conn, post)?;
+        if !document.visible {
+            return Ok(());
+        }
+        let mut notified = post
+            .get_authors(conn)?
+            .into_iter()
+            .map(|a| a.id)
+            .collect::<HashSet<_>>();
+        // only check the searches sharing a word with this post, and the ones without words
+        let indexed = saved_search_terms::table.select(saved_search_terms::saved_search_id);
+        let mut ids = saved_searches::table
+            .filter(not(saved_searches::id.eq_any(indexed)))
+            .select(saved_searches::id)
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let words = document.words().into_iter().collect::<Vec<_>>();
+        // SQLite limits the number of parameters of a query
+        for words in words.chunks(500) {
+            ids.extend(
+                indexed
+                    .filter(saved_search_terms::term.eq_any(words))
+                    .load::<i32>(conn)?,
+            );
+        }
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let mut candidates = vec![];
+        for ids in ids.chunks(500) {
+            candidates.extend(
+                saved_searches::table
+                    .filter(saved_searches::id.eq_any(ids))
+                    .load::<SavedSearch>(conn)?,
+            );
+        }
+        for search in candidates {
+            if notified.contains(&search.user_id) || !document.matches(search.to_query()) {
+                continue;
+            }
+            // the post may have already been published and switched back to draft
+            let already_notified = Notification::find_for_matched_post(conn, post)?
+                .iter()
+                .any(|n| n.user_id == search.user_id);
+            if !already_notified {
+                Notification::insert(
+                    conn,
+                    NewNotification {
+                        kind: notification_kind::SAVED_SEARCH.to_string(),
+                        object_id: post.id,
+                        user_id: search.user_id,
+                    },
+                )?;
+            }
+            notified.insert(search.user_id);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use post_authors::*;
+    use posts::{post_visibility, NewPost};
+    use safe_string::SafeString;
+    use search::tests::get_searcher;
+    use tags::*;
+    use tests::db;
+
+    #[test]
+    fn notify_matches() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            let searcher = get_searcher();
+
+            let rust =
+                SavedSearch::create(conn, users[1].id, "tag:Rust \"borrow checker\"".to_owned())
+                    .unwrap();
+            SavedSearch::create(conn, users[2].id, "tag:Haskell".to_owned()).unwrap();
+            // searches without words are always checked
+            SavedSearch::create(conn, users[2].id, "lang:english".to_owned()).unwrap();
+            assert_eq!(
+                SavedSearch::list_for_user(conn, users[1].id).unwrap()[0].id,
+                rust.id
+            );
+
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "borrowck".to_owned(),
+                    title: "Fighting the borrow checker".to_owned(),
+                    content: SafeString::new(""),
+                    published: true,
+                    license: "CC-BY-SA".to_owned(),
+                    ap_url: "".to_owned(),
+                    creation_date: None,
+                    subtitle: "".to_owned(),
+                    source: "".to_owned(),
+                    cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
+                },
+                &searcher,
+            )
+            .unwrap();
+            PostAuthor::insert(
+                conn,
+                NewPostAuthor {
+                    post_id: post.id,
+                    author_id: users[0].id,
+                },
+            )
+            .unwrap();
+            Tag::insert(
+                conn,
+                NewTag {
+                    tag: "Rust".to_owned(),
+                    is_hashtag: false,
+                    post_id: post.id,
+                },
+            )
+            .unwrap();
+
+            SavedSearch::notify_matches(conn, &post).unwrap();
+            // publishing again doesn't notify twice
+            SavedSearch::notify_matches(conn, &post).unwrap();
+
+            let mut notifications = Notification::find_for_matched_post(conn, &post).unwrap();
+            notifications.sort_by_key(|n| n.user_id);
+            assert_eq!(notifications.len(), 2);
+            assert_eq!(notifications[0].user_id, users[1].id);
+            assert_eq!(notifications[1].user_id, users[2].id);
+            assert_eq!(notifications[0].get_post(conn).unwrap().id, post.id);
+
+            Ok(())
+        });
+    }
+}
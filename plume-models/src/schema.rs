@@ -26,7 +26,19 @@ table! {
         id -> Int4,
         blog_id -> Int4,
         author_id -> Int4,
-        is_owner -> Bool,
+        role -> Varchar,
+    }
+}
+
+table! {
+    blog_invitations (id) {
+        id -> Int4,
+        blog_id -> Int4,
+        user_id -> Int4,
+        invited_by_id -> Int4,
+        role -> Varchar,
+        ap_url -> Varchar,
+        creation_date -> Timestamp,
     }
 }
 
@@ -48,6 +60,7 @@ table! {
         icon_id -> Nullable<Int4>,
         banner_id -> Nullable<Int4>,
         theme -> Nullable<Varchar>,
+        require_review -> Bool,
     }
 }
 
@@ -97,6 +110,7 @@ table! {
         default_license -> Text,
         long_description_html -> Varchar,
         short_description_html -> Varchar,
+        require_admin_2fa -> Bool,
     }
 }
 
@@ -110,6 +124,15 @@ table! {
     }
 }
 
+table! {
+    media_albums (id) {
+        id -> Int4,
+        owner_id -> Int4,
+        name -> Varchar,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     medias (id) {
         id -> Int4,
@@ -120,6 +143,18 @@ table! {
         sensitive -> Bool,
         content_warning -> Nullable<Text>,
         owner_id -> Int4,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        variants -> Text,
+        size -> Int4,
+        cached_at -> Nullable<Timestamp>,
+        album_id -> Nullable<Int4>,
+        original_name -> Text,
+        duration -> Nullable<Int4>,
+        blurhash -> Nullable<Text>,
+        focal_x -> Nullable<Float4>,
+        focal_y -> Nullable<Float4>,
+        variants_size -> Int4,
     }
 }
 
@@ -159,6 +194,40 @@ table! {
     }
 }
 
+table! {
+    post_reviews (id) {
+        id -> Int4,
+        post_id -> Int4,
+        requested_by_id -> Int4,
+        reviewer_id -> Nullable<Int4>,
+        status -> Varchar,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    post_revisions (id) {
+        id -> Int4,
+        post_id -> Int4,
+        author_id -> Nullable<Int4>,
+        title -> Text,
+        subtitle -> Text,
+        source -> Text,
+        license -> Text,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    post_slug_history (id) {
+        id -> Int4,
+        post_id -> Int4,
+        blog_id -> Int4,
+        slug -> Varchar,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     posts (id) {
         id -> Int4,
@@ -173,6 +242,16 @@ table! {
         subtitle -> Text,
         source -> Text,
         cover_id -> Nullable<Int4>,
+        publish_at -> Nullable<Timestamp>,
+        visibility -> Varchar,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Varchar,
     }
 }
 
@@ -186,6 +265,68 @@ table! {
     }
 }
 
+table! {
+    review_comments (id) {
+        id -> Int4,
+        review_id -> Int4,
+        author_id -> Int4,
+        quote -> Text,
+        content -> Text,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    saved_search_terms (id) {
+        id -> Int4,
+        saved_search_id -> Int4,
+        term -> Text,
+    }
+}
+
+table! {
+    saved_searches (id) {
+        id -> Int4,
+        user_id -> Int4,
+        query -> Text,
+        creation_date -> Timestamp,
+        token -> Varchar,
+    }
+}
+
+table! {
+    series (id) {
+        id -> Int4,
+        blog_id -> Int4,
+        slug -> Varchar,
+        title -> Varchar,
+        description -> Text,
+        ap_url -> Varchar,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    series_posts (id) {
+        id -> Int4,
+        series_id -> Int4,
+        post_id -> Int4,
+        position -> Int4,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token -> Varchar,
+        creation_date -> Timestamp,
+        last_seen -> Timestamp,
+        user_agent -> Varchar,
+        ip -> Varchar,
+    }
+}
+
 table! {
     tags (id) {
         id -> Int4,
@@ -195,6 +336,28 @@ table! {
     }
 }
 
+table! {
+    two_factor_auths (id) {
+        id -> Int4,
+        user_id -> Int4,
+        secret -> Varchar,
+        last_step -> Int4,
+        creation_date -> Timestamp,
+        failed_attempts -> Int4,
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    user_exports (id) {
+        id -> Int4,
+        user_id -> Int4,
+        file_path -> Varchar,
+        ready -> Bool,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     users (id) {
         id -> Int4,
@@ -219,6 +382,8 @@ table! {
         summary_html -> Text,
         preferred_theme -> Nullable<Varchar>,
         hide_custom_css -> Bool,
+        also_known_as -> Text,
+        moved_to -> Nullable<Text>,
     }
 }
 
@@ -226,6 +391,7 @@ joinable!(api_tokens -> apps (app_id));
 joinable!(api_tokens -> users (user_id));
 joinable!(blog_authors -> blogs (blog_id));
 joinable!(blog_authors -> users (author_id));
+joinable!(blog_invitations -> blogs (blog_id));
 joinable!(blogs -> instances (instance_id));
 joinable!(comment_seers -> comments (comment_id));
 joinable!(comment_seers -> users (user_id));
@@ -233,36 +399,68 @@ joinable!(comments -> posts (post_id));
 joinable!(comments -> users (author_id));
 joinable!(likes -> posts (post_id));
 joinable!(likes -> users (user_id));
+joinable!(media_albums -> users (owner_id));
+joinable!(medias -> media_albums (album_id));
 joinable!(mentions -> comments (comment_id));
 joinable!(mentions -> posts (post_id));
 joinable!(mentions -> users (mentioned_id));
 joinable!(notifications -> users (user_id));
 joinable!(post_authors -> posts (post_id));
 joinable!(post_authors -> users (author_id));
+joinable!(post_reviews -> posts (post_id));
+joinable!(post_revisions -> posts (post_id));
+joinable!(post_revisions -> users (author_id));
+joinable!(post_slug_history -> blogs (blog_id));
+joinable!(post_slug_history -> posts (post_id));
 joinable!(posts -> blogs (blog_id));
 joinable!(posts -> medias (cover_id));
+joinable!(recovery_codes -> users (user_id));
 joinable!(reshares -> posts (post_id));
 joinable!(reshares -> users (user_id));
+joinable!(review_comments -> post_reviews (review_id));
+joinable!(review_comments -> users (author_id));
+joinable!(saved_search_terms -> saved_searches (saved_search_id));
+joinable!(saved_searches -> users (user_id));
+joinable!(series -> blogs (blog_id));
+joinable!(series_posts -> posts (post_id));
+joinable!(series_posts -> series (series_id));
+joinable!(sessions -> users (user_id));
 joinable!(tags -> posts (post_id));
+joinable!(two_factor_auths -> users (user_id));
+joinable!(user_exports -> users (user_id));
 joinable!(users -> instances (instance_id));
 
 allow_tables_to_appear_in_same_query!(
     api_tokens,
     apps,
     blog_authors,
+    blog_invitations,
     blogs,
     comments,
     comment_seers,
     follows,
     instances,
     likes,
+    media_albums,
     medias,
     mentions,
     notifications,
     password_reset_requests,
     post_authors,
+    post_reviews,
+    post_revisions,
+    post_slug_history,
     posts,
+    recovery_codes,
     reshares,
+    review_comments,
+    saved_search_terms,
+    saved_searches,
+    series,
+    series_posts,
+    sessions,
     tags,
+    two_factor_auths,
+    user_exports,
     users,
 );
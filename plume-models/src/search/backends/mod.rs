@@ -0,0 +1,12 @@
+mod tantivy;
+pub use self::tantivy::TantivyBackend;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresBackend as DatabaseBackend;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteBackend as DatabaseBackend;
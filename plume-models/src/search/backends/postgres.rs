@@ -0,0 +1,310 @@
+use chrono::{Datelike, Utc};
+use diesel::{
+    self,
+    dsl::{not, sql},
+    pg::Pg,
+    sql_query,
+    sql_types::{BigInt, Bool, Float, Integer, Text},
+    BoolExpressionMethods, BoxableExpression, ExpressionMethods, OptionalExtension, QueryDsl,
+    RunQueryDsl,
+};
+use itertools::Itertools;
+use tantivy::query::Occur;
+
+use posts::Post;
+use search::query::{Criterion, PlumeQuery};
+use search::searcher::{SearchBackend, SearchDocument};
+use {like_prefix, Connection, Result, CONFIG};
+
+table! {
+    post_search_documents (post_id) {
+        post_id -> Int4,
+        creation_date -> Int4,
+        visible -> Bool,
+        instance -> Text,
+        author -> Text,
+        tag -> Text,
+        blog -> Text,
+        title -> Text,
+        subtitle -> Text,
+        content -> Text,
+        lang -> Text,
+        license -> Text,
+    }
+}
+
+#[derive(QueryableByName)]
+struct Word {
+    #[sql_type = "Text"]
+    word: String,
+}
+
+type Filter = Box<dyn BoxableExpression<post_search_documents::table, Pg, SqlType = Bool>>;
+
+/// Full-text search using PostgreSQL's `tsvector`s.
+///
+/// The index lives in the database, so it is shared by every Plume process using it.
+pub struct PostgresBackend;
+
+impl PostgresBackend {
+    fn criterion_to_filter(criterion: Criterion) -> Filter {
+        match criterion {
+            Criterion::Term(field, token) => Self::term_to_filter(field, &token.to_lowercase()),
+            // fuzzy matching would need the pg_trgm extension, match these terms exactly
+            Criterion::Fuzzy(field, token, _) => Self::term_to_filter(field, &token.to_lowercase()),
+            Criterion::Any(criteria) => any(criteria.into_iter().map(Self::criterion_to_filter)),
+            Criterion::DateRange(after, before) => {
+                Box::new(post_search_documents::creation_date.between(after as i32, before as i32))
+            }
+        }
+    }
+
+    fn term_to_filter(field: &'static str, token: &str) -> Filter {
+        match field {
+            "author" | "blog" if token.contains('@') => {
+                let pos = token.find('@').unwrap();
+                let local: Filter = Box::new(
+                    Self::term_to_filter(field, &token[..pos])
+                        .and(Self::term_to_filter("instance", &token[pos + 1..])),
+                );
+                if field == "author" {
+                    // remote authors are indexed with their fqn
+                    Box::new(Self::word_filter(field, token).or(local))
+                } else {
+                    local
+                }
+            }
+            "instance" | "author" | "tag" => any(token
+                .split_whitespace()
+                .map(|word| Self::word_filter(field, word))),
+            "lang" | "license" => Box::new(
+                sql::<Bool>(&format!("strpos(lower({}), ", field))
+                    .bind::<Text, _>(token.to_owned())
+                    .sql(") > 0"),
+            ),
+            "blog" => Box::new(
+                // tags are indexed with the same weight as the blog, the column makes sure
+                // this is the blog, but the index on `document` still does most of the work
+                Self::document_filter("C", token).and(
+                    sql::<Bool>("to_tsvector('simple', blog) @@ phraseto_tsquery('simple', ")
+                        .bind::<Text, _>(token.to_owned())
+                        .sql(")"),
+                ),
+            ),
+            "title" => Self::document_filter("A", token),
+            "subtitle" => Self::document_filter("B", token),
+            _ => Self::document_filter("D", token),
+        }
+    }
+
+    // the words of the token, in this order, with the given weight in the indexed `document`
+    fn document_filter(weight: &str, token: &str) -> Filter {
+        let words = token
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| format!("{}:{}", w, weight))
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            return Box::new(sql::<Bool>("FALSE"));
+        }
+        Box::new(
+            sql::<Bool>("document @@ to_tsquery('simple', ")
+                .bind::<Text, _>(words.join(" <-> "))
+                .sql(")"),
+        )
+    }
+
+    // the field, a space separated list, contains this exact word
+    fn word_filter(field: &'static str, word: &str) -> Filter {
+        Box::new(
+            sql::<Bool>(&format!("strpos(' ' || lower({}) || ' ', ' ' || ", field))
+                .bind::<Text, _>(word.to_owned())
+                .sql(" || ' ') > 0"),
+        )
+    }
+
+    // collect the words used to rank the results
+    fn rank_words(criterion: &Criterion, words: &mut Vec<String>) {
+        match criterion {
+            Criterion::Term(field, token) | Criterion::Fuzzy(field, token, _)
+                if ["title", "subtitle", "content", "blog"].contains(field) =>
+            {
+                words.extend(
+                    token
+                        .split(|c: char| !c.is_alphanumeric())
+                        .filter(|w| !w.is_empty())
+                        .map(str::to_lowercase),
+                )
+            }
+            Criterion::Any(criteria) => {
+                for criterion in criteria {
+                    Self::rank_words(criterion, words);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn any<I: Iterator<Item = Filter>>(filters: I) -> Filter {
+    filters
+        .fold1(|a, b| Box::new(a.or(b)) as Filter)
+        .unwrap_or_else(|| Box::new(sql::<Bool>("FALSE")))
+}
+
+/// Keep `post_search_words`, the words of visible titles used for suggestions, up to date
+fn count_words(conn: &Connection, title: &str, delta: i32) -> Result<()> {
+    sql_query(
+        "INSERT INTO post_search_words (word, ndoc) \
+         SELECT lexeme, $2 FROM unnest(to_tsvector('simple', $1)) \
+         ON CONFLICT (word) DO UPDATE SET ndoc = post_search_words.ndoc + $2",
+    )
+    .bind::<Text, _>(title)
+    .bind::<Integer, _>(delta)
+    .execute(conn)?;
+    if delta < 0 {
+        sql_query("DELETE FROM post_search_words WHERE ndoc <= 0").execute(conn)?;
+    }
+    Ok(())
+}
+
+impl SearchBackend for PostgresBackend {
+    fn add_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        if !post.published {
+            return Ok(());
+        }
+
+        let document = SearchDocument::from_post(conn, post)?;
+        if document.visible {
+            count_words(conn, &document.title, 1)?;
+        }
+        sql_query(
+            "INSERT INTO post_search_documents (post_id, creation_date, visible, instance, author, \
+             tag, blog, title, subtitle, content, lang, license, document) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, \
+             setweight(to_tsvector('simple', $8), 'A') || \
+             setweight(to_tsvector('simple', $9), 'B') || \
+             setweight(to_tsvector('simple', $6 || ' ' || $7), 'C') || \
+             setweight(to_tsvector('simple', $10), 'D'))",
+        )
+        .bind::<Integer, _>(document.post_id)
+        .bind::<Integer, _>(document.creation_date)
+        .bind::<Bool, _>(document.visible)
+        .bind::<Text, _>(document.instance)
+        .bind::<Text, _>(document.author)
+        .bind::<Text, _>(document.tag)
+        .bind::<Text, _>(document.blog)
+        .bind::<Text, _>(document.title)
+        .bind::<Text, _>(document.subtitle)
+        .bind::<Text, _>(document.content)
+        .bind::<Text, _>(document.lang)
+        .bind::<Text, _>(document.license)
+        .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        let title = post_search_documents::table
+            .filter(post_search_documents::post_id.eq(post.id))
+            .filter(post_search_documents::visible.eq(true))
+            .select(post_search_documents::title)
+            .first::<String>(conn)
+            .optional()?;
+        if let Some(title) = title {
+            count_words(conn, &title, -1)?;
+        }
+        diesel::delete(
+            post_search_documents::table.filter(post_search_documents::post_id.eq(post.id)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    fn search_document(
+        &self,
+        conn: &Connection,
+        query: PlumeQuery,
+        (min, max): (i32, i32),
+    ) -> Result<Vec<Post>> {
+        let mut musts = vec![];
+        let mut shoulds = vec![];
+        let mut must_nots = vec![];
+        let mut rank_words = vec![];
+        for (occur, criterion) in query.into_criteria() {
+            if occur != Occur::MustNot {
+                Self::rank_words(&criterion, &mut rank_words);
+            }
+            let filter = Self::criterion_to_filter(criterion);
+            match occur {
+                Occur::Must => musts.push(filter),
+                Occur::Should => shoulds.push(filter),
+                Occur::MustNot => must_nots.push(filter),
+            }
+        }
+
+        let mut request = post_search_documents::table
+            .select(post_search_documents::post_id)
+            .filter(post_search_documents::visible.eq(true))
+            .into_boxed();
+        if musts.is_empty() {
+            // like tantivy, a query with nothing required matches if any optional part does
+            if shoulds.is_empty() {
+                return Ok(vec![]);
+            }
+            request = request.filter(any(shoulds.into_iter()));
+        } else {
+            for filter in musts {
+                request = request.filter(filter);
+            }
+        }
+        for filter in must_nots {
+            request = request.filter(not(filter));
+        }
+
+        if !rank_words.is_empty() {
+            let relevance = &CONFIG.search_relevance;
+            // weights are given from D to A, and PostgreSQL only accepts them between 0 and 1
+            let [title, subtitle, tag, content] = relevance.normalized_boosts();
+            let rank = format!(
+                "ts_rank('{{{:.3}, {:.3}, {:.3}, {:.3}}}', document, to_tsquery('simple', ",
+                content, tag, subtitle, title
+            );
+            let recency = if relevance.recency_half_life > 0.0 {
+                format!(
+                    " * {0:.2}::real / ({0:.2}::real + GREATEST({1} - creation_date, 0))",
+                    relevance.recency_half_life,
+                    Utc::today().num_days_from_ce()
+                )
+            } else {
+                String::new()
+            };
+            request = request.order(
+                sql::<Float>(&rank)
+                    .bind::<Text, _>(rank_words.join(" | "))
+                    .sql(&format!(")){}", recency))
+                    .desc(),
+            );
+        }
+
+        Ok(request
+            .then_order_by(post_search_documents::creation_date.desc())
+            .offset(min.into())
+            .limit((max - min).into())
+            .load::<i32>(conn)?
+            .into_iter()
+            .filter_map(|id| Post::get(conn, id).ok())
+            .collect())
+    }
+
+    fn suggest_terms(&self, conn: &Connection, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        Ok(sql_query(
+            "SELECT word FROM post_search_words WHERE word LIKE $1 ORDER BY ndoc DESC LIMIT $2",
+        )
+        .bind::<Text, _>(like_prefix(&prefix.to_lowercase()))
+        .bind::<BigInt, _>(limit as i64)
+        .load::<Word>(conn)?
+        .into_iter()
+        .map(|w| w.word)
+        .collect())
+    }
+}
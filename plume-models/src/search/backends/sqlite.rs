@@ -0,0 +1,245 @@
+use chrono::{Datelike, Utc};
+use diesel::{
+    connection::SimpleConnection,
+    sql_query,
+    sql_types::{Bool, Integer, Text},
+    RunQueryDsl,
+};
+use tantivy::query::Occur;
+
+use posts::Post;
+use search::query::{Criterion, PlumeQuery};
+use search::searcher::{SearchBackend, SearchDocument};
+use {Connection, Error, Result, CONFIG};
+
+#[derive(QueryableByName)]
+struct SearchResult {
+    #[sql_type = "Integer"]
+    post_id: i32,
+}
+
+#[derive(QueryableByName)]
+struct Term {
+    #[sql_type = "Text"]
+    term: String,
+}
+
+/// Full-text search using SQLite's FTS5 extension.
+///
+/// The index lives in the database, so it is shared by every Plume process using it.
+pub struct SqliteBackend;
+
+impl SqliteBackend {
+    /// Create the FTS5 tables of the index if they don't exist yet.
+    ///
+    /// The migrations don't create them, as SQLite may be built without FTS5 and instances
+    /// searching with tantivy don't need it. Without FTS5, this fails with
+    /// "no such module: fts5".
+    fn create_tables(conn: &Connection) -> Result<()> {
+        conn.batch_execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS post_search_documents USING fts5(\
+             creation_date UNINDEXED, instance, author, tag, blog, title, subtitle, content, \
+             lang, license, visible UNINDEXED); \
+             CREATE VIRTUAL TABLE IF NOT EXISTS post_search_terms \
+             USING fts5vocab(post_search_documents, 'col');",
+        )
+        .map_err(Error::from)
+    }
+
+    // translate a criterion to the FTS5 query syntax
+    fn criterion_to_match(criterion: &Criterion) -> Option<String> {
+        match criterion {
+            Criterion::Term(field, token) => {
+                Some(Self::term_to_match(field, &token.to_lowercase()))
+            }
+            // FTS5 can't do fuzzy matching, match these terms exactly
+            Criterion::Fuzzy(field, token, _) => {
+                Some(Self::term_to_match(field, &token.to_lowercase()))
+            }
+            Criterion::Any(criteria) => {
+                let criteria = criteria
+                    .iter()
+                    .filter_map(Self::criterion_to_match)
+                    .collect::<Vec<_>>();
+                if criteria.is_empty() {
+                    None
+                } else {
+                    Some(format!("({})", criteria.join(" OR ")))
+                }
+            }
+            // dates are not part of the full-text index, they are filtered separately
+            Criterion::DateRange(_, _) => None,
+        }
+    }
+
+    fn term_to_match(field: &str, token: &str) -> String {
+        match field {
+            "author" | "blog" if token.contains('@') => {
+                let pos = token.find('@').unwrap();
+                let local = format!(
+                    "({} AND {})",
+                    Self::term_to_match(field, &token[..pos]),
+                    Self::term_to_match("instance", &token[pos + 1..])
+                );
+                if field == "author" {
+                    // remote authors are indexed with their fqn
+                    format!("({} : {} OR {})", field, quote(token), local)
+                } else {
+                    local
+                }
+            }
+            "instance" | "author" | "tag" => format!(
+                "({})",
+                token
+                    .split_whitespace()
+                    .map(|word| format!("{} : {}", field, quote(word)))
+                    .collect::<Vec<_>>()
+                    .join(" OR ")
+            ),
+            "lang" | "license" => format!("{} : {} *", field, quote(token)),
+            _ => format!("{} : {}", field, quote(token)),
+        }
+    }
+}
+
+// quote a string for FTS5, making it a phrase
+fn quote(token: &str) -> String {
+    format!("\"{}\"", token.replace('"', "\"\""))
+}
+
+impl SearchBackend for SqliteBackend {
+    fn add_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        if !post.published {
+            return Ok(());
+        }
+
+        Self::create_tables(conn)?;
+        let document = SearchDocument::from_post(conn, post)?;
+        sql_query(
+            "INSERT INTO post_search_documents (rowid, creation_date, instance, author, tag, \
+             blog, title, subtitle, content, lang, license, visible) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind::<Integer, _>(document.post_id)
+        .bind::<Integer, _>(document.creation_date)
+        .bind::<Text, _>(document.instance)
+        .bind::<Text, _>(document.author)
+        .bind::<Text, _>(document.tag)
+        .bind::<Text, _>(document.blog)
+        .bind::<Text, _>(document.title)
+        .bind::<Text, _>(document.subtitle)
+        .bind::<Text, _>(document.content)
+        .bind::<Text, _>(document.lang)
+        .bind::<Text, _>(document.license)
+        .bind::<Bool, _>(document.visible)
+        .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        Self::create_tables(conn)?;
+        sql_query("DELETE FROM post_search_documents WHERE rowid = ?")
+            .bind::<Integer, _>(post.id)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn search_document(
+        &self,
+        conn: &Connection,
+        query: PlumeQuery,
+        (min, max): (i32, i32),
+    ) -> Result<Vec<Post>> {
+        Self::create_tables(conn)?;
+        let mut musts = vec![];
+        let mut shoulds = vec![];
+        let mut must_nots = vec![];
+        let (mut after, mut before) = (0, i32::max_value());
+        for (occur, criterion) in query.into_criteria() {
+            if let Criterion::DateRange(a, b) = criterion {
+                after = a as i32;
+                before = b as i32;
+            } else if let Some(fts) = Self::criterion_to_match(&criterion) {
+                match occur {
+                    Occur::Must => musts.push(fts),
+                    Occur::Should => shoulds.push(fts),
+                    Occur::MustNot => must_nots.push(fts),
+                }
+            }
+        }
+
+        // FTS5 has no unary NOT, so there must be something to look for
+        let mut fts_query = if musts.is_empty() {
+            if shoulds.is_empty() {
+                return Ok(vec![]);
+            }
+            format!("({})", shoulds.join(" OR "))
+        } else if shoulds.is_empty() {
+            format!("({})", musts.join(" AND "))
+        } else {
+            // optional terms don't filter anything, but they still count when ranking
+            let musts = musts.join(" AND ");
+            format!("({}) AND (({}) OR {})", musts, musts, shoulds.join(" OR "))
+        };
+        if !must_nots.is_empty() {
+            fts_query = format!("{} NOT ({})", fts_query, must_nots.join(" OR "));
+        }
+
+        // bm25 gives negative scores, the lower the better, with a weight for each column
+        let relevance = &CONFIG.search_relevance;
+        let rank = format!(
+            "bm25(post_search_documents, 0, 1, 1, {:.2}, 1, {:.2}, {:.2}, {:.2}, 1, 1, 0)",
+            relevance.tag_boost,
+            relevance.title_boost,
+            relevance.subtitle_boost,
+            relevance.content_boost
+        );
+        let recency = if relevance.recency_half_life > 0.0 {
+            format!(
+                " * {0:.2} / ({0:.2} + MAX({1} - CAST(creation_date AS INTEGER), 0))",
+                relevance.recency_half_life,
+                Utc::today().num_days_from_ce()
+            )
+        } else {
+            String::new()
+        };
+
+        Ok(sql_query(format!(
+            "SELECT rowid AS post_id FROM post_search_documents \
+             WHERE post_search_documents MATCH ? \
+             AND CAST(creation_date AS INTEGER) BETWEEN ? AND ? \
+             AND CAST(visible AS INTEGER) = 1 \
+             ORDER BY {}{} LIMIT ? OFFSET ?",
+            rank, recency
+        ))
+        .bind::<Text, _>(fts_query)
+        .bind::<Integer, _>(after)
+        .bind::<Integer, _>(before)
+        .bind::<Integer, _>(max - min)
+        .bind::<Integer, _>(min)
+        .load::<SearchResult>(conn)?
+        .into_iter()
+        .filter_map(|res| Post::get(conn, res.post_id).ok())
+        .collect())
+    }
+
+    fn suggest_terms(&self, conn: &Connection, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        Self::create_tables(conn)?;
+        // post_search_terms is a fts5vocab table, listing the terms of the index,
+        // including the ones only used by hidden posts
+        Ok(sql_query(
+            "SELECT term FROM post_search_terms \
+             WHERE col = 'title' AND substr(term, 1, length(?1)) = ?1 \
+             AND EXISTS (SELECT 1 FROM post_search_documents \
+             WHERE post_search_documents MATCH 'title : \"' || term || '\"' \
+             AND CAST(visible AS INTEGER) = 1) \
+             ORDER BY doc DESC LIMIT ?2",
+        )
+        .bind::<Text, _>(prefix.to_lowercase())
+        .bind::<Integer, _>(limit as i32)
+        .load::<Term>(conn)?
+        .into_iter()
+        .map(|t| t.term)
+        .collect())
+    }
+}
@@ -0,0 +1,333 @@
+use posts::Post;
+use Connection;
+
+use chrono::{Datelike, Utc};
+use itertools::Itertools;
+use std::{cmp, collections::HashMap, fs::create_dir_all, path::Path, str, sync::Mutex};
+use tantivy::{
+    collector::{Count, TopDocs},
+    directory::MmapDirectory,
+    query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery},
+    schema::*,
+    tokenizer::*,
+    DocId, Index, IndexReader, IndexWriter, ReloadPolicy, Score, SegmentReader, Term,
+};
+
+use search::query::PlumeQuery;
+use search::searcher::{SearchBackend, SearchDocument, SearcherError};
+use search::tokenizer;
+use {Result, CONFIG};
+
+/// How many terms of each segment can be suggested for a prefix
+const MAX_SUGGESTION_CANDIDATES: usize = 200;
+
+/// A tantivy index, stored on the local disk
+pub struct TantivyBackend {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<Option<IndexWriter>>,
+}
+
+impl TantivyBackend {
+    pub fn schema() -> Schema {
+        let tag_indexing = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("whitespace_tokenizer")
+                .set_index_option(IndexRecordOption::Basic),
+        );
+
+        let content_indexing = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("content_tokenizer")
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
+        let property_indexing = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("property_tokenizer")
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
+        let mut schema_builder = SchemaBuilder::default();
+
+        schema_builder.add_i64_field("post_id", STORED | INDEXED);
+        schema_builder.add_i64_field("creation_date", INDEXED | FAST);
+        schema_builder.add_u64_field("visible", INDEXED);
+
+        schema_builder.add_text_field("instance", tag_indexing.clone());
+        schema_builder.add_text_field("author", tag_indexing.clone());
+        schema_builder.add_text_field("tag", tag_indexing);
+
+        schema_builder.add_text_field("blog", content_indexing.clone());
+        schema_builder.add_text_field("content", content_indexing.clone());
+        schema_builder.add_text_field("subtitle", content_indexing.clone());
+        schema_builder.add_text_field("title", content_indexing);
+
+        schema_builder.add_text_field("lang", property_indexing.clone());
+        schema_builder.add_text_field("license", property_indexing);
+
+        schema_builder.build()
+    }
+
+    pub fn create(path: &AsRef<Path>) -> Result<Self> {
+        let whitespace_tokenizer = tokenizer::WhitespaceTokenizer.filter(LowerCaser);
+
+        let content_tokenizer = SimpleTokenizer
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser);
+
+        let property_tokenizer = NgramTokenizer::new(2, 8, false).filter(LowerCaser);
+
+        let schema = Self::schema();
+
+        create_dir_all(path).map_err(|_| SearcherError::IndexCreationError)?;
+        let index = Index::create(
+            MmapDirectory::open(path).map_err(|_| SearcherError::IndexCreationError)?,
+            schema,
+        )
+        .map_err(|_| SearcherError::IndexCreationError)?;
+
+        {
+            let tokenizer_manager = index.tokenizers();
+            tokenizer_manager.register("whitespace_tokenizer", whitespace_tokenizer);
+            tokenizer_manager.register("content_tokenizer", content_tokenizer);
+            tokenizer_manager.register("property_tokenizer", property_tokenizer);
+        } //to please the borrow checker
+        Ok(Self {
+            writer: Mutex::new(Some(
+                index
+                    .writer(50_000_000)
+                    .map_err(|_| SearcherError::WriteLockAcquisitionError)?,
+            )),
+            reader: index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()
+                .map_err(|_| SearcherError::IndexCreationError)?,
+            index,
+        })
+    }
+
+    pub fn open(path: &AsRef<Path>) -> Result<Self> {
+        let whitespace_tokenizer = tokenizer::WhitespaceTokenizer.filter(LowerCaser);
+
+        let content_tokenizer = SimpleTokenizer
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser);
+
+        let property_tokenizer = NgramTokenizer::new(2, 8, false).filter(LowerCaser);
+
+        let index =
+            Index::open(MmapDirectory::open(path).map_err(|_| SearcherError::IndexOpeningError)?)
+                .map_err(|_| SearcherError::IndexSearchError)?;
+
+        {
+            let tokenizer_manager = index.tokenizers();
+            tokenizer_manager.register("whitespace_tokenizer", whitespace_tokenizer);
+            tokenizer_manager.register("content_tokenizer", content_tokenizer);
+            tokenizer_manager.register("property_tokenizer", property_tokenizer);
+        } //to please the borrow checker
+        let mut writer = index
+            .writer(50_000_000)
+            .map_err(|_| SearcherError::WriteLockAcquisitionError)?;
+        writer
+            .garbage_collect_files()
+            .map_err(|_| SearcherError::IndexEditionError)?;
+        Ok(Self {
+            writer: Mutex::new(Some(writer)),
+            reader: index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()
+                .map_err(|_| SearcherError::IndexCreationError)?,
+            index,
+        })
+    }
+}
+
+impl SearchBackend for TantivyBackend {
+    fn add_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        if !post.published {
+            return Ok(());
+        }
+
+        let schema = self.index.schema();
+
+        let post_id = schema.get_field("post_id").unwrap();
+        let creation_date = schema.get_field("creation_date").unwrap();
+        let visible = schema.get_field("visible").unwrap();
+
+        let instance = schema.get_field("instance").unwrap();
+        let author = schema.get_field("author").unwrap();
+        let tag = schema.get_field("tag").unwrap();
+
+        let blog_name = schema.get_field("blog").unwrap();
+        let content = schema.get_field("content").unwrap();
+        let subtitle = schema.get_field("subtitle").unwrap();
+        let title = schema.get_field("title").unwrap();
+
+        let lang = schema.get_field("lang").unwrap();
+        let license = schema.get_field("license").unwrap();
+
+        let document = SearchDocument::from_post(conn, post)?;
+        let mut writer = self.writer.lock().unwrap();
+        let writer = writer.as_mut().unwrap();
+        writer.add_document(doc!(
+            post_id => i64::from(document.post_id),
+            author => document.author,
+            creation_date => i64::from(document.creation_date),
+            visible => u64::from(document.visible),
+            instance => document.instance,
+            tag => document.tag,
+            blog_name => document.blog,
+            content => document.content,
+            subtitle => document.subtitle,
+            title => document.title,
+            lang => document.lang,
+            license => document.license,
+        ));
+        Ok(())
+    }
+
+    fn delete_document(&self, _conn: &Connection, post: &Post) -> Result<()> {
+        let schema = self.index.schema();
+        let post_id = schema.get_field("post_id").unwrap();
+
+        let doc_id = Term::from_field_i64(post_id, i64::from(post.id));
+        let mut writer = self.writer.lock().unwrap();
+        let writer = writer.as_mut().unwrap();
+        writer.delete_term(doc_id);
+        Ok(())
+    }
+
+    fn search_document(
+        &self,
+        conn: &Connection,
+        query: PlumeQuery,
+        (min, max): (i32, i32),
+    ) -> Result<Vec<Post>> {
+        let schema = self.index.schema();
+        let post_id = schema.get_field("post_id").unwrap();
+        let creation_date = schema.get_field("creation_date").unwrap();
+        let visible = schema.get_field("visible").unwrap();
+
+        let today = i64::from(Utc::today().num_days_from_ce());
+        let collector = TopDocs::with_limit(cmp::max(1, max) as usize).tweak_score(
+            move |segment_reader: &SegmentReader| {
+                let creation_dates = segment_reader.fast_fields().i64(creation_date);
+                move |doc: DocId, score: Score| match creation_dates {
+                    Some(ref dates) => {
+                        score * CONFIG.search_relevance.recency(today - dates.get(doc))
+                    }
+                    // indexes created before dates were fast fields can't be ranked by age
+                    None => score,
+                }
+            },
+        );
+
+        let query = query.into_query();
+        // hidden posts are excluded, without changing the score of the others
+        let query = BooleanQuery::from(vec![
+            (Occur::Must, Box::new(query) as Box<dyn Query>),
+            (
+                Occur::Must,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(
+                        Term::from_field_u64(visible, 1),
+                        IndexRecordOption::Basic,
+                    )),
+                    0.0,
+                )),
+            ),
+        ]);
+
+        let searcher = self.reader.searcher();
+        let res = searcher
+            .search(&query, &collector)
+            .map_err(|_| SearcherError::IndexSearchError)?;
+
+        Ok(res
+            .get(min as usize..)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|(_, doc_add)| {
+                let doc = searcher.doc(*doc_add).ok()?;
+                let id = doc.get_first(post_id)?;
+                Post::get(conn, id.i64_value() as i32).ok()
+                //borrow checker don't want me to use filter_map or and_then here
+            })
+            .collect())
+    }
+
+    fn suggest_terms(&self, _conn: &Connection, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        let title = self.index.schema().get_field("title").unwrap();
+        let prefix = prefix.to_lowercase();
+
+        // a term can appear in many segments, add up its frequencies. Short prefixes can match
+        // a lot of terms, only the first ones of each segment are candidates.
+        let mut terms = HashMap::<String, u32>::new();
+        for segment_reader in self.reader.searcher().segment_readers() {
+            let inverted_index = segment_reader.inverted_index(title);
+            let mut stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream();
+            let mut candidates = 0;
+            while candidates < MAX_SUGGESTION_CANDIDATES
+                && stream.advance()
+                && stream.key().starts_with(prefix.as_bytes())
+            {
+                if let Ok(term) = str::from_utf8(stream.key()) {
+                    *terms.entry(term.to_owned()).or_insert(0) += stream.value().doc_freq;
+                    candidates += 1;
+                }
+            }
+        }
+
+        // hidden posts are indexed too, only suggest terms used by visible ones
+        let visible = self.index.schema().get_field("visible").unwrap();
+        let searcher = self.reader.searcher();
+        let is_visible = |term: &str| {
+            let query = BooleanQuery::from(vec![
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(title, term),
+                        IndexRecordOption::Basic,
+                    )) as Box<dyn Query>,
+                ),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_u64(visible, 1),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ]);
+            searcher
+                .search(&query, &Count)
+                .map(|n| n > 0)
+                .unwrap_or(false)
+        };
+
+        // the most frequent terms are checked first, until there are enough visible ones
+        Ok(terms
+            .into_iter()
+            .sorted_by(|(_, a), (_, b)| b.cmp(a))
+            .map(|(term, _)| term)
+            .filter(|term| is_visible(term))
+            .take(limit)
+            .collect())
+    }
+
+    fn commit(&self) {
+        let mut writer = self.writer.lock().unwrap();
+        writer.as_mut().unwrap().commit().unwrap();
+        self.reader.reload().unwrap();
+    }
+
+    fn drop_writer(&self) {
+        self.writer.lock().unwrap().take();
+    }
+}
@@ -1,20 +1,24 @@
+mod backends;
 mod query;
 mod searcher;
 mod tokenizer;
-pub use self::query::PlumeQuery as Query;
+pub use self::backends::*;
+pub use self::query::{Criterion, PlumeQuery as Query};
 pub use self::searcher::*;
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use super::{Query, Searcher};
+    use super::{Criterion, DatabaseBackend, Query, SearchDocument, Searcher};
     use diesel::Connection;
     use std::env::temp_dir;
     use std::str::FromStr;
+    use tantivy::query::Occur;
 
     use blogs::tests::fill_database;
+    use instance::Instance;
     use plume_common::utils::random_hex;
     use post_authors::*;
-    use posts::{NewPost, Post};
+    use posts::{post_visibility, NewPost, Post};
     use safe_string::SafeString;
     use tests::db;
 
@@ -115,11 +119,9 @@ pub(crate) mod tests {
         Searcher::open(&dir).unwrap(); //verify it's well created
     }
 
-    #[test]
-    fn search() {
+    fn search_with(searcher: Searcher) {
         let conn = &db();
         conn.test_transaction::<_, (), _>(|| {
-            let searcher = get_searcher();
             let blog = &fill_database(conn).1[0];
             let author = &blog.list_authors(conn).unwrap()[0];
 
@@ -139,6 +141,8 @@ pub(crate) mod tests {
                     subtitle: "".to_owned(),
                     source: "".to_owned(),
                     cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
                 },
                 &searcher,
             )
@@ -154,7 +158,10 @@ pub(crate) mod tests {
 
             searcher.commit();
             assert_eq!(
-                searcher.search_document(conn, Query::from_str(&title).unwrap(), (0, 1))[0].id,
+                searcher
+                    .search_document(conn, Query::from_str(&title).unwrap(), (0, 1))
+                    .unwrap()[0]
+                    .id,
                 post.id
             );
 
@@ -163,23 +170,240 @@ pub(crate) mod tests {
             post.update(conn, &searcher).unwrap();
             searcher.commit();
             assert_eq!(
-                searcher.search_document(conn, Query::from_str(&newtitle).unwrap(), (0, 1))[0].id,
+                searcher
+                    .search_document(conn, Query::from_str(&newtitle).unwrap(), (0, 1))
+                    .unwrap()[0]
+                    .id,
                 post.id
             );
             assert!(searcher
                 .search_document(conn, Query::from_str(&title).unwrap(), (0, 1))
+                .unwrap()
                 .is_empty());
 
             post.delete(conn, &searcher).unwrap();
             searcher.commit();
             assert!(searcher
                 .search_document(conn, Query::from_str(&newtitle).unwrap(), (0, 1))
+                .unwrap()
                 .is_empty());
 
             Ok(())
         });
     }
 
+    #[test]
+    fn search() {
+        search_with(get_searcher());
+    }
+
+    #[test]
+    fn search_database() {
+        search_with(Searcher::new(Box::new(DatabaseBackend)));
+    }
+
+    #[test]
+    fn fuzzy_and_phrase() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let searcher = get_searcher();
+            let blog = &fill_database(conn).1[0];
+
+            let word = random_hex()[..8].to_owned();
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blog.id,
+                    slug: word.clone(),
+                    title: word.clone(),
+                    content: SafeString::new(&format!("{} jumps over the lazy dog", word)),
+                    published: true,
+                    license: "CC-BY-SA".to_owned(),
+                    ap_url: "".to_owned(),
+                    creation_date: None,
+                    subtitle: "".to_owned(),
+                    source: "".to_owned(),
+                    cover_id: None,
+                    publish_at: None,
+                    visibility: post_visibility::PUBLIC.to_owned(),
+                },
+                &searcher,
+            )
+            .unwrap();
+            searcher.commit();
+
+            let found = |query: &str| {
+                searcher
+                    .search_document(conn, Query::from_str(query).unwrap(), (0, 10))
+                    .unwrap()
+                    .iter()
+                    .any(|p| p.id == post.id)
+            };
+            // one typo is forgiven
+            assert!(found(&format!("title:{}~", &word[..7])));
+            assert!(!found(&format!("title:{}", &word[..7])));
+            assert!(found("content:\"the lazy dog\""));
+            assert!(!found("content:\"dog lazy the\""));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn suggest_terms() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let blog = &fill_database(conn).1[0];
+            for searcher in vec![get_searcher(), Searcher::new(Box::new(DatabaseBackend))] {
+                let title = random_hex()[..8].to_owned();
+                Post::insert(
+                    conn,
+                    NewPost {
+                        blog_id: blog.id,
+                        slug: title.clone(),
+                        title: title.clone(),
+                        content: SafeString::new(""),
+                        published: true,
+                        license: "CC-BY-SA".to_owned(),
+                        ap_url: "".to_owned(),
+                        creation_date: None,
+                        subtitle: "".to_owned(),
+                        source: "".to_owned(),
+                        cover_id: None,
+                        publish_at: None,
+                        visibility: post_visibility::PUBLIC.to_owned(),
+                    },
+                    &searcher,
+                )
+                .unwrap();
+                searcher.commit();
+
+                assert!(searcher
+                    .suggest_terms(conn, &title[..6], 5)
+                    .unwrap()
+                    .contains(&title));
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn blocked_instance() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let blog = &fill_database(conn).1[0];
+            for searcher in vec![get_searcher(), Searcher::new(Box::new(DatabaseBackend))] {
+                let title = random_hex()[..8].to_owned();
+                let post = Post::insert(
+                    conn,
+                    NewPost {
+                        blog_id: blog.id,
+                        slug: title.clone(),
+                        title: title.clone(),
+                        content: SafeString::new(""),
+                        published: true,
+                        license: "CC-BY-SA".to_owned(),
+                        ap_url: "".to_owned(),
+                        creation_date: None,
+                        subtitle: "".to_owned(),
+                        source: "".to_owned(),
+                        cover_id: None,
+                        publish_at: None,
+                        visibility: post_visibility::PUBLIC.to_owned(),
+                    },
+                    &searcher,
+                )
+                .unwrap();
+                searcher.commit();
+                let found = || {
+                    searcher
+                        .search_document(conn, Query::from_str(&title).unwrap(), (0, 1))
+                        .unwrap()
+                        .iter()
+                        .any(|p| p.id == post.id)
+                };
+                assert!(found());
+
+                let instance = Instance::get(conn, blog.instance_id).unwrap();
+                instance.toggle_block(conn, &searcher).unwrap();
+                searcher.commit();
+                assert!(!found());
+
+                Instance::get(conn, blog.instance_id)
+                    .unwrap()
+                    .toggle_block(conn, &searcher)
+                    .unwrap();
+                searcher.commit();
+                assert!(found());
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn criteria() {
+        let vector = vec![
+            (
+                "+something",
+                vec![(
+                    Occur::Must,
+                    Criterion::Any(vec![
+                        Criterion::Term("title", "something".to_owned()),
+                        Criterion::Term("subtitle", "something".to_owned()),
+                        Criterion::Term("content", "something".to_owned()),
+                    ]),
+                )],
+            ),
+            (
+                "-tag:a author:b",
+                vec![
+                    (Occur::MustNot, Criterion::Term("tag", "a".to_owned())),
+                    (Occur::Should, Criterion::Term("author", "b".to_owned())),
+                ],
+            ),
+            (
+                "+lang:fr +lang:en",
+                vec![(
+                    Occur::Must,
+                    Criterion::Any(vec![
+                        Criterion::Term("lang", "fr".to_owned()),
+                        Criterion::Term("lang", "en".to_owned()),
+                    ]),
+                )],
+            ),
+            (
+                "title:helo~ tag:wrld~5",
+                vec![
+                    (
+                        Occur::Should,
+                        Criterion::Fuzzy("title", "helo".to_owned(), 1),
+                    ),
+                    (Occur::Should, Criterion::Fuzzy("tag", "wrld".to_owned(), 2)),
+                ],
+            ),
+        ];
+        for (source, res) in vector {
+            assert_eq!(Query::from_str(source).unwrap().into_criteria(), res);
+        }
+    }
+
+    #[test]
+    fn required_words() {
+        let vector = vec![
+            ("+something", Some(vec!["something"; 3])),
+            ("-tag:a author:b", Some(vec!["b"])),
+            ("+lang:fr +title:\"borrow checker\"", Some(vec!["borrow"])),
+            ("+lang:fr tag:a", None),
+            ("title:helo~ tag:a", None),
+        ];
+        for (source, res) in vector {
+            assert_eq!(
+                SearchDocument::required_words(Query::from_str(source).unwrap()),
+                res.map(|words| words.into_iter().map(str::to_owned).collect())
+            );
+        }
+    }
+
     #[test]
     fn drop_writer() {
         let searcher = get_searcher();
@@ -1,7 +1,8 @@
 use chrono::{naive::NaiveDate, offset::Utc, Datelike};
-use search::searcher::Searcher;
+use search::backends::TantivyBackend;
 use std::{cmp, ops::Bound};
-use tantivy::{query::*, schema::*, Term};
+use tantivy::{query::*, schema::*, tokenizer::*, Term};
+use CONFIG;
 
 //Generate functions for advanced search
 macro_rules! gen_func {
@@ -100,12 +101,12 @@ macro_rules! gen_to_string {
     }
 }
 
-// convert PlumeQuery to Tantivy's Query
-macro_rules! gen_to_query {
+// convert PlumeQuery to a list of backend independent Criterion
+macro_rules! gen_to_criteria {
     ( $self:ident, $result:ident; normal: $($normal:ident),*; oneoff: $($oneoff:ident),*) => {
         $(  // classic fields
             for (occur, token) in $self.$normal {
-                $result.push((occur, Self::token_to_query(&token, stringify!($normal))));
+                $result.push((occur, Criterion::term(stringify!($normal), token)));
             }
         )*
         $(  // fields where having more than on Must make no sense in general, so it's considered a Must be one of these instead.
@@ -113,17 +114,55 @@ macro_rules! gen_to_query {
             let mut subresult = Vec::new();
             for (occur, token) in $self.$oneoff {
                 match occur {
-                    Occur::Must => subresult.push((Occur::Should, Self::token_to_query(&token, stringify!($oneoff)))),
-                    occur => $result.push((occur, Self::token_to_query(&token, stringify!($oneoff)))),
+                    Occur::Must => subresult.push(Criterion::term(stringify!($oneoff), token)),
+                    occur => $result.push((occur, Criterion::term(stringify!($oneoff), token))),
                 }
             }
             if !subresult.is_empty() {
-                $result.push((Occur::Must, Box::new(BooleanQuery::from(subresult))));
+                $result.push((Occur::Must, Criterion::Any(subresult)));
             }
         )*
     }
 }
 
+/// A single condition of a query, independent of the search backend answering it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Criterion {
+    /// The field must contain this token (or phrase, if it contains spaces)
+    Term(&'static str, String),
+    /// The field must contain a word at most this edit distance away from the token
+    Fuzzy(&'static str, String, u8),
+    /// At least one of these criteria must match
+    Any(Vec<Criterion>),
+    /// The creation date, in days from CE, must be between these two bounds (inclusive)
+    DateRange(i64, i64),
+}
+
+/// Fuzzy terms allowing more edits than that are too slow and too vague
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+impl Criterion {
+    // a word followed by `~` (or `~N`) is a fuzzy term, everything else is a simple term
+    fn term(field: &'static str, token: String) -> Self {
+        if !token.contains(' ') {
+            if let Some(pos) = token.rfind('~').filter(|pos| *pos > 0) {
+                let distance = match &token[pos + 1..] {
+                    "" => Some(1),
+                    distance => distance.parse::<u8>().ok(),
+                };
+                if let Some(distance) = distance {
+                    return Criterion::Fuzzy(
+                        field,
+                        token[..pos].to_owned(),
+                        cmp::min(distance, MAX_FUZZY_DISTANCE),
+                    );
+                }
+            }
+        }
+        Criterion::Term(field, token)
+    }
+}
+
 #[derive(Default)]
 pub struct PlumeQuery {
     text: Vec<(Occur, String)>,
@@ -151,10 +190,10 @@ impl PlumeQuery {
         self.from_str_req(&query.trim())
     }
 
-    /// Convert this Query to a Tantivy Query
-    pub fn into_query(self) -> BooleanQuery {
-        let mut result: Vec<(Occur, Box<Query>)> = Vec::new();
-        gen_to_query!(self, result; normal: title, subtitle, content, tag;
+    /// Convert this Query to a list of criteria, each with how it should occur
+    pub fn into_criteria(self) -> Vec<(Occur, Criterion)> {
+        let mut result = Vec::new();
+        gen_to_criteria!(self, result; normal: title, subtitle, content, tag;
                       oneoff: instance, author, blog, lang, license);
 
         for (occur, token) in self.text {
@@ -163,17 +202,17 @@ impl PlumeQuery {
                 Occur::Must => {
                     // a Must mean this must be in one of title subtitle or content, not in all 3
                     let subresult = vec![
-                        (Occur::Should, Self::token_to_query(&token, "title")),
-                        (Occur::Should, Self::token_to_query(&token, "subtitle")),
-                        (Occur::Should, Self::token_to_query(&token, "content")),
+                        Criterion::term("title", token.clone()),
+                        Criterion::term("subtitle", token.clone()),
+                        Criterion::term("content", token),
                     ];
 
-                    result.push((Occur::Must, Box::new(BooleanQuery::from(subresult))));
+                    result.push((Occur::Must, Criterion::Any(subresult)));
                 }
                 occur => {
-                    result.push((occur, Self::token_to_query(&token, "title")));
-                    result.push((occur, Self::token_to_query(&token, "subtitle")));
-                    result.push((occur, Self::token_to_query(&token, "content")));
+                    result.push((occur, Criterion::term("title", token.clone())));
+                    result.push((occur, Criterion::term("subtitle", token.clone())));
+                    result.push((occur, Criterion::term("content", token)));
                 }
             }
         }
@@ -186,13 +225,19 @@ impl PlumeQuery {
             let before = self
                 .before
                 .unwrap_or_else(|| i64::from(Utc::today().num_days_from_ce()));
-            let field = Searcher::schema().get_field("creation_date").unwrap();
-            let range =
-                RangeQuery::new_i64_bounds(field, Bound::Included(after), Bound::Included(before));
-            result.push((Occur::Must, Box::new(range)));
+            result.push((Occur::Must, Criterion::DateRange(after, before)));
         }
 
-        result.into()
+        result
+    }
+
+    /// Convert this Query to a Tantivy Query
+    pub fn into_query(self) -> BooleanQuery {
+        self.into_criteria()
+            .into_iter()
+            .map(|(occur, criterion)| (occur, Self::criterion_to_query(criterion)))
+            .collect::<Vec<_>>()
+            .into()
     }
 
     //generate most setters functions
@@ -278,16 +323,66 @@ impl PlumeQuery {
         self.from_str_req(query)
     }
 
+    // map a criterion to a tantivy query
+    fn criterion_to_query(criterion: Criterion) -> Box<Query> {
+        match criterion {
+            Criterion::Term(field_name, token) => {
+                Self::boost(field_name, Self::token_to_query(&token, field_name))
+            }
+            Criterion::Fuzzy(field_name, token, distance) => {
+                let field = TantivyBackend::schema().get_field(field_name).unwrap();
+                let term = Term::from_field_text(field, &token.to_lowercase());
+                Self::boost(
+                    field_name,
+                    Box::new(FuzzyTermQuery::new(term, distance, true)),
+                )
+            }
+            Criterion::Any(criteria) => Box::new(BooleanQuery::from(
+                criteria
+                    .into_iter()
+                    .map(|criterion| (Occur::Should, Self::criterion_to_query(criterion)))
+                    .collect::<Vec<_>>(),
+            )),
+            Criterion::DateRange(after, before) => {
+                let field = TantivyBackend::schema().get_field("creation_date").unwrap();
+                Box::new(RangeQuery::new_i64_bounds(
+                    field,
+                    Bound::Included(after),
+                    Bound::Included(before),
+                ))
+            }
+        }
+    }
+
+    // weight a query according to the importance of its field
+    fn boost(field_name: &str, query: Box<Query>) -> Box<Query> {
+        Box::new(BoostQuery::new(
+            query,
+            CONFIG.search_relevance.boost(field_name),
+        ))
+    }
+
+    // split a phrase in the terms it was indexed as
+    pub(crate) fn tokenize(text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        SimpleTokenizer
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .token_stream(text)
+            .process(&mut |token: &Token| terms.push(token.text.clone()));
+        terms
+    }
+
     // map a token and it's field to a query
     fn token_to_query(token: &str, field_name: &str) -> Box<Query> {
         let token = token.to_lowercase();
         let token = token.as_str();
-        let field = Searcher::schema().get_field(field_name).unwrap();
+        let field = TantivyBackend::schema().get_field(field_name).unwrap();
         if token.contains('@') && (field_name == "author" || field_name == "blog") {
             let pos = token.find('@').unwrap();
             let user_term = Term::from_field_text(field, &token[..pos]);
             let instance_term = Term::from_field_text(
-                Searcher::schema().get_field("instance").unwrap(),
+                TantivyBackend::schema().get_field("instance").unwrap(),
                 &token[pos + 1..],
             );
             Box::new(BooleanQuery::from(vec![
@@ -307,12 +402,10 @@ impl PlumeQuery {
                     Box::new(TermQuery::new(instance_term, IndexRecordOption::Basic)),
                 ),
             ]))
-        } else if token.contains(' ') {
-            // phrase query
+        } else {
             match field_name {
-                "instance" | "author" | "tag" =>
-                // phrase query are not available on these fields, treat it as multiple Term queries
-                {
+                "instance" | "author" | "tag" => {
+                    // phrase query are not available on these fields, treat it as multiple Term queries
                     Box::new(BooleanQuery::from(
                         token
                             .split_whitespace()
@@ -327,21 +420,41 @@ impl PlumeQuery {
                             .collect::<Vec<_>>(),
                     ))
                 }
-                _ => Box::new(PhraseQuery::new(
-                    token
-                        .split_whitespace()
-                        .map(|token| Term::from_field_text(field, token))
-                        .collect(),
-                )),
+                "lang" | "license" => {
+                    if token.contains(' ') {
+                        Box::new(PhraseQuery::new(
+                            token
+                                .split_whitespace()
+                                .map(|token| Term::from_field_text(field, token))
+                                .collect(),
+                        ))
+                    } else {
+                        let term = Term::from_field_text(field, token);
+                        Box::new(TermQuery::new(
+                            term,
+                            IndexRecordOption::WithFreqsAndPositions,
+                        ))
+                    }
+                }
+                _ => {
+                    // quoted tokens are phrases, split like they were when indexing
+                    let mut terms = Self::tokenize(token)
+                        .into_iter()
+                        .map(|token| Term::from_field_text(field, &token))
+                        .collect::<Vec<_>>();
+                    if terms.len() > 1 {
+                        Box::new(PhraseQuery::new(terms))
+                    } else if let Some(term) = terms.pop() {
+                        Box::new(TermQuery::new(
+                            term,
+                            IndexRecordOption::WithFreqsAndPositions,
+                        ))
+                    } else {
+                        // nothing to look for
+                        Box::new(BooleanQuery::from(Vec::new()))
+                    }
+                }
             }
-        } else {
-            // Term Query
-            let term = Term::from_field_text(field, token);
-            let index_option = match field_name {
-                "instance" | "author" | "tag" => IndexRecordOption::Basic,
-                _ => IndexRecordOption::WithFreqsAndPositions,
-            };
-            Box::new(TermQuery::new(term, index_option))
         }
     }
 }
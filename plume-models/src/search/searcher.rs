@@ -1,5 +1,5 @@
 use instance::Instance;
-use posts::Post;
+use posts::{post_visibility, Post};
 use schema::posts;
 use tags::Tag;
 use Connection;
@@ -7,16 +7,14 @@ use Connection;
 use chrono::Datelike;
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use itertools::Itertools;
-use std::{cmp, fs::create_dir_all, path::Path, sync::Mutex};
-use tantivy::{
-    collector::TopDocs, directory::MmapDirectory, schema::*, tokenizer::*, Index, IndexReader,
-    IndexWriter, ReloadPolicy, Term,
-};
+use std::{cmp, collections::HashSet, path::Path};
+use tantivy::query::Occur;
 use whatlang::{detect as detect_lang, Lang};
 
-use super::tokenizer;
-use search::query::PlumeQuery;
-use Result;
+use config::SearchEngine;
+use search::backends::{DatabaseBackend, TantivyBackend};
+use search::query::{Criterion, PlumeQuery};
+use {Result, CONFIG};
 
 #[derive(Debug)]
 pub enum SearcherError {
@@ -24,208 +22,337 @@ pub enum SearcherError {
     WriteLockAcquisitionError,
     IndexOpeningError,
     IndexEditionError,
+    IndexSearchError,
 }
 
-pub struct Searcher {
-    index: Index,
-    reader: IndexReader,
-    writer: Mutex<Option<IndexWriter>>,
+/// Everything a search backend needs to know about a post to index it
+pub struct SearchDocument {
+    pub post_id: i32,
+    /// Days from CE
+    pub creation_date: i32,
+    /// False if this post should not be shown in search results, for instance
+    /// because it is not public or because its instance has been blocked
+    pub visible: bool,
+    pub instance: String,
+    /// Space separated list of author fqns
+    pub author: String,
+    /// Space separated list of tags
+    pub tag: String,
+    pub blog: String,
+    pub title: String,
+    pub subtitle: String,
+    pub content: String,
+    pub lang: String,
+    pub license: String,
 }
 
-impl Searcher {
-    pub fn schema() -> Schema {
-        let tag_indexing = TextOptions::default().set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer("whitespace_tokenizer")
-                .set_index_option(IndexRecordOption::Basic),
-        );
-
-        let content_indexing = TextOptions::default().set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer("content_tokenizer")
-                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-        );
-
-        let property_indexing = TextOptions::default().set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer("property_tokenizer")
-                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-        );
+impl SearchDocument {
+    pub fn from_post(conn: &Connection, post: &Post) -> Result<Self> {
+        let blog = post.get_blog(conn)?;
+        let instance = Instance::get(conn, blog.instance_id)?;
+        let authors = post.get_authors(conn)?;
+        let blocked_author = authors.iter().any(|author| {
+            author
+                .get_instance(conn)
+                .map(|i| i.blocked)
+                .unwrap_or(false)
+        });
+        Ok(SearchDocument {
+            post_id: post.id,
+            creation_date: post.creation_date.num_days_from_ce(),
+            visible: post.published
+                && post.visibility == post_visibility::PUBLIC
+                && !instance.blocked
+                && !blocked_author,
+            instance: instance.public_domain,
+            author: authors.into_iter().map(|u| u.fqn).join(" "),
+            tag: Tag::for_post(conn, post.id)?
+                .into_iter()
+                .map(|t| t.tag)
+                .join(" "),
+            blog: blog.title,
+            content: post.content.get().clone(),
+            subtitle: post.subtitle.clone(),
+            title: post.title.clone(),
+            lang: detect_lang(post.content.get())
+                .and_then(|i| {
+                    if i.is_reliable() {
+                        Some(i.lang())
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(Lang::Eng)
+                .name()
+                .to_owned(),
+            license: post.license.clone(),
+        })
+    }
 
-        let mut schema_builder = SchemaBuilder::default();
+    /// Check if this document would be a result of `query`, without using any index
+    pub fn matches(&self, query: PlumeQuery) -> bool {
+        let mut has_must = false;
+        let mut should_matched = false;
+        for (occur, criterion) in query.into_criteria() {
+            let matched = self.matches_criterion(&criterion);
+            match occur {
+                Occur::Must if !matched => return false,
+                Occur::Must => has_must = true,
+                Occur::MustNot if matched => return false,
+                Occur::MustNot => {}
+                Occur::Should => should_matched |= matched,
+            }
+        }
+        // like with the backends, optional criteria are only needed when nothing is required
+        has_must || should_matched
+    }
 
-        schema_builder.add_i64_field("post_id", STORED | INDEXED);
-        schema_builder.add_i64_field("creation_date", INDEXED);
+    /// The words `matches` compares the terms of a query with
+    ///
+    /// Languages and licenses are matched by substrings, they are not included.
+    pub fn words(&self) -> HashSet<String> {
+        let mut words = HashSet::new();
+        for value in &[&self.instance, &self.author, &self.tag] {
+            words.extend(value.to_lowercase().split_whitespace().map(str::to_owned));
+        }
+        for value in &[&self.blog, &self.title, &self.subtitle, &self.content] {
+            words.extend(PlumeQuery::tokenize(value));
+        }
+        words
+    }
 
-        schema_builder.add_text_field("instance", tag_indexing.clone());
-        schema_builder.add_text_field("author", tag_indexing.clone());
-        schema_builder.add_text_field("tag", tag_indexing);
+    /// Words a document must contain at least one of to match `query`
+    ///
+    /// Returns `None` if the query can match documents without any specific word,
+    /// for instance if it only uses dates, languages or fuzzy terms.
+    pub fn required_words(query: PlumeQuery) -> Option<Vec<String>> {
+        let mut has_must = false;
+        let mut should = Some(vec![]);
+        for (occur, criterion) in query.into_criteria() {
+            match occur {
+                Occur::Must => {
+                    if let Some(words) = Self::criterion_words(&criterion) {
+                        return Some(words);
+                    }
+                    has_must = true;
+                }
+                Occur::Should => {
+                    should = should.and_then(|mut should| {
+                        should.extend(Self::criterion_words(&criterion)?);
+                        Some(should)
+                    })
+                }
+                Occur::MustNot => {}
+            }
+        }
+        // optional criteria are ignored as soon as something is required
+        if has_must {
+            None
+        } else {
+            should
+        }
+    }
 
-        schema_builder.add_text_field("blog", content_indexing.clone());
-        schema_builder.add_text_field("content", content_indexing.clone());
-        schema_builder.add_text_field("subtitle", content_indexing.clone());
-        schema_builder.add_text_field("title", content_indexing);
+    fn criterion_words(criterion: &Criterion) -> Option<Vec<String>> {
+        match criterion {
+            Criterion::Term(field, token) | Criterion::Fuzzy(field, token, 0) => {
+                let token = token.to_lowercase();
+                match *field {
+                    "lang" | "license" => None,
+                    "author" | "blog" if token.contains('@') => None,
+                    "instance" | "author" | "tag" => {
+                        Some(token.split_whitespace().map(str::to_owned).collect())
+                    }
+                    // every word of a phrase is needed, the first one is enough to filter
+                    _ => PlumeQuery::tokenize(&token)
+                        .into_iter()
+                        .next()
+                        .map(|word| vec![word]),
+                }
+            }
+            Criterion::Fuzzy(..) | Criterion::DateRange(..) => None,
+            Criterion::Any(criteria) => criteria
+                .iter()
+                .map(Self::criterion_words)
+                .collect::<Option<Vec<_>>>()
+                .map(|words| words.concat()),
+        }
+    }
 
-        schema_builder.add_text_field("lang", property_indexing.clone());
-        schema_builder.add_text_field("license", property_indexing);
+    fn matches_criterion(&self, criterion: &Criterion) -> bool {
+        match criterion {
+            Criterion::Term(field, token) => self.matches_term(field, &token.to_lowercase(), 0),
+            Criterion::Fuzzy(field, token, distance) => {
+                self.matches_term(field, &token.to_lowercase(), *distance)
+            }
+            Criterion::Any(criteria) => criteria.iter().any(|c| self.matches_criterion(c)),
+            Criterion::DateRange(after, before) => {
+                let date = i64::from(self.creation_date);
+                *after <= date && date <= *before
+            }
+        }
+    }
 
-        schema_builder.build()
+    fn matches_term(&self, field: &str, token: &str, distance: u8) -> bool {
+        let value = match field {
+            "instance" => &self.instance,
+            "author" => &self.author,
+            "tag" => &self.tag,
+            "blog" => &self.blog,
+            "title" => &self.title,
+            "subtitle" => &self.subtitle,
+            "content" => &self.content,
+            "lang" => &self.lang,
+            "license" => &self.license,
+            _ => return false,
+        }
+        .to_lowercase();
+
+        match field {
+            "author" | "blog" if token.contains('@') => {
+                let pos = token.find('@').unwrap();
+                // remote authors are indexed with their fqn
+                (field == "author" && value.split_whitespace().any(|word| word == token))
+                    || (self.matches_term(field, &token[..pos], distance)
+                        && self.matches_term("instance", &token[pos + 1..], 0))
+            }
+            "instance" | "author" | "tag" => token
+                .split_whitespace()
+                .any(|token| value.split_whitespace().any(|word| word == token)),
+            "lang" | "license" => value.contains(token),
+            _ => {
+                let words = PlumeQuery::tokenize(&value);
+                let phrase = PlumeQuery::tokenize(token);
+                if phrase.is_empty() {
+                    false
+                } else if phrase.len() == 1 {
+                    words
+                        .iter()
+                        .any(|word| edit_distance(word, &phrase[0]) <= usize::from(distance))
+                } else {
+                    words
+                        .windows(phrase.len())
+                        .any(|window| window == &phrase[..])
+                }
+            }
+        }
     }
+}
 
-    pub fn create(path: &AsRef<Path>) -> Result<Self> {
-        let whitespace_tokenizer = tokenizer::WhitespaceTokenizer.filter(LowerCaser);
+// Levenshtein distance between two words
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + cmp::min(previous, cmp::min(row[j], current))
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
 
-        let content_tokenizer = SimpleTokenizer
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser);
+/// A search engine, able to index posts and to find them back
+pub trait SearchBackend: Send + Sync {
+    /// Index a post. Drafts are ignored.
+    fn add_document(&self, conn: &Connection, post: &Post) -> Result<()>;
 
-        let property_tokenizer = NgramTokenizer::new(2, 8, false).filter(LowerCaser);
+    /// Remove a post from the index
+    fn delete_document(&self, conn: &Connection, post: &Post) -> Result<()>;
 
-        let schema = Self::schema();
+    fn update_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        self.delete_document(conn, post)?;
+        self.add_document(conn, post)
+    }
 
-        create_dir_all(path).map_err(|_| SearcherError::IndexCreationError)?;
-        let index = Index::create(
-            MmapDirectory::open(path).map_err(|_| SearcherError::IndexCreationError)?,
-            schema,
-        )
-        .map_err(|_| SearcherError::IndexCreationError)?;
+    /// Find posts matching `query`, ordered by relevance
+    fn search_document(
+        &self,
+        conn: &Connection,
+        query: PlumeQuery,
+        bounds: (i32, i32),
+    ) -> Result<Vec<Post>>;
 
-        {
-            let tokenizer_manager = index.tokenizers();
-            tokenizer_manager.register("whitespace_tokenizer", whitespace_tokenizer);
-            tokenizer_manager.register("content_tokenizer", content_tokenizer);
-            tokenizer_manager.register("property_tokenizer", property_tokenizer);
-        } //to please the borrow checker
-        Ok(Self {
-            writer: Mutex::new(Some(
-                index
-                    .writer(50_000_000)
-                    .map_err(|_| SearcherError::WriteLockAcquisitionError)?,
-            )),
-            reader: index
-                .reader_builder()
-                .reload_policy(ReloadPolicy::Manual)
-                .try_into()
-                .map_err(|_| SearcherError::IndexCreationError)?,
-            index,
-        })
+    /// The most frequent words of post titles starting with `prefix`
+    fn suggest_terms(
+        &self,
+        _conn: &Connection,
+        _prefix: &str,
+        _limit: usize,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
     }
 
-    pub fn open(path: &AsRef<Path>) -> Result<Self> {
-        let whitespace_tokenizer = tokenizer::WhitespaceTokenizer.filter(LowerCaser);
+    /// Make pending changes visible to searches
+    fn commit(&self) {}
 
-        let content_tokenizer = SimpleTokenizer
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser);
+    /// Release any lock this backend may hold on its index
+    fn drop_writer(&self) {}
+}
 
-        let property_tokenizer = NgramTokenizer::new(2, 8, false).filter(LowerCaser);
+/// The search engine of this instance, whatever backend it is using
+pub struct Searcher {
+    backend: Box<dyn SearchBackend>,
+}
 
-        let index =
-            Index::open(MmapDirectory::open(path).map_err(|_| SearcherError::IndexOpeningError)?)
-                .map_err(|_| SearcherError::IndexOpeningError)?;
+impl Searcher {
+    pub fn new(backend: Box<dyn SearchBackend>) -> Self {
+        Searcher { backend }
+    }
 
-        {
-            let tokenizer_manager = index.tokenizers();
-            tokenizer_manager.register("whitespace_tokenizer", whitespace_tokenizer);
-            tokenizer_manager.register("content_tokenizer", content_tokenizer);
-            tokenizer_manager.register("property_tokenizer", property_tokenizer);
-        } //to please the borrow checker
-        let mut writer = index
-            .writer(50_000_000)
-            .map_err(|_| SearcherError::WriteLockAcquisitionError)?;
-        writer
-            .garbage_collect_files()
-            .map_err(|_| SearcherError::IndexEditionError)?;
-        Ok(Self {
-            writer: Mutex::new(Some(writer)),
-            reader: index
-                .reader_builder()
-                .reload_policy(ReloadPolicy::Manual)
-                .try_into()
-                .map_err(|_| SearcherError::IndexCreationError)?,
-            index,
-        })
+    /// Create a new tantivy index in `path`
+    pub fn create(path: &AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Box::new(TantivyBackend::create(path)?)))
     }
 
-    pub fn add_document(&self, conn: &Connection, post: &Post) -> Result<()> {
-        if !post.published {
-            return Ok(());
-        }
+    /// Open an existing tantivy index in `path`
+    pub fn open(path: &AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Box::new(TantivyBackend::open(path)?)))
+    }
 
-        let schema = self.index.schema();
-
-        let post_id = schema.get_field("post_id").unwrap();
-        let creation_date = schema.get_field("creation_date").unwrap();
-
-        let instance = schema.get_field("instance").unwrap();
-        let author = schema.get_field("author").unwrap();
-        let tag = schema.get_field("tag").unwrap();
-
-        let blog_name = schema.get_field("blog").unwrap();
-        let content = schema.get_field("content").unwrap();
-        let subtitle = schema.get_field("subtitle").unwrap();
-        let title = schema.get_field("title").unwrap();
-
-        let lang = schema.get_field("lang").unwrap();
-        let license = schema.get_field("license").unwrap();
-
-        let mut writer = self.writer.lock().unwrap();
-        let writer = writer.as_mut().unwrap();
-        writer.add_document(doc!(
-            post_id => i64::from(post.id),
-            author => post.get_authors(conn)?.into_iter().map(|u| u.fqn).join(" "),
-            creation_date => i64::from(post.creation_date.num_days_from_ce()),
-            instance => Instance::get(conn, post.get_blog(conn)?.instance_id)?.public_domain.clone(),
-            tag => Tag::for_post(conn, post.id)?.into_iter().map(|t| t.tag).join(" "),
-            blog_name => post.get_blog(conn)?.title,
-            content => post.content.get().clone(),
-            subtitle => post.subtitle.clone(),
-            title => post.title.clone(),
-            lang => detect_lang(post.content.get()).and_then(|i| if i.is_reliable() { Some(i.lang()) } else {None} ).unwrap_or(Lang::Eng).name(),
-            license => post.license.clone(),
-        ));
-        Ok(())
+    /// Open the search backend selected in the configuration
+    pub fn open_configured() -> Result<Self> {
+        match CONFIG.search_engine {
+            SearchEngine::Tantivy => Self::open(&CONFIG.search_index),
+            SearchEngine::Database => Ok(Self::new(Box::new(DatabaseBackend))),
+        }
     }
 
-    pub fn delete_document(&self, post: &Post) {
-        let schema = self.index.schema();
-        let post_id = schema.get_field("post_id").unwrap();
+    pub fn add_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        self.backend.add_document(conn, post)
+    }
 
-        let doc_id = Term::from_field_i64(post_id, i64::from(post.id));
-        let mut writer = self.writer.lock().unwrap();
-        let writer = writer.as_mut().unwrap();
-        writer.delete_term(doc_id);
+    pub fn delete_document(&self, conn: &Connection, post: &Post) -> Result<()> {
+        self.backend.delete_document(conn, post)
     }
 
     pub fn update_document(&self, conn: &Connection, post: &Post) -> Result<()> {
-        self.delete_document(post);
-        self.add_document(conn, post)
+        self.backend.update_document(conn, post)
     }
 
     pub fn search_document(
         &self,
         conn: &Connection,
         query: PlumeQuery,
-        (min, max): (i32, i32),
-    ) -> Vec<Post> {
-        let schema = self.index.schema();
-        let post_id = schema.get_field("post_id").unwrap();
-
-        let collector = TopDocs::with_limit(cmp::max(1, max) as usize);
-
-        let searcher = self.reader.searcher();
-        let res = searcher.search(&query.into_query(), &collector).unwrap();
+        bounds: (i32, i32),
+    ) -> Result<Vec<Post>> {
+        self.backend.search_document(conn, query, bounds)
+    }
 
-        res.get(min as usize..)
-            .unwrap_or(&[])
-            .iter()
-            .filter_map(|(_, doc_add)| {
-                let doc = searcher.doc(*doc_add).ok()?;
-                let id = doc.get_first(post_id)?;
-                Post::get(conn, id.i64_value() as i32).ok()
-                //borrow checker don't want me to use filter_map or and_then here
-            })
-            .collect()
+    pub fn suggest_terms(
+        &self,
+        conn: &Connection,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.backend.suggest_terms(conn, prefix, limit)
     }
 
     pub fn fill(&self, conn: &Connection) -> Result<()> {
@@ -239,12 +366,10 @@ impl Searcher {
     }
 
     pub fn commit(&self) {
-        let mut writer = self.writer.lock().unwrap();
-        writer.as_mut().unwrap().commit().unwrap();
-        self.reader.reload().unwrap();
+        self.backend.commit()
     }
 
     pub fn drop_writer(&self) {
-        self.writer.lock().unwrap().take();
+        self.backend.drop_writer()
     }
 }
@@ -0,0 +1,359 @@
+use activitypub::collection::OrderedCollection;
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+use serde_json;
+
+use blogs::Blog;
+use plume_common::{
+    activity_pub::{inbox::FromId, Id, IntoId},
+    utils::make_slug,
+};
+use posts::{post_visibility, Post};
+use schema::{posts, series, series_posts};
+use series_posts::{NewSeriesPost, SeriesPost};
+use users::User;
+use {ap_url, Connection, Error, PlumeRocket, Result, CONFIG};
+
+/// An ordered list of posts of a blog, for instance the parts of a tutorial
+#[derive(Clone, Queryable, Identifiable, AsChangeset)]
+#[table_name = "series"]
+pub struct Series {
+    pub id: i32,
+    pub blog_id: i32,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub ap_url: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "series"]
+pub struct NewSeries {
+    pub blog_id: i32,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub ap_url: String,
+}
+
+/// Where a post is in its series
+pub struct SeriesNavigation {
+    pub series: Series,
+    /// Position of the post among the visible posts of the series, starting at 0
+    pub position: usize,
+    pub total: usize,
+    pub previous: Option<Post>,
+    pub next: Option<Post>,
+}
+
+impl Series {
+    insert!(series, NewSeries, |inserted, conn| {
+        if inserted.ap_url.is_empty() {
+            inserted.ap_url = ap_url(&format!(
+                "{}/~/{}/series/{}",
+                CONFIG.base_url,
+                inserted.get_blog(conn)?.fqn,
+                inserted.slug
+            ));
+            inserted.save_changes(conn).map_err(Error::from)
+        } else {
+            Ok(inserted)
+        }
+    });
+    get!(series);
+    find_by!(series, find_by_slug, slug as &str, blog_id as i32);
+    find_by!(series, find_by_ap_url, ap_url as &str);
+    list_by!(series, list_for_blog, blog_id as i32);
+
+    pub fn slug_for(title: &str) -> String {
+        make_slug(title)
+    }
+
+    pub fn get_blog(&self, conn: &Connection) -> Result<Blog> {
+        Blog::get(conn, self.blog_id)
+    }
+
+    /// The series containing this post, if any
+    pub fn find_for_post(conn: &Connection, post: &Post) -> Result<Series> {
+        Self::get(conn, SeriesPost::find_by_post(conn, post.id)?.series_id)
+    }
+
+    /// All the posts of this series, in order, including drafts and non-public ones
+    pub fn get_posts(&self, conn: &Connection) -> Result<Vec<Post>> {
+        series_posts::table
+            .inner_join(posts::table)
+            .filter(series_posts::series_id.eq(self.id))
+            .order(series_posts::position.asc())
+            .select(posts::all_columns)
+            .load::<Post>(conn)
+            .map_err(Error::from)
+    }
+
+    /// The published posts of this series that `user` can see, in order
+    pub fn get_visible_posts(&self, conn: &Connection, user: Option<&User>) -> Result<Vec<Post>> {
+        Ok(self
+            .get_posts(conn)?
+            .into_iter()
+            .filter(|p| p.published && p.can_see(conn, user))
+            .collect())
+    }
+
+    /// Add a post at the end of this series, removing it from any other series
+    pub fn add_post(&self, conn: &Connection, post: &Post) -> Result<SeriesPost> {
+        if let Ok(current) = SeriesPost::find_by_post(conn, post.id) {
+            if current.series_id == self.id {
+                return Ok(current);
+            }
+            Self::get(conn, current.series_id)?.remove_post(conn, post)?;
+        }
+
+        let position = series_posts::table
+            .filter(series_posts::series_id.eq(self.id))
+            .count()
+            .get_result::<i64>(conn)?;
+        SeriesPost::insert(
+            conn,
+            NewSeriesPost {
+                series_id: self.id,
+                post_id: post.id,
+                position: position as i32,
+            },
+        )
+    }
+
+    pub fn remove_post(&self, conn: &Connection, post: &Post) -> Result<()> {
+        diesel::delete(
+            series_posts::table
+                .filter(series_posts::series_id.eq(self.id))
+                .filter(series_posts::post_id.eq(post.id)),
+        )
+        .execute(conn)?;
+        self.renumber(conn)
+    }
+
+    /// Move a post `offset` places later in this series (or earlier if it is negative)
+    pub fn move_post(&self, conn: &Connection, post: &Post, offset: i32) -> Result<()> {
+        let mut entries = self.get_entries(conn)?;
+        let from = entries
+            .iter()
+            .position(|e| e.post_id == post.id)
+            .ok_or(Error::NotFound)?;
+        let to = (from as i32 + offset).max(0).min(entries.len() as i32 - 1) as usize;
+        let entry = entries.remove(from);
+        entries.insert(to, entry);
+        Self::save_positions(conn, entries)
+    }
+
+    fn get_entries(&self, conn: &Connection) -> Result<Vec<SeriesPost>> {
+        series_posts::table
+            .filter(series_posts::series_id.eq(self.id))
+            .order(series_posts::position.asc())
+            .load::<SeriesPost>(conn)
+            .map_err(Error::from)
+    }
+
+    // make positions contiguous again, after a post was removed
+    fn renumber(&self, conn: &Connection) -> Result<()> {
+        Self::save_positions(conn, self.get_entries(conn)?)
+    }
+
+    fn save_positions(conn: &Connection, entries: Vec<SeriesPost>) -> Result<()> {
+        for (position, mut entry) in entries.into_iter().enumerate() {
+            if entry.position != position as i32 {
+                entry.position = position as i32;
+                let _: SeriesPost = entry.save_changes(conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where `post` is in its series, if it is part of one, counting only the posts `user` can see
+    pub fn navigation(
+        conn: &Connection,
+        post: &Post,
+        user: Option<&User>,
+    ) -> Result<Option<SeriesNavigation>> {
+        let series = match Self::find_for_post(conn, post) {
+            Ok(series) => series,
+            Err(Error::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let posts = series.get_visible_posts(conn, user)?;
+        Ok(posts
+            .iter()
+            .position(|p| p.id == post.id)
+            .map(|position| SeriesNavigation {
+                position,
+                total: posts.len(),
+                previous: if position > 0 {
+                    posts.get(position - 1).cloned()
+                } else {
+                    None
+                },
+                next: posts.get(position + 1).cloned(),
+                series,
+            }))
+    }
+
+    pub fn update(&self, conn: &Connection) -> Result<Self> {
+        diesel::update(self).set(self).execute(conn)?;
+        Self::get(conn, self.id)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn to_activity(&self, conn: &Connection) -> Result<OrderedCollection> {
+        let items = self
+            .get_posts(conn)?
+            .into_iter()
+            .filter(|p| {
+                p.published
+                    && (p.visibility == post_visibility::PUBLIC
+                        || p.visibility == post_visibility::UNLISTED)
+            })
+            .map(|p| p.ap_url)
+            .collect::<Vec<_>>();
+
+        let mut coll = OrderedCollection::default();
+        coll.object_props.set_id_string(self.ap_url.clone())?;
+        coll.object_props.set_name_string(self.title.clone())?;
+        coll.object_props
+            .set_summary_string(self.description.clone())?;
+        coll.object_props
+            .set_attributed_to_link(self.get_blog(conn)?.into_id())?;
+        coll.collection_props
+            .set_total_items_u64(items.len() as u64)?;
+        coll.collection_props.items = serde_json::to_value(items)?;
+        Ok(coll)
+    }
+}
+
+impl FromId<PlumeRocket> for Series {
+    type Error = Error;
+    type Object = OrderedCollection;
+
+    fn from_db(c: &PlumeRocket, id: &str) -> Result<Self> {
+        Self::find_by_ap_url(&c.conn, id)
+    }
+
+    fn from_activity(c: &PlumeRocket, coll: OrderedCollection) -> Result<Self> {
+        let blog = Blog::from_id(c, &coll.object_props.attributed_to_link::<Id>()?, None)
+            .map_err(|(_, e)| e)?;
+        let title = coll.object_props.name_string()?;
+        Self::insert(
+            &c.conn,
+            NewSeries {
+                blog_id: blog.id,
+                slug: Self::slug_for(&title),
+                title,
+                description: coll.object_props.summary_string().unwrap_or_default(),
+                ap_url: coll.object_props.id_string()?,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blogs::tests::fill_database;
+    use diesel::Connection;
+    use post_authors::*;
+    use posts::NewPost;
+    use safe_string::SafeString;
+    use search::tests::get_searcher;
+    use tests::db;
+
+    #[test]
+    fn ordering() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = fill_database(conn);
+            let searcher = get_searcher();
+            let series = Series::insert(
+                conn,
+                NewSeries {
+                    blog_id: blogs[0].id,
+                    slug: "tutorial".to_owned(),
+                    title: "Tutorial".to_owned(),
+                    description: String::new(),
+                    ap_url: String::new(),
+                },
+            )
+            .unwrap();
+            assert!(series.ap_url.ends_with("/series/tutorial"));
+
+            let parts = (1..=3)
+                .map(|i| {
+                    let post = Post::insert(
+                        conn,
+                        NewPost {
+                            blog_id: blogs[0].id,
+                            slug: format!("part-{}", i),
+                            title: format!("Part {}", i),
+                            content: SafeString::new(""),
+                            published: true,
+                            license: "CC-BY-SA".to_owned(),
+                            ap_url: String::new(),
+                            creation_date: None,
+                            subtitle: String::new(),
+                            source: String::new(),
+                            cover_id: None,
+                            publish_at: None,
+                            visibility: post_visibility::PUBLIC.to_owned(),
+                        },
+                        &searcher,
+                    )
+                    .unwrap();
+                    PostAuthor::insert(
+                        conn,
+                        NewPostAuthor {
+                            post_id: post.id,
+                            author_id: users[0].id,
+                        },
+                    )
+                    .unwrap();
+                    series.add_post(conn, &post).unwrap();
+                    post
+                })
+                .collect::<Vec<_>>();
+
+            let ids = |series: &Series| {
+                series
+                    .get_posts(conn)
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.id)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(ids(&series), vec![parts[0].id, parts[1].id, parts[2].id]);
+
+            series.move_post(conn, &parts[2], -1).unwrap();
+            assert_eq!(ids(&series), vec![parts[0].id, parts[2].id, parts[1].id]);
+
+            series.remove_post(conn, &parts[0]).unwrap();
+            assert_eq!(ids(&series), vec![parts[2].id, parts[1].id]);
+            assert_eq!(
+                SeriesPost::find_by_post(conn, parts[2].id)
+                    .unwrap()
+                    .position,
+                0
+            );
+
+            let nav = Series::navigation(conn, &parts[1], None).unwrap().unwrap();
+            assert_eq!(nav.position, 1);
+            assert_eq!(nav.total, 2);
+            assert_eq!(nav.previous.unwrap().id, parts[2].id);
+            assert!(nav.next.is_none());
+            assert!(Series::navigation(conn, &parts[0], None).unwrap().is_none());
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,31 @@
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use posts::Post;
+use schema::series_posts;
+use series::Series;
+use {Error, Result};
+
+#[derive(Clone, Queryable, Identifiable, Associations, AsChangeset)]
+#[belongs_to(Post)]
+#[belongs_to(Series)]
+pub struct SeriesPost {
+    pub id: i32,
+    pub series_id: i32,
+    pub post_id: i32,
+    /// The position of the post in its series, starting at 0
+    pub position: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "series_posts"]
+pub struct NewSeriesPost {
+    pub series_id: i32,
+    pub post_id: i32,
+    pub position: i32,
+}
+
+impl SeriesPost {
+    insert!(series_posts, NewSeriesPost);
+    get!(series_posts);
+    find_by!(series_posts, find_by_post, post_id as i32);
+}
@@ -0,0 +1,237 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use rocket::{
+    outcome::IntoOutcome,
+    request::{self, FromRequest, Request},
+    Outcome,
+};
+
+use db_conn::DbConn;
+use plume_common::utils::random_hex;
+use schema::sessions;
+use users::{User, AUTH_COOKIE};
+use {Connection, Error, Result};
+
+/// Sessions that were not used for this many days are closed
+const SESSION_VALIDITY_DAYS: i64 = 30;
+
+/// `last_seen` is only updated when it is older than that, to avoid writing to the
+/// database on every request
+const LAST_SEEN_PRECISION_MINUTES: i64 = 5;
+
+/// A browser in which a user is logged in.
+///
+/// Its cookie only contains the token of the session, so it can be closed from another
+/// device, or when the password of the user changes.
+#[derive(Clone, Queryable, Identifiable)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub token: String,
+    pub creation_date: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+    /// The `User-Agent` of the browser, to help users recognize their devices
+    pub user_agent: String,
+    /// The IP address the session was opened from
+    pub ip: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession {
+    pub user_id: i32,
+    pub token: String,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+/// The oldest `last_seen` date of a valid session
+fn expiration_limit() -> NaiveDateTime {
+    Utc::now().naive_utc() - Duration::days(SESSION_VALIDITY_DAYS)
+}
+
+impl Session {
+    insert!(sessions, NewSession);
+    get!(sessions);
+    find_by!(sessions, find_by_token, token as &str);
+
+    /// Open a new session for `user`, in the browser described by `client`
+    pub fn start(conn: &Connection, user: &User, client: &Client) -> Result<Session> {
+        // it is a good time to forget the sessions that expired
+        diesel::delete(
+            sessions::table
+                .filter(sessions::user_id.eq(user.id))
+                .filter(sessions::last_seen.lt(expiration_limit())),
+        )
+        .execute(conn)?;
+
+        Session::insert(
+            conn,
+            NewSession {
+                user_id: user.id,
+                token: random_hex(),
+                user_agent: client.user_agent.clone(),
+                ip: client.ip.clone(),
+            },
+        )
+    }
+
+    /// The valid sessions of a user, the most recently used first
+    pub fn list_for_user(conn: &Connection, user_id: i32) -> Result<Vec<Session>> {
+        sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .filter(sessions::last_seen.ge(expiration_limit()))
+            .order(sessions::last_seen.desc())
+            .load::<Session>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.last_seen < expiration_limit()
+    }
+
+    /// Note that this session has just been used
+    pub fn touch(&self, conn: &Connection) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        if self.last_seen > now - Duration::minutes(LAST_SEEN_PRECISION_MINUTES) {
+            return Ok(());
+        }
+        diesel::update(self)
+            .set(sessions::last_seen.eq(now))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Close this session
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Close all the sessions of a user, except `keep` if it is given
+    pub fn delete_for_user(conn: &Connection, user_id: i32, keep: Option<i32>) -> Result<()> {
+        diesel::delete(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::id.ne(keep.unwrap_or(-1))),
+        )
+        .execute(conn)
+        .map(|_| ())
+        .map_err(Error::from)
+    }
+}
+
+/// The session of the browser sending a request, if it is logged in
+impl<'a, 'r> FromRequest<'a, 'r> for Session {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Session, ()> {
+        let conn = request.guard::<DbConn>()?;
+        request
+            .cookies()
+            .get_private(AUTH_COOKIE)
+            .and_then(|cookie| Session::find_by_token(&*conn, cookie.value()).ok())
+            .filter(|session| !session.is_expired())
+            .and_then(|session| session.touch(&*conn).ok().map(|_| session))
+            .or_forward(())
+    }
+}
+
+/// What we know about the browser sending a request
+pub struct Client {
+    pub user_agent: String,
+    pub ip: String,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Client {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Client, ()> {
+        Outcome::Success(Client {
+            user_agent: request
+                .headers()
+                .get_one("User-Agent")
+                .unwrap_or_default()
+                .chars()
+                .take(512)
+                .collect(),
+            ip: request
+                .client_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::Connection;
+    use tests::db;
+    use users::tests as user_tests;
+
+    fn client() -> Client {
+        Client {
+            user_agent: "Mozilla/5.0 (X11; Linux x86_64; rv:69.0) Gecko/20100101 Firefox/69.0"
+                .to_owned(),
+            ip: "127.0.0.1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn start_and_close() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = user_tests::fill_database(conn);
+            let first = Session::start(conn, &users[0], &client()).unwrap();
+            let second = Session::start(conn, &users[0], &client()).unwrap();
+            let other = Session::start(conn, &users[1], &client()).unwrap();
+            assert_ne!(first.token, second.token);
+            assert_eq!(
+                Session::find_by_token(conn, &first.token).unwrap().user_id,
+                users[0].id
+            );
+            assert_eq!(Session::list_for_user(conn, users[0].id).unwrap().len(), 2);
+
+            Session::delete_for_user(conn, users[0].id, Some(second.id)).unwrap();
+            let remaining = Session::list_for_user(conn, users[0].id).unwrap();
+            assert_eq!(remaining.len(), 1);
+            assert_eq!(remaining[0].id, second.id);
+            assert!(Session::get(conn, other.id).is_ok());
+
+            users[0].reset_password(conn, "new password").unwrap();
+            assert!(Session::list_for_user(conn, users[0].id)
+                .unwrap()
+                .is_empty());
+            assert!(Session::get(conn, other.id).is_ok());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn expiration() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = user_tests::fill_database(conn);
+            let session = Session::start(conn, &users[0], &client()).unwrap();
+            assert!(!session.is_expired());
+
+            diesel::update(&session)
+                .set(sessions::last_seen.eq(expiration_limit() - Duration::hours(1)))
+                .execute(conn)
+                .unwrap();
+            let session = Session::get(conn, session.id).unwrap();
+            assert!(session.is_expired());
+            assert!(Session::list_for_user(conn, users[0].id)
+                .unwrap()
+                .is_empty());
+
+            session.touch(conn).unwrap();
+            assert!(!Session::get(conn, session.id).unwrap().is_expired());
+            Ok(())
+        });
+    }
+}
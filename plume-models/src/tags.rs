@@ -1,9 +1,12 @@
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{
+    self, EscapeExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl, TextExpressionMethods,
+};
 
 use instance::Instance;
 use plume_common::activity_pub::Hashtag;
-use schema::tags;
-use {ap_url, Connection, Error, Result};
+use posts::post_visibility;
+use schema::{posts, tags};
+use {ap_url, like_prefix, Connection, Error, Result};
 
 #[derive(Clone, Identifiable, Queryable)]
 pub struct Tag {
@@ -27,6 +30,21 @@ impl Tag {
     find_by!(tags, find_by_name, tag as &str);
     list_by!(tags, for_post, post_id as i32);
 
+    /// The names of the tags starting with `prefix`, used by public posts
+    pub fn suggest(conn: &Connection, prefix: &str, limit: i64) -> Result<Vec<String>> {
+        tags::table
+            .inner_join(posts::table)
+            .filter(posts::published.eq(true))
+            .filter(posts::visibility.eq(post_visibility::PUBLIC))
+            .filter(tags::tag.like(like_prefix(prefix)).escape('\\'))
+            .select(tags::tag)
+            .distinct()
+            .order(tags::tag)
+            .limit(limit)
+            .load::<String>(conn)
+            .map_err(Error::from)
+    }
+
     pub fn to_activity(&self) -> Result<Hashtag> {
         let mut ht = Hashtag::default();
         ht.set_href_string(ap_url(&format!(
@@ -0,0 +1,422 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use openssl::{
+    hash::MessageDigest, memcmp, pkey::PKey, rand::rand_bytes, sha::sha256, sign::Signer,
+};
+use url::form_urlencoded::byte_serialize;
+
+use schema::{recovery_codes, two_factor_auths};
+use {Connection, Error, Result};
+
+/// Duration of a time step, in seconds: a new code is generated this often
+const TIME_STEP: i64 = 30;
+
+/// Codes from this many steps before or after the current one are accepted too, to
+/// compensate for clocks that are not perfectly synchronized
+const ALLOWED_DRIFT: i64 = 1;
+
+const CODE_DIGITS: usize = 6;
+
+const RECOVERY_CODES_COUNT: usize = 10;
+
+/// After this many wrong codes in a row, no code is accepted for a while
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+/// How long codes are refused after too many wrong ones, in minutes
+const LOCKOUT_DURATION: i64 = 15;
+
+const BASE32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// The TOTP (RFC 6238) secret of a user who enabled two-factor authentication.
+#[derive(Clone, Queryable, Identifiable)]
+pub struct TwoFactorAuth {
+    pub id: i32,
+    pub user_id: i32,
+    /// The shared secret, encoded in base 32 like in authenticator apps
+    pub secret: String,
+    /// The last time step for which a code was used, to refuse replayed codes
+    pub last_step: i32,
+    pub creation_date: NaiveDateTime,
+    /// The number of wrong codes given since the last right one
+    pub failed_attempts: i32,
+    /// No code is accepted until then, if too many wrong codes were given
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "two_factor_auths"]
+pub struct NewTwoFactorAuth {
+    pub user_id: i32,
+    pub secret: String,
+    pub last_step: i32,
+}
+
+impl TwoFactorAuth {
+    insert!(two_factor_auths, NewTwoFactorAuth);
+    get!(two_factor_auths);
+    find_by!(two_factor_auths, find_by_user, user_id as i32);
+
+    pub fn is_enabled(conn: &Connection, user_id: i32) -> bool {
+        TwoFactorAuth::find_by_user(conn, user_id).is_ok()
+    }
+
+    /// Enable two-factor authentication for a user, once they proved their app knows
+    /// `secret` by giving the current `code`.
+    ///
+    /// Returns their recovery codes, that can't be read again later. If it is already
+    /// enabled, it has to be disabled first, with a code from the current device.
+    pub fn enable(
+        conn: &Connection,
+        user_id: i32,
+        secret: &str,
+        code: &str,
+    ) -> Result<Vec<String>> {
+        if TwoFactorAuth::is_enabled(conn, user_id) {
+            return Err(Error::InvalidValue);
+        }
+        let step = matching_step(secret, code, 0).ok_or(Error::Unauthorized)?;
+        TwoFactorAuth::insert(
+            conn,
+            NewTwoFactorAuth {
+                user_id,
+                secret: secret.to_owned(),
+                last_step: step,
+            },
+        )?;
+        RecoveryCode::generate(conn, user_id)
+    }
+
+    pub fn disable(conn: &Connection, user_id: i32) -> Result<()> {
+        diesel::delete(two_factor_auths::table.filter(two_factor_auths::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Whether too many wrong codes were given recently, and no code is accepted for now
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .map(|date| date > Utc::now().naive_utc())
+            .unwrap_or(false)
+    }
+
+    /// Check a code given by the user: either the current one from their app, or one of
+    /// their recovery codes. Each code can only be used once.
+    ///
+    /// Wrong codes are counted, and after `MAX_FAILED_ATTEMPTS` of them, every code is
+    /// refused for `LOCKOUT_DURATION` minutes.
+    pub fn verify(&self, conn: &Connection, code: &str) -> Result<bool> {
+        // the lock may have been set by another request since this was loaded
+        if TwoFactorAuth::get(conn, self.id)?.is_locked() {
+            return Ok(false);
+        }
+
+        let code = code
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        let valid = if let Some(step) = matching_step(&self.secret, &code, self.last_step) {
+            // the filter makes sure two requests can't use the same code at once
+            let updated = diesel::update(
+                two_factor_auths::table
+                    .filter(two_factor_auths::id.eq(self.id))
+                    .filter(two_factor_auths::last_step.lt(step)),
+            )
+            .set(two_factor_auths::last_step.eq(step))
+            .execute(conn)?;
+            updated == 1
+        } else {
+            RecoveryCode::consume(conn, self.user_id, &code)?
+        };
+
+        let this = || two_factor_auths::table.filter(two_factor_auths::id.eq(self.id));
+        if valid {
+            diesel::update(this())
+                .set(two_factor_auths::failed_attempts.eq(0))
+                .execute(conn)?;
+        } else {
+            diesel::update(this())
+                .set(two_factor_auths::failed_attempts.eq(two_factor_auths::failed_attempts + 1))
+                .execute(conn)?;
+            diesel::update(
+                this().filter(two_factor_auths::failed_attempts.ge(MAX_FAILED_ATTEMPTS)),
+            )
+            .set((
+                two_factor_auths::failed_attempts.eq(0),
+                two_factor_auths::locked_until
+                    .eq(Utc::now().naive_utc() + Duration::minutes(LOCKOUT_DURATION)),
+            ))
+            .execute(conn)?;
+        }
+        Ok(valid)
+    }
+}
+
+/// A code that can be used once instead of a TOTP, if the user lost their device.
+///
+/// Only a hash of the code is stored.
+#[derive(Clone, Queryable, Identifiable)]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "recovery_codes"]
+pub struct NewRecoveryCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+impl RecoveryCode {
+    insert!(recovery_codes, NewRecoveryCode);
+
+    /// Replace the recovery codes of a user with new ones, and return them
+    pub fn generate(conn: &Connection, user_id: i32) -> Result<Vec<String>> {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        (0..RECOVERY_CODES_COUNT)
+            .map(|_| {
+                let mut bytes = [0; 10];
+                rand_bytes(&mut bytes)?;
+                let code = base32_encode(&bytes)
+                    .to_lowercase()
+                    .as_bytes()
+                    .chunks(4)
+                    .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+                    .collect::<Vec<_>>()
+                    .join("-");
+                RecoveryCode::insert(
+                    conn,
+                    NewRecoveryCode {
+                        user_id,
+                        code_hash: hash_recovery_code(&code),
+                    },
+                )?;
+                Ok(code)
+            })
+            .collect()
+    }
+
+    pub fn count_for_user(conn: &Connection, user_id: i32) -> Result<i64> {
+        recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+
+    /// Use a recovery code, returns `false` if it is not one of the codes of this user
+    pub fn consume(conn: &Connection, user_id: i32, code: &str) -> Result<bool> {
+        let deleted = diesel::delete(
+            recovery_codes::table
+                .filter(recovery_codes::user_id.eq(user_id))
+                .filter(recovery_codes::code_hash.eq(hash_recovery_code(code))),
+        )
+        .execute(conn)?;
+        Ok(deleted > 0)
+    }
+}
+
+/// A new random secret, to be shown to the user while they enable two-factor authentication
+pub fn generate_secret() -> Result<String> {
+    let mut bytes = [0; 20];
+    rand_bytes(&mut bytes)?;
+    Ok(base32_encode(&bytes))
+}
+
+/// The URI encoded in the QR code that authenticator apps scan
+pub fn provisioning_uri(secret: &str, account: &str, issuer: &str) -> String {
+    let encode = |s: &str| {
+        byte_serialize(s.as_bytes())
+            .collect::<String>()
+            .replace('+', "%20")
+    };
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&digits={digits}&period={period}",
+        issuer = encode(issuer),
+        account = encode(account),
+        secret = secret,
+        digits = CODE_DIGITS,
+        period = TIME_STEP,
+    )
+}
+
+/// Ignore case, spaces and dashes, so that users can type recovery codes as they want
+fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    sha256(normalized.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The time step at which `code` was valid, if it is more recent than `last_step`
+fn matching_step(secret: &str, code: &str, last_step: i32) -> Option<i32> {
+    if code.len() != CODE_DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let key = base32_decode(secret)?;
+    let now = Utc::now().timestamp() / TIME_STEP;
+    (now - ALLOWED_DRIFT..=now + ALLOWED_DRIFT)
+        .filter(|step| *step > i64::from(last_step))
+        .find(|step| {
+            code_at(&key, *step)
+                .map(|expected| memcmp::eq(expected.as_bytes(), code.as_bytes()))
+                .unwrap_or(false)
+        })
+        .map(|step| step as i32)
+}
+
+/// The HOTP (RFC 4226) code for a counter, which is the time step for TOTP
+fn code_at(key: &[u8], step: i64) -> Result<String> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&(step as u64).to_be_bytes())?;
+    let hmac = signer.sign_to_vec()?;
+    let offset = (hmac[hmac.len() - 1] & 0xf) as usize;
+    let truncated = hmac[offset..offset + 4]
+        .iter()
+        .fold(0u32, |value, byte| (value << 8) | u32::from(*byte))
+        & 0x7fff_ffff;
+    Ok(format!(
+        "{:0width$}",
+        truncated % 10u32.pow(CODE_DIGITS as u32),
+        width = CODE_DIGITS
+    ))
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut res = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(BASE32[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        res.push(BASE32[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    res
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in encoded.bytes().filter(|c| *c != b'=' && *c != b' ') {
+        let digit = BASE32.iter().position(|d| *d == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | digit as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((buffer >> bits) as u8);
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::Connection;
+    use tests::db;
+    use users::tests as user_tests;
+
+    fn current_code(secret: &str) -> String {
+        let step = Utc::now().timestamp() / TIME_STEP;
+        code_at(&base32_decode(secret).unwrap(), step).unwrap()
+    }
+
+    #[test]
+    fn rfc_6238_codes() {
+        let secret = base32_encode(b"12345678901234567890");
+        assert_eq!(secret, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        let key = base32_decode(&secret).unwrap();
+        assert_eq!(key, b"12345678901234567890");
+        assert_eq!(code_at(&key, 59 / TIME_STEP).unwrap(), "287082");
+        assert_eq!(code_at(&key, 1_111_111_109 / TIME_STEP).unwrap(), "081804");
+        assert_eq!(code_at(&key, 2_000_000_000 / TIME_STEP).unwrap(), "279037");
+    }
+
+    #[test]
+    fn enable_and_verify() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = user_tests::fill_database(conn);
+            let secret = generate_secret().unwrap();
+            assert!(!TwoFactorAuth::is_enabled(conn, users[0].id));
+            assert!(TwoFactorAuth::enable(conn, users[0].id, &secret, "000000x").is_err());
+
+            let code = current_code(&secret);
+            let codes = TwoFactorAuth::enable(conn, users[0].id, &secret, &code).unwrap();
+            assert_eq!(codes.len(), RECOVERY_CODES_COUNT);
+            let auth = TwoFactorAuth::find_by_user(conn, users[0].id).unwrap();
+
+            // another device can't replace the current one without disabling it first
+            let other_secret = generate_secret().unwrap();
+            assert!(TwoFactorAuth::enable(
+                conn,
+                users[0].id,
+                &other_secret,
+                &current_code(&other_secret)
+            )
+            .is_err());
+            assert_eq!(
+                TwoFactorAuth::find_by_user(conn, users[0].id)
+                    .unwrap()
+                    .secret,
+                secret
+            );
+            assert_eq!(
+                RecoveryCode::count_for_user(conn, users[0].id).unwrap(),
+                RECOVERY_CODES_COUNT as i64
+            );
+
+            // the code used to enable it can't be used again
+            assert!(!auth.verify(conn, &code).unwrap());
+
+            assert!(auth.verify(conn, &codes[0].to_uppercase()).unwrap());
+            assert!(!auth.verify(conn, &codes[0]).unwrap());
+            assert_eq!(
+                RecoveryCode::count_for_user(conn, users[0].id).unwrap(),
+                RECOVERY_CODES_COUNT as i64 - 1
+            );
+            assert!(!TwoFactorAuth::is_enabled(conn, users[1].id));
+
+            for _ in 0..MAX_FAILED_ATTEMPTS {
+                assert!(!auth.verify(conn, "000000x").unwrap());
+            }
+            let auth = TwoFactorAuth::find_by_user(conn, users[0].id).unwrap();
+            assert!(auth.is_locked());
+            // even right codes are refused for now
+            assert!(!auth.verify(conn, &codes[1]).unwrap());
+            assert_eq!(
+                RecoveryCode::count_for_user(conn, users[0].id).unwrap(),
+                RECOVERY_CODES_COUNT as i64 - 1
+            );
+
+            TwoFactorAuth::disable(conn, users[0].id).unwrap();
+            assert!(!TwoFactorAuth::is_enabled(conn, users[0].id));
+            assert_eq!(RecoveryCode::count_for_user(conn, users[0].id).unwrap(), 0);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn uri() {
+        assert_eq!(
+            provisioning_uri("GEZDGNBV", "admin@plu.me", "My Plume"),
+            "otpauth://totp/My%20Plume:admin%40plu.me?secret=GEZDGNBV&issuer=My%20Plume&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use activitypub::collection::OrderedCollection;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+use guid_create::GUID;
+use serde_json;
+use serde_yaml;
+use std::io::{Cursor, Seek, Write};
+use zip::{write::FileOptions, ZipWriter};
+
+use comments::Comment;
+use follows::Follow;
+use media_store::MEDIA_STORE;
+use medias::Media;
+use plume_common::activity_pub::context;
+use posts::Post;
+use schema::{comments, post_authors, posts, user_exports};
+use tags::Tag;
+use users::User;
+use {Connection, Error, Result};
+
+/// How long users have to wait before asking for a new archive, in hours
+const EXPORT_INTERVAL: i64 = 24;
+
+/// How long an archive can take to be generated, in hours. If it is still not ready after
+/// that, something went wrong and a new one can be requested.
+const EXPORT_TIMEOUT: i64 = 1;
+
+/// An archive of everything a user published, that they can download
+#[derive(Clone, Queryable, Identifiable, AsChangeset)]
+pub struct UserExport {
+    pub id: i32,
+    pub user_id: i32,
+    /// The key of the archive in the media store. It is only served by Plume, to its owner.
+    pub file_path: String,
+    /// Whether the archive has been completely generated
+    pub ready: bool,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "user_exports"]
+pub struct NewUserExport {
+    pub user_id: i32,
+    pub file_path: String,
+}
+
+#[derive(Serialize)]
+struct FrontMatter<'a> {
+    title: &'a str,
+    subtitle: &'a str,
+    slug: &'a str,
+    blog: &'a str,
+    date: String,
+    license: &'a str,
+    tags: Vec<String>,
+    draft: bool,
+    visibility: &'a str,
+    url: &'a str,
+}
+
+impl UserExport {
+    insert!(user_exports, NewUserExport);
+    get!(user_exports);
+    list_by!(user_exports, list_for_user, user_id as i32);
+
+    /// Whether `user` can ask for a new archive: generating one is expensive, so they can't
+    /// while the previous one is being generated, or if it is recent.
+    pub fn can_request(conn: &Connection, user: &User) -> Result<bool> {
+        let last = match Self::last_for_user(conn, user.id) {
+            Ok(last) => last,
+            Err(Error::NotFound) => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        let age = Utc::now().naive_utc() - last.creation_date;
+        Ok(if last.ready {
+            age > Duration::hours(EXPORT_INTERVAL)
+        } else {
+            age > Duration::hours(EXPORT_TIMEOUT)
+        })
+    }
+
+    /// Start a new export for `user`, replacing the previous ones. The archive still has to
+    /// be written with `generate`, which can take a while.
+    pub fn request(conn: &Connection, user: &User) -> Result<Self> {
+        for previous in Self::list_for_user(conn, user.id)? {
+            previous.delete(conn)?;
+        }
+
+        Self::insert(
+            conn,
+            NewUserExport {
+                user_id: user.id,
+                file_path: format!("export-{}.zip", GUID::rand().to_string()),
+            },
+        )
+    }
+
+    pub fn last_for_user(conn: &Connection, user_id: i32) -> Result<Self> {
+        user_exports::table
+            .filter(user_exports::user_id.eq(user_id))
+            .order(user_exports::id.desc())
+            .limit(1)
+            .load::<Self>(conn)?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound)
+    }
+
+    /// Write the archive, and mark it as ready to be downloaded
+    pub fn generate(&mut self, conn: &Connection) -> Result<()> {
+        let user = User::get(conn, self.user_id)?;
+        let archive = write_archive(conn, &user, Cursor::new(vec![]))?.into_inner();
+        MEDIA_STORE.save(&self.file_path, &archive)?;
+
+        self.ready = true;
+        let _: Self = self.save_changes(conn)?;
+        Ok(())
+    }
+
+    /// The content of the archive, once it is ready
+    pub fn read(&self) -> Result<Vec<u8>> {
+        if !self.ready {
+            return Err(Error::NotFound);
+        }
+        MEDIA_STORE.read(&self.file_path)
+    }
+
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        // the archive may not have been generated yet
+        if self.ready {
+            MEDIA_STORE.delete(&self.file_path)?;
+        }
+        diesel::delete(self)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+/// Write a zip archive of everything `user` published: their posts as Markdown files with
+/// a front matter (that can be imported back), their medias and comments, the lists of
+/// the accounts they follow and that follow them, and their ActivityPub actor and outbox.
+pub fn write_archive<W: Write + Seek>(conn: &Connection, user: &User, writer: W) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default();
+
+    let mut actor = serde_json::to_value(user.to_activity(conn)?)?;
+    actor["@context"] = context();
+    zip.start_file("actor.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&actor)?.as_bytes())?;
+
+    let posts = posts::table
+        .filter(
+            posts::id.eq_any(
+                post_authors::table
+                    .filter(post_authors::author_id.eq(user.id))
+                    .select(post_authors::post_id),
+            ),
+        )
+        .order(posts::creation_date.asc())
+        .load::<Post>(conn)?;
+
+    let activities = posts
+        .iter()
+        .filter(|p| p.published)
+        .filter_map(|p| p.create_activity(conn).ok())
+        .collect::<Vec<_>>();
+    let mut outbox = OrderedCollection::default();
+    outbox
+        .collection_props
+        .set_total_items_u64(activities.len() as u64)?;
+    outbox.collection_props.items = serde_json::to_value(activities)?;
+    let mut outbox = serde_json::to_value(outbox)?;
+    outbox["@context"] = context();
+    zip.start_file("outbox.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&outbox)?.as_bytes())?;
+
+    for post in &posts {
+        let blog = post.get_blog(conn)?;
+        let front_matter = serde_yaml::to_string(&FrontMatter {
+            title: &post.title,
+            subtitle: &post.subtitle,
+            slug: &post.slug,
+            blog: &blog.fqn,
+            date: post.creation_date.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            license: &post.license,
+            tags: Tag::for_post(conn, post.id)?
+                .into_iter()
+                .filter(|t| !t.is_hashtag)
+                .map(|t| t.tag)
+                .collect(),
+            draft: !post.published,
+            visibility: &post.visibility,
+            url: &post.ap_url,
+        })
+        .map_err(|_| Error::SerDe)?;
+        let source = if post.source.is_empty() {
+            post.content.get().clone()
+        } else {
+            post.source.clone()
+        };
+        zip.start_file(format!("posts/{}/{}.md", blog.fqn, post.slug), options)?;
+        write!(zip, "{}\n---\n\n{}\n", front_matter, source)?;
+    }
+
+    let medias = Media::for_user(conn, user.id)?
+        .into_iter()
+        .filter(|m| !m.is_remote)
+        .collect::<Vec<_>>();
+    let mut media_list = vec![];
+    for media in medias {
+        let name = media.key().to_owned();
+        // the file may have been removed by hand
+        if let Ok(content) = media.read_file() {
+            zip.start_file(format!("media/{}", name), options)?;
+            zip.write_all(&content)?;
+            media_list.push(json!({
+                "id": media.id,
+                "file": format!("media/{}", name),
+                "alt_text": media.alt_text,
+                "content_warning": media.content_warning,
+            }));
+        }
+    }
+    // posts refer to their medias by id
+    zip.start_file("media.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&media_list)?.as_bytes())?;
+
+    let comments = comments::table
+        .filter(comments::author_id.eq(user.id))
+        .order(comments::creation_date.asc())
+        .load::<Comment>(conn)?
+        .into_iter()
+        .map(|c| {
+            Ok(json!({
+                "url": c.ap_url,
+                "post": Post::get(conn, c.post_id)?.ap_url,
+                "in_reply_to": c
+                    .in_response_to_id
+                    .and_then(|id| Comment::get(conn, id).ok())
+                    .and_then(|parent| parent.ap_url),
+                "date": c.creation_date.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                "content": c.content.get(),
+                "spoiler_text": c.spoiler_text,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    zip.start_file("comments.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&comments)?.as_bytes())?;
+
+    zip.start_file("following.csv", options)?;
+    zip.write_all(Follow::export_csv(conn, user)?.as_bytes())?;
+    zip.start_file("followers.csv", options)?;
+    writeln!(zip, "Account address")?;
+    for account in user.get_followers(conn)? {
+        writeln!(zip, "{}", account.account_address()?)?;
+    }
+
+    zip.finish().map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::Connection;
+    use follows::NewFollow;
+    use inbox::tests::fill_database;
+    use std::io::{Cursor, Read};
+    use tests::rockets;
+    use zip::ZipArchive;
+
+    #[test]
+    fn archive() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (posts, users, _) = fill_database(&r);
+            Follow::insert(
+                conn,
+                NewFollow {
+                    follower_id: users[0].id,
+                    following_id: users[1].id,
+                    ap_url: String::new(),
+                },
+            )
+            .unwrap();
+
+            let archive = write_archive(conn, &users[0], Cursor::new(vec![])).unwrap();
+            let mut archive = ZipArchive::new(Cursor::new(archive.into_inner())).unwrap();
+            let mut read = |name: &str| {
+                let mut content = String::new();
+                archive
+                    .by_name(name)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                content
+            };
+
+            let post = read(&format!(
+                "posts/{}/{}.md",
+                posts[0].get_blog(conn).unwrap().fqn,
+                posts[0].slug
+            ));
+            assert!(post.starts_with("---\n"));
+            assert!(post.contains(&format!("title: {}", posts[0].title)));
+            let following = read("following.csv");
+            assert_eq!(following.lines().count(), 2);
+            assert!(following.contains(&users[1].fqn));
+            let outbox: serde_json::Value = serde_json::from_str(&read("outbox.json")).unwrap();
+            assert_eq!(outbox["totalItems"], 1);
+            let actor: serde_json::Value = serde_json::from_str(&read("actor.json")).unwrap();
+            assert_eq!(actor["id"], users[0].ap_url);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn throttling() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (_, users, _) = fill_database(&r);
+            assert!(UserExport::can_request(conn, &users[0]).unwrap());
+
+            let mut export = UserExport::request(conn, &users[0]).unwrap();
+            assert!(!UserExport::can_request(conn, &users[0]).unwrap());
+            assert!(export.read().is_err());
+
+            export.generate(conn).unwrap();
+            assert!(!UserExport::can_request(conn, &users[0]).unwrap());
+            ZipArchive::new(Cursor::new(export.read().unwrap())).unwrap();
+            export.delete(conn).unwrap();
+            assert!(UserExport::can_request(conn, &users[0]).unwrap());
+
+            Ok(())
+        });
+    }
+}
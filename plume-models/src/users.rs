@@ -1,5 +1,5 @@
 use activitypub::{
-    activity::Delete,
+    activity::{Delete, Move},
     actor::Person,
     collection::OrderedCollection,
     object::{Image, Tombstone},
@@ -7,7 +7,10 @@ use activitypub::{
 };
 use bcrypt;
 use chrono::{NaiveDateTime, Utc};
-use diesel::{self, BelongingToDsl, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{
+    self, BelongingToDsl, EscapeExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl,
+    SaveChangesDsl, TextExpressionMethods,
+};
 use openssl::{
     hash::MessageDigest,
     pkey::{PKey, Private},
@@ -15,7 +18,7 @@ use openssl::{
     sign,
 };
 use plume_common::activity_pub::{
-    ap_accept_header,
+    ap_accept_header, broadcast,
     inbox::{AsActor, AsObject, FromId},
     sign::{gen_keypair, Signer},
     ActivityStream, ApSignature, Id, IntoId, PublicKey, PUBLIC_VISIBILITY,
@@ -37,17 +40,20 @@ use std::{
 use url::Url;
 use webfinger::*;
 
+use blog_authors::BlogAuthor;
 use blogs::Blog;
 use db_conn::DbConn;
-use follows::Follow;
+use follows::{Follow, NewFollow};
 use instance::*;
 use medias::Media;
 use post_authors::PostAuthor;
-use posts::Post;
+use posts::{post_visibility, Post};
 use safe_string::SafeString;
 use schema::users;
 use search::Searcher;
-use {ap_url, Connection, Error, PlumeRocket, Result};
+use sessions::Session;
+use two_factor::TwoFactorAuth;
+use {ap_url, like_prefix, Connection, Error, PlumeRocket, Result};
 
 pub type CustomPerson = CustomObject<ApSignature, Person>;
 
@@ -75,6 +81,10 @@ pub struct User {
     pub summary_html: SafeString,
     pub preferred_theme: Option<String>,
     pub hide_custom_css: bool,
+    /// The ActivityPub IDs of the other accounts of this user, separated by spaces
+    pub also_known_as: String,
+    /// The ActivityPub ID of the account this user moved to, if any
+    pub moved_to: Option<String>,
 }
 
 #[derive(Default, Insertable)]
@@ -97,9 +107,12 @@ pub struct NewUser {
     pub avatar_id: Option<i32>,
     pub summary_html: SafeString,
     pub fqn: String,
+    pub also_known_as: String,
+    pub moved_to: Option<String>,
 }
 
-pub const AUTH_COOKIE: &str = "user_id";
+/// The private cookie holding the token of the `Session` of a browser
+pub const AUTH_COOKIE: &str = "session";
 const USER_PREFIX: &str = "@";
 
 impl User {
@@ -119,6 +132,9 @@ impl User {
     pub fn delete(&self, conn: &Connection, searcher: &Searcher) -> Result<()> {
         use schema::post_authors;
 
+        Session::delete_for_user(conn, self.id, None)?;
+        TwoFactorAuth::disable(conn, self.id)?;
+
         for blog in Blog::find_for_author(conn, self)?
             .iter()
             .filter(|b| b.count_authors(conn).map(|c| c <= 1).unwrap_or(false))
@@ -130,6 +146,7 @@ impl User {
             .filter(post_authors::author_id.eq(self.id))
             .select(post_authors::post_id)
             .load(conn)?;
+        let mut shared_posts = vec![];
         for post_id in all_their_posts_ids {
             // disabling this lint, because otherwise we'd have to turn it on
             // the head, and make it even harder to follow!
@@ -142,15 +159,20 @@ impl User {
                 .first()
                 .unwrap_or(&0)
                 > &0;
-            if !has_other_authors {
+            if has_other_authors {
+                shared_posts.push(post_id);
+            } else {
                 Post::get(conn, post_id)?.delete(conn, searcher)?;
             }
         }
 
-        diesel::delete(self)
-            .execute(conn)
-            .map(|_| ())
-            .map_err(Error::from)
+        diesel::delete(self).execute(conn)?;
+
+        // the remaining posts should not be found by searching for this user anymore
+        for post_id in shared_posts {
+            searcher.update_document(conn, &Post::get(conn, post_id)?)?;
+        }
+        Ok(())
     }
 
     pub fn get_instance(&self, conn: &Connection) -> Result<Instance> {
@@ -273,6 +295,8 @@ impl User {
                         .custom_props
                         .public_key_publickey()?
                         .public_key_pem_string()?),
+                    users::also_known_as.eq(aliases_of(&json)),
+                    users::moved_to.eq(json.custom_props.moved_to_string().ok()),
                 ))
                 .execute(conn)
                 .map(|_| ())
@@ -295,7 +319,18 @@ impl User {
         diesel::update(self)
             .set(users::hashed_password.eq(User::hash_pass(pass)?))
             .execute(conn)?;
-        Ok(())
+        // someone else may know the previous password
+        Session::delete_for_user(conn, self.id, None)
+    }
+
+    /// Whether this user is an admin who still has to enable two-factor authentication,
+    /// because the instance requires it
+    pub fn must_enable_2fa(&self, conn: &Connection) -> bool {
+        self.is_admin
+            && Instance::get_local()
+                .map(|instance| instance.require_admin_2fa)
+                .unwrap_or(false)
+            && !TwoFactorAuth::is_enabled(conn, self.id)
     }
 
     pub fn get_local_page(conn: &Connection, (min, max): (i32, i32)) -> Result<Vec<User>> {
@@ -308,6 +343,16 @@ impl User {
             .map_err(Error::from)
     }
 
+    /// Users whose fully qualified name starts with `prefix`
+    pub fn suggest(conn: &Connection, prefix: &str, limit: i64) -> Result<Vec<User>> {
+        users::table
+            .filter(users::fqn.like(like_prefix(prefix)).escape('\\'))
+            .order(users::fqn.asc())
+            .limit(limit)
+            .load::<User>(conn)
+            .map_err(Error::from)
+    }
+
     pub fn outbox(&self, conn: &Connection) -> Result<ActivityStream<OrderedCollection>> {
         let acts = self.get_activities(conn)?;
         let n_acts = acts.len();
@@ -371,8 +416,12 @@ impl User {
         use schema::post_authors;
         use schema::posts;
         let posts_by_self = PostAuthor::belonging_to(self).select(post_authors::post_id);
+        // the outbox is public, the other posts are only sent to their audience
         let posts = posts::table
             .filter(posts::published.eq(true))
+            .filter(
+                posts::visibility.eq_any(vec![post_visibility::PUBLIC, post_visibility::UNLISTED]),
+            )
             .filter(posts::id.eq_any(posts_by_self))
             .load::<Post>(conn)?;
         Ok(posts
@@ -509,6 +558,25 @@ impl User {
             .map(|r| r > 0)
     }
 
+    /// Whether this user can publish in `blog`, and edit and publish the articles of its other
+    /// authors, or can only write drafts
+    pub fn can_publish_in(&self, conn: &Connection, blog: &Blog) -> Result<bool> {
+        match BlogAuthor::find(conn, blog.id, self.id) {
+            Ok(author) => Ok(author.can_publish()),
+            Err(Error::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether this user can manage `blog` and its authors
+    pub fn is_owner_of(&self, conn: &Connection, blog: &Blog) -> Result<bool> {
+        match BlogAuthor::find(conn, blog.id, self.id) {
+            Ok(author) => Ok(author.is_owner()),
+            Err(Error::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn get_keypair(&self) -> Result<PKey<Private>> {
         PKey::from_rsa(Rsa::private_key_from_pem(
             self.private_key.clone()?.as_ref(),
@@ -577,6 +645,14 @@ impl User {
         public_key.set_public_key_pem_string(self.public_key.clone())?;
         let mut ap_signature = ApSignature::default();
         ap_signature.set_public_key_publickey(public_key)?;
+        let aliases = self.aliases();
+        if !aliases.is_empty() {
+            ap_signature
+                .set_also_known_as_string_vec(aliases.into_iter().map(String::from).collect())?;
+        }
+        if let Some(ref moved_to) = self.moved_to {
+            ap_signature.set_moved_to_string(moved_to.clone())?;
+        }
 
         let mut avatar = Image::default();
         avatar.object_props.set_url_string(
@@ -681,6 +757,127 @@ impl User {
             self.fqn.clone()
         }
     }
+
+    /// The address of this account, with its domain even if it is local
+    pub fn account_address(&self) -> Result<String> {
+        if self.fqn.contains('@') {
+            Ok(self.fqn.clone())
+        } else {
+            Ok(format!(
+                "{}@{}",
+                self.fqn,
+                Instance::get_local()?.public_domain
+            ))
+        }
+    }
+
+    /// The ActivityPub IDs of the other accounts of this user
+    pub fn aliases(&self) -> Vec<&str> {
+        self.also_known_as.split_whitespace().collect()
+    }
+
+    /// Find the account at `url`, refetching it if it is remote: its aliases have to be
+    /// up to date before following a Move.
+    pub fn find_fresh(c: &PlumeRocket, url: &str) -> Result<User> {
+        match User::find_by_ap_url(&c.conn, url) {
+            Ok(user) => {
+                if !user.is_local() {
+                    user.refetch(&c.conn)?;
+                }
+                User::get(&c.conn, user.id)
+            }
+            Err(_) => User::fetch_from_url(c, url),
+        }
+    }
+
+    /// Make the local followers of this account follow `new` too
+    pub fn move_followers(&self, conn: &Connection, new: &User) -> Result<()> {
+        for follower in self.get_followers(conn)? {
+            if !follower.is_local()
+                || follower.id == new.id
+                || follower.is_following(conn, new.id)?
+            {
+                continue;
+            }
+
+            let follow = Follow::insert(
+                conn,
+                NewFollow {
+                    follower_id: follower.id,
+                    following_id: new.id,
+                    ap_url: String::new(),
+                },
+            )?;
+            follow.notify(conn)?;
+            let act = follow.to_activity(conn)?;
+            broadcast(&follower, act, vec![new.clone()]);
+        }
+        Ok(())
+    }
+
+    /// Move this local account to `new`, that must list it as one of its aliases.
+    ///
+    /// Local followers follow the new account right away, the returned activity
+    /// should be sent to the remote ones.
+    pub fn move_to(&mut self, conn: &Connection, new: &User) -> Result<Move> {
+        if self.id == new.id || !new.aliases().contains(&self.ap_url.as_str()) {
+            return Err(Error::Unauthorized);
+        }
+
+        self.moved_to = Some(new.ap_url.clone());
+        let _: User = self.save_changes(conn)?;
+        self.move_followers(conn, new)?;
+        self.move_activity(new)
+    }
+
+    pub fn move_activity(&self, new: &User) -> Result<Move> {
+        let mut act = Move::default();
+        act.move_props
+            .set_actor_link(Id::new(self.ap_url.clone()))?;
+        act.move_props
+            .set_object_link(Id::new(self.ap_url.clone()))?;
+        act.move_props
+            .set_target_link(Id::new(new.ap_url.clone()))?;
+        act.object_props.set_id_string(format!(
+            "{}#move/{}",
+            self.ap_url,
+            Utc::now().timestamp()
+        ))?;
+        act.object_props
+            .set_to_link_vec(vec![Id::new(self.followers_endpoint.clone())])?;
+        act.object_props
+            .set_cc_link_vec(vec![Id::new(PUBLIC_VISIBILITY)])?;
+        Ok(act)
+    }
+
+    /// Handle a Move received in the inbox: the local followers of its actor follow
+    /// its target, once we checked that the target is really the same person.
+    pub fn receive_move(c: &PlumeRocket, act: Move) -> Result<()> {
+        let actor_id = act.move_props.actor_link::<Id>()?;
+        if act.move_props.object_link::<Id>()?.as_ref() != actor_id.as_ref() {
+            return Err(Error::Unauthorized);
+        }
+
+        let old = User::from_id(c, &actor_id, None).map_err(|(_, e)| e)?;
+        let new = User::find_fresh(c, &act.move_props.target_link::<Id>()?)?;
+        if old.id == new.id || !new.aliases().contains(&old.ap_url.as_str()) {
+            return Err(Error::Unauthorized);
+        }
+
+        diesel::update(&old)
+            .set(users::moved_to.eq(&new.ap_url))
+            .execute(&*c.conn)?;
+        old.move_followers(&c.conn, &new)
+    }
+}
+
+/// The aliases of a remote actor, that may be a single ID or an array of them
+fn aliases_of(acct: &CustomPerson) -> String {
+    acct.custom_props
+        .also_known_as_string_vec()
+        .or_else(|_| acct.custom_props.also_known_as_string().map(|a| vec![a]))
+        .unwrap_or_default()
+        .join(" ")
 }
 
 impl<'a, 'r> FromRequest<'a, 'r> for User {
@@ -688,12 +885,8 @@ impl<'a, 'r> FromRequest<'a, 'r> for User {
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<User, ()> {
         let conn = request.guard::<DbConn>()?;
-        request
-            .cookies()
-            .get_private(AUTH_COOKIE)
-            .and_then(|cookie| cookie.value().parse().ok())
-            .and_then(|id| User::get(&*conn, id).ok())
-            .or_forward(())
+        let session = request.guard::<Session>()?;
+        User::get(&*conn, session.user_id).ok().or_forward(())
     }
 }
 
@@ -788,6 +981,8 @@ impl FromId<PlumeRocket> for User {
                 followers_endpoint: acct.object.ap_actor_props.followers_string()?,
                 fqn,
                 avatar_id: None,
+                also_known_as: aliases_of(&acct),
+                moved_to: acct.custom_props.moved_to_string().ok(),
             },
         )?;
 
@@ -902,6 +1097,8 @@ impl NewUser {
                 followers_endpoint: instance.compute_box(USER_PREFIX, &username, "followers"),
                 fqn: username,
                 avatar_id: None,
+                also_known_as: String::new(),
+                moved_to: None,
             },
         )
     }
@@ -911,6 +1108,7 @@ impl NewUser {
 pub(crate) mod tests {
     use super::*;
     use diesel::Connection;
+    use inbox::tests as inbox_tests;
     use instance::{tests as instance_tests, Instance};
     use search::tests::get_searcher;
     use tests::{db, rockets};
@@ -1117,4 +1315,73 @@ pub(crate) mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn outbox_visibility() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let (posts, users, _) = inbox_tests::fill_database(&r);
+            let in_outbox = |post: &Post| {
+                users[0]
+                    .get_activities(conn)
+                    .unwrap()
+                    .iter()
+                    .any(|act| act["object"]["id"] == json!(post.ap_url))
+            };
+            assert!(in_outbox(&posts[0]));
+
+            let mut post = posts[0].clone();
+            post.visibility = post_visibility::FOLLOWERS.to_owned();
+            let post = post.update(conn, &r.searcher).unwrap();
+            assert!(!in_outbox(&post));
+
+            let mut post = post;
+            post.visibility = post_visibility::DIRECT.to_owned();
+            let post = post.update(conn, &r.searcher).unwrap();
+            assert!(!in_outbox(&post));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn move_account() {
+        let r = rockets();
+        let conn = &*r.conn;
+        conn.test_transaction::<_, (), _>(|| {
+            let mut users = fill_database(conn);
+            Follow::insert(
+                conn,
+                NewFollow {
+                    follower_id: users[2].id,
+                    following_id: users[0].id,
+                    ap_url: String::new(),
+                },
+            )
+            .unwrap();
+
+            // the new account has to list the old one as an alias
+            let new = users[1].clone();
+            assert!(users[0].move_to(conn, &new).is_err());
+
+            users[1].also_known_as = users[0].ap_url.clone();
+            let new: User = users[1].save_changes(conn).unwrap();
+            let ap_repr = serde_json::to_value(new.to_activity(conn).unwrap()).unwrap();
+            assert_eq!(ap_repr["alsoKnownAs"], json!([users[0].ap_url]));
+
+            let act = users[0].move_to(conn, &new).unwrap();
+            assert_eq!(
+                act.move_props.target_link::<Id>().unwrap().as_ref(),
+                new.ap_url
+            );
+            assert_eq!(
+                User::get(conn, users[0].id).unwrap().moved_to,
+                Some(new.ap_url.clone())
+            );
+            assert!(users[2].is_following(conn, new.id).unwrap());
+
+            Ok(())
+        });
+    }
 }
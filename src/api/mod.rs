@@ -7,7 +7,9 @@ use rocket_contrib::json::Json;
 use serde_json;
 
 use plume_common::utils::random_hex;
-use plume_models::{api_tokens::*, apps::App, users::User, Error, PlumeRocket};
+use plume_models::{
+    api_tokens::*, apps::App, two_factor::TwoFactorAuth, users::User, Error, PlumeRocket,
+};
 
 type Api<T> = Result<Json<T>, ApiError>;
 
@@ -52,6 +54,8 @@ pub struct OAuthRequest {
     password: String,
     username: String,
     scopes: String,
+    /// The current code of the user, if they enabled two-factor authentication
+    totp: Option<String>,
 }
 
 #[get("/oauth2?<query..>")]
@@ -64,6 +68,22 @@ pub fn oauth(
     if app.client_secret == query.client_secret {
         if let Ok(user) = User::find_by_fqn(&rockets, &query.username) {
             if user.auth(&query.password) {
+                if let Ok(two_factor) = TwoFactorAuth::find_by_user(conn, user.id) {
+                    let error = match query.totp {
+                        None => Some("Two-factor authentication code required"),
+                        Some(ref code) if !two_factor.verify(conn, code)? => {
+                            if TwoFactorAuth::get(conn, two_factor.id)?.is_locked() {
+                                Some("Too many invalid two-factor authentication codes, try again later")
+                            } else {
+                                Some("Invalid two-factor authentication code")
+                            }
+                        }
+                        Some(_) => None,
+                    };
+                    if let Some(error) = error {
+                        return Ok(Json(json!({ "error": error })));
+                    }
+                }
                 let token = ApiToken::insert(
                     conn,
                     NewApiToken {
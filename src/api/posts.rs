@@ -1,10 +1,13 @@
 use chrono::NaiveDateTime;
-use heck::{CamelCase, KebabCase};
+use heck::CamelCase;
 use rocket_contrib::json::Json;
 
 use crate::api::{authorization::*, Api};
 use plume_api::posts::*;
-use plume_common::{activity_pub::broadcast, utils::md_to_html};
+use plume_common::{
+    activity_pub::broadcast,
+    utils::{make_slug, md_to_html},
+};
 use plume_models::{
     blogs::Blog, db_conn::DbConn, instance::Instance, medias::Media, mentions::*, post_authors::*,
     posts::*, safe_string::SafeString, tags::*, users::User, Error, PlumeRocket,
@@ -15,10 +18,11 @@ pub fn get(id: i32, auth: Option<Authorization<Read, Post>>, conn: DbConn) -> Ap
     let user = auth.and_then(|a| User::get(&conn, a.0.user_id).ok());
     let post = Post::get(&conn, id)?;
 
-    if !post.published
-        && !user
-            .and_then(|u| post.is_author(&conn, u.id).ok())
-            .unwrap_or(false)
+    if !post.can_see(&conn, user.as_ref())
+        || (!post.published
+            && !user
+                .and_then(|u| post.is_author(&conn, u.id).ok())
+                .unwrap_or(false))
     {
         return Err(Error::Unauthorized.into());
     }
@@ -56,7 +60,7 @@ pub fn list(
     conn: DbConn,
 ) -> Api<Vec<PostData>> {
     let user = auth.and_then(|a| User::get(&conn, a.0.user_id).ok());
-    let user_id = user.map(|u| u.id);
+    let user_id = user.as_ref().map(|u| u.id);
 
     Ok(Json(
         Post::list_filtered(&conn, title, subtitle, content)?
@@ -67,6 +71,7 @@ pub fn list(
                         .and_then(|u| p.is_author(&conn, u).ok())
                         .unwrap_or(false)
             })
+            .filter(|p| p.can_see(&conn, user.as_ref()))
             .filter_map(|p| {
                 Some(PostData {
                     authors: p
@@ -109,7 +114,6 @@ pub fn create(
 
     let author = User::get(conn, auth.0.user_id)?;
 
-    let slug = &payload.title.clone().to_kebab_case();
     let date = payload.creation_date.clone().and_then(|d| {
         NaiveDateTime::parse_from_str(format!("{} 00:00:00", d).as_ref(), "%Y-%m-%d %H:%M:%S").ok()
     });
@@ -130,10 +134,15 @@ pub fn create(
             None
         }
     })?;
-
-    if Post::find_by_slug(conn, slug, blog).is_ok() {
-        return Err(Error::InvalidValue.into());
+    let blog = Blog::get(conn, blog)?;
+    if !author.is_author_in(conn, &blog)? {
+        return Err(Error::Unauthorized.into());
     }
+    // the authors who can't publish in this blog can only write drafts
+    let published = payload.published.unwrap_or(true) && author.can_publish_in(conn, &blog)?;
+    let blog = blog.id;
+
+    let slug = &Post::unique_slug(conn, blog, &make_slug(&payload.title), None)?;
 
     let post = Post::insert(
         conn,
@@ -142,7 +151,7 @@ pub fn create(
             slug: slug.to_string(),
             title: payload.title.clone(),
             content: SafeString::new(content.as_ref()),
-            published: payload.published.unwrap_or(true),
+            published,
             license: payload.license.clone().unwrap_or_else(|| {
                 Instance::get_local()
                     .map(|i| i.default_license)
@@ -153,6 +162,8 @@ pub fn create(
             subtitle: payload.subtitle.clone().unwrap_or_default(),
             source: payload.source.clone(),
             cover_id: payload.cover_id,
+            publish_at: None,
+            visibility: post_visibility::PUBLIC.to_owned(),
         },
         search,
     )?;
@@ -200,7 +211,7 @@ pub fn create(
         }
 
         let act = post.create_activity(&*conn)?;
-        let dest = User::one_by_instance(&*conn)?;
+        let dest = post.get_receivers(&*conn)?;
         worker.execute(move || broadcast(&author, act, dest));
     }
 
@@ -22,11 +22,13 @@ extern crate num_cpus;
 extern crate plume_api;
 extern crate plume_common;
 extern crate plume_models;
+extern crate qrcode;
 #[macro_use]
 extern crate rocket;
 extern crate rocket_contrib;
 extern crate rocket_csrf;
 extern crate rocket_i18n;
+extern crate rss;
 #[macro_use]
 extern crate runtime_fmt;
 extern crate scheduled_thread_pool;
@@ -44,7 +46,9 @@ use diesel::r2d2::ConnectionManager;
 use plume_models::{
     db_conn::{DbPool, PragmaForeignKey},
     instance::Instance,
+    medias::Media,
     migrations::IMPORTED_MIGRATIONS,
+    posts::Post,
     search::{Searcher as UnmanagedSearcher, SearcherError},
     Connection, Error, CONFIG,
 };
@@ -124,7 +128,7 @@ Then try to restart Plume.
     let workpool = ScheduledThreadPool::with_name("worker {}", num_cpus::get());
     // we want a fast exit here, so
     #[allow(clippy::match_wild_err_arm)]
-    let searcher = match UnmanagedSearcher::open(&CONFIG.search_index) {
+    let searcher = match UnmanagedSearcher::open_configured() {
         Err(Error::Search(e)) => match e {
             SearcherError::WriteLockAcquisitionError => panic!(
                 r#"
@@ -141,7 +145,8 @@ Then try to restart Plume.
 Plume was unable to open the search index. If you created the index
 before, make sure to run Plume in the same directory it was created in, or
 to set SEARCH_INDEX accordingly. If you did not yet create the search
-index, run this command:
+index, or want to use the database as search engine (SEARCH_ENGINE=database),
+run this command:
 
     plm search init
 
@@ -161,6 +166,32 @@ Then try to restart Plume
         move || commiter.commit(),
     );
 
+    let (publisher_pool, publisher_searcher) = (dbpool.clone(), searcher.clone());
+    workpool.execute_with_fixed_delay(
+        Duration::from_secs(10),
+        Duration::from_secs(60),
+        move || {
+            if let Ok(conn) = publisher_pool.get() {
+                if let Err(e) = Post::publish_scheduled(&conn, &publisher_searcher) {
+                    eprintln!("Error while publishing scheduled posts: {:?}", e);
+                }
+            }
+        },
+    );
+
+    let pruner_pool = dbpool.clone();
+    workpool.execute_with_fixed_delay(
+        Duration::from_secs(60),
+        Duration::from_secs(60 * 60),
+        move || {
+            if let Ok(conn) = pruner_pool.get() {
+                if let Err(e) = Media::prune_remote(&conn) {
+                    eprintln!("Error while pruning remote media: {:?}", e);
+                }
+            }
+        },
+    );
+
     let search_unlocker = searcher.clone();
     ctrlc::set_handler(move || {
         search_unlocker.commit();
@@ -189,6 +220,16 @@ Then try to restart Plume
                 routes::blogs::edit,
                 routes::blogs::update,
                 routes::blogs::atom_feed,
+                routes::blogs::podcast_feed,
+                routes::blogs::import_form,
+                routes::blogs::import,
+                routes::blogs::authors,
+                routes::blogs::invite,
+                routes::blogs::change_role,
+                routes::blogs::remove_author,
+                routes::blogs::cancel_invitation,
+                routes::blogs::accept_invitation,
+                routes::blogs::decline_invitation,
                 routes::comments::create,
                 routes::comments::delete,
                 routes::comments::activity_pub,
@@ -213,9 +254,15 @@ Then try to restart Plume
                 routes::medias::list,
                 routes::medias::new,
                 routes::medias::upload,
+                routes::medias::upload_json,
                 routes::medias::details,
+                routes::medias::proxy,
                 routes::medias::delete,
                 routes::medias::set_avatar,
+                routes::medias::set_focal_point,
+                routes::medias::bulk,
+                routes::medias::create_album,
+                routes::medias::delete_album,
                 routes::notifications::notifications,
                 routes::notifications::notifications_auth,
                 routes::posts::details,
@@ -226,11 +273,32 @@ Then try to restart Plume
                 routes::posts::new_auth,
                 routes::posts::create,
                 routes::posts::delete,
+                routes::posts::revisions,
+                routes::posts::restore_revision,
+                routes::posts::review,
+                routes::posts::submit_review,
+                routes::posts::comment_review,
+                routes::posts::request_changes,
+                routes::posts::approve_review,
                 routes::posts::remote_interact,
                 routes::posts::remote_interact_post,
                 routes::reshares::create,
                 routes::reshares::create_auth,
                 routes::search::search,
+                routes::search::suggest,
+                routes::search::saved,
+                routes::search::saved_auth,
+                routes::search::save,
+                routes::search::delete_saved,
+                routes::search::saved_atom_feed,
+                routes::series::details,
+                routes::series::activity_details,
+                routes::series::atom_feed,
+                routes::series::new,
+                routes::series::new_auth,
+                routes::series::create,
+                routes::series::update_posts,
+                routes::series::delete,
                 routes::session::new,
                 routes::session::create,
                 routes::session::delete,
@@ -238,8 +306,18 @@ Then try to restart Plume
                 routes::session::password_reset_request,
                 routes::session::password_reset_form,
                 routes::session::password_reset,
+                routes::session::list,
+                routes::session::revoke,
+                routes::session::revoke_others,
+                routes::session::two_factor_form,
+                routes::session::two_factor,
+                routes::session::two_factor_settings,
+                routes::session::enable_two_factor,
+                routes::session::regenerate_recovery_codes,
+                routes::session::disable_two_factor,
                 routes::theme_files,
                 routes::plume_static_files,
+                routes::media_files,
                 routes::static_files,
                 routes::tags::tag,
                 routes::user::me,
@@ -251,7 +329,12 @@ Then try to restart Plume
                 routes::user::edit,
                 routes::user::edit_auth,
                 routes::user::update,
+                routes::user::move_account,
+                routes::user::export_following,
+                routes::user::import_following,
                 routes::user::delete,
+                routes::user::request_export,
+                routes::user::export,
                 routes::user::follow,
                 routes::user::follow_not_connected,
                 routes::user::follow_auth,
@@ -1,20 +1,42 @@
 use activitypub::collection::OrderedCollection;
 use atom_syndication::{Entry, FeedBuilder};
+use chrono::{DateTime, Utc};
 use diesel::SaveChangesDsl;
+use guid_create::GUID;
+use multipart::server::{
+    save::{PartialReason, SaveResult, SavedData},
+    Multipart,
+};
 use rocket::{
     http::ContentType,
     request::LenientForm,
     response::{content::Content, Flash, Redirect},
+    Data, State,
 };
 use rocket_i18n::I18n;
-use std::{borrow::Cow, collections::HashMap};
+use rss::{
+    extension::itunes::{ITunesChannelExtensionBuilder, ITunesItemExtensionBuilder},
+    ChannelBuilder, EnclosureBuilder, GuidBuilder, Item, ItemBuilder,
+};
+use std::{borrow::Cow, collections::HashMap, env, fs};
 use validator::{Validate, ValidationError, ValidationErrors};
 
-use plume_common::activity_pub::{ActivityStream, ApRequest};
+use plume_common::activity_pub::{broadcast, ActivityStream, ApRequest};
 use plume_common::utils;
 use plume_models::{
-    blog_authors::*, blogs::*, instance::Instance, medias::*, posts::Post, safe_string::SafeString,
-    users::User, Connection, PlumeRocket,
+    blog_authors::*,
+    blog_invitations::BlogInvitation,
+    blogs::*,
+    db_conn::DbPool,
+    import::{Export, Federation, ImportFormat, Importer, MAX_EXPORT_SIZE},
+    instance::Instance,
+    medias::*,
+    notifications::{notification_kind, NewNotification, Notification},
+    posts::Post,
+    safe_string::SafeString,
+    series::Series,
+    users::User,
+    Connection, Error, PlumeRocket,
 };
 use routes::{errors::ErrorPage, Page, RespondOrRedirect};
 use template_utils::{IntoContext, Ructe};
@@ -34,7 +56,8 @@ pub fn details(name: String, page: Option<Page>, rockets: PlumeRocket) -> Result
         authors,
         page.0,
         Page::total(articles_count as i32),
-        posts
+        posts,
+        Series::list_for_blog(conn, blog.id)?
     )))
 }
 
@@ -131,7 +154,7 @@ pub fn create(form: LenientForm<NewBlogForm>, rockets: PlumeRocket) -> RespondOr
         NewBlogAuthor {
             blog_id: blog.id,
             author_id: user.id,
-            is_owner: true,
+            role: author_role::OWNER.to_owned(),
         },
     )
     .expect("blog::create: author error");
@@ -151,7 +174,7 @@ pub fn delete(name: String, rockets: PlumeRocket) -> RespondOrRedirect {
     if rockets
         .user
         .clone()
-        .and_then(|u| u.is_author_in(&*conn, &blog).ok())
+        .and_then(|u| u.is_owner_of(&*conn, &blog).ok())
         .unwrap_or(false)
     {
         blog.delete(&conn, &rockets.searcher)
@@ -182,6 +205,7 @@ pub struct EditForm {
     pub icon: Option<i32>,
     pub banner: Option<i32>,
     pub theme: Option<String>,
+    pub require_review: bool,
 }
 
 #[get("/~/<name>/edit")]
@@ -191,7 +215,7 @@ pub fn edit(name: String, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
     if rockets
         .user
         .clone()
-        .and_then(|u| u.is_author_in(conn, &blog).ok())
+        .and_then(|u| u.is_owner_of(conn, &blog).ok())
         .unwrap_or(false)
     {
         let user = rockets
@@ -209,6 +233,7 @@ pub fn edit(name: String, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
                 icon: blog.icon_id,
                 banner: blog.banner_id,
                 theme: blog.theme.clone(),
+                require_review: blog.require_review,
             },
             ValidationErrors::default()
         )))
@@ -245,7 +270,7 @@ pub fn update(
     if !rockets
         .user
         .clone()
-        .and_then(|u| u.is_author_in(&*conn, &blog).ok())
+        .and_then(|u| u.is_owner_of(&*conn, &blog).ok())
         .unwrap_or(false)
     {
         // TODO actually return 403 error code
@@ -321,6 +346,7 @@ pub fn update(
             blog.icon_id = form.icon;
             blog.banner_id = form.banner;
             blog.theme = form.theme.clone();
+            blog.require_review = form.require_review;
             blog.save_changes::<Blog>(&*conn)
                 .expect("Couldn't save blog changes");
             Ok(Flash::success(
@@ -342,6 +368,365 @@ pub fn update(
         .into()
 }
 
+#[get("/~/<name>/authors")]
+pub fn authors(name: String, user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    if !user.is_owner_of(conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let authors = BlogAuthor::list_for_blog(conn, blog.id)?
+        .into_iter()
+        .map(|a| Ok((User::get(conn, a.author_id)?, a)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let invitations = BlogInvitation::list_for_blog(conn, blog.id)?
+        .into_iter()
+        .map(|i| Ok((i.get_user(conn)?, i)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(render!(blogs::authors(
+        &rockets.to_context(),
+        blog,
+        authors,
+        invitations
+    )))
+}
+
+#[derive(FromForm)]
+pub struct InviteForm {
+    /// The address of the invited user
+    pub user: String,
+    pub role: String,
+}
+
+#[post("/~/<name>/authors/invite", data = "<form>")]
+pub fn invite(
+    name: String,
+    user: User,
+    form: LenientForm<InviteForm>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let intl = &rockets.intl.catalog;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    if !user.is_owner_of(conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let invited =
+        User::find_by_fqn(&rockets, form.user.trim().trim_start_matches('@')).and_then(|invited| {
+            BlogInvitation::invite(conn, &blog, &user, &invited, &form.role)
+                .map(|invitation| (invited, invitation))
+        });
+    match invited {
+        Ok((invited, invitation)) => {
+            let msg = i18n!(intl, "{0} has been invited."; invited.name());
+            if !invited.is_local() {
+                let act = invitation.to_activity(conn)?;
+                rockets
+                    .worker
+                    .execute(move || broadcast(&user, act, vec![invited]));
+            }
+            Ok(Flash::success(
+                Redirect::to(uri!(authors: name = &name)),
+                msg,
+            ))
+        }
+        Err(_) => Ok(Flash::error(
+            Redirect::to(uri!(authors: name = &name)),
+            i18n!(
+                intl,
+                "This user couldn't be invited. Maybe they are already an author of this blog?"
+            ),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+pub struct RoleForm {
+    pub role: String,
+}
+
+#[post("/~/<name>/authors/<id>/role", data = "<form>")]
+pub fn change_role(
+    name: String,
+    id: i32,
+    user: User,
+    form: LenientForm<RoleForm>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    let mut author = BlogAuthor::get(conn, id)?;
+    if !user.is_owner_of(conn, &blog)? || author.blog_id != blog.id {
+        return Err(Error::Unauthorized.into());
+    }
+    if !author_role::is_valid(&form.role) {
+        return Err(Error::InvalidValue.into());
+    }
+
+    if author.is_owner()
+        && form.role != author_role::OWNER
+        && BlogAuthor::count_owners(conn, blog.id)? <= 1
+    {
+        return Ok(Flash::error(
+            Redirect::to(uri!(authors: name = &name)),
+            i18n!(rockets.intl.catalog, "A blog needs at least one owner."),
+        ));
+    }
+    author.role = form.role.clone();
+    author
+        .save_changes::<BlogAuthor>(conn)
+        .map_err(Error::from)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(authors: name = &name)),
+        i18n!(
+            rockets.intl.catalog,
+            "The role of this author has been changed."
+        ),
+    ))
+}
+
+#[post("/~/<name>/authors/<id>/remove")]
+pub fn remove_author(
+    name: String,
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    let author = BlogAuthor::get(conn, id)?;
+    if !user.is_owner_of(conn, &blog)? || author.blog_id != blog.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    if author.is_owner() && BlogAuthor::count_owners(conn, blog.id)? <= 1 {
+        return Ok(Flash::error(
+            Redirect::to(uri!(authors: name = &name)),
+            i18n!(rockets.intl.catalog, "A blog needs at least one owner."),
+        ));
+    }
+    author.delete(conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(authors: name = &name)),
+        i18n!(
+            rockets.intl.catalog,
+            "This author has been removed from the blog."
+        ),
+    ))
+}
+
+#[post("/~/<name>/invitations/<id>/cancel")]
+pub fn cancel_invitation(
+    name: String,
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    let invitation = BlogInvitation::get(conn, id)?;
+    if !user.is_owner_of(conn, &blog)? || invitation.blog_id != blog.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    invitation.delete(conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(authors: name = &name)),
+        i18n!(rockets.intl.catalog, "The invitation has been cancelled."),
+    ))
+}
+
+#[post("/invitations/<id>/accept")]
+pub fn accept_invitation(
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let invitation = BlogInvitation::get(conn, id)?;
+    if invitation.user_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let blog = invitation.get_blog(conn)?;
+    invitation.accept(conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(details: name = &blog.fqn, page = _)),
+        i18n!(rockets.intl.catalog, "You are now an author of {0}."; &blog.title),
+    ))
+}
+
+#[post("/invitations/<id>/decline")]
+pub fn decline_invitation(
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let invitation = BlogInvitation::get(conn, id)?;
+    if invitation.user_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    invitation.delete(conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(super::notifications::notifications: page = _)),
+        i18n!(rockets.intl.catalog, "The invitation has been declined."),
+    ))
+}
+
+#[get("/~/<name>/import")]
+pub fn import_form(name: String, user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    if !user.is_author_in(&*rockets.conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    Ok(render!(blogs::import(&rockets.to_context(), blog)))
+}
+
+#[post("/~/<name>/import", data = "<data>")]
+pub fn import(
+    name: String,
+    user: User,
+    data: Data,
+    ct: &ContentType,
+    pool: State<DbPool>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let intl = &rockets.intl.catalog;
+    let blog = Blog::find_by_fqn(&rockets, &name)?;
+    if !user.is_author_in(conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let failed = || {
+        Flash::error(
+            Redirect::to(uri!(import_form: name = &name)),
+            i18n!(intl, "This export couldn't be imported."),
+        )
+    };
+    if !ct.is_form_data() {
+        return Ok(failed());
+    }
+    let boundary = match ct.params().find(|&(k, _)| k == "boundary") {
+        Some((_, boundary)) => boundary,
+        None => return Ok(failed()),
+    };
+    let fields = match Multipart::with_body(data.open(), boundary)
+        .save()
+        .size_limit(MAX_EXPORT_SIZE)
+        .temp()
+    {
+        SaveResult::Full(entries) => entries.fields,
+        SaveResult::Partial(_, PartialReason::SizeLimit) => {
+            return Ok(Flash::error(
+                Redirect::to(uri!(import_form: name = &name)),
+                i18n!(intl, "This export is too large."),
+            ));
+        }
+        _ => return Ok(failed()),
+    };
+    let file = match fields.get("file").and_then(|f| f.iter().next()) {
+        Some(file) => file,
+        None => return Ok(failed()),
+    };
+    let text = |field: &str| {
+        fields
+            .get(field)
+            .and_then(|f| f.iter().next())
+            .and_then(|f| match f.data {
+                SavedData::Text(ref text) => Some(text.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+
+    // the export gets a directory of its own: the files it references are searched next to it
+    let dir = env::temp_dir().join(format!("plume-import-{}", GUID::rand().to_string()));
+    if fs::create_dir(&dir).is_err() {
+        return Ok(failed());
+    }
+    // the extension is kept, to guess the format of the export
+    let ext = file
+        .headers
+        .filename
+        .as_ref()
+        .and_then(|f| f.rsplit('.').next())
+        .filter(|ext| ext.chars().all(|c| c.is_alphanumeric()))
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    let path = dir.join(format!("export.{}", ext));
+    let saved = match file.data {
+        SavedData::Bytes(ref bytes) => fs::write(&path, bytes).is_ok(),
+        SavedData::File(ref tmp, _) => fs::copy(tmp, &path).is_ok(),
+        _ => false,
+    };
+    let format = match text("format").as_ref() {
+        "wordpress" => Some(ImportFormat::WordPress),
+        "ghost" => Some(ImportFormat::Ghost),
+        "markdown" => Some(ImportFormat::Markdown),
+        _ => ImportFormat::guess(&path),
+    };
+    // the authors who can't publish in this blog only import drafts
+    let publish = user.can_publish_in(conn, &blog)?;
+    let federation = if text("federation") == "backdated" {
+        Federation::Backdated
+    } else {
+        Federation::None
+    };
+    let format = match format.filter(|_| saved) {
+        Some(format) => format,
+        None => {
+            fs::remove_dir_all(&dir).ok();
+            return Ok(failed());
+        }
+    };
+
+    // reading the export and downloading its images can take a while
+    let pool = pool.clone();
+    let searcher = rockets.searcher.clone();
+    rockets.worker.execute(move || {
+        if let Ok(conn) = pool.get() {
+            let imported = Export::read(&path, format).and_then(|export| {
+                Importer {
+                    conn: &conn,
+                    searcher: &searcher,
+                    blog: &blog,
+                    author: &user,
+                    publish,
+                    federation,
+                }
+                .import(&export)
+            });
+            // let the author know, as they were told it would work
+            if imported.is_err() {
+                Notification::insert(
+                    &conn,
+                    NewNotification {
+                        user_id: user.id,
+                        kind: notification_kind::IMPORT_FAILED.to_string(),
+                        object_id: blog.id,
+                    },
+                )
+                .ok();
+            }
+        }
+        fs::remove_dir_all(&dir).ok();
+    });
+
+    Ok(Flash::success(
+        Redirect::to(uri!(details: name = &name, page = _)),
+        i18n!(
+            intl,
+            "Your export is being imported. The articles will appear on this blog in a few minutes."
+        ),
+    ))
+}
+
 #[get("/~/<name>/outbox")]
 pub fn outbox(name: String, rockets: PlumeRocket) -> Option<ActivityStream<OrderedCollection>> {
     let blog = Blog::find_by_fqn(&rockets, &name).ok()?;
@@ -371,3 +756,81 @@ pub fn atom_feed(name: String, rockets: PlumeRocket) -> Option<Content<String>>
         feed.to_string(),
     ))
 }
+
+/// An RSS feed of the posts with a sound, that podcast apps can subscribe to
+#[get("/~/<name>/podcast.xml")]
+pub fn podcast_feed(name: String, rockets: PlumeRocket) -> Option<Content<String>> {
+    let blog = Blog::find_by_fqn(&rockets, &name).ok()?;
+    let conn = &*rockets.conn;
+    let authors = blog
+        .list_authors(conn)
+        .ok()?
+        .into_iter()
+        .map(|a| a.display_name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let image = blog.icon_id.map(|_| blog.icon_url(conn));
+
+    let items = Post::podcast_episodes(conn, &blog, 50)
+        .ok()?
+        .into_iter()
+        .map(|(post, sound)| {
+            let duration = sound
+                .duration
+                .map(|d| format!("{:02}:{:02}:{:02}", d / 3600, d / 60 % 60, d % 60));
+            ItemBuilder::default()
+                .title(post.title.clone())
+                .link(post.ap_url.clone())
+                .description(post.content.get().clone())
+                .author(authors.clone())
+                .guid(
+                    GuidBuilder::default()
+                        .value(post.ap_url.clone())
+                        .permalink(true)
+                        .build()
+                        .ok()?,
+                )
+                .pub_date(DateTime::<Utc>::from_utc(post.creation_date, Utc).to_rfc2822())
+                .enclosure(
+                    EnclosureBuilder::default()
+                        .url(sound.url().ok()?)
+                        .length(sound.size.to_string())
+                        .mime_type(sound.mime_type().to_owned())
+                        .build()
+                        .ok()?,
+                )
+                .itunes_ext(
+                    ITunesItemExtensionBuilder::default()
+                        .author(authors.clone())
+                        .subtitle(post.subtitle.clone())
+                        .duration(duration)
+                        .explicit("no".to_owned())
+                        .build()
+                        .ok()?,
+                )
+                .build()
+                .ok()
+        })
+        .collect::<Option<Vec<Item>>>()?;
+
+    let channel = ChannelBuilder::default()
+        .title(blog.title.clone())
+        .link(blog.ap_url.clone())
+        .description(blog.summary.clone())
+        .items(items)
+        .itunes_ext(
+            ITunesChannelExtensionBuilder::default()
+                .author(authors)
+                .summary(blog.summary.clone())
+                .image(image)
+                .explicit("no".to_owned())
+                .build()
+                .ok()?,
+        )
+        .build()
+        .ok()?;
+    Some(Content(
+        ContentType::new("application", "rss+xml"),
+        channel.to_string(),
+    ))
+}
@@ -14,7 +14,8 @@ use plume_common::{
 };
 use plume_models::{
     blogs::Blog, comments::*, inbox::inbox, instance::Instance, medias::Media, mentions::Mention,
-    posts::Post, safe_string::SafeString, tags::Tag, users::User, Error, PlumeRocket,
+    posts::Post, safe_string::SafeString, series::Series, tags::Tag, users::User, Error,
+    PlumeRocket,
 };
 use routes::errors::ErrorPage;
 use template_utils::IntoContext;
@@ -132,7 +133,9 @@ pub fn create(
                 .expect("comments::create: following error"),
                 post.get_authors(&*conn)
                     .expect("comments::create: authors error")[0]
-                    .clone()
+                    .clone(),
+                Series::navigation(&*conn, &post, Some(&user))
+                    .expect("comments::create: series error")
             ))
         })
 }
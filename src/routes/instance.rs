@@ -3,7 +3,6 @@ use rocket::{
     response::{status, Flash, Redirect},
 };
 use rocket_contrib::json::Json;
-use rocket_i18n::I18n;
 use serde_json;
 use validator::{Validate, ValidationErrors};
 
@@ -11,7 +10,7 @@ use inbox;
 use plume_common::activity_pub::{broadcast, inbox::FromId};
 use plume_models::{
     admin::Admin, comments::Comment, db_conn::DbConn, headers::Headers, instance::*, posts::Post,
-    safe_string::SafeString, users::User, Error, PlumeRocket, CONFIG,
+    safe_string::SafeString, two_factor::TwoFactorAuth, users::User, Error, PlumeRocket, CONFIG,
 };
 use routes::{errors::ErrorPage, rocket_uri_macro_static_files, Page, RespondOrRedirect};
 use template_utils::{IntoContext, Ructe};
@@ -93,6 +92,7 @@ pub fn admin(_admin: Admin, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
             short_description: local_inst.short_description,
             long_description: local_inst.long_description,
             default_license: local_inst.default_license,
+            require_admin_2fa: local_inst.require_admin_2fa,
         },
         ValidationErrors::default()
     )))
@@ -107,11 +107,12 @@ pub struct InstanceSettingsForm {
     pub long_description: SafeString,
     #[validate(length(min = "1"))]
     pub default_license: String,
+    pub require_admin_2fa: bool,
 }
 
 #[post("/admin", data = "<form>")]
 pub fn update_settings(
-    _admin: Admin,
+    admin: Admin,
     form: LenientForm<InstanceSettingsForm>,
     rockets: PlumeRocket,
 ) -> RespondOrRedirect {
@@ -126,6 +127,16 @@ pub fn update_settings(
             e
         ))
         .into()
+    } else if form.require_admin_2fa && !TwoFactorAuth::is_enabled(conn, admin.0.id) {
+        // otherwise they would lock themselves out of this page
+        Flash::error(
+            Redirect::to(uri!(admin)),
+            i18n!(
+                rockets.intl.catalog,
+                "Enable two-factor authentication for your own account before requiring it."
+            ),
+        )
+        .into()
     } else {
         let instance =
             Instance::get_local().expect("instance::update_settings: local instance error");
@@ -139,6 +150,9 @@ pub fn update_settings(
                 form.default_license.clone(),
             )
             .expect("instance::update_settings: save error");
+        instance
+            .set_require_admin_2fa(conn, form.require_admin_2fa)
+            .expect("instance::update_settings: save error");
         Flash::success(
             Redirect::to(uri!(admin)),
             i18n!(rockets.intl.catalog, "Instance settings have been saved."),
@@ -167,18 +181,17 @@ pub fn admin_instances(
 #[post("/admin/instances/<id>/block")]
 pub fn toggle_block(
     _admin: Admin,
-    conn: DbConn,
     id: i32,
-    intl: I18n,
+    rockets: PlumeRocket,
 ) -> Result<Flash<Redirect>, ErrorPage> {
-    let inst = Instance::get(&*conn, id)?;
+    let inst = Instance::get(&*rockets.conn, id)?;
     let message = if inst.blocked {
-        i18n!(intl.catalog, "{} has been unblocked."; &inst.name)
+        i18n!(rockets.intl.catalog, "{} has been unblocked."; &inst.name)
     } else {
-        i18n!(intl.catalog, "{} has been blocked."; &inst.name)
+        i18n!(rockets.intl.catalog, "{} has been blocked."; &inst.name)
     };
 
-    inst.toggle_block(&*conn)?;
+    inst.toggle_block(&*rockets.conn, &rockets.searcher)?;
     Ok(Flash::success(
         Redirect::to(uri!(admin_instances: page = _)),
         message,
@@ -1,34 +1,68 @@
-use guid_create::GUID;
 use multipart::server::{
-    save::{SaveResult, SavedData},
+    save::{PartialReason, SaveResult, SavedData},
     Multipart,
 };
-use plume_models::{db_conn::DbConn, medias::*, users::User, Error, PlumeRocket};
+use plume_models::{
+    db_conn::{DbConn, DbPool},
+    media_albums::{MediaAlbum, NewMediaAlbum},
+    medias::*,
+    users::User,
+    Error, PlumeRocket, CONFIG,
+};
 use rocket::{
-    http::ContentType,
-    response::{status, Flash, Redirect},
-    Data,
+    http::{
+        hyper::header::{CacheControl, CacheDirective},
+        ContentType, Header,
+    },
+    request::{Form, FormItems, FromForm, LenientForm},
+    response::{content::Content, status, Flash, Redirect},
+    Data, State,
 };
+use rocket_contrib::json::Json;
 use rocket_i18n::I18n;
 use routes::{errors::ErrorPage, Page};
+use serde_json;
 use std::fs;
 use template_utils::{IntoContext, Ructe};
 
-#[get("/medias?<page>")]
-pub fn list(user: User, page: Option<Page>, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+#[get("/medias?<page>&<album>&<q>")]
+pub fn list(
+    user: User,
+    page: Option<Page>,
+    album: Option<i32>,
+    q: Option<String>,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
     let page = page.unwrap_or_default();
-    let medias = Media::page_for_user(&*rockets.conn, &user, page.limits())?;
+    let album = match album {
+        Some(id) => {
+            let album = MediaAlbum::get(conn, id)?;
+            if album.owner_id != user.id {
+                return Err(Error::Unauthorized.into());
+            }
+            Some(album)
+        }
+        None => None,
+    };
+    let album_id = album.as_ref().map(|a| a.id);
+    let query = q.as_ref().map(String::as_str);
+    let medias = Media::page_for_user(conn, &user, album_id, query, page.limits())?;
     Ok(render!(medias::index(
         &rockets.to_context(),
         medias,
+        MediaAlbum::list_for_user(conn, user.id)?,
+        album,
+        q.clone().unwrap_or_default(),
         page.0,
-        Page::total(Media::count_for_user(&*rockets.conn, &user)? as i32)
+        Page::total(Media::count_for_user(conn, &user, album_id, query)? as i32)
     )))
 }
 
 #[get("/medias/new")]
-pub fn new(_user: User, rockets: PlumeRocket) -> Ructe {
-    render!(medias::new(&rockets.to_context()))
+pub fn new(user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let albums = MediaAlbum::list_for_user(&*rockets.conn, user.id)?;
+    Ok(render!(medias::new(&rockets.to_context(), albums, None)))
 }
 
 #[post("/medias/new", data = "<data>")]
@@ -36,87 +70,168 @@ pub fn upload(
     user: User,
     data: Data,
     ct: &ContentType,
-    conn: DbConn,
-) -> Result<Redirect, status::BadRequest<&'static str>> {
-    if !ct.is_form_data() {
-        return Ok(Redirect::to(uri!(new)));
+    pool: State<DbPool>,
+    rockets: PlumeRocket,
+) -> Result<Redirect, status::BadRequest<Ructe>> {
+    match save_upload(&user, data, ct, &pool, &rockets) {
+        Ok(media) => Ok(Redirect::to(uri!(details: id = media.id))),
+        Err(msg) => {
+            let albums = MediaAlbum::list_for_user(&*rockets.conn, user.id).unwrap_or_default();
+            Err(status::BadRequest(Some(render!(medias::new(
+                &rockets.to_context(),
+                albums,
+                Some(msg)
+            )))))
+        }
     }
+}
+
+/// Upload a media from the editor, and get its Markdown code
+#[post("/medias/new.json", data = "<data>")]
+pub fn upload_json(
+    user: User,
+    data: Data,
+    ct: &ContentType,
+    pool: State<DbPool>,
+    rockets: PlumeRocket,
+) -> Result<Json<serde_json::Value>, status::BadRequest<Json<serde_json::Value>>> {
+    save_upload(&user, data, ct, &pool, &rockets)
+        .and_then(|media| {
+            Ok(Json(json!({
+                "id": media.id,
+                "url": media.url().map_err(|_| String::new())?,
+                "markdown": media.markdown().map_err(|_| String::new())?.to_string(),
+            })))
+        })
+        .map_err(|msg| status::BadRequest(Some(Json(json!({ "error": msg })))))
+}
+
+/// Save a file sent with a multipart form, with its description and content warning.
+/// Pictures are then processed in the background.
+///
+/// The error is a message for the user.
+fn save_upload(
+    user: &User,
+    data: Data,
+    ct: &ContentType,
+    pool: &DbPool,
+    rockets: &PlumeRocket,
+) -> Result<Media, String> {
+    let cat = &rockets.intl.catalog;
+    let no_file = || i18n!(cat, "No file uploaded");
+    let cant_save = || i18n!(cat, "Couldn't save the uploaded file");
 
+    if !ct.is_form_data() {
+        return Err(no_file());
+    }
     let (_, boundary) = ct
         .params()
         .find(|&(k, _)| k == "boundary")
-        .ok_or_else(|| status::BadRequest(Some("No boundary")))?;
-
-    if let SaveResult::Full(entries) = Multipart::with_body(data.open(), boundary).save().temp() {
-        let fields = entries.fields;
-
-        let filename = fields
-            .get("file")
-            .and_then(|v| v.iter().next())
-            .ok_or_else(|| status::BadRequest(Some("No file uploaded")))?
-            .headers
-            .filename
-            .clone();
-        // Remove extension if it contains something else than just letters and numbers
-        let ext = filename
-            .and_then(|f| {
-                f.rsplit('.')
-                    .next()
-                    .and_then(|ext| {
-                        if ext.chars().any(|c| !c.is_alphanumeric()) {
-                            None
-                        } else {
-                            Some(ext.to_lowercase())
-                        }
-                    })
-                    .map(|ext| format!(".{}", ext))
-            })
-            .unwrap_or_default();
-        let dest = format!("static/media/{}{}", GUID::rand().to_string(), ext);
-
-        match fields["file"][0].data {
-            SavedData::Bytes(ref bytes) => fs::write(&dest, bytes)
-                .map_err(|_| status::BadRequest(Some("Couldn't save upload")))?,
-            SavedData::File(ref path, _) => {
-                fs::copy(path, &dest)
-                    .map_err(|_| status::BadRequest(Some("Couldn't copy upload")))?;
-            }
-            _ => {
-                return Ok(Redirect::to(uri!(new)));
-            }
+        .ok_or_else(no_file)?;
+
+    // the other fields are small, this leaves them enough room
+    let size_limit = CONFIG.media.max_file_size() + 64 * 1024;
+    let entries = match Multipart::with_body(data.open(), boundary)
+        .save()
+        .size_limit(size_limit)
+        .temp()
+    {
+        SaveResult::Full(entries) => entries,
+        SaveResult::Partial(_, PartialReason::SizeLimit) => {
+            return Err(i18n!(cat, "This file is too large."));
         }
+        _ => return Err(no_file()),
+    };
+    let fields = entries.fields;
 
-        let has_cw = !read(&fields["cw"][0].data)
-            .map(|cw| cw.is_empty())
-            .unwrap_or(false);
-        let media = Media::insert(
-            &*conn,
-            NewMedia {
-                file_path: dest,
-                alt_text: read(&fields["alt"][0].data)?,
-                is_remote: false,
-                remote_url: None,
-                sensitive: has_cw,
-                content_warning: if has_cw {
-                    Some(read(&fields["cw"][0].data)?)
-                } else {
-                    None
-                },
-                owner_id: user.id,
-            },
-        )
-        .map_err(|_| status::BadRequest(Some("Error while saving media")))?;
-        Ok(Redirect::to(uri!(details: id = media.id)))
-    } else {
-        Ok(Redirect::to(uri!(new)))
+    let file = fields
+        .get("file")
+        .and_then(|v| v.iter().next())
+        .ok_or_else(no_file)?;
+    let content = match file.data {
+        SavedData::Bytes(ref bytes) => bytes.clone(),
+        SavedData::File(ref path, _) => {
+            fs::read(path).map_err(|_| i18n!(cat, "Couldn't read the uploaded file"))?
+        }
+        _ => return Err(no_file()),
+    };
+    let (content, ext) = Media::validate_upload(&*rockets.conn, user.id, content).map_err(
+        |e| match e {
+            UploadError::UnsupportedType => i18n!(
+                cat,
+                "This file type is not supported. You can upload pictures (PNG, JPEG, GIF, WebP or SVG), sounds (MP3, Ogg, FLAC or WAV) and videos (MP4 or WebM)."
+            ),
+            UploadError::TooLarge(limit) => i18n!(
+                cat,
+                "This file is too large, files of this type can't be bigger than {0}.";
+                mib(limit)
+            ),
+            UploadError::QuotaExceeded(quota) => i18n!(
+                cat,
+                "You don't have enough space left for this file. Your media can't use more than {0}, delete some of them to upload new ones.";
+                mib(quota)
+            ),
+            UploadError::TooManyPixels(max) => i18n!(
+                cat,
+                "This picture is too large, it can't have more than {0} million pixels.";
+                max / 1_000_000
+            ),
+            UploadError::Internal => cant_save(),
+        },
+    )?;
+    let size = content.len() as i32;
+    let dest = Media::save_file(&content, ext).map_err(|_| cant_save())?;
+
+    let field = |name: &str| fields.get(name).and_then(|v| read(&v[0].data));
+    let albums = MediaAlbum::list_for_user(&*rockets.conn, user.id).map_err(|_| cant_save())?;
+    let album_id = field("album")
+        .and_then(|id| id.parse::<i32>().ok())
+        .filter(|id| albums.iter().any(|a| a.id == *id));
+    let content_warning = field("cw").filter(|cw| !cw.is_empty());
+    let media = Media::insert(
+        &*rockets.conn,
+        NewMedia {
+            file_path: dest,
+            alt_text: field("alt").unwrap_or_default(),
+            is_remote: false,
+            remote_url: None,
+            sensitive: content_warning.is_some(),
+            content_warning,
+            owner_id: user.id,
+            size,
+            album_id,
+            original_name: file.headers.filename.clone().unwrap_or_default(),
+        },
+    )
+    .map_err(|_| cant_save())?;
+    media
+        .detect_duration(&*rockets.conn, &content)
+        .map_err(|_| cant_save())?;
+
+    if media.category() == MediaCategory::Image {
+        let media = media.clone();
+        let pool = pool.clone();
+        rockets.worker.execute(move || {
+            if let Ok(conn) = pool.get() {
+                if let Err(e) = media.process_image(&conn) {
+                    eprintln!("Error while processing an uploaded picture: {:?}", e);
+                }
+            }
+        });
     }
+    Ok(media)
+}
+
+/// Format a size in bytes in MiB, for error messages
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
 }
 
-fn read(data: &SavedData) -> Result<String, status::BadRequest<&'static str>> {
+fn read(data: &SavedData) -> Option<String> {
     if let SavedData::Text(s) = data {
-        Ok(s.clone())
+        Some(s.clone())
     } else {
-        Err(status::BadRequest(Some("Error while reading data")))
+        None
     }
 }
 
@@ -124,24 +239,102 @@ fn read(data: &SavedData) -> Result<String, status::BadRequest<&'static str>> {
 pub fn details(id: i32, user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
     let media = Media::get(&*rockets.conn, id)?;
     if media.owner_id == user.id {
-        Ok(render!(medias::details(&rockets.to_context(), media)))
+        let usage = media.usage(&*rockets.conn)?;
+        Ok(render!(medias::details(
+            &rockets.to_context(),
+            media,
+            usage
+        )))
     } else {
         Err(Error::Unauthorized.into())
     }
 }
 
+/// A copy of a remote media
+#[derive(Responder)]
+#[response()]
+pub struct ProxiedMedia {
+    inner: Content<Vec<u8>>,
+    cache_control: CacheControl,
+    // remote SVGs are sanitized, but are still served from our origin
+    csp: Header<'static>,
+}
+
+/// Serve a remote media from our copy of it.
+///
+/// The first time it is needed, the copy is downloaded in the background, and the original
+/// is used until it is ready.
+#[get("/medias/<id>/proxy")]
+pub fn proxy(
+    id: i32,
+    pool: State<DbPool>,
+    rockets: PlumeRocket,
+) -> Option<Result<ProxiedMedia, Redirect>> {
+    let media = Media::get(&*rockets.conn, id).ok()?;
+    let (key, content) = match media.cached_copy() {
+        Some(copy) => copy,
+        None => {
+            let url = media.remote_url.clone()?;
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return None;
+            }
+            let pool = pool.clone();
+            rockets
+                .worker
+                .execute(move || match media.download_remote() {
+                    Ok(Some(download)) => {
+                        if let Ok(conn) = pool.get() {
+                            if let Err(e) = download.save(&conn) {
+                                eprintln!("Error while saving a remote media: {:?}", e);
+                            }
+                        }
+                    }
+                    // it is already being downloaded
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Error while downloading a remote media: {:?}", e),
+                });
+            return Some(Err(Redirect::to(url)));
+        }
+    };
+    let content_type = key
+        .rsplit('.')
+        .next()
+        .and_then(ContentType::from_extension)
+        .unwrap_or(ContentType::Binary);
+    Some(Ok(ProxiedMedia {
+        inner: Content(content_type, content),
+        cache_control: CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(60 * 60 * 24),
+        ]),
+        csp: Header::new(
+            "Content-Security-Policy",
+            "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+        ),
+    }))
+}
+
 #[post("/medias/<id>/delete")]
 pub fn delete(id: i32, user: User, conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
     let media = Media::get(&*conn, id)?;
     if media.owner_id == user.id {
+        if !media.usage(&*conn)?.is_empty() {
+            return Ok(Flash::error(
+                Redirect::to(uri!(details: id = id)),
+                i18n!(
+                    intl.catalog,
+                    "This media is still used. Remove it from the places listed below before deleting it."
+                ),
+            ));
+        }
         media.delete(&*conn)?;
         Ok(Flash::success(
-            Redirect::to(uri!(list: page = _)),
+            Redirect::to(uri!(list: page = _, album = _, q = _)),
             i18n!(intl.catalog, "Your media have been deleted."),
         ))
     } else {
         Ok(Flash::error(
-            Redirect::to(uri!(list: page = _)),
+            Redirect::to(uri!(list: page = _, album = _, q = _)),
             i18n!(intl.catalog, "You are not allowed to delete this media."),
         ))
     }
@@ -168,3 +361,194 @@ pub fn set_avatar(
         ))
     }
 }
+
+#[derive(FromForm)]
+pub struct FocalPointForm {
+    /// In percents of the width of the picture, from the left
+    pub x: Option<f32>,
+    /// In percents of the height of the picture, from the top
+    pub y: Option<f32>,
+}
+
+/// Choose the point of a picture that stays visible when it is cropped, or reset it if
+/// no position is given
+#[post("/medias/<id>/focal-point", data = "<form>")]
+pub fn set_focal_point(
+    id: i32,
+    user: User,
+    form: LenientForm<FocalPointForm>,
+    conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let media = Media::get(&*conn, id)?;
+    if media.owner_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    let point = match (form.x, form.y) {
+        (Some(x), Some(y)) => Some((x / 50.0 - 1.0, 1.0 - y / 50.0)),
+        _ => None,
+    };
+    media.set_focal_point(&*conn, point)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(details: id = id)),
+        i18n!(
+            intl.catalog,
+            "The focal point of this picture has been updated."
+        ),
+    ))
+}
+
+#[derive(Default)]
+pub struct BulkForm {
+    pub action: String,
+    pub medias: Vec<i32>,
+    pub content_warning: String,
+    pub album: Option<i32>,
+}
+
+// Checkboxes with the same name can't be collected with the derived implementation
+impl<'f> FromForm<'f> for BulkForm {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, _strict: bool) -> Result<BulkForm, ()> {
+        let mut form = BulkForm::default();
+        for item in items {
+            let (key, value) = item.key_value_decoded();
+            match key.as_ref() {
+                "action" => form.action = value,
+                "media" => form.medias.push(value.parse().map_err(|_| ())?),
+                "content_warning" => form.content_warning = value,
+                "album" => form.album = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(form)
+    }
+}
+
+/// Apply the same action to many media
+#[post("/medias/bulk", data = "<form>")]
+pub fn bulk(
+    user: User,
+    form: Form<BulkForm>,
+    conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let medias = form
+        .medias
+        .iter()
+        .filter_map(|id| Media::get(&*conn, *id).ok())
+        .filter(|m| m.owner_id == user.id)
+        .collect::<Vec<_>>();
+    let back = Redirect::to(uri!(list: page = _, album = _, q = _));
+
+    match form.action.as_ref() {
+        "delete" => {
+            let mut used = vec![];
+            for media in medias {
+                if media.usage(&*conn)?.is_empty() {
+                    media.delete(&*conn)?;
+                } else if media.alt_text.is_empty() {
+                    used.push(media.original_name);
+                } else {
+                    used.push(media.alt_text);
+                }
+            }
+            if !used.is_empty() {
+                return Ok(Flash::error(
+                    back,
+                    i18n!(
+                        intl.catalog,
+                        "These media are still used, and have not been deleted: {0}";
+                        used.join(", ")
+                    ),
+                ));
+            }
+        }
+        "add-cw" => {
+            if form.content_warning.is_empty() {
+                return Ok(Flash::error(
+                    back,
+                    i18n!(intl.catalog, "Please write the content warning to add."),
+                ));
+            }
+            for media in medias {
+                media.set_content_warning(&*conn, Some(form.content_warning.clone()))?;
+            }
+        }
+        "remove-cw" => {
+            for media in medias {
+                media.set_content_warning(&*conn, None)?;
+            }
+        }
+        "move" => {
+            if let Some(album) = form.album {
+                if MediaAlbum::get(&*conn, album)?.owner_id != user.id {
+                    return Err(Error::Unauthorized.into());
+                }
+            }
+            for media in medias {
+                media.set_album(&*conn, form.album)?;
+            }
+        }
+        _ => return Err(Error::InvalidValue.into()),
+    }
+
+    Ok(Flash::success(
+        back,
+        i18n!(intl.catalog, "Your media have been updated."),
+    ))
+}
+
+#[derive(FromForm)]
+pub struct AlbumForm {
+    pub name: String,
+}
+
+#[post("/medias/albums/new", data = "<form>")]
+pub fn create_album(
+    user: User,
+    form: LenientForm<AlbumForm>,
+    conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok(Flash::error(
+            Redirect::to(uri!(list: page = _, album = _, q = _)),
+            i18n!(intl.catalog, "Albums need a name."),
+        ));
+    }
+    let album = MediaAlbum::insert(
+        &*conn,
+        NewMediaAlbum {
+            owner_id: user.id,
+            name: name.to_owned(),
+        },
+    )?;
+    Ok(Flash::success(
+        Redirect::to(uri!(list: page = _, album = album.id, q = _)),
+        i18n!(intl.catalog, "Your album has been created."),
+    ))
+}
+
+#[post("/medias/albums/<id>/delete")]
+pub fn delete_album(
+    id: i32,
+    user: User,
+    conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let album = MediaAlbum::get(&*conn, id)?;
+    if album.owner_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    album.delete(&*conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(list: page = _, album = _, q = _)),
+        i18n!(
+            intl.catalog,
+            "Your album has been deleted. The media it contained are still in your gallery."
+        ),
+    ))
+}
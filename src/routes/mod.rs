@@ -17,7 +17,7 @@ use std::{
 };
 use template_utils::Ructe;
 
-use plume_models::{posts::Post, Connection};
+use plume_models::{posts::Post, Connection, MediaStorage, CONFIG};
 
 const ITEMS_PER_PAGE: i32 = 12;
 
@@ -160,6 +160,7 @@ pub mod notifications;
 pub mod posts;
 pub mod reshares;
 pub mod search;
+pub mod series;
 pub mod session;
 pub mod tags;
 pub mod user;
@@ -212,6 +213,22 @@ pub fn plume_static_files(file: PathBuf, _build_id: &RawStr) -> Option<CachedFil
     static_files(file)
 }
 
+/// The files of the local media store, that can be kept outside of `static/`
+#[get("/static/media/<file..>", rank = 2)]
+pub fn media_files(file: PathBuf) -> Option<CachedFile> {
+    match CONFIG.media.storage {
+        MediaStorage::Local(ref dir) => {
+            NamedFile::open(Path::new(dir).join(file))
+                .ok()
+                .map(|f| CachedFile {
+                    inner: f,
+                    cache_control: CacheControl(vec![CacheDirective::MaxAge(60 * 60 * 24 * 30)]),
+                })
+        }
+        MediaStorage::S3(_) => None,
+    }
+}
+
 #[get("/static/<file..>", rank = 3)]
 pub fn static_files(file: PathBuf) -> Option<CachedFile> {
     NamedFile::open(Path::new("static/").join(file))
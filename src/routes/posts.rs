@@ -1,5 +1,5 @@
-use chrono::Utc;
-use heck::{CamelCase, KebabCase};
+use chrono::{NaiveDateTime, Utc};
+use heck::CamelCase;
 use rocket::request::LenientForm;
 use rocket::response::{Flash, Redirect};
 use rocket_i18n::I18n;
@@ -19,12 +19,15 @@ use plume_models::{
     instance::Instance,
     medias::Media,
     mentions::Mention,
-    post_authors::*,
+    post_reviews::PostReview,
+    post_revisions::PostRevision,
+    post_slug_history::PreviousSlug,
     posts::*,
     safe_string::SafeString,
+    series::Series,
     tags::*,
     users::User,
-    Error, PlumeRocket,
+    Connection, Error, PlumeRocket,
 };
 use routes::{
     comments::NewCommentForm, errors::ErrorPage, ContentLen, RemoteForm, RespondOrRedirect,
@@ -37,21 +40,43 @@ pub fn details(
     slug: String,
     responding_to: Option<i32>,
     rockets: PlumeRocket,
-) -> Result<Ructe, ErrorPage> {
+) -> Result<RespondOrRedirect, ErrorPage> {
     let conn = &*rockets.conn;
     let user = rockets.user.clone();
     let blog = Blog::find_by_fqn(&rockets, &blog)?;
-    let post = Post::find_by_slug(&*conn, &slug, blog.id)?;
-    if !(post.published
-        || post
-            .get_authors(&*conn)?
-            .into_iter()
-            .any(|a| a.id == user.clone().map(|u| u.id).unwrap_or(0)))
-    {
+    let post = match Post::find_by_slug(&*conn, &slug, blog.id) {
+        Ok(post) => post,
+        Err(Error::NotFound) => {
+            // the post may have been renamed
+            let post = PreviousSlug::find_post(&*conn, &slug, blog.id)?;
+            return Ok(Redirect::moved(
+                uri!(details: blog = &blog.fqn, slug = &post.slug, responding_to = _),
+            )
+            .into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // drafts can be seen by the people who can edit them
+    let can_edit = match user {
+        Some(ref user) => post.can_edit(&*conn, user)?,
+        None => false,
+    };
+    if !(post.published || can_edit) {
         return Ok(render!(errors::not_authorized(
             &rockets.to_context(),
             i18n!(rockets.intl.catalog, "This post isn't published yet.")
-        )));
+        ))
+        .into());
+    }
+    if !post.can_see(&*conn, user.as_ref()) {
+        return Ok(render!(errors::not_authorized(
+            &rockets.to_context(),
+            i18n!(
+                rockets.intl.catalog,
+                "You are not allowed to see this article."
+            )
+        ))
+        .into());
     }
 
     let comments = CommentTree::from_post(&*conn, &post, user.as_ref())?;
@@ -92,9 +117,10 @@ pub fn details(
             post.count_reshares(&*conn)?,
             user.clone().and_then(|u| u.has_liked(&*conn, &post).ok()).unwrap_or(false),
             user.clone().and_then(|u| u.has_reshared(&*conn, &post).ok()).unwrap_or(false),
-            user.and_then(|u| u.is_following(&*conn, post.get_authors(&*conn).ok()?[0].id).ok()).unwrap_or(false),
-            post.get_authors(&*conn)?[0].clone()
-        )))
+            user.clone().and_then(|u| u.is_following(&*conn, post.get_authors(&*conn).ok()?[0].id).ok()).unwrap_or(false),
+            post.get_authors(&*conn)?[0].clone(),
+            Series::navigation(&*conn, &post, user.as_ref())?
+        )).into())
 }
 
 #[get("/~/<blog>/<slug>", rank = 3)]
@@ -106,14 +132,20 @@ pub fn activity_details(
 ) -> Result<ActivityStream<LicensedArticle>, Option<String>> {
     let conn = &*rockets.conn;
     let blog = Blog::find_by_fqn(&rockets, &blog).map_err(|_| None)?;
-    let post = Post::find_by_slug(&*conn, &slug, blog.id).map_err(|_| None)?;
-    if post.published {
+    // the AP id of a post keeps its first slug, even if it was renamed
+    let post = Post::find_by_slug(&*conn, &slug, blog.id)
+        .or_else(|_| PreviousSlug::find_post(&*conn, &slug, blog.id))
+        .map_err(|_| None)?;
+    if !post.published {
+        Err(Some(String::from("Not published yet.")))
+    } else if !post.can_see(&*conn, None) {
+        // fetches are not authenticated, only the recipients of the activities can know about it
+        Err(Some(String::from("Not public.")))
+    } else {
         Ok(ActivityStream::new(
             post.to_activity(&*conn)
                 .map_err(|_| String::from("Post serialization error"))?,
         ))
-    } else {
-        Err(Some(String::from("Not published yet.")))
     }
 }
 
@@ -150,6 +182,7 @@ pub fn new(blog: String, cl: ContentLen, rockets: PlumeRocket) -> Result<Ructe,
         false,
         &NewPostForm {
             license: Instance::get_local()?.default_license,
+            visibility: post_visibility::PUBLIC.to_owned(),
             ..NewPostForm::default()
         },
         true,
@@ -173,10 +206,10 @@ pub fn edit(
     let post = Post::find_by_slug(&*conn, &slug, b.id)?;
     let user = rockets.user.clone().unwrap();
 
-    if !user.is_author_in(&*conn, &b)? {
+    if !post.can_edit(&*conn, &user)? {
         return Ok(render!(errors::not_authorized(
             &rockets.to_context(),
-            i18n!(intl, "You are not an author of this blog.")
+            i18n!(intl, "You are not allowed to edit this article.")
         )));
     }
 
@@ -195,6 +228,7 @@ pub fn edit(
         true,
         &NewPostForm {
             title: post.title.clone(),
+            slug: post.slug.clone(),
             subtitle: post.subtitle.clone(),
             content: source,
             tags: Tag::for_post(&*conn, post.id)?
@@ -205,6 +239,19 @@ pub fn edit(
             license: post.license.clone(),
             draft: true,
             cover: post.cover_id,
+            publish_at: post
+                .publish_at
+                .map(|date| date.format(PUBLISH_AT_FORMAT).to_string())
+                .unwrap_or_default(),
+            visibility: post.visibility.clone(),
+            series: Series::find_for_post(&*conn, &post).ok().map(|s| s.id),
+            authors: post
+                .get_authors(&*conn)?
+                .into_iter()
+                .filter(|a| a.id != user.id)
+                .map(|a| a.fqn)
+                .collect::<Vec<_>>()
+                .join(", "),
         },
         !post.published,
         Some(post),
@@ -226,43 +273,39 @@ pub fn update(
     let b = Blog::find_by_fqn(&rockets, &blog).expect("post::update: blog error");
     let mut post =
         Post::find_by_slug(&*conn, &slug, b.id).expect("post::update: find by slug error");
+    let previous = post.clone();
     let user = rockets.user.clone().unwrap();
     let intl = &rockets.intl.catalog;
 
-    let new_slug = if !post.published {
-        form.title.to_string().to_kebab_case()
+    // published posts keep their URL when they are retitled, unless a new slug is given
+    let new_slug = if !post.published || !form.slug.is_empty() {
+        Post::unique_slug(&*conn, b.id, &form.slug(), Some(post.id))
+            .expect("post::update: slug error")
     } else {
         post.slug.clone()
     };
 
-    let mut errors = match form.validate() {
+    let errors = match form.validate() {
         Ok(_) => ValidationErrors::new(),
         Err(e) => e,
     };
 
-    if new_slug != slug && Post::find_by_slug(&*conn, &new_slug, b.id).is_ok() {
-        errors.add(
-            "title",
-            ValidationError {
-                code: Cow::from("existing_slug"),
-                message: Some(Cow::from("A post with the same title already exists.")),
-                params: HashMap::new(),
-            },
-        );
-    }
-
     if errors.is_empty() {
-        if !user
-            .is_author_in(&*conn, &b)
-            .expect("posts::update: is author in error")
+        if !post
+            .can_edit(&*conn, &user)
+            .expect("posts::update: can edit error")
         {
             // actually it's not "Ok"…
             Flash::error(
                 Redirect::to(uri!(super::blogs::details: name = blog, page = _)),
-                i18n!(&intl, "You are not allowed to publish on this blog."),
+                i18n!(&intl, "You are not allowed to edit this article."),
             )
             .into()
         } else {
+            // writers of blogs that require reviews can only save drafts, that an editor will publish
+            let can_publish = user
+                .can_publish_in(&*conn, &b)
+                .expect("posts::update: can publish error");
             let (content, mentions, hashtags) = utils::md_to_html(
                 form.content.to_string().as_ref(),
                 Some(
@@ -280,26 +323,71 @@ pub fn update(
                 )),
             );
 
-            // update publication date if when this article is no longer a draft
-            let newly_published = if !post.published && !form.draft {
-                post.published = true;
-                post.creation_date = Utc::now().naive_utc();
-                true
+            post.change_slug(&*conn, new_slug.clone())
+                .expect("post::update: slug error");
+
+            // update publication date if when this article is no longer a draft,
+            // it is published with `Post::publish` once everything else is saved
+            let newly_published = if !post.published && !form.draft && can_publish {
+                post.publish_at = form.publish_at();
+                if post.publish_at.is_none() {
+                    post.creation_date = Utc::now().naive_utc();
+                }
+                post.publish_at.is_none()
             } else {
+                if form.draft {
+                    post.publish_at = None;
+                }
                 false
             };
 
-            post.slug = new_slug.clone();
             post.title = form.title.clone();
             post.subtitle = form.subtitle.clone();
             post.content = SafeString::new(&content);
             post.source = form.content.clone();
             post.license = form.license.clone();
             post.cover_id = form.cover;
+            post.visibility = form.visibility().to_owned();
             post.update(&*conn, &rockets.searcher)
                 .expect("post::update: update error");;
 
-            if post.published {
+            let mut authors = form
+                .co_authors(&*conn, &b)
+                .expect("post::update: authors error");
+            if previous
+                .is_author(&*conn, user.id)
+                .expect("post::update: authors error")
+            {
+                authors.push(user.clone());
+            }
+            if !authors.is_empty() {
+                post.set_authors(&*conn, &authors)
+                    .expect("post::update: authors error");
+            }
+
+            match (
+                Series::find_for_post(&*conn, &post).ok(),
+                form.series(&*conn, &b),
+            ) {
+                (Some(ref current), Some(ref series)) if current.id == series.id => {}
+                (current, series) => {
+                    if let Some(current) = current {
+                        current
+                            .remove_post(&*conn, &post)
+                            .expect("post::update: series error");
+                    }
+                    if let Some(series) = series {
+                        series
+                            .add_post(&*conn, &post)
+                            .expect("post::update: series error");
+                    }
+                }
+            }
+            PostRevision::record(&*conn, &previous, &post, Some(&user))
+                .expect("post::update: revision error");
+
+            // mentions of posts that are not published yet are notified by `Post::publish`
+            if post.published || post.publish_at.is_some() || newly_published {
                 post.update_mentions(
                     &conn,
                     mentions
@@ -332,20 +420,25 @@ pub fn update(
             post.update_hashtags(&conn, hashtags)
                 .expect("post::update: hashtags error");
 
-            if post.published {
-                if newly_published {
-                    let act = post
-                        .create_activity(&conn)
-                        .expect("post::update: act error");
-                    let dest = User::one_by_instance(&*conn).expect("post::update: dest error");
-                    rockets.worker.execute(move || broadcast(&user, act, dest));
-                } else {
-                    let act = post
-                        .update_activity(&*conn)
-                        .expect("post::update: act error");
-                    let dest = User::one_by_instance(&*conn).expect("posts::update: dest error");
-                    rockets.worker.execute(move || broadcast(&user, act, dest));
-                }
+            if newly_published {
+                let post = post
+                    .publish(&*conn, &rockets.searcher)
+                    .expect("post::update: publication error");
+                let act = post
+                    .create_activity(&conn)
+                    .expect("post::update: act error");
+                let dest = post
+                    .get_receivers(&*conn)
+                    .expect("post::update: dest error");
+                rockets.worker.execute(move || broadcast(&user, act, dest));
+            } else if post.published {
+                let act = post
+                    .update_activity(&*conn)
+                    .expect("post::update: act error");
+                let dest = post
+                    .get_receivers(&*conn)
+                    .expect("posts::update: dest error");
+                rockets.worker.execute(move || broadcast(&user, act, dest));
             }
 
             Flash::success(
@@ -376,16 +469,105 @@ pub fn update(
 pub struct NewPostForm {
     #[validate(custom(function = "valid_slug", message = "Invalid title"))]
     pub title: String,
+    #[validate(custom(function = "valid_custom_slug", message = "Invalid slug"))]
+    pub slug: String,
     pub subtitle: String,
     pub content: String,
     pub tags: String,
     pub license: String,
     pub draft: bool,
     pub cover: Option<i32>,
+    #[validate(custom(function = "valid_publish_at", message = "Invalid date"))]
+    pub publish_at: String,
+    #[validate(custom(function = "valid_visibility", message = "Invalid visibility"))]
+    pub visibility: String,
+    pub series: Option<i32>,
+    /// The other authors of this post, separated by commas
+    pub authors: String,
+}
+
+/// The format of `datetime-local` inputs
+const PUBLISH_AT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+impl NewPostForm {
+    /// The slug to use for this post, before de-duplication
+    pub fn slug(&self) -> String {
+        if self.slug.is_empty() {
+            utils::make_slug(&self.title)
+        } else {
+            utils::make_slug(&self.slug)
+        }
+    }
+
+    /// The date this post should be published at, if it is scheduled for later
+    pub fn publish_at(&self) -> Option<NaiveDateTime> {
+        parse_publish_at(&self.publish_at).filter(|date| *date > Utc::now().naive_utc())
+    }
+
+    /// The series of `blog` this post should be part of
+    pub fn series(&self, conn: &Connection, blog: &Blog) -> Option<Series> {
+        self.series
+            .and_then(|id| Series::get(conn, id).ok())
+            .filter(|s| s.blog_id == blog.id)
+    }
+
+    /// The other authors of this post, that have to be authors of `blog` too
+    pub fn co_authors(&self, conn: &Connection, blog: &Blog) -> Result<Vec<User>, Error> {
+        let names = self
+            .authors
+            .split(',')
+            .map(|a| a.trim().trim_start_matches('@'))
+            .filter(|a| !a.is_empty())
+            .collect::<Vec<_>>();
+        Ok(blog
+            .list_authors(conn)?
+            .into_iter()
+            .filter(|a| names.contains(&a.fqn.as_str()))
+            .collect())
+    }
+
+    pub fn visibility(&self) -> &str {
+        if self.visibility.is_empty() {
+            post_visibility::PUBLIC
+        } else {
+            &self.visibility
+        }
+    }
+}
+
+pub fn valid_visibility(visibility: &str) -> Result<(), ValidationError> {
+    if visibility.is_empty() || post_visibility::is_valid(visibility) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_visibility"))
+    }
+}
+
+fn parse_publish_at(date: &str) -> Option<NaiveDateTime> {
+    // seconds may be included, depending on the browser
+    NaiveDateTime::parse_from_str(date, PUBLISH_AT_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+}
+
+pub fn valid_publish_at(date: &str) -> Result<(), ValidationError> {
+    if date.is_empty() || parse_publish_at(date).is_some() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_date"))
+    }
+}
+
+pub fn valid_custom_slug(slug: &str) -> Result<(), ValidationError> {
+    if slug.is_empty() {
+        Ok(())
+    } else {
+        valid_slug(slug)
+    }
 }
 
 pub fn valid_slug(title: &str) -> Result<(), ValidationError> {
-    let slug = title.to_string().to_kebab_case();
+    let slug = utils::make_slug(title);
     if slug.is_empty() {
         Err(ValidationError::new("empty_slug"))
     } else if slug == "new" {
@@ -404,23 +586,13 @@ pub fn create(
 ) -> Result<RespondOrRedirect, ErrorPage> {
     let conn = &*rockets.conn;
     let blog = Blog::find_by_fqn(&rockets, &blog_name).expect("post::create: blog error");;
-    let slug = form.title.to_string().to_kebab_case();
+    let slug = Post::unique_slug(&*conn, blog.id, &form.slug(), None)?;
     let user = rockets.user.clone().unwrap();
 
-    let mut errors = match form.validate() {
+    let errors = match form.validate() {
         Ok(_) => ValidationErrors::new(),
         Err(e) => e,
     };
-    if Post::find_by_slug(&*conn, &slug, blog.id).is_ok() {
-        errors.add(
-            "title",
-            ValidationError {
-                code: Cow::from("existing_slug"),
-                message: Some(Cow::from("A post with the same title already exists.")),
-                params: HashMap::new(),
-            },
-        );
-    }
 
     if errors.is_empty() {
         if !user
@@ -455,6 +627,13 @@ pub fn create(
             )),
         );
 
+        // writers of blogs that require reviews can only save drafts, that an editor will publish
+        let draft = form.draft
+            || !user
+                .can_publish_in(&*conn, &blog)
+                .expect("post::create: can publish error");
+        let publish_at = if draft { None } else { form.publish_at() };
+        // saved as unpublished, and published with `Post::publish` once everything else is saved
         let post = Post::insert(
             &*conn,
             NewPost {
@@ -462,26 +641,32 @@ pub fn create(
                 slug: slug.to_string(),
                 title: form.title.to_string(),
                 content: SafeString::new(&content),
-                published: !form.draft,
+                published: false,
                 license: form.license.clone(),
                 ap_url: "".to_string(),
                 creation_date: None,
                 subtitle: form.subtitle.clone(),
                 source: form.content.clone(),
                 cover_id: form.cover,
+                publish_at,
+                visibility: form.visibility().to_owned(),
             },
             &rockets.searcher,
         )
         .expect("post::create: post save error");
 
-        PostAuthor::insert(
-            &*conn,
-            NewPostAuthor {
-                post_id: post.id,
-                author_id: user.id,
-            },
-        )
-        .expect("post::create: author save error");
+        let mut authors = form
+            .co_authors(&*conn, &blog)
+            .expect("post::create: authors error");
+        authors.push(user.clone());
+        post.set_authors(&*conn, &authors)
+            .expect("post::create: author save error");
+
+        if let Some(series) = form.series(&*conn, &blog) {
+            series
+                .add_post(&*conn, &post)
+                .expect("post::create: series error");
+        }
 
         let tags = form
             .tags
@@ -512,7 +697,8 @@ pub fn create(
             .expect("post::create: hashtags save error");
         }
 
-        if post.published {
+        if !draft {
+            // mentions are notified on publication
             for m in mentions {
                 Mention::from_activity(
                     &*conn,
@@ -520,15 +706,22 @@ pub fn create(
                         .expect("post::create: mention build error"),
                     post.id,
                     true,
-                    true,
+                    false,
                 )
                 .expect("post::create: mention save error");
             }
+        }
 
+        if !draft && publish_at.is_none() {
+            let post = post
+                .publish(&*conn, &rockets.searcher)
+                .expect("post::create: publication error");
             let act = post
                 .create_activity(&*conn)
                 .expect("posts::create: activity error");
-            let dest = User::one_by_instance(&*conn).expect("posts::create: dest error");
+            let dest = post
+                .get_receivers(&*conn)
+                .expect("posts::create: dest error");
             let worker = rockets.worker;
             worker.execute(move || broadcast(&user, act, dest));
         }
@@ -556,6 +749,291 @@ pub fn create(
     }
 }
 
+#[get("/~/<blog>/<slug>/revisions")]
+pub fn revisions(blog: String, slug: String, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &blog)?;
+    let post = Post::find_by_slug(&*conn, &slug, blog.id)?;
+    let is_author = rockets
+        .user
+        .as_ref()
+        .and_then(|u| post.is_author(&*conn, u.id).ok())
+        .unwrap_or(false);
+    if !post.published && !is_author {
+        return Ok(render!(errors::not_authorized(
+            &rockets.to_context(),
+            i18n!(rockets.intl.catalog, "This post isn't published yet.")
+        )));
+    }
+    if !post.can_see(&*conn, rockets.user.as_ref()) {
+        return Ok(render!(errors::not_authorized(
+            &rockets.to_context(),
+            i18n!(
+                rockets.intl.catalog,
+                "You are not allowed to see this article."
+            )
+        )));
+    }
+
+    let revisions = PostRevision::list_for_post(&*conn, post.id)?;
+    let history = revisions
+        .iter()
+        .enumerate()
+        .map(|(i, revision)| {
+            (
+                revision.clone(),
+                revision.get_author(&*conn).ok().and_then(|a| a),
+                revision.diff(revisions.get(i + 1)),
+            )
+        })
+        .collect();
+    Ok(render!(posts::revisions(
+        &rockets.to_context(),
+        post,
+        blog,
+        history,
+        is_author
+    )))
+}
+
+#[post("/~/<blog>/<slug>/revisions/<id>/restore")]
+pub fn restore_revision(
+    blog: String,
+    slug: String,
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let mut post = Post::find_by_slug(&*conn, &slug, b.id)?;
+    let revision = PostRevision::get(&*conn, id)?;
+    if revision.post_id != post.id || !post.is_author(&*conn, user.id)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let previous = post.clone();
+    let (content, mentions, hashtags) = utils::md_to_html(
+        &revision.source,
+        Some(&Instance::get_local()?.public_domain),
+        false,
+        Some(Media::get_media_processor(
+            &conn,
+            b.list_authors(&conn)?.iter().collect(),
+        )),
+    );
+    post.title = revision.title;
+    post.subtitle = revision.subtitle;
+    post.source = revision.source;
+    post.license = revision.license;
+    post.content = SafeString::new(&content);
+    let post = post.update(&*conn, &rockets.searcher)?;
+    PostRevision::record(&*conn, &previous, &post, Some(&user))?;
+
+    if post.published || post.publish_at.is_some() {
+        post.update_mentions(
+            &conn,
+            mentions
+                .into_iter()
+                .filter_map(|m| Mention::build_activity(&rockets, &m).ok())
+                .collect(),
+        )?;
+    }
+    post.update_hashtags(
+        &conn,
+        hashtags
+            .into_iter()
+            .map(|h| h.to_camel_case())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|t| Tag::build_activity(t).ok())
+            .collect(),
+    )?;
+
+    if post.published {
+        let act = post.update_activity(&*conn)?;
+        let dest = post.get_receivers(&*conn)?;
+        rockets.worker.execute(move || broadcast(&user, act, dest));
+    }
+
+    Ok(Flash::success(
+        Redirect::to(uri!(details: blog = blog, slug = slug, responding_to = _)),
+        i18n!(rockets.intl.catalog, "This revision has been restored."),
+    ))
+}
+
+#[get("/~/<blog>/<slug>/review")]
+pub fn review(
+    blog: String,
+    slug: String,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &blog)?;
+    let post = Post::find_by_slug(&*conn, &slug, blog.id)?;
+    if !post.can_edit(&*conn, &user)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let review = PostReview::find_for_post(&*conn, post.id).ok();
+    let comments = match review {
+        Some(ref review) => review
+            .list_comments(&*conn)?
+            .into_iter()
+            .map(|c| Ok((c.get_author(&*conn)?, c)))
+            .collect::<Result<Vec<_>, Error>>()?,
+        None => vec![],
+    };
+    let is_reviewer = user.can_publish_in(&*conn, &blog)?;
+    Ok(render!(posts::review(
+        &rockets.to_context(),
+        post,
+        blog,
+        review,
+        comments,
+        is_reviewer
+    )))
+}
+
+#[post("/~/<blog>/<slug>/review")]
+pub fn submit_review(
+    blog: String,
+    slug: String,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    if !b.require_review {
+        return Err(Error::Unauthorized.into());
+    }
+    let post = Post::find_by_slug(&*conn, &slug, b.id)?;
+    PostReview::submit(&*conn, &post, &user)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(review: blog = blog, slug = slug)),
+        i18n!(
+            rockets.intl.catalog,
+            "Your article has been submitted for review."
+        ),
+    ))
+}
+
+#[derive(Default, FromForm)]
+pub struct ReviewCommentForm {
+    /// The passage of the draft that is commented
+    pub quote: String,
+    pub content: String,
+}
+
+#[post("/~/<blog>/<slug>/review/comment", data = "<form>")]
+pub fn comment_review(
+    blog: String,
+    slug: String,
+    user: User,
+    form: LenientForm<ReviewCommentForm>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let post = Post::find_by_slug(&*conn, &slug, b.id)?;
+    if !post.can_edit(&*conn, &user)? {
+        return Err(Error::Unauthorized.into());
+    }
+    if form.content.trim().is_empty() {
+        return Ok(Flash::error(
+            Redirect::to(uri!(review: blog = blog, slug = slug)),
+            i18n!(rockets.intl.catalog, "Your comment can't be empty."),
+        ));
+    }
+
+    PostReview::find_for_post(&*conn, post.id)?.comment(
+        &*conn,
+        &user,
+        form.quote.trim().to_owned(),
+        form.content.clone(),
+    )?;
+    Ok(Flash::success(
+        Redirect::to(uri!(review: blog = blog, slug = slug)),
+        i18n!(rockets.intl.catalog, "Your comment has been posted."),
+    ))
+}
+
+#[post("/~/<blog>/<slug>/review/changes")]
+pub fn request_changes(
+    blog: String,
+    slug: String,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let post = Post::find_by_slug(&*conn, &slug, b.id)?;
+    if !user.can_publish_in(&*conn, &b)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    PostReview::find_for_post(&*conn, post.id)?.request_changes(&*conn, &user)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(review: blog = blog, slug = slug)),
+        i18n!(
+            rockets.intl.catalog,
+            "The authors have been asked to change their article."
+        ),
+    ))
+}
+
+#[post("/~/<blog>/<slug>/review/approve")]
+pub fn approve_review(
+    blog: String,
+    slug: String,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let mut post = Post::find_by_slug(&*conn, &slug, b.id)?;
+    if !user.can_publish_in(&*conn, &b)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    PostReview::find_for_post(&*conn, post.id)?.approve(&*conn, &user)?;
+
+    // mentions are not saved while a post is a draft
+    let (_, mentions, _) = utils::md_to_html(
+        &post.source,
+        Some(&Instance::get_local()?.public_domain),
+        false,
+        None,
+    );
+    post.update_mentions(
+        &conn,
+        mentions
+            .into_iter()
+            .filter_map(|m| Mention::build_activity(&rockets, &m).ok())
+            .collect(),
+    )?;
+    post.publish_at = None;
+    post.creation_date = Utc::now().naive_utc();
+    let post = post.publish(&*conn, &rockets.searcher)?;
+
+    let author = post
+        .get_authors(&*conn)?
+        .into_iter()
+        .next()
+        .ok_or(Error::NotFound)?;
+    let act = post.create_activity(&*conn)?;
+    let dest = post.get_receivers(&*conn)?;
+    rockets
+        .worker
+        .execute(move || broadcast(&author, act, dest));
+
+    Ok(Flash::success(
+        Redirect::to(uri!(details: blog = blog, slug = slug, responding_to = _)),
+        i18n!(rockets.intl.catalog, "The article has been published."),
+    ))
+}
+
 #[post("/~/<blog_name>/<slug>/delete")]
 pub fn delete(
     blog_name: String,
@@ -581,7 +1059,7 @@ pub fn delete(
             ));
         }
 
-        let dest = User::one_by_instance(&*rockets.conn)?;
+        let dest = post.get_receivers(&*rockets.conn)?;
         let delete_activity = post.build_delete(&*rockets.conn)?;
         inbox(
             &rockets,
@@ -1,8 +1,20 @@
+use atom_syndication::{Entry, FeedBuilder};
 use chrono::offset::Utc;
-use rocket::request::Form;
+use rocket::{
+    http::ContentType,
+    request::{Form, LenientForm},
+    response::{Content, Flash, Redirect},
+};
+use rocket_contrib::json::Json;
+use rocket_i18n::I18n;
+use serde_json;
 
-use plume_models::{search::Query, PlumeRocket};
-use routes::Page;
+use plume_common::utils;
+use plume_models::{
+    blogs::Blog, instance::Instance, saved_searches::*, search::Query, tags::Tag, users::User,
+    Error, PlumeRocket,
+};
+use routes::{errors::ErrorPage, Page};
 use std::str::FromStr;
 use template_utils::{IntoContext, Ructe};
 
@@ -50,7 +62,7 @@ macro_rules! param_to_query {
 }
 
 #[get("/search?<query..>")]
-pub fn search(query: Option<Form<SearchQuery>>, rockets: PlumeRocket) -> Ructe {
+pub fn search(query: Option<Form<SearchQuery>>, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
     let conn = &*rockets.conn;
     let query = query.map(Form::into_inner).unwrap_or_default();
     let page = query.page.unwrap_or_default();
@@ -65,21 +77,176 @@ pub fn search(query: Option<Form<SearchQuery>>, rockets: PlumeRocket) -> Ructe {
     let str_query = parsed_query.to_string();
 
     if str_query.is_empty() {
-        render!(search::index(
+        Ok(render!(search::index(
             &rockets.to_context(),
             &format!("{}", Utc::today().format("%Y-%m-d"))
-        ))
+        )))
     } else {
         let res = rockets
             .searcher
-            .search_document(&conn, parsed_query, page.limits());
+            .search_document(&conn, parsed_query, page.limits())?;
         let next_page = if res.is_empty() { 0 } else { page.0 + 1 };
-        render!(search::result(
+        Ok(render!(search::result(
             &rockets.to_context(),
             &str_query,
             res,
             page.0,
             next_page
-        ))
+        )))
     }
 }
+
+/// How many suggestions of each kind are given
+const SUGGESTIONS: i64 = 5;
+
+/// How many characters have to be typed before anything is suggested
+const MIN_SUGGESTION_PREFIX: usize = 2;
+
+#[get("/search/suggest?<q>")]
+pub fn suggest(q: String, rockets: PlumeRocket) -> Result<Json<serde_json::Value>, ErrorPage> {
+    let conn = &*rockets.conn;
+
+    // only the last word, the one being typed, is completed
+    let word = q.rsplit(' ').next().unwrap_or_default();
+    let (occur, word) = match word.get(0..1) {
+        Some("+") | Some("-") => word.split_at(1),
+        _ => ("", word),
+    };
+    let (field, prefix) = match word.find(':') {
+        Some(pos) => (&word[..pos], &word[pos + 1..]),
+        None => ("", word),
+    };
+
+    let mut suggestions = vec![];
+    if prefix.chars().count() >= MIN_SUGGESTION_PREFIX {
+        let mut suggest = |kind: &str, value: String, label: String| {
+            suggestions.push(json!({
+                "kind": kind,
+                "value": format!("{}{}:{}", occur, kind, quote(&value)),
+                "label": label,
+            }))
+        };
+
+        if field.is_empty() || field == "tag" {
+            for tag in Tag::suggest(conn, prefix, SUGGESTIONS)? {
+                suggest("tag", tag.clone(), tag);
+            }
+        }
+        if field.is_empty() || field == "author" {
+            for user in User::suggest(conn, prefix, SUGGESTIONS)? {
+                suggest("author", user.fqn, user.display_name);
+            }
+        }
+        if field.is_empty() || field == "blog" {
+            for blog in Blog::suggest(conn, prefix, SUGGESTIONS)? {
+                // blogs are searched by title
+                suggest("blog", blog.title.clone(), blog.title);
+            }
+        }
+        if field.is_empty() || field == "title" {
+            for term in rockets
+                .searcher
+                .suggest_terms(conn, prefix, SUGGESTIONS as usize)?
+            {
+                suggest("title", term.clone(), term);
+            }
+        }
+    }
+
+    Ok(Json(json!(suggestions)))
+}
+
+// quote a value if it would be split in many tokens otherwise
+fn quote(value: &str) -> String {
+    if value.contains(' ') {
+        format!("\"{}\"", value.replace('"', ""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[get("/search/saved")]
+pub fn saved(user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    Ok(render!(search::saved(
+        &rockets.to_context(),
+        SavedSearch::list_for_user(&*rockets.conn, user.id)?
+    )))
+}
+
+#[get("/search/saved", rank = 2)]
+pub fn saved_auth(i18n: I18n) -> Flash<Redirect> {
+    utils::requires_login(
+        &i18n!(
+            i18n.catalog,
+            "To see your saved searches, you need to be logged in"
+        ),
+        uri!(saved),
+    )
+}
+
+#[derive(FromForm)]
+pub struct SavedSearchForm {
+    q: String,
+}
+
+#[post("/search/saved", data = "<form>")]
+pub fn save(
+    user: User,
+    form: LenientForm<SavedSearchForm>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    SavedSearch::create(
+        &*rockets.conn,
+        user.id,
+        Query::from_str(&form.q).unwrap_or_default().to_string(),
+    )?;
+    Ok(Flash::success(
+        Redirect::to(uri!(saved)),
+        i18n!(
+            rockets.intl.catalog,
+            "Your search has been saved. You will be notified of new matching articles."
+        ),
+    ))
+}
+
+#[post("/search/saved/<id>/delete")]
+pub fn delete_saved(
+    id: i32,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let search = SavedSearch::get(&*rockets.conn, id)?;
+    if search.user_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    search.delete(&*rockets.conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(saved)),
+        i18n!(rockets.intl.catalog, "Your saved search has been deleted."),
+    ))
+}
+
+#[get("/search/saved/<token>/atom.xml")]
+pub fn saved_atom_feed(token: String, rockets: PlumeRocket) -> Option<Content<String>> {
+    let conn = &*rockets.conn;
+    let search = SavedSearch::find_by_token(conn, &token).ok()?;
+    let feed = FeedBuilder::default()
+        .title(search.query.clone())
+        .id(Instance::get_local()
+            .unwrap()
+            .compute_box("search/saved", &token, "atom.xml"))
+        .entries(
+            search
+                .results(conn, &rockets.searcher, 15)
+                .ok()?
+                .into_iter()
+                .map(|p| super::post_to_atom(p, conn))
+                .collect::<Vec<Entry>>(),
+        )
+        .build()
+        .expect("search::saved_atom_feed: Error building Atom feed");
+    Some(Content(
+        ContentType::new("application", "atom+xml"),
+        feed.to_string(),
+    ))
+}
@@ -0,0 +1,231 @@
+use activitypub::collection::OrderedCollection;
+use atom_syndication::{Entry, FeedBuilder};
+use rocket::{
+    http::ContentType,
+    request::LenientForm,
+    response::{content::Content, Flash, Redirect},
+};
+use rocket_i18n::I18n;
+use std::{borrow::Cow, collections::HashMap};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use plume_common::activity_pub::{ActivityStream, ApRequest};
+use plume_common::utils;
+use plume_models::{blogs::Blog, posts::Post, series::*, users::User, Error, PlumeRocket};
+use routes::{errors::ErrorPage, RespondOrRedirect};
+use template_utils::{IntoContext, Ructe};
+
+#[get("/~/<blog>/series/<slug>", rank = 6)]
+pub fn details(blog: String, slug: String, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &blog)?;
+    let series = Series::find_by_slug(&*conn, &slug, blog.id)?;
+    let is_author = rockets
+        .user
+        .as_ref()
+        .and_then(|u| u.is_author_in(&*conn, &blog).ok())
+        .unwrap_or(false);
+    // authors can see drafts, to organize the series before publishing everything
+    let posts = if is_author {
+        series.get_posts(&*conn)?
+    } else {
+        series.get_visible_posts(&*conn, rockets.user.as_ref())?
+    };
+
+    Ok(render!(series::details(
+        &rockets.to_context(),
+        blog,
+        series,
+        posts,
+        is_author
+    )))
+}
+
+#[get("/~/<blog>/series/<slug>", rank = 5)]
+pub fn activity_details(
+    blog: String,
+    slug: String,
+    _ap: ApRequest,
+    rockets: PlumeRocket,
+) -> Option<ActivityStream<OrderedCollection>> {
+    let blog = Blog::find_by_fqn(&rockets, &blog).ok()?;
+    let series = Series::find_by_slug(&*rockets.conn, &slug, blog.id).ok()?;
+    Some(ActivityStream::new(
+        series.to_activity(&*rockets.conn).ok()?,
+    ))
+}
+
+#[get("/~/<blog>/series/<slug>/atom.xml", rank = 5)]
+pub fn atom_feed(blog: String, slug: String, rockets: PlumeRocket) -> Option<Content<String>> {
+    let conn = &*rockets.conn;
+    let blog = Blog::find_by_fqn(&rockets, &blog).ok()?;
+    let series = Series::find_by_slug(&*conn, &slug, blog.id).ok()?;
+    let feed = FeedBuilder::default()
+        .title(series.title.clone())
+        .id(format!("{}/atom.xml", series.ap_url))
+        .entries(
+            series
+                .get_visible_posts(&*conn, None)
+                .ok()?
+                .into_iter()
+                .rev()
+                .map(|p| super::post_to_atom(p, &*conn))
+                .collect::<Vec<Entry>>(),
+        )
+        .build()
+        .ok()?;
+    Some(Content(
+        ContentType::new("application", "atom+xml"),
+        feed.to_string(),
+    ))
+}
+
+#[get("/~/<blog>/series/new")]
+pub fn new(blog: String, user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let blog = Blog::find_by_fqn(&rockets, &blog)?;
+    if !user.is_author_in(&*rockets.conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    Ok(render!(series::new(
+        &rockets.to_context(),
+        blog,
+        &NewSeriesForm::default(),
+        ValidationErrors::default()
+    )))
+}
+
+#[get("/~/<blog>/series/new", rank = 2)]
+pub fn new_auth(blog: String, i18n: I18n) -> Flash<Redirect> {
+    utils::requires_login(
+        &i18n!(
+            i18n.catalog,
+            "To create a new series, you need to be logged in"
+        ),
+        uri!(new: blog = blog),
+    )
+}
+
+#[derive(Default, FromForm, Validate)]
+pub struct NewSeriesForm {
+    #[validate(custom(function = "valid_slug", message = "Invalid title"))]
+    pub title: String,
+    pub description: String,
+}
+
+fn valid_slug(title: &str) -> Result<(), ValidationError> {
+    let slug = Series::slug_for(title);
+    if slug.is_empty() || slug == "new" {
+        Err(ValidationError::new("invalid_slug"))
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/~/<blog>/series/new", data = "<form>")]
+pub fn create(
+    blog: String,
+    user: User,
+    form: LenientForm<NewSeriesForm>,
+    rockets: PlumeRocket,
+) -> Result<RespondOrRedirect, ErrorPage> {
+    let conn = &*rockets.conn;
+    let intl = &rockets.intl.catalog;
+    let blog = Blog::find_by_fqn(&rockets, &blog)?;
+    if !user.is_author_in(&*conn, &blog)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let slug = Series::slug_for(&form.title);
+    let mut errors = match form.validate() {
+        Ok(_) => ValidationErrors::new(),
+        Err(e) => e,
+    };
+    if Series::find_by_slug(&*conn, &slug, blog.id).is_ok() {
+        errors.add(
+            "title",
+            ValidationError {
+                code: Cow::from("existing_slug"),
+                message: Some(Cow::from(i18n!(
+                    intl,
+                    "A series with the same title already exists."
+                ))),
+                params: HashMap::new(),
+            },
+        );
+    }
+
+    if !errors.is_empty() {
+        return Ok(render!(series::new(&rockets.to_context(), blog, &*form, errors)).into());
+    }
+
+    Series::insert(
+        &*conn,
+        NewSeries {
+            blog_id: blog.id,
+            slug: slug.clone(),
+            title: form.title.clone(),
+            description: form.description.clone(),
+            ap_url: String::new(),
+        },
+    )?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(details: blog = &blog.fqn, slug = slug)),
+        i18n!(intl, "Your series has been created."),
+    )
+    .into())
+}
+
+#[derive(FromForm)]
+pub struct SeriesPostForm {
+    pub post: i32,
+    /// "up", "down" or "remove"
+    pub action: String,
+}
+
+#[post("/~/<blog>/series/<slug>/posts", data = "<form>")]
+pub fn update_posts(
+    blog: String,
+    slug: String,
+    user: User,
+    form: LenientForm<SeriesPostForm>,
+    rockets: PlumeRocket,
+) -> Result<Redirect, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let series = Series::find_by_slug(&*conn, &slug, b.id)?;
+    let post = Post::get(&*conn, form.post)?;
+    if !user.is_author_in(&*conn, &b)? || post.blog_id != b.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    match form.action.as_ref() {
+        "up" => series.move_post(&*conn, &post, -1)?,
+        "down" => series.move_post(&*conn, &post, 1)?,
+        "remove" => series.remove_post(&*conn, &post)?,
+        _ => return Err(Error::InvalidValue.into()),
+    }
+    Ok(Redirect::to(uri!(details: blog = blog, slug = slug)))
+}
+
+#[post("/~/<blog>/series/<slug>/delete")]
+pub fn delete(
+    blog: String,
+    slug: String,
+    user: User,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let b = Blog::find_by_fqn(&rockets, &blog)?;
+    let series = Series::find_by_slug(&*conn, &slug, b.id)?;
+    if !user.is_author_in(&*conn, &b)? {
+        return Err(Error::Unauthorized.into());
+    }
+
+    series.delete(&*conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(super::blogs::details: name = blog, page = _)),
+        i18n!(rockets.intl.catalog, "Your series has been deleted."),
+    ))
+}
@@ -1,4 +1,6 @@
+use chrono::Utc;
 use lettre::Transport;
+use qrcode::{render::svg, QrCode};
 use rocket::http::ext::IntoOwned;
 use rocket::{
     http::{uri::Uri, Cookie, Cookies, SameSite},
@@ -7,7 +9,7 @@ use rocket::{
     State,
 };
 use rocket_i18n::I18n;
-use routes::RespondOrRedirect;
+use routes::{errors::ErrorPage, RespondOrRedirect};
 use std::{
     borrow::Cow,
     sync::{Arc, Mutex},
@@ -17,7 +19,11 @@ use validator::{Validate, ValidationError, ValidationErrors};
 
 use mail::{build_mail, Mailer};
 use plume_models::{
+    db_conn::DbConn,
+    instance::Instance,
     password_reset_requests::*,
+    sessions::{Client, Session},
+    two_factor::{generate_secret, provisioning_uri, RecoveryCode, TwoFactorAuth},
     users::{User, AUTH_COOKIE},
     Error, PlumeRocket, CONFIG,
 };
@@ -44,6 +50,7 @@ pub struct LoginForm {
 #[post("/login", data = "<form>")]
 pub fn create(
     form: LenientForm<LoginForm>,
+    client: Client,
     mut cookies: Cookies,
     rockets: PlumeRocket,
 ) -> RespondOrRedirect {
@@ -55,14 +62,14 @@ pub fn create(
         Err(e) => e,
     };
 
-    let user_id = if let Ok(user) = user {
+    let user = if let Ok(user) = user {
         if !user.auth(&form.password) {
             let mut err = ValidationError::new("invalid_login");
             err.message = Some(Cow::from("Invalid username, or password"));
             errors.add("email_or_name", err);
-            String::new()
+            None
         } else {
-            user.id.to_string()
+            Some(user)
         }
     } else {
         // Fake password verification, only to avoid different login times
@@ -74,18 +81,16 @@ pub fn create(
         let mut err = ValidationError::new("invalid_login");
         err.message = Some(Cow::from("Invalid username, or password"));
         errors.add("email_or_name", err);
-        String::new()
+        None
     };
 
-    if !errors.is_empty() {
-        return render!(session::login(&rockets.to_context(), None, &*form, errors)).into();
-    }
+    let user = match user {
+        Some(user) if errors.is_empty() => user,
+        _ => {
+            return render!(session::login(&rockets.to_context(), None, &*form, errors)).into();
+        }
+    };
 
-    cookies.add_private(
-        Cookie::build(AUTH_COOKIE, user_id)
-            .same_site(SameSite::Lax)
-            .finish(),
-    );
     let destination = rockets
         .flash_msg
         .clone()
@@ -100,12 +105,20 @@ pub fn create(
         )
         .unwrap_or_else(|| "/".to_owned());
 
-    if let Ok(uri) = Uri::parse(&destination).map(IntoOwned::into_owned) {
-        Flash::success(
-            Redirect::to(uri),
-            i18n!(&rockets.intl.catalog, "You are now connected."),
-        )
-        .into()
+    if TwoFactorAuth::is_enabled(conn, user.id) {
+        cookies.add_private(
+            Cookie::build(
+                TWO_FACTOR_COOKIE,
+                format!("{}:{}:{}", user.id, Utc::now().timestamp(), destination),
+            )
+            .same_site(SameSite::Lax)
+            .finish(),
+        );
+        return Redirect::to(uri!(two_factor_form)).into();
+    }
+
+    if let Some(response) = open_session(&user, &destination, &client, &mut cookies, &rockets) {
+        response.into()
     } else {
         render!(session::login(
             &(conn, &rockets.intl.catalog, None, None),
@@ -117,8 +130,135 @@ pub fn create(
     }
 }
 
+/// Remembers who gave a valid password, until they also give a valid code
+const TWO_FACTOR_COOKIE: &str = "two_factor_pending";
+
+/// How long users have to give their code once they gave their password, in seconds
+const TWO_FACTOR_DELAY: i64 = 300;
+
+/// The ID of the user who is logging in, and where to send them after that
+fn pending_two_factor(cookies: &mut Cookies) -> Option<(i32, String)> {
+    let cookie = cookies.get_private(TWO_FACTOR_COOKIE)?;
+    let mut parts = cookie.value().splitn(3, ':');
+    let user_id = parts.next()?.parse().ok()?;
+    let started = parts.next()?.parse::<i64>().ok()?;
+    let destination = parts.next()?.to_owned();
+    if Utc::now().timestamp() - started > TWO_FACTOR_DELAY {
+        None
+    } else {
+        Some((user_id, destination))
+    }
+}
+
+/// Log `user` in this browser, and send them to `destination`
+fn open_session(
+    user: &User,
+    destination: &str,
+    client: &Client,
+    cookies: &mut Cookies,
+    rockets: &PlumeRocket,
+) -> Option<Flash<Redirect>> {
+    let conn = &*rockets.conn;
+    let session = Session::start(conn, user, client).ok()?;
+    cookies.add_private(
+        Cookie::build(AUTH_COOKIE, session.token)
+            .same_site(SameSite::Lax)
+            .finish(),
+    );
+
+    if user.must_enable_2fa(conn) {
+        return Some(Flash::error(
+            Redirect::to(uri!(two_factor_settings)),
+            i18n!(
+                &rockets.intl.catalog,
+                "Administrators of this instance must enable two-factor authentication."
+            ),
+        ));
+    }
+
+    let uri = Uri::parse(destination).map(IntoOwned::into_owned).ok()?;
+    Some(Flash::success(
+        Redirect::to(uri),
+        i18n!(&rockets.intl.catalog, "You are now connected."),
+    ))
+}
+
+#[get("/login/2fa")]
+pub fn two_factor_form(mut cookies: Cookies, rockets: PlumeRocket) -> RespondOrRedirect {
+    if pending_two_factor(&mut cookies).is_none() {
+        return Redirect::to(uri!(new: m = _)).into();
+    }
+    render!(session::two_factor(
+        &rockets.to_context(),
+        ValidationErrors::default()
+    ))
+    .into()
+}
+
+#[derive(Default, FromForm)]
+pub struct TwoFactorForm {
+    pub code: String,
+}
+
+#[post("/login/2fa", data = "<form>")]
+pub fn two_factor(
+    form: LenientForm<TwoFactorForm>,
+    client: Client,
+    mut cookies: Cookies,
+    rockets: PlumeRocket,
+) -> RespondOrRedirect {
+    let conn = &*rockets.conn;
+    let (user_id, destination) = match pending_two_factor(&mut cookies) {
+        Some(pending) => pending,
+        None => {
+            return Flash::error(
+                Redirect::to(uri!(new: m = _)),
+                i18n!(&rockets.intl.catalog, "Please log in again."),
+            )
+            .into();
+        }
+    };
+
+    let valid = TwoFactorAuth::find_by_user(conn, user_id)
+        .and_then(|auth| auth.verify(conn, &form.code))
+        .unwrap_or(false);
+    if valid {
+        if let Ok(user) = User::get(conn, user_id) {
+            if let Some(response) =
+                open_session(&user, &destination, &client, &mut cookies, &rockets)
+            {
+                if let Some(cookie) = cookies.get_private(TWO_FACTOR_COOKIE) {
+                    cookies.remove_private(cookie);
+                }
+                return response.into();
+            }
+        }
+    }
+
+    let locked = TwoFactorAuth::find_by_user(conn, user_id)
+        .map(|auth| auth.is_locked())
+        .unwrap_or(false);
+    let errors = if locked {
+        locked_errors()
+    } else {
+        invalid_code_errors()
+    };
+    render!(session::two_factor(&rockets.to_context(), errors)).into()
+}
+
 #[get("/logout")]
-pub fn delete(mut cookies: Cookies, intl: I18n) -> Flash<Redirect> {
+pub fn delete(
+    session: Option<Session>,
+    conn: DbConn,
+    mut cookies: Cookies,
+    intl: I18n,
+) -> Flash<Redirect> {
+    if let Some(session) = session {
+        session
+            .delete(&*conn)
+            .map_err(|_| eprintln!("Couldn't close a session"))
+            .ok();
+    }
     if let Some(cookie) = cookies.get_private(AUTH_COOKIE) {
         cookies.remove_private(cookie);
     }
@@ -128,6 +268,209 @@ pub fn delete(mut cookies: Cookies, intl: I18n) -> Flash<Redirect> {
     )
 }
 
+/// The browsers in which the current user is logged in
+#[get("/sessions")]
+pub fn list(user: User, session: Session, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let sessions = Session::list_for_user(&*rockets.conn, user.id)?;
+    Ok(render!(session::list(
+        &rockets.to_context(),
+        sessions,
+        session.id
+    )))
+}
+
+#[post("/sessions/<id>/delete")]
+pub fn revoke(id: i32, user: User, conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let session = Session::get(&*conn, id)?;
+    if session.user_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    session.delete(&*conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(list)),
+        i18n!(intl.catalog, "This session has been closed."),
+    ))
+}
+
+#[post("/sessions/delete-others")]
+pub fn revoke_others(
+    user: User,
+    session: Session,
+    conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    Session::delete_for_user(&*conn, user.id, Some(session.id))?;
+    Ok(Flash::success(
+        Redirect::to(uri!(list)),
+        i18n!(intl.catalog, "You have been logged out everywhere else."),
+    ))
+}
+
+#[get("/settings/2fa")]
+pub fn two_factor_settings(user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    if TwoFactorAuth::is_enabled(conn, user.id) {
+        Ok(render!(session::two_factor_settings(
+            &rockets.to_context(),
+            RecoveryCode::count_for_user(conn, user.id)?,
+            vec![],
+            ValidationErrors::default()
+        )))
+    } else {
+        two_factor_setup(
+            &user,
+            &generate_secret()?,
+            ValidationErrors::default(),
+            &rockets,
+        )
+    }
+}
+
+/// The page to scan the QR code of a new secret, and confirm it with a first code
+fn two_factor_setup(
+    user: &User,
+    secret: &str,
+    errors: ValidationErrors,
+    rockets: &PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let uri = provisioning_uri(secret, &user.fqn, &CONFIG.base_url);
+    let qr_code = QrCode::new(uri.as_bytes())
+        .map_err(|_| Error::InvalidValue)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(render!(session::two_factor_setup(
+        &rockets.to_context(),
+        secret.to_owned(),
+        qr_code,
+        errors
+    )))
+}
+
+#[derive(Default, FromForm)]
+pub struct EnableTwoFactorForm {
+    pub secret: String,
+    pub code: String,
+}
+
+#[post("/settings/2fa", data = "<form>")]
+pub fn enable_two_factor(
+    user: User,
+    form: LenientForm<EnableTwoFactorForm>,
+    rockets: PlumeRocket,
+) -> Result<RespondOrRedirect, ErrorPage> {
+    let conn = &*rockets.conn;
+    // replacing the current device would need no code from it
+    if TwoFactorAuth::is_enabled(conn, user.id) {
+        return Ok(Flash::error(
+            Redirect::to(uri!(two_factor_settings)),
+            i18n!(
+                rockets.intl.catalog,
+                "Two-factor authentication is already enabled. Disable it first to use another device."
+            ),
+        )
+        .into());
+    }
+
+    match TwoFactorAuth::enable(conn, user.id, &form.secret, &form.code) {
+        Ok(recovery_codes) => Ok(render!(session::two_factor_settings(
+            &rockets.to_context(),
+            recovery_codes.len() as i64,
+            recovery_codes,
+            ValidationErrors::default()
+        ))
+        .into()),
+        Err(Error::Unauthorized) => {
+            two_factor_setup(&user, &form.secret, invalid_code_errors(), &rockets).map(Into::into)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[post("/settings/2fa/recovery-codes", data = "<form>")]
+pub fn regenerate_recovery_codes(
+    user: User,
+    form: LenientForm<TwoFactorForm>,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let conn = &*rockets.conn;
+    let auth = TwoFactorAuth::find_by_user(conn, user.id)?;
+    if auth.verify(conn, &form.code)? {
+        let recovery_codes = RecoveryCode::generate(conn, user.id)?;
+        Ok(render!(session::two_factor_settings(
+            &rockets.to_context(),
+            recovery_codes.len() as i64,
+            recovery_codes,
+            ValidationErrors::default()
+        )))
+    } else {
+        Ok(render!(session::two_factor_settings(
+            &rockets.to_context(),
+            RecoveryCode::count_for_user(conn, user.id)?,
+            vec![],
+            invalid_code_errors()
+        )))
+    }
+}
+
+#[post("/settings/2fa/disable", data = "<form>")]
+pub fn disable_two_factor(
+    user: User,
+    form: LenientForm<TwoFactorForm>,
+    rockets: PlumeRocket,
+) -> Result<RespondOrRedirect, ErrorPage> {
+    let conn = &*rockets.conn;
+    if user.is_admin && Instance::get_local()?.require_admin_2fa {
+        return Ok(Flash::error(
+            Redirect::to(uri!(two_factor_settings)),
+            i18n!(
+                rockets.intl.catalog,
+                "Administrators of this instance must use two-factor authentication."
+            ),
+        )
+        .into());
+    }
+
+    let auth = TwoFactorAuth::find_by_user(conn, user.id)?;
+    if auth.verify(conn, &form.code)? {
+        TwoFactorAuth::disable(conn, user.id)?;
+        Ok(Flash::success(
+            Redirect::to(uri!(two_factor_settings)),
+            i18n!(
+                rockets.intl.catalog,
+                "Two-factor authentication has been disabled."
+            ),
+        )
+        .into())
+    } else {
+        Ok(render!(session::two_factor_settings(
+            &rockets.to_context(),
+            RecoveryCode::count_for_user(conn, user.id)?,
+            vec![],
+            invalid_code_errors()
+        ))
+        .into())
+    }
+}
+
+fn invalid_code_errors() -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    let mut err = ValidationError::new("invalid_code");
+    err.message = Some(Cow::from("Invalid code"));
+    errors.add("code", err);
+    errors
+}
+
+fn locked_errors() -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    let mut err = ValidationError::new("locked");
+    err.message = Some(Cow::from(
+        "Too many invalid codes, please try again in a few minutes",
+    ));
+    errors.add("code", err);
+    errors
+}
+
 #[derive(Clone)]
 pub struct ResetRequest {
     pub mail: String,
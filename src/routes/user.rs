@@ -1,14 +1,19 @@
 use activitypub::{activity::Create, collection::OrderedCollection};
 use atom_syndication::{Entry, FeedBuilder};
 use diesel::SaveChangesDsl;
+use multipart::server::{
+    save::{PartialReason, SaveResult, SavedData},
+    Multipart,
+};
 use rocket::{
     http::{ContentType, Cookies},
     request::LenientForm,
     response::{status, Content, Flash, Redirect},
+    Data, State,
 };
 use rocket_i18n::I18n;
 use serde_json;
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, fs};
 use validator::{Validate, ValidationError, ValidationErrors};
 
 use inbox;
@@ -16,15 +21,17 @@ use plume_common::activity_pub::{broadcast, inbox::FromId, ActivityStream, ApReq
 use plume_common::utils;
 use plume_models::{
     blogs::Blog,
-    db_conn::DbConn,
+    db_conn::{DbConn, DbPool},
     follows,
     headers::Headers,
     inbox::inbox as local_inbox,
     instance::Instance,
     medias::Media,
+    post_reviews::PostReview,
     posts::{LicensedArticle, Post},
     reshares::Reshare,
     safe_string::SafeString,
+    user_exports::UserExport,
     users::*,
     Error, PlumeRocket,
 };
@@ -128,7 +135,9 @@ pub fn dashboard(user: User, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
     Ok(render!(users::dashboard(
         &rockets.to_context(),
         blogs,
-        Post::drafts_by_author(&*rockets.conn, &user)?
+        Post::drafts_by_author(&*rockets.conn, &user)?,
+        Post::scheduled_by_author(&*rockets.conn, &user)?,
+        PostReview::pending_for_reviewer(&*rockets.conn, &user)?
     )))
 }
 
@@ -348,8 +357,10 @@ pub fn edit(name: String, user: User, rockets: PlumeRocket) -> Result<Ructe, Err
                 summary: user.summary.clone(),
                 theme: user.preferred_theme,
                 hide_custom_css: user.hide_custom_css,
+                also_known_as: user.aliases().join("\n"),
             },
-            ValidationErrors::default()
+            ValidationErrors::default(),
+            UserExport::last_for_user(&*rockets.conn, user.id).ok()
         )))
     } else {
         Err(Error::Unauthorized)?
@@ -374,16 +385,34 @@ pub struct UpdateUserForm {
     pub summary: String,
     pub theme: Option<String>,
     pub hide_custom_css: bool,
+    /// The other accounts of this user, one per line
+    pub also_known_as: String,
 }
 
 #[put("/@/<_name>/edit", data = "<form>")]
 pub fn update(
     _name: String,
-    conn: DbConn,
     mut user: User,
     form: LenientForm<UpdateUserForm>,
-    intl: I18n,
+    rockets: PlumeRocket,
 ) -> Result<Flash<Redirect>, ErrorPage> {
+    let conn = &*rockets.conn;
+    let intl = &rockets.intl.catalog;
+    let mut aliases = vec![];
+    for alias in form.also_known_as.split_whitespace() {
+        if alias.starts_with("https://") || alias.starts_with("http://") {
+            aliases.push(alias.to_owned());
+        } else if let Ok(account) = User::find_by_fqn(&rockets, alias.trim_start_matches('@')) {
+            aliases.push(account.ap_url);
+        } else {
+            return Ok(Flash::error(
+                Redirect::to(uri!(edit: name = &user.username)),
+                i18n!(intl, "Couldn't find the account {0}."; alias),
+            ));
+        }
+    }
+
+    user.also_known_as = aliases.join(" ");
     user.display_name = form.display_name.clone();
     user.email = Some(form.email.clone());
     user.summary = form.summary.clone();
@@ -392,17 +421,215 @@ pub fn update(
             &form.summary,
             None,
             false,
-            Some(Media::get_media_processor(&conn, vec![&user])),
+            Some(Media::get_media_processor(conn, vec![&user])),
         )
         .0,
     );
     user.preferred_theme = form.theme.clone();
     user.hide_custom_css = form.hide_custom_css;
-    let _: User = user.save_changes(&*conn).map_err(Error::from)?;
+    let _: User = user.save_changes(conn).map_err(Error::from)?;
 
     Ok(Flash::success(
         Redirect::to(uri!(me)),
-        i18n!(intl.catalog, "Your profile has been updated."),
+        i18n!(intl, "Your profile has been updated."),
+    ))
+}
+
+#[derive(FromForm)]
+pub struct MoveForm {
+    /// The address of the new account
+    pub to: String,
+}
+
+#[post("/@/<name>/move", data = "<form>")]
+pub fn move_account(
+    name: String,
+    mut user: User,
+    form: LenientForm<MoveForm>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    if user.username != name {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let conn = &*rockets.conn;
+    let intl = &rockets.intl.catalog;
+    let moved = User::find_by_fqn(&rockets, form.to.trim().trim_start_matches('@'))
+        .and_then(|new| User::find_fresh(&rockets, &new.ap_url))
+        .and_then(|new| user.move_to(conn, &new).map(|act| (new, act)));
+    match moved {
+        Ok((new, act)) => {
+            let followers = user.get_followers(conn)?;
+            let msg = i18n!(intl, "Your followers are being moved to {0}."; new.name());
+            rockets
+                .worker
+                .execute(move || broadcast(&user, act, followers));
+            Ok(Flash::success(Redirect::to(uri!(edit: name = name)), msg))
+        }
+        Err(_) => Ok(Flash::error(
+            Redirect::to(uri!(edit: name = name)),
+            i18n!(
+                intl,
+                "Your account couldn't be moved. Make sure your new account lists this one as an alias."
+            ),
+        )),
+    }
+}
+
+#[get("/@/<name>/following.csv")]
+pub fn export_following(
+    name: String,
+    user: User,
+    conn: DbConn,
+) -> Result<Content<String>, ErrorPage> {
+    if user.username != name {
+        return Err(Error::Unauthorized.into());
+    }
+
+    Ok(Content(
+        ContentType::new("text", "csv"),
+        follows::Follow::export_csv(&*conn, &user)?,
+    ))
+}
+
+#[post("/@/<name>/following.csv", data = "<data>")]
+pub fn import_following(
+    name: String,
+    user: User,
+    data: Data,
+    ct: &ContentType,
+    rockets: PlumeRocket,
+    import_rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    if user.username != name {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let intl = &rockets.intl.catalog;
+    let failed = || {
+        Flash::error(
+            Redirect::to(uri!(edit: name = &name)),
+            i18n!(intl, "This list couldn't be imported."),
+        )
+    };
+    if !ct.is_form_data() {
+        return Ok(failed());
+    }
+    let boundary = match ct.params().find(|&(k, _)| k == "boundary") {
+        Some((_, boundary)) => boundary,
+        None => return Ok(failed()),
+    };
+    let fields = match Multipart::with_body(data.open(), boundary)
+        .save()
+        .size_limit(follows::MAX_CSV_SIZE)
+        .temp()
+    {
+        SaveResult::Full(entries) => entries.fields,
+        SaveResult::Partial(_, PartialReason::SizeLimit) => {
+            return Ok(Flash::error(
+                Redirect::to(uri!(edit: name = &name)),
+                i18n!(intl, "This list is too large."),
+            ));
+        }
+        _ => return Ok(failed()),
+    };
+    let csv = match fields
+        .get("file")
+        .and_then(|f| f.iter().next())
+        .map(|f| &f.data)
+    {
+        Some(SavedData::Text(text)) => text.clone(),
+        Some(SavedData::Bytes(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+        Some(SavedData::File(path, _)) => match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return Ok(failed()),
+        },
+        _ => return Ok(failed()),
+    };
+
+    // remote accounts have to be looked up, which can take a while
+    rockets.worker.execute(move || {
+        let conn = &*import_rockets.conn;
+        let imported = follows::Follow::import_csv(&import_rockets, &user, &csv).and_then(
+            |(follows, not_found)| {
+                for follow in follows {
+                    let act = follow.to_activity(conn)?;
+                    let target = User::get(conn, follow.following_id)?;
+                    broadcast(&user, act, vec![target]);
+                }
+                if !not_found.is_empty() {
+                    eprintln!(
+                        "These accounts couldn't be followed: {}",
+                        not_found.join(", ")
+                    );
+                }
+                Ok(())
+            },
+        );
+        if let Err(e) = imported {
+            eprintln!("Error while importing followed accounts: {:?}", e);
+        }
+    });
+
+    Ok(Flash::success(
+        Redirect::to(uri!(edit: name = &name)),
+        i18n!(
+            intl,
+            "Your list is being imported. You will follow these accounts in a few minutes."
+        ),
+    ))
+}
+
+#[post("/@/<name>/export")]
+pub fn request_export(
+    name: String,
+    user: User,
+    pool: State<DbPool>,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    if user.username != name {
+        return Err(Error::Unauthorized.into());
+    }
+
+    if !UserExport::can_request(&*rockets.conn, &user)? {
+        return Ok(Flash::error(
+            Redirect::to(uri!(edit: name = &name)),
+            i18n!(
+                rockets.intl.catalog,
+                "An archive of your data has been requested recently. Please try again later."
+            ),
+        ));
+    }
+
+    let mut export = UserExport::request(&*rockets.conn, &user)?;
+    let pool = pool.clone();
+    rockets.worker.execute(move || {
+        if let Ok(conn) = pool.get() {
+            if let Err(e) = export.generate(&conn) {
+                eprintln!("Error while exporting the data of a user: {:?}", e);
+            }
+        }
+    });
+
+    Ok(Flash::success(
+        Redirect::to(uri!(edit: name = &name)),
+        i18n!(
+            rockets.intl.catalog,
+            "Your archive is being prepared. You will be able to download it from this page in a few minutes."
+        ),
+    ))
+}
+
+#[get("/@/<name>/export")]
+pub fn export(name: String, user: User, conn: DbConn) -> Result<Content<Vec<u8>>, ErrorPage> {
+    if user.username != name {
+        return Err(Error::Unauthorized.into());
+    }
+
+    let export = UserExport::last_for_user(&*conn, user.id)?;
+    Ok(Content(
+        ContentType::new("application", "zip"),
+        export.read()?,
     ))
 }
 
@@ -1,4 +1,11 @@
-use plume_models::{notifications::*, users::User, Connection, PlumeRocket};
+use plume_models::{
+    blog_invitations::BlogInvitation,
+    blogs::Blog,
+    notifications::*,
+    post_reviews::{review_status, PostReview},
+    users::User,
+    Connection, PlumeRocket,
+};
 
 use rocket::http::hyper::header::{ETag, EntityTag};
 use rocket::http::{Method, Status};
@@ -101,9 +108,36 @@ pub fn translate_notification(ctx: BaseContext, notif: Notification) -> String {
     match notif.kind.as_ref() {
         notification_kind::COMMENT => i18n!(ctx.1, "{0} commented on your article."; &name),
         notification_kind::FOLLOW => i18n!(ctx.1, "{0} is subscribed to you."; &name),
+        notification_kind::IMPORT_FAILED => {
+            let blog = Blog::get(ctx.0, notif.object_id)
+                .map(|b| b.title)
+                .unwrap_or_default();
+            i18n!(ctx.1, "Your export could not be imported in {0}."; blog)
+        }
+        notification_kind::INVITATION => {
+            let blog = BlogInvitation::get(ctx.0, notif.object_id)
+                .and_then(|i| i.get_blog(ctx.0))
+                .map(|b| b.title)
+                .unwrap_or_default();
+            i18n!(ctx.1, "{0} invited you to write in {1}."; &name, blog)
+        }
         notification_kind::LIKE => i18n!(ctx.1, "{0} liked your article."; &name),
         notification_kind::MENTION => i18n!(ctx.1, "{0} mentioned you."; &name),
         notification_kind::RESHARE => i18n!(ctx.1, "{0} boosted your article."; &name),
+        notification_kind::REVIEW => match PostReview::get(ctx.0, notif.object_id) {
+            Ok(ref review) if review.status == review_status::APPROVED => {
+                i18n!(ctx.1, "{0} approved and published your article."; &name)
+            }
+            _ => i18n!(ctx.1, "{0} requested changes on your article."; &name),
+        },
+        notification_kind::REVIEW_REQUEST => {
+            i18n!(ctx.1, "{0} submitted an article for review."; &name)
+        }
+        notification_kind::SAVED_SEARCH => i18n!(
+            ctx.1,
+            "{0} published an article matching one of your saved searches.";
+            &name
+        ),
         _ => unreachable!("translate_notification: Unknow type"),
     }
 }